@@ -0,0 +1,185 @@
+// examples/adc_dma.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example samples PA0 (Arduino A0) continuously with ADC1, and lets DMA1
+//! move every conversion into a circular buffer, so the CPU is not involved in
+//! sampling at all. Once a second, the running average is reported over USART.
+//!
+//! The buffer has two halves. DMA sets the half transfer flag when it finishes
+//! filling the first half, and the transfer complete flag when it finishes the
+//! second half and wraps around to the first. While DMA fills one half, the other
+//! half is stable, so the main loop averages each half as soon as it is complete.
+//! The HAL tracks which half was read last, and clears each flag as its half is
+//! taken.
+//!
+//! If the main loop falls a whole half behind, both flags are set at once, and DMA
+//! may be overwriting the half being read. That is counted as an overrun, and the
+//! transfer is restarted, which clears the flags. With the default ADC clock of
+//! 6MHz and the longest sample time, each half takes about 10ms to fill, which is
+//! plenty of time to send a report.
+//!
+//! Each report also includes an exponential moving average of the half means, which
+//! carries over from one report to the next, so it changes more slowly than the
+//! mean for the last second. `EMA_SHIFT` sets how strongly it smooths.
+
+use core::fmt::Write;
+use cortex_m::singleton;
+use cortex_m_rt::{entry, exception};
+use heapless::String;
+use hello_nucleo_f103rb::{
+    adc::{mean_u16, scale_adc, Ema, VDDA_NOMINAL_MV},
+    board::Board,
+    millis::{self, millis},
+};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    adc::{Adc, SampleTime},
+    dma::Half,
+    pac::USART2,
+    prelude::*,
+    serial::{Config, Serial, Tx},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const HALF_SAMPLES: usize = 256;
+const REPORT_MS: u32 = 1_000;
+// About 100 halves are completed per second, so this settles over a few seconds.
+const EMA_SHIFT: u8 = 8;
+
+/// Averages of the completed buffer halves since the last report.
+#[derive(Default)]
+struct RunningAverage {
+    sum: u32,
+    halves: u32,
+    overruns: u32,
+}
+
+impl RunningAverage {
+    /// Every half has the same number of samples, so the mean of the half means is
+    /// the mean of all samples.
+    fn mean(&self) -> u16 {
+        match self.halves {
+            0 => 0,
+            halves => (self.sum / halves) as u16,
+        }
+    }
+}
+
+#[exception]
+fn SysTick() {
+    millis::tick();
+}
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_average(tx: &mut Tx<USART2>, average: &RunningAverage, smoothed: &Ema) {
+    let mean = average.mean();
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(
+        buffer,
+        "A0 mean {} raw, {} mV, over {} samples, {} overruns, smoothed {} mV.",
+        mean,
+        scale_adc(mean, VDDA_NOMINAL_MV),
+        average.halves as usize * HALF_SAMPLES,
+        average.overruns,
+        scale_adc(smoothed.value(), VDDA_NOMINAL_MV)
+    )
+    .unwrap();
+    send_string(tx, &buffer);
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, and acquire the GPIOA pins.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut gpioa = board.gpioa;
+
+    // Start the millisecond time base used for reporting.
+    let cp = cortex_m::Peripherals::take().unwrap();
+    millis::init(cp.SYST, &clocks);
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, _rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    send_start_message(&mut tx);
+    send_string(&mut tx, "Sampling A0 with DMA, reporting once a second.");
+
+    // Setup ADC1 with PA0 as an analog input, converting continuously into the
+    // buffer with DMA1 channel 1, the only channel wired to ADC1.
+    let mut adc1 = Adc::adc1(board.adc1, clocks);
+    adc1.set_sample_time(SampleTime::T_239);
+    let a0 = gpioa.pa0.into_analog(&mut gpioa.crl); // Arduino A0
+    let dma_ch1 = board.dma1.split().1;
+    let buffer = singleton!(: [[u16; HALF_SAMPLES]; 2] = [[0; HALF_SAMPLES]; 2]).unwrap();
+    let mut samples = adc1.with_dma(a0, dma_ch1).circ_read(buffer);
+
+    // The HAL starts out as if the second half was read last.
+    let mut last_half = Half::Second;
+    let mut average = RunningAverage::default();
+    let mut smoothed = Ema::new(EMA_SHIFT);
+    let mut last_report_ms = millis();
+    loop {
+        match samples.readable_half() {
+            Ok(half) if half != last_half => {
+                last_half = half;
+                match samples.peek(|half, _| mean_u16(half)) {
+                    Ok(mean) => {
+                        smoothed.update(mean);
+                        average.sum += mean as u32;
+                        average.halves += 1;
+                    }
+                    // DMA caught up with the half while it was being read.
+                    Err(_) => average.overruns += 1,
+                }
+            }
+            Ok(_) => (),
+            Err(_) => {
+                average.overruns += 1;
+                let (buffer, adc_dma) = samples.stop();
+                samples = adc_dma.circ_read(buffer);
+                last_half = Half::Second;
+            }
+        }
+
+        let now_ms = millis();
+        if REPORT_MS <= now_ms.wrapping_sub(last_report_ms) {
+            last_report_ms = now_ms;
+            send_average(&mut tx, &average, &smoothed);
+            average = RunningAverage::default();
+        }
+    }
+}
@@ -0,0 +1,166 @@
+// examples/adc_temperature.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example reads the internal temperature sensor on ADC1 channel 16, and
+//! reports the temperature over RTT and USART once per second.
+//!
+//! The sensor is sampled every `SAMPLE_MS` milliseconds and smoothed with an
+//! exponential moving average, because a single reading jumps around by a degree or
+//! so. VDDA is measured with VREFINT at every report, and `compute_temp_decidegrees`
+//! from `hello_nucleo_f103rb::adc` converts the smoothed reading with the typical
+//! datasheet values, 1.43V at 25C and 4.3mV per degree.
+//!
+//! Do not expect the result to match a thermometer. Those values vary so much from
+//! chip to chip that the absolute temperature can be tens of degrees off, and the
+//! chip itself runs warmer than the air around it. An offset measured once against a
+//! reference is fairly stable, but the slope also varies by up to 7%, so even
+//! changes in temperature are only roughly right. Touching the chip, or blowing on
+//! it, should still show up clearly. The accuracy of VREFINT, see
+//! `hello_nucleo_f103rb::adc`, adds its own error on top.
+//!
+//! The sensor needs a sample time of at least 17.1us, so the slowest sample time is
+//! used, which takes about 40us at the default 6MHz ADC clock.
+
+use core::fmt::Write;
+use cortex_m_rt::{entry, exception};
+use heapless::String;
+use hello_nucleo_f103rb::{
+    adc::{compute_temp_decidegrees, compute_vdda_mv, scale_adc, Ema, VDDA_NOMINAL_MV},
+    adc_timeout::{enable_temp_sensor, read_adc_timeout},
+    board::Board,
+    millis::{self, millis},
+};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    adc::{Adc, SampleTime},
+    pac::USART2,
+    prelude::*,
+    serial::{Config, Serial, Tx},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+/// The temperature sensor is ADC1 channel 16.
+const TEMP_CHANNEL: u8 = 16;
+/// Polls allowed for each step of a conversion, far more than a working ADC needs.
+const ADC_MAX_SPINS: u32 = 10_000;
+/// The datasheet gives the sensor at most 10us to start up.
+const TEMP_START_US: u32 = 10;
+// Each sample has a weight of 1/16, so the average settles over roughly 16 samples.
+const EMA_SHIFT: u8 = 4;
+const SAMPLE_MS: u32 = 50;
+const REPORT_MS: u32 = 1_000;
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+#[exception]
+fn SysTick() {
+    millis::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks and the delay provider, and acquire the GPIOA pins.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut delay = board.delay;
+    let mut gpioa = board.gpioa;
+
+    // Start the millisecond time base that paces the samples and reports.
+    let cp = cortex_m::Peripherals::take().unwrap();
+    millis::init(cp.SYST, &clocks);
+
+    // Setup ADC1, and power up the temperature sensor and VREFINT.
+    let mut adc1 = Adc::adc1(board.adc1, clocks);
+    adc1.set_sample_time(SampleTime::T_239);
+    enable_temp_sensor(&mut adc1);
+    delay.delay_us(TEMP_START_US);
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, _rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    send_start_message(&mut tx);
+    send_string(
+        &mut tx,
+        "Reporting the internal temperature sensor once per second.",
+    );
+
+    let mut smoothed = Ema::new(EMA_SHIFT);
+    let mut failures: u32 = 0;
+    let mut last_sample_ms = millis();
+    let mut last_report_ms = last_sample_ms;
+    loop {
+        let now_ms = millis();
+        if SAMPLE_MS <= now_ms.wrapping_sub(last_sample_ms) {
+            last_sample_ms = now_ms;
+            match read_adc_timeout(&mut adc1, TEMP_CHANNEL, SampleTime::T_239, ADC_MAX_SPINS) {
+                Ok(sample) => {
+                    smoothed.update(sample);
+                }
+                Err(_) => failures = failures.saturating_add(1),
+            }
+        }
+
+        if REPORT_MS <= now_ms.wrapping_sub(last_report_ms) {
+            last_report_ms = now_ms;
+            let vdda_mv = match compute_vdda_mv(adc1.read_vref()) {
+                0 => VDDA_NOMINAL_MV,
+                measured_mv => measured_mv,
+            };
+            let temp_raw = smoothed.value();
+            let decidegrees = compute_temp_decidegrees(temp_raw, vdda_mv);
+            let sign = if decidegrees < 0 { "-" } else { "" };
+            let magnitude = decidegrees.unsigned_abs();
+            let mut buffer: String<BUFFER_SIZE> = String::new();
+            write!(
+                buffer,
+                "Temperature {}{}.{} C, sensor {} mV at VDDA {} mV.",
+                sign,
+                magnitude / 10,
+                magnitude % 10,
+                scale_adc(temp_raw, vdda_mv),
+                vdda_mv
+            )
+            .unwrap();
+            send_string(&mut tx, &buffer);
+            if 0 < failures {
+                let mut buffer: String<BUFFER_SIZE> = String::new();
+                write!(buffer, "{} failed reads since the last report.", failures).unwrap();
+                send_string(&mut tx, &buffer);
+                failures = 0;
+            }
+        }
+    }
+}
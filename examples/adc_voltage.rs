@@ -0,0 +1,212 @@
+// examples/adc_voltage.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example measures the analog supply voltage VDDA using the internal
+//! reference voltage VREFINT, and uses the result to accurately convert readings
+//! from PA0 (Arduino A0) into millivolts.
+//!
+//! Without the VREFINT measurement, conversions have to assume VDDA is exactly 3.3V,
+//! so any deviation in the supply shows up as an error in every reading.
+//! See `hello_nucleo_f103rb::adc` for the accuracy of the VREFINT calibration.
+//!
+//! A0 is also sampled on every pass through the main loop, and smoothed with an
+//! exponential moving average, so `a` reports a steady value next to the noisy
+//! single reading. `EMA_SHIFT` sets how strongly the readings are smoothed.
+//!
+//! A0 is read with `read_adc_timeout`, so a conversion that never finishes, or
+//! returns an impossible value, is reported by `a` instead of hanging the loop.
+//! Failed samples are left out of the average.
+//!
+//! `g` shows the smoothed A0 reading as a bar graph, redrawn in place every
+//! `GRAPH_REFRESH_MS` milliseconds, for watching a potentiometer or sensor move
+//! without any tools beyond a terminal. Any key stops the graph.
+
+use core::fmt::Write;
+use cortex_m_rt::{entry, exception};
+use heapless::String;
+use hello_nucleo_f103rb::{
+    adc::{compute_vdda_mv, scale_adc, Ema, ADC_MAX, VDDA_NOMINAL_MV},
+    adc_timeout::{read_adc_timeout, AdcError},
+    board::Board,
+    millis::{self, millis},
+    text::bar_graph,
+};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    adc::{Adc, SampleTime},
+    pac::USART2,
+    prelude::*,
+    serial::{Config, Serial, Tx},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+// Each sample has a weight of 1/16, so the average settles over roughly 16 samples.
+const EMA_SHIFT: u8 = 4;
+/// PA0 is ADC1 channel 0.
+const A0_CHANNEL: u8 = 0;
+/// Polls allowed for each step of a conversion, far more than a working ADC needs.
+const ADC_MAX_SPINS: u32 = 10_000;
+const BAR_GRAPH_SIZE: usize = 64;
+const GRAPH_WIDTH: usize = 50;
+const GRAPH_REFRESH_MS: u32 = 100;
+
+#[exception]
+fn SysTick() {
+    millis::tick();
+}
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_help_text(tx: &mut Tx<USART2>) {
+    let help_text = "\
+The following commands can be sent of USART:\r\n\
+v - Measure the supply voltage VDDA using VREFINT\r\n\
+a - Read A0 using the last measured VDDA, with the smoothed average\r\n\
+g - Graph the smoothed A0 reading live, until any key is pressed\r\n\
+? - Display this help message\
+";
+    send_string(tx, help_text);
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, and acquire the GPIOA pins.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut gpioa = board.gpioa;
+
+    // Start the millisecond time base used for the graph refresh.
+    let cp = cortex_m::Peripherals::take().unwrap();
+    millis::init(cp.SYST, &clocks);
+
+    // Setup ADC1 with PA0 as an analog input.
+    // VREFINT needs a long sample time, see section 5.3.4 of the datasheet.
+    let mut adc1 = Adc::adc1(board.adc1, clocks);
+    adc1.set_sample_time(SampleTime::T_239);
+    // The pin is only configured here, `read_adc_timeout` selects the channel.
+    let _a0 = gpioa.pa0.into_analog(&mut gpioa.crl); // Arduino A0
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, mut rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    send_start_message(&mut tx);
+    send_help_text(&mut tx);
+
+    let mut vdda_mv: u16 = VDDA_NOMINAL_MV;
+    let mut smoothed = Ema::new(EMA_SHIFT);
+    let mut failures: u32 = 0;
+    let mut graph_ms: Option<u32> = None;
+    loop {
+        let sample: Result<u16, AdcError> =
+            read_adc_timeout(&mut adc1, A0_CHANNEL, SampleTime::T_239, ADC_MAX_SPINS);
+        match sample {
+            Ok(sample) => {
+                smoothed.update(sample);
+            }
+            Err(_) => failures = failures.saturating_add(1),
+        }
+        let received = rx.read();
+
+        // The graph line starts with a carriage return and has no line ending, so
+        // each refresh overwrites the last one.
+        if let Some(last_ms) = graph_ms {
+            let now_ms = millis();
+            if received.is_ok() {
+                graph_ms = None;
+                write!(tx, "\r\n").unwrap();
+                send_string(&mut tx, "Graph stopped.");
+                continue;
+            }
+            if GRAPH_REFRESH_MS <= now_ms.wrapping_sub(last_ms) {
+                graph_ms = Some(now_ms);
+                let mut line: String<BAR_GRAPH_SIZE> = String::new();
+                bar_graph(smoothed.value(), ADC_MAX, GRAPH_WIDTH, &mut line);
+                write!(tx, "\r{}", line).unwrap();
+            }
+        }
+
+        match received {
+            Ok(b'?') => {
+                send_help_text(&mut tx);
+            }
+            Ok(b'v') => {
+                let vrefint_raw = adc1.read_vref();
+                let mut buffer: String<BUFFER_SIZE> = String::new();
+                match compute_vdda_mv(vrefint_raw) {
+                    0 => write!(buffer, "VREFINT read 0, VDDA unchanged.").unwrap(),
+                    measured_mv => {
+                        vdda_mv = measured_mv;
+                        write!(buffer, "VREFINT {} raw, VDDA {} mV.", vrefint_raw, vdda_mv)
+                            .unwrap();
+                    }
+                }
+                send_string(&mut tx, &buffer);
+            }
+            Ok(b'a') => {
+                let mut buffer: String<BUFFER_SIZE> = String::new();
+                match sample {
+                    Ok(sample) => write!(
+                        buffer,
+                        "A0 {} raw, {} mV, smoothed {} mV at VDDA {} mV.",
+                        sample,
+                        scale_adc(sample, vdda_mv),
+                        scale_adc(smoothed.value(), vdda_mv),
+                        vdda_mv
+                    )
+                    .unwrap(),
+                    Err(AdcError::OutOfRange(raw)) => {
+                        write!(buffer, "A0 read failed, {} raw is above {}.", raw, ADC_MAX).unwrap()
+                    }
+                    Err(error) => write!(buffer, "A0 read failed, {}.", error.describe()).unwrap(),
+                }
+                send_string(&mut tx, &buffer);
+                if 0 < failures {
+                    let mut buffer: String<BUFFER_SIZE> = String::new();
+                    write!(buffer, "{} failed reads since the last report.", failures).unwrap();
+                    send_string(&mut tx, &buffer);
+                    failures = 0;
+                }
+            }
+            Ok(b'g') => {
+                send_string(&mut tx, "Graphing A0, press any key to stop.");
+                graph_ms = Some(millis().wrapping_sub(GRAPH_REFRESH_MS));
+            }
+            Ok(_) => (),
+            Err(nb::Error::WouldBlock) => (),
+            Err(_) => (),
+        }
+    }
+}
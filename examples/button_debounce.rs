@@ -0,0 +1,204 @@
+// examples/button_debounce.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example debounces user button B1 with two interrupts, and cycles the on
+//! board LED through off, on, blinking and strobing with each confirmed press.
+//!
+//! The button contacts bounce for a few milliseconds when pressed or released,
+//! so a single press can produce several falling edges on PC13. Each falling edge
+//! triggers EXTI15_10, which only restarts TIM2 as a `DEBOUNCE_MS` one-shot timer.
+//! Bounces restart the timer again, so it only expires once the pin has been quiet
+//! for `DEBOUNCE_MS`. The TIM2 interrupt then samples the pin, and counts a press
+//! only if the button is still held down. Falling edges from a bouncing release
+//! are rejected the same way, because the pin has settled high by the time TIM2
+//! samples it.
+//!
+//! EXTI15_10 and TIM2 run at the same priority, so neither preempts the other.
+//! Everything they share, the button, the timer and the confirmed press count, is
+//! accessed through `hello_nucleo_f103rb::shared::Shared`. The main loop sleeps
+//! until an interrupt arrives, changes mode when the press count changes, and
+//! drives the LED from `millis()`.
+
+use cortex_m::peripheral::NVIC;
+use cortex_m_rt::{entry, exception};
+use hello_nucleo_f103rb::{
+    millis::{self, millis},
+    pins::led_output,
+    shared::Shared,
+};
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    gpio::{Edge, ExtiPin, Input, PullUp, PC13},
+    pac,
+    pac::{interrupt, Interrupt, TIM2},
+    prelude::*,
+    timer::{CounterMs, Event},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BLINK_MS: u32 = 500;
+const STROBE_MS: u32 = 50;
+const DEBOUNCE_MS: u32 = 20;
+
+#[derive(Clone, Copy, PartialEq)]
+enum LedMode {
+    Off,
+    On,
+    Blink,
+    Strobe,
+}
+
+impl LedMode {
+    fn next(self) -> LedMode {
+        match self {
+            LedMode::Off => LedMode::On,
+            LedMode::On => LedMode::Blink,
+            LedMode::Blink => LedMode::Strobe,
+            LedMode::Strobe => LedMode::Off,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            LedMode::Off => "Off",
+            LedMode::On => "On",
+            LedMode::Blink => "Blinking",
+            LedMode::Strobe => "Strobing",
+        }
+    }
+
+    /// LED level at `now_ms`.
+    fn level(self, now_ms: u32) -> bool {
+        match self {
+            LedMode::Off => false,
+            LedMode::On => true,
+            LedMode::Blink => (now_ms / BLINK_MS).is_multiple_of(2),
+            LedMode::Strobe => (now_ms / STROBE_MS).is_multiple_of(2),
+        }
+    }
+}
+
+static G_BUTTON: Shared<Option<PC13<Input<PullUp>>>> = Shared::new(None);
+static G_DEBOUNCE: Shared<Option<CounterMs<TIM2>>> = Shared::new(None);
+static G_PRESSES: Shared<u32> = Shared::new(0);
+
+#[interrupt]
+fn EXTI15_10() {
+    let edge = G_BUTTON.with(|button| match button.as_mut() {
+        Some(button) if button.check_interrupt() => {
+            // The interrupt fires again immediately unless the pending bit is cleared.
+            button.clear_interrupt_pending_bit();
+            true
+        }
+        _ => false,
+    });
+    if edge {
+        // Restarting the timer on every edge delays the sample until bouncing stops.
+        G_DEBOUNCE.with(|timer| {
+            timer
+                .as_mut()
+                .map(|timer| timer.start(DEBOUNCE_MS.millis()))
+        });
+    }
+}
+
+#[interrupt]
+fn TIM2() {
+    G_DEBOUNCE.with(|timer| {
+        if let Some(timer) = timer.as_mut() {
+            timer.clear_interrupt(Event::Update);
+            // Stop after one period, so the pin is only sampled once per edge.
+            let _ = timer.cancel();
+        }
+    });
+    let pressed = G_BUTTON.with(|button| button.as_ref().is_some_and(|button| button.is_low()));
+    if pressed {
+        G_PRESSES.with(|presses| *presses += 1);
+    }
+}
+
+#[exception]
+fn SysTick() {
+    millis::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    // This example does its own setup instead of using `Board`, because it needs
+    // PC13 itself to make user button B1 an EXTI interrupt source, and `Board` only
+    // hands B1 out as a `Button`.
+
+    // Access device specific peripherals.
+    let mut dp = pac::Peripherals::take().unwrap();
+
+    // Configure GPIO pins as push-pull output, with the slow slew rate used for LEDs.
+    // For pins 0-7, use `crl`, and for pins 8-15, use `crh`.
+    let mut gpioa = dp.GPIOA.split();
+    let mut led = led_output(gpioa.pa5, &mut gpioa.crl).erase(); // On Board LED LD2
+
+    // Configure user button B1 as an interrupt source on the falling edge.
+    let mut gpioc = dp.GPIOC.split();
+    let mut afio = dp.AFIO.constrain();
+    let mut button = gpioc.pc13.into_pull_up_input(&mut gpioc.crh);
+    button.make_interrupt_source(&mut afio);
+    button.trigger_on_edge(&mut dp.EXTI, Edge::Falling);
+    button.enable_interrupt(&mut dp.EXTI);
+
+    // Take ownership of raw flash and rcc devices.
+    let mut flash = dp.FLASH.constrain();
+    let rcc = dp.RCC.constrain();
+
+    // Set up system clock.
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.MHz())
+        .sysclk(48.MHz())
+        .freeze(&mut flash.acr);
+
+    // Start the millisecond time base that drives the LED patterns.
+    let cp = cortex_m::Peripherals::take().unwrap();
+    millis::init(cp.SYST, &clocks);
+
+    // TIM2 is the debounce timer. It interrupts when a started period expires.
+    let mut debounce = dp.TIM2.counter_ms(&clocks);
+    debounce.listen(Event::Update);
+
+    rtt_init_print!();
+    rprintln!("Hello, {}!", BOARD);
+    rprintln!("Press user button B1 to change the LED mode.");
+
+    // Move the button and timer into shared storage for the interrupt handlers.
+    G_BUTTON.lock_set(Some(button));
+    G_DEBOUNCE.lock_set(Some(debounce));
+
+    // Unmasking an interrupt is unsafe because it can break critical sections,
+    // but all shared state is only accessed through `Shared`.
+    #[allow(unsafe_code)]
+    unsafe {
+        NVIC::unmask(Interrupt::EXTI15_10);
+        NVIC::unmask(Interrupt::TIM2);
+    }
+
+    let mut mode = LedMode::Off;
+    let mut reported: u32 = 0;
+    loop {
+        // SysTick wakes the core every millisecond, so the LED pattern keeps running.
+        cortex_m::asm::wfi();
+        let presses = G_PRESSES.lock_get();
+        if presses != reported {
+            mode = mode.next();
+            reported = presses;
+            rprintln!("{} LED mode after {} presses.", mode.name(), presses);
+        }
+        if mode.level(millis()) {
+            led.set_high();
+        } else {
+            led.set_low();
+        }
+    }
+}
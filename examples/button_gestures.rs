@@ -0,0 +1,120 @@
+// examples/button_gestures.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example tells apart three gestures on user button B1, with
+//! `GestureDetector` from `hello_nucleo_f103rb::pins`, and gives each one a job.
+//!
+//! - A single click lights the next group of LEDs, and turns the LEDs back on if
+//!   they were off.
+//! - A double click turns all the LEDs off, or back on.
+//! - A long press resets to the first group, with the LEDs on.
+//!
+//! The LEDs are wired as in `gpio_led`, in the same four groups, with the onboard
+//! LED LD2 in the last one. Each gesture is also reported over RTT.
+//!
+//! A single click is only reported once a double click is ruled out, which takes
+//! up to `DOUBLE_CLICK_MS` after the press, so it responds a little later than the
+//! other two.
+
+use cortex_m_rt::entry;
+use hello_nucleo_f103rb::{
+    board::Board,
+    pins::{led_output, ButtonGesture, GestureDetector},
+};
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    gpio::{ErasedPin, Output},
+    prelude::*,
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+/// The button is sampled this often, well below the debounce time.
+const SAMPLE_MS: u32 = 5;
+const GROUP_COUNT: usize = 4;
+
+fn set_leds(led_set: &mut [ErasedPin<Output>], led_on: bool) {
+    if led_on {
+        for led in led_set {
+            led.set_high();
+        }
+    } else {
+        for led in led_set {
+            led.set_low();
+        }
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, the delay provider, the onboard LED and user button B1,
+    // and acquire the remaining GPIOA, GPIOB and GPIOC pins.
+    let board = Board::take();
+    let mut gpioa = board.gpioa;
+    let mut gpiob = board.gpiob;
+    let mut gpioc = board.gpioc;
+    let mut delay = board.delay;
+    let button = board.button;
+
+    // Configure GPIO pins as push-pull output, with the slow slew rate used for LEDs.
+    // For pins 0-7, use `crl`, and for pins 8-15, use `crh`.
+    // `erase()` removes the type so different pins can be collected in an array.
+    //   Wire external LEDs as follows.
+    //     GPIO Pin >---|>|---[R]--- GND
+    //                  LED   Resistor
+    let mut group_0 = [
+        led_output(gpioa.pa7, &mut gpioa.crl).erase(), // Arduino D11/PWM/MOSI
+        led_output(gpiob.pb6, &mut gpiob.crl).erase(), // Arduino D10/PWM/CS
+        led_output(gpioc.pc7, &mut gpioc.crl).erase(), // Arduino D9/PWM
+    ];
+    let mut group_1 = [
+        led_output(gpiob.pb10, &mut gpiob.crh).erase(), // Arduino D6/PWM
+        led_output(gpioa.pa8, &mut gpioa.crh).erase(),  // Arduino D7
+    ];
+    let mut group_2 = [
+        led_output(gpioa.pa9, &mut gpioa.crh).erase(), // Arduino D8
+        led_output(gpiob.pb5, &mut gpiob.crl).erase(), // Arduino D4
+    ];
+    let mut group_3 = [
+        board.led,                                      // On Board LED LD2
+        led_output(gpioa.pa10, &mut gpioa.crh).erase(), // Arduino D2
+    ];
+
+    rtt_init_print!();
+    rprintln!("Hello, {}!", BOARD);
+    rprintln!("Click B1 for the next LED group, double click to toggle the LEDs,");
+    rprintln!("or hold it to start over.");
+
+    let mut gestures = GestureDetector::default();
+    let mut group: usize = 0;
+    let mut leds_on = true;
+    loop {
+        match gestures.update(button.is_pressed(), SAMPLE_MS) {
+            Some(ButtonGesture::SingleClick) => {
+                group = (group + 1) % GROUP_COUNT;
+                leds_on = true;
+                rprintln!("Single click, group {}.", group);
+            }
+            Some(ButtonGesture::DoubleClick) => {
+                leds_on = !leds_on;
+                rprintln!("Double click, LEDs {}.", if leds_on { "on" } else { "off" });
+            }
+            Some(ButtonGesture::LongPress) => {
+                group = 0;
+                leds_on = true;
+                rprintln!("Long press, reset to group 0.");
+            }
+            None => (),
+        }
+
+        set_leds(&mut group_0, leds_on && 0 == group);
+        set_leds(&mut group_1, leds_on && 1 == group);
+        set_leds(&mut group_2, leds_on && 2 == group);
+        set_leds(&mut group_3, leds_on && 3 == group);
+        delay.delay_ms(SAMPLE_MS);
+    }
+}
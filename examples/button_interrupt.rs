@@ -0,0 +1,156 @@
+// examples/button_interrupt.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example handles user button B1 with an EXTI interrupt instead of polling.
+//!
+//! Pressing the button pulls PC13 low, and the falling edge triggers EXTI15_10.
+//! The interrupt handler toggles the on board LED, counts the press, and logs it.
+//! The main loop sleeps until an interrupt arrives, and logs the new total.
+//!
+//! Both log over RTT and USART through `hello_nucleo_f103rb::log`, each with its
+//! own `LineWriter`. The interrupt handler can run while the main loop is partway
+//! through a line, but every line reaches the terminal whole. The main loop moves
+//! the queued text into the USART, and only sleeps once the queue is empty.
+//!
+//! The button pin, LED, and press count are shared with the interrupt handler
+//! through `hello_nucleo_f103rb::shared::Shared`. The button is not debounced, so
+//! one press may be counted more than once.
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use hello_nucleo_f103rb::{
+    log::{LineWriter, Log},
+    pins::led_output,
+    shared::Shared,
+};
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::rtt_init_print;
+use stm32f1xx_hal::{
+    gpio::{Edge, ErasedPin, ExtiPin, Input, Output, PullUp, PC13},
+    pac,
+    pac::{interrupt, Interrupt},
+    prelude::*,
+    serial::{Config, Serial},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const LOG_QUEUE_SIZE: usize = 512;
+
+static LOG: Log<LOG_QUEUE_SIZE> = Log::new();
+
+static G_BUTTON: Shared<Option<PC13<Input<PullUp>>>> = Shared::new(None);
+static G_LED: Shared<Option<ErasedPin<Output>>> = Shared::new(None);
+static G_PRESSES: Shared<u32> = Shared::new(0);
+
+#[interrupt]
+fn EXTI15_10() {
+    let pressed = G_BUTTON.with(|button| match button.as_mut() {
+        Some(button) if button.check_interrupt() => {
+            // The interrupt fires again immediately unless the pending bit is cleared.
+            button.clear_interrupt_pending_bit();
+            true
+        }
+        _ => false,
+    });
+    if pressed {
+        G_LED.with(|led| led.as_mut().map(|led| led.toggle()));
+        let presses = G_PRESSES.with(|presses| {
+            *presses += 1;
+            *presses
+        });
+        // Written in two parts, but logged as one line when the writer is dropped.
+        let mut log: LineWriter<BUFFER_SIZE, LOG_QUEUE_SIZE> = LOG.writer();
+        write!(log, "Interrupt: ").ok();
+        write!(log, "press {}, LED toggled.", presses).ok();
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    // This example does its own setup instead of using `Board`, because it needs
+    // PC13 itself to make user button B1 an EXTI interrupt source, and `Board` only
+    // hands B1 out as a `Button`.
+
+    // Access device specific peripherals.
+    let mut dp = pac::Peripherals::take().unwrap();
+
+    // Configure GPIO pins as push-pull output, with the slow slew rate used for LEDs.
+    // For pins 0-7, use `crl`, and for pins 8-15, use `crh`.
+    let mut gpioa = dp.GPIOA.split();
+    let led = led_output(gpioa.pa5, &mut gpioa.crl).erase(); // On Board LED LD2
+
+    // Take ownership of raw flash and rcc devices.
+    let mut flash = dp.FLASH.constrain();
+    let rcc = dp.RCC.constrain();
+
+    // Set up system clock.
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.MHz())
+        .sysclk(48.MHz())
+        .freeze(&mut flash.acr);
+
+    // Configure user button B1 as an interrupt source on the falling edge.
+    let mut gpioc = dp.GPIOC.split();
+    let mut afio = dp.AFIO.constrain();
+    let mut button = gpioc.pc13.into_pull_up_input(&mut gpioc.crh);
+    button.make_interrupt_source(&mut afio);
+    button.trigger_on_edge(&mut dp.EXTI, Edge::Falling);
+    button.enable_interrupt(&mut dp.EXTI);
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        dp.USART2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, _rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    let mut log: LineWriter<BUFFER_SIZE, LOG_QUEUE_SIZE> = LOG.writer();
+    writeln!(log, "Hello, {}!", BOARD).ok();
+    writeln!(log, "Press user button B1 to toggle the LED.").ok();
+
+    // Move the button and LED into shared storage for the interrupt handler.
+    G_BUTTON.lock_set(Some(button));
+    G_LED.lock_set(Some(led));
+
+    // Unmasking an interrupt is unsafe because it can break critical sections,
+    // but all shared state is only accessed through `Shared`.
+    #[allow(unsafe_code)]
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(Interrupt::EXTI15_10);
+    }
+
+    let mut reported: u32 = 0;
+    loop {
+        let presses = G_PRESSES.lock_get();
+        if presses != reported {
+            writeln!(log, "Main loop: button pressed {} times.", presses).ok();
+            reported = presses;
+        }
+        if LOG.pump(&mut tx) {
+            continue;
+        }
+        // Checking for queued text and sleeping in one critical section means a
+        // line logged in between still wakes the loop. A pending interrupt ends
+        // `wfi` even while interrupts are disabled, and runs once they are enabled.
+        cortex_m::interrupt::free(|_| {
+            if LOG.is_empty() {
+                cortex_m::asm::wfi();
+            }
+        });
+    }
+}
@@ -0,0 +1,127 @@
+// examples/calc.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example is a small integer calculator over USART.
+//!
+//! Type an expression like `12 + 30 * 2` and press enter to print the result.
+//! `+ - * /` follow the usual precedence, `*` and `/` before `+` and `-`, and
+//! parentheses group. A `-` in front of a number or parenthesis negates it.
+//! Arithmetic is 32 bit signed, division truncates toward zero, and an overflow
+//! or division by zero is reported as an error instead of wrapping or panicking.
+//!
+//! `eval` from `hello_nucleo_f103rb::calc` uses the shunting-yard algorithm with
+//! fixed-size value and operator stacks, so no heap is needed. An expression
+//! nested too deeply for the stacks is rejected.
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use heapless::String;
+use hello_nucleo_f103rb::{
+    board::Board,
+    calc::{eval, CalcError},
+};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    pac::USART2,
+    prelude::*,
+    serial::{Config, Serial, Tx},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+/// A full line, ` = `, and the longest `i32`, which is `-2147483648`.
+const RESULT_SIZE: usize = BUFFER_SIZE + 3 + 11;
+
+fn error_message(error: CalcError) -> &'static str {
+    match error {
+        CalcError::Syntax => "Syntax error.",
+        CalcError::DivideByZero => "Division by zero.",
+        CalcError::Overflow => "Overflow.",
+        CalcError::TooComplex => "Expression too complex.",
+    }
+}
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_help_text(tx: &mut Tx<USART2>) {
+    let help_text = "\
+Integer expressions can be sent of USART, completed with enter.\r\n\
++ - * / and parentheses are supported, for example 12 + 30 * 2\r\n\
+? - Display this help message\
+";
+    send_string(tx, help_text);
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, and acquire the GPIOA pins.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut gpioa = board.gpioa;
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, mut rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    send_start_message(&mut tx);
+    send_help_text(&mut tx);
+
+    let mut line: String<BUFFER_SIZE> = String::new();
+    loop {
+        match rx.read() {
+            Ok(b'?') if line.is_empty() => {
+                send_help_text(&mut tx);
+            }
+            Ok(b'\r') => {
+                if !line.trim().is_empty() {
+                    let mut buffer: String<RESULT_SIZE> = String::new();
+                    match eval(&line) {
+                        Ok(result) => write!(buffer, "{} = {}", line.trim(), result).unwrap(),
+                        Err(error) => write!(buffer, "{}", error_message(error)).unwrap(),
+                    }
+                    send_string(&mut tx, &buffer);
+                }
+                line.clear();
+            }
+            Ok(c) => {
+                if line.push(c as char).is_ok() {
+                    // Echo back the received character.
+                    block!(tx.write(c)).ok();
+                }
+            }
+            Err(nb::Error::WouldBlock) => (),
+            Err(_) => (),
+        }
+    }
+}
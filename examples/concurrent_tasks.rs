@@ -0,0 +1,159 @@
+// examples/concurrent_tasks.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example runs three independent tasks at their own rates in a single main
+//! loop, without ever waiting in a blocking delay.
+//!
+//! - USART echo runs on every pass through the loop, so typed characters come back
+//!   immediately.
+//! - The onboard LED LD2 steps through a heartbeat pattern every `PATTERN_STEP_MS`.
+//! - A status line with the uptime, the number of bytes echoed, the number of
+//!   passes through the loop per second, and the number of bytes dropped because
+//!   the transmit queue was full is printed every `STATUS_MS`.
+//!
+//! Examples like `serial_echo` end each pass with a fixed delay, which sets the
+//! rate of everything at once. A slower LED pattern means a slower USART poll, and
+//! any work in the loop stretches every period. Here, SysTick counts milliseconds
+//! in the background, and `Scheduler` from `hello_nucleo_f103rb::scheduler` runs
+//! each task when its own period has elapsed. Text is queued in a `TxQueue` and
+//! sent one byte per pass, so printing the status never holds up the echo or the
+//! LED either.
+//!
+//! The price is that every task has to return quickly. A task that waits on
+//! something, like `block!(tx.flush())`, stalls all the others for as long as it
+//! waits. The loops per second figure shows how much time is left over, and drops
+//! if a task starts to hog the loop.
+
+use core::fmt::Write;
+use cortex_m_rt::{entry, exception};
+use heapless::String;
+use hello_nucleo_f103rb::{
+    board::Board,
+    millis::{self, millis},
+    scheduler::Scheduler,
+    tx_queue::TxQueue,
+};
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    prelude::*,
+    serial::{Config, Serial},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const TX_QUEUE_SIZE: usize = 512;
+const PATTERN_STEP_MS: u32 = 100;
+const STATUS_MS: u32 = 5_000;
+const TASK_PATTERN: usize = 0;
+const TASK_STATUS: usize = 1;
+/// Two short beats and a pause, one step per `PATTERN_STEP_MS`.
+const HEARTBEAT: [bool; 10] = [
+    true, false, true, false, false, false, false, false, false, false,
+];
+
+#[exception]
+fn SysTick() {
+    millis::tick();
+}
+
+fn send_string(tx: &mut TxQueue<TX_QUEUE_SIZE>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).ok();
+}
+
+fn send_start_message(tx: &mut TxQueue<TX_QUEUE_SIZE>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_status(tx: &mut TxQueue<TX_QUEUE_SIZE>, uptime_ms: u32, echoed: u32, loops: u32) {
+    let dropped = tx.dropped();
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(
+        buffer,
+        "Up {} s, {} bytes echoed, {} loops per second, {} bytes dropped.",
+        uptime_ms / 1_000,
+        echoed,
+        loops / (STATUS_MS / 1_000),
+        dropped
+    )
+    .unwrap();
+    send_string(tx, &buffer);
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks and the onboard LED LD2, and acquire the GPIOA pins.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut led = board.led; // On Board LED LD2
+    let mut gpioa = board.gpioa;
+
+    // Start the millisecond time base that drives the scheduler.
+    let cp = cortex_m::Peripherals::take().unwrap();
+    millis::init(cp.SYST, &clocks);
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, mut rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    let mut tx_queue: TxQueue<TX_QUEUE_SIZE> = TxQueue::new();
+    send_start_message(&mut tx_queue);
+    send_string(&mut tx_queue, "Typed characters are echoed back.");
+
+    let mut scheduler = Scheduler::new(millis(), [PATTERN_STEP_MS, STATUS_MS]);
+    let mut pattern_step: usize = 0;
+    let mut echoed: u32 = 0;
+    let mut loops: u32 = 0;
+    loop {
+        loops += 1;
+
+        // The echo task runs on every pass.
+        if let Ok(c) = rx.read() {
+            if tx_queue.enqueue(c).is_ok() {
+                echoed += 1;
+            }
+        }
+
+        while let Some(task) = scheduler.next_due(millis()) {
+            match task {
+                TASK_PATTERN => {
+                    match HEARTBEAT[pattern_step] {
+                        true => led.set_high(),
+                        false => led.set_low(),
+                    }
+                    pattern_step = (pattern_step + 1) % HEARTBEAT.len();
+                }
+                TASK_STATUS => {
+                    send_status(&mut tx_queue, millis(), echoed, loops);
+                    loops = 0;
+                }
+                _ => (),
+            }
+        }
+
+        // Transmit queued text without waiting for the USART.
+        tx_queue.pump(&mut tx);
+    }
+}
@@ -0,0 +1,278 @@
+// examples/ds18b20.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example reads the temperature from a Maxim DS18B20 sensor over 1-Wire,
+//! and prints it over USART every two seconds.
+//!
+//! Wire the sensor as follows. 1-Wire is open drain, so the data line needs a
+//! pull-up resistor, usually 4.7k ohms from DQ to 3V3. Many breakout boards
+//! include it. Parasite power, with VDD tied to GND, is not supported, because the
+//! bus would need a strong pull-up during the conversion.
+//!   DS18B20 VDD >--- 3V3
+//!   DS18B20 DQ  >--- PA10 (Arduino D2), and 4.7k >--- 3V3
+//!   DS18B20 GND >--- GND
+//!
+//! There is no 1-Wire peripheral, so the protocol is bit banged on PA10. Every
+//! transaction starts with a reset pulse, and the sensor answers with a presence
+//! pulse. A missing presence pulse, a bus held low, a conversion that never
+//! finishes or a CRC mismatch on the scratchpad is reported, and the next reading
+//! is tried as usual, so the sensor can be connected while running.
+//!
+//! Bits are sent in time slots that are a few microseconds to a few hundred
+//! microseconds long. TIM2 runs freely at 1MHz, and each wait spins on it, so the
+//! timing does not depend on how long the code between waits takes. Each slot
+//! runs with interrupts disabled, so an interrupt cannot stretch it.
+//!
+//! Only one sensor is expected on the bus, so it is addressed with Skip ROM rather
+//! than by its 64-bit ROM code. The scratchpad is checked and converted with
+//! `hello_nucleo_f103rb::ds18b20`, and temperatures are kept in hundredths of a
+//! degree Celsius. A reading of exactly 85.00 C straight after power up is the reset
+//! value of the scratchpad, and usually means the conversion did not run.
+
+use core::fmt::Write;
+use cortex_m::interrupt;
+use cortex_m_rt::entry;
+use heapless::String;
+use hello_nucleo_f103rb::{
+    board::Board,
+    ds18b20::{raw_to_celsius, scratchpad_temperature, SCRATCHPAD_SIZE},
+};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    gpio::{OpenDrain, Output, PA10},
+    pac::{TIM2, USART2},
+    prelude::*,
+    serial::{Config, Serial, Tx},
+    timer::CounterUs,
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+// The counter runs over a full 16 bits, so elapsed time is a wrapping `u16` difference.
+const COUNTER_PERIOD_US: u32 = 1 << 16;
+// Reset and presence timing, see the "1-Wire signaling" section of the datasheet.
+const RESET_LOW_US: u16 = 480;
+const PRESENCE_SAMPLE_US: u16 = 70;
+const PRESENCE_WAIT_US: u16 = 410;
+// Every time slot lasts at least 60us, with at least 1us of recovery between slots.
+const SLOT_US: u16 = 65;
+const WRITE_ONE_LOW_US: u16 = 6;
+const WRITE_ZERO_LOW_US: u16 = 60;
+const READ_LOW_US: u16 = 3;
+// The sensor's data is only valid for 15us after the falling edge, so sample early.
+const READ_SAMPLE_US: u16 = 12;
+const SKIP_ROM: u8 = 0xCC;
+const CONVERT_T: u8 = 0x44;
+const READ_SCRATCHPAD: u8 = 0xBE;
+// A 12-bit conversion takes up to 750ms. The sensor reads 0 until it is done.
+const CONVERT_TIMEOUT_MS: u32 = 1_000;
+const READING_INTERVAL_MS: u32 = 2_000;
+
+enum SensorError {
+    BusLow,
+    NoPresence,
+    Timeout,
+    Crc,
+}
+
+/// A 1-Wire bus on one open drain pin, timed by a free running microsecond counter.
+struct OneWire {
+    pin: PA10<Output<OpenDrain>>,
+    timer: CounterUs<TIM2>,
+}
+
+impl OneWire {
+    fn new(mut pin: PA10<Output<OpenDrain>>, mut timer: CounterUs<TIM2>) -> Self {
+        pin.set_high();
+        timer.start(COUNTER_PERIOD_US.micros()).unwrap();
+        OneWire { pin, timer }
+    }
+
+    fn now_us(&self) -> u16 {
+        self.timer.now().ticks() as u16
+    }
+
+    /// Spin until `us` microseconds after `start_us`.
+    fn wait_until(&self, start_us: u16, us: u16) {
+        while self.now_us().wrapping_sub(start_us) < us {}
+    }
+
+    fn wait_ms(&self, ms: u32) {
+        for _ in 0..ms {
+            self.wait_until(self.now_us(), 1_000);
+        }
+    }
+
+    /// Send a reset pulse, and check that a sensor answers with a presence pulse.
+    fn reset(&mut self) -> Result<(), SensorError> {
+        // Nothing can answer if the line is already held low, and every bit would read 0.
+        if self.pin.is_low() {
+            return Err(SensorError::BusLow);
+        }
+        let present = interrupt::free(|_| {
+            let start_us = self.now_us();
+            self.pin.set_low();
+            self.wait_until(start_us, RESET_LOW_US);
+            self.pin.set_high();
+            let released_us = self.now_us();
+            self.wait_until(released_us, PRESENCE_SAMPLE_US);
+            let present = self.pin.is_low();
+            self.wait_until(released_us, PRESENCE_WAIT_US);
+            present
+        });
+        match present {
+            true => Ok(()),
+            false => Err(SensorError::NoPresence),
+        }
+    }
+
+    fn write_bit(&mut self, bit: bool) {
+        let low_us = match bit {
+            true => WRITE_ONE_LOW_US,
+            false => WRITE_ZERO_LOW_US,
+        };
+        interrupt::free(|_| {
+            let start_us = self.now_us();
+            self.pin.set_low();
+            self.wait_until(start_us, low_us);
+            self.pin.set_high();
+            self.wait_until(start_us, SLOT_US);
+        });
+    }
+
+    fn read_bit(&mut self) -> bool {
+        interrupt::free(|_| {
+            let start_us = self.now_us();
+            self.pin.set_low();
+            self.wait_until(start_us, READ_LOW_US);
+            self.pin.set_high();
+            self.wait_until(start_us, READ_SAMPLE_US);
+            let bit = self.pin.is_high();
+            self.wait_until(start_us, SLOT_US);
+            bit
+        })
+    }
+
+    /// Bytes go out least significant bit first.
+    fn write_byte(&mut self, byte: u8) {
+        for i in 0..8 {
+            self.write_bit(0 != byte & (1 << i));
+        }
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        (0..8).fold(0, |byte, i| byte | (self.read_bit() as u8) << i)
+    }
+}
+
+/// Start a conversion, wait for it, and return the raw temperature.
+fn read_temperature(bus: &mut OneWire) -> Result<i16, SensorError> {
+    bus.reset()?;
+    bus.write_byte(SKIP_ROM);
+    bus.write_byte(CONVERT_T);
+    // Read slots return 0 while the conversion runs, and 1 once it is done.
+    let mut elapsed_ms = 0;
+    while !bus.read_bit() {
+        if CONVERT_TIMEOUT_MS <= elapsed_ms {
+            return Err(SensorError::Timeout);
+        }
+        bus.wait_ms(1);
+        elapsed_ms += 1;
+    }
+
+    bus.reset()?;
+    bus.write_byte(SKIP_ROM);
+    bus.write_byte(READ_SCRATCHPAD);
+    let mut scratchpad = [0u8; SCRATCHPAD_SIZE];
+    for byte in scratchpad.iter_mut() {
+        *byte = bus.read_byte();
+    }
+    scratchpad_temperature(&scratchpad).ok_or(SensorError::Crc)
+}
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_reading(tx: &mut Tx<USART2>, temp: i32) {
+    let sign = if temp < 0 { "-" } else { "" };
+    let temp = temp.unsigned_abs();
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(
+        buffer,
+        "Temperature {}{}.{:02} C.",
+        sign,
+        temp / 100,
+        temp % 100
+    )
+    .unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_error(tx: &mut Tx<USART2>, error: SensorError) {
+    let message = match error {
+        SensorError::BusLow => "1-Wire bus held low, check the wiring.",
+        SensorError::NoPresence => "No DS18B20 presence pulse, check the sensor and pull-up.",
+        SensorError::Timeout => "DS18B20 conversion timed out.",
+        SensorError::Crc => "DS18B20 CRC mismatch, reading discarded.",
+    };
+    send_string(tx, message);
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, and acquire the GPIOA pins.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut gpioa = board.gpioa;
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, _rx) = serial.split();
+
+    // The open drain output is released high, and can be read back, so the one
+    // pin both drives and samples the bus.
+    let dq = gpioa.pa10.into_open_drain_output(&mut gpioa.crh); // Arduino D2
+                                                                // The board already runs TIM2 at the 1MHz a `CounterUs` needs.
+    let mut bus = OneWire::new(dq, board.delay.release().counter());
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    send_start_message(&mut tx);
+    send_string(&mut tx, "Reading a DS18B20 sensor on PA10 (DQ).");
+
+    loop {
+        match read_temperature(&mut bus) {
+            Ok(raw) => send_reading(&mut tx, raw_to_celsius(raw)),
+            Err(error) => send_error(&mut tx, error),
+        }
+        bus.wait_ms(READING_INTERVAL_MS);
+    }
+}
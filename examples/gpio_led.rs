@@ -5,11 +5,12 @@
 #![no_main]
 
 use cortex_m_rt::entry;
+use hello_nucleo_f103rb::{board::Board, pins::led_output};
+#[cfg(not(feature = "panic-sos"))]
 use panic_halt as _;
 use rtt_target::{rprintln, rtt_init_print};
 use stm32f1xx_hal::{
     gpio::{ErasedPin, Output},
-    pac,
     prelude::*,
 };
 
@@ -31,50 +32,40 @@ fn set_leds(led_set: &mut [ErasedPin<Output>], led_on: bool) {
 
 #[entry]
 fn main() -> ! {
-    // Access device specific peripherals, and acquire GPIOA, GPIOB GPIOC.
-    let dp = pac::Peripherals::take().unwrap();
-    let mut gpioa = dp.GPIOA.split();
-    let mut gpiob = dp.GPIOB.split();
-    let mut gpioc = dp.GPIOC.split();
+    // Set up the clocks, the delay provider, the onboard LED and user button B1,
+    // and acquire the remaining GPIOA, GPIOB GPIOC pins.
+    let board = Board::take();
+    let mut gpioa = board.gpioa;
+    let mut gpiob = board.gpiob;
+    let mut gpioc = board.gpioc;
+    let mut delay = board.delay;
 
-    // Configure GPIO pins as push-pull output.
+    // Configure GPIO pins as push-pull output, with the slow slew rate used for LEDs.
     // For pins 0-7, use `crl`, and for pins 8-15, use `crh`.
     // `erase()` removes the type so different pins can be collected in an array.
     //   Wire external LEDs as follows.
     //     GPIO Pin >---|>|---[R]--- GND
     //                  LED   Resistor
     let mut leds_static = [
-        gpioa.pa7.into_push_pull_output(&mut gpioa.crl).erase(), // Arduino D11/PWM/MOSI
-        gpiob.pb6.into_push_pull_output(&mut gpiob.crl).erase(), // Arduino D10/PWM/CS
-        gpioc.pc7.into_push_pull_output(&mut gpioc.crl).erase(), // Arduino D9/PWM
+        led_output(gpioa.pa7, &mut gpioa.crl).erase(), // Arduino D11/PWM/MOSI
+        led_output(gpiob.pb6, &mut gpiob.crl).erase(), // Arduino D10/PWM/CS
+        led_output(gpioc.pc7, &mut gpioc.crl).erase(), // Arduino D9/PWM
     ];
     let mut leds_blink = [
-        gpiob.pb10.into_push_pull_output(&mut gpiob.crh).erase(), // Arduino D6/PWM
-        gpioa.pa8.into_push_pull_output(&mut gpioa.crh).erase(),  // Arduino D7
+        led_output(gpiob.pb10, &mut gpiob.crh).erase(), // Arduino D6/PWM
+        led_output(gpioa.pa8, &mut gpioa.crh).erase(),  // Arduino D7
     ];
     let mut leds_strobe = [
-        gpioa.pa9.into_push_pull_output(&mut gpioa.crh).erase(), // Arduino D8
-        gpiob.pb5.into_push_pull_output(&mut gpiob.crl).erase(), // Arduino D4
+        led_output(gpioa.pa9, &mut gpioa.crh).erase(), // Arduino D8
+        led_output(gpiob.pb5, &mut gpiob.crl).erase(), // Arduino D4
     ];
     let mut leds_controlled = [
-        gpioa.pa5.into_push_pull_output(&mut gpioa.crl).erase(), // On Board LED LD2
-        gpioa.pa10.into_push_pull_output(&mut gpioa.crh).erase(), // Arduino D2
+        board.led,                                      // On Board LED LD2
+        led_output(gpioa.pa10, &mut gpioa.crh).erase(), // Arduino D2
     ];
 
     // Acquire read-only user button B1, not mutable.
-    let button = gpioc.pc13;
-
-    // Take ownership of raw flash and rcc devices.
-    let mut flash = dp.FLASH.constrain();
-    let rcc = dp.RCC.constrain();
-
-    // Set up system clock and configure delay provider.
-    let clocks = rcc
-        .cfgr
-        .use_hse(8.MHz())
-        .sysclk(48.MHz())
-        .freeze(&mut flash.acr);
-    let mut delay = dp.TIM2.delay_us(&clocks);
+    let button = board.button;
 
     rtt_init_print!();
     rprintln!("Hello, {}!", BOARD);
@@ -87,13 +78,13 @@ fn main() -> ! {
     let mut controlled_on: bool = false;
     loop {
         delay.delay_ms(STROBE_MS);
-        counter = counter + STROBE_MS;
+        counter += STROBE_MS;
         if 2 * BLINK_MS < counter {
             counter = 0;
         }
         strobe_on = !strobe_on;
         blink_on = BLINK_MS <= counter;
-        if button.is_low() {
+        if button.is_pressed() {
             if !controlled_on {
                 rprintln!("On");
             }
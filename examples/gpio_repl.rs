@@ -0,0 +1,327 @@
+// examples/gpio_repl.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example is a small read-eval-print loop for exploring GPIO behavior over
+//! USART without recompiling. A fixed set of pins can be switched between pull-up
+//! input and push-pull output at runtime, driven, and read back.
+//!
+//! The HAL needs the port configuration register (CRL or CRH) to change a pin mode,
+//! so every pin in the set is on GPIOA CRH, and `DynPins` owns that register along
+//! with the pins. Pins start out as pull-up inputs, so nothing is driven until an
+//! `out` command is given. Input pins are polled, and level changes are reported.
+//!
+//! Commands are completed with enter. `N` is the pin number from the help text.
+//! - `in N` makes pin N a pull-up input.
+//! - `out N` makes pin N a push-pull output.
+//! - `hi N` and `lo N` drive output pin N high or low.
+//! - `rd N` reads pin N.
+//! - `cfg P` decodes the configuration of every pin on port P, from `a` to `d`, and
+//!   `cfg P N` decodes pin N of port P only, like `cfg a 5` for LD2 on PA5. Any pin
+//!   can be checked, not just the fixed set. See `hello_nucleo_f103rb::gpio_config`.
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use embedded_hal_02::digital::v2::{InputPin, OutputPin};
+use heapless::String;
+use hello_nucleo_f103rb::{
+    board::Board,
+    gpio_command::{parse_pin_command, PinAction, PinCommand},
+    gpio_config::{
+        parse_cfg_command, read_port_config, PinFunction, Port, PortConfig, PINS_PER_PORT,
+    },
+};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    gpio::{Cr, Dynamic, PinModeError, PA10, PA8, PA9},
+    pac::USART2,
+    prelude::*,
+    serial::{Config, Serial, Tx},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const PIN_COUNT: usize = 3;
+const PIN_NAMES: [&str; PIN_COUNT] = ["PA8 (Arduino D7)", "PA9 (Arduino D8)", "PA10 (Arduino D2)"];
+
+#[derive(PartialEq)]
+enum PinError {
+    NoSuchPin,
+    IncorrectMode,
+}
+
+impl From<PinModeError> for PinError {
+    fn from(_: PinModeError) -> Self {
+        PinError::IncorrectMode
+    }
+}
+
+/// The fixed set of pins whose modes can change at runtime.
+struct DynPins {
+    pa8: PA8<Dynamic>,
+    pa9: PA9<Dynamic>,
+    pa10: PA10<Dynamic>,
+    crh: Cr<'A', true>,
+    is_output: [bool; PIN_COUNT],
+}
+
+impl DynPins {
+    fn new(pa8: PA8<Dynamic>, pa9: PA9<Dynamic>, pa10: PA10<Dynamic>, crh: Cr<'A', true>) -> Self {
+        let mut pins = DynPins {
+            pa8,
+            pa9,
+            pa10,
+            crh,
+            is_output: [false; PIN_COUNT],
+        };
+        for pin in 0..PIN_COUNT {
+            let _ = pins.make_input_pullup(pin);
+        }
+        pins
+    }
+
+    fn make_input_pullup(&mut self, pin: usize) -> Result<(), PinError> {
+        match pin {
+            0 => self.pa8.make_pull_up_input(&mut self.crh),
+            1 => self.pa9.make_pull_up_input(&mut self.crh),
+            2 => self.pa10.make_pull_up_input(&mut self.crh),
+            _ => return Err(PinError::NoSuchPin),
+        }
+        self.is_output[pin] = false;
+        Ok(())
+    }
+
+    fn make_output(&mut self, pin: usize) -> Result<(), PinError> {
+        match pin {
+            0 => self.pa8.make_push_pull_output(&mut self.crh),
+            1 => self.pa9.make_push_pull_output(&mut self.crh),
+            2 => self.pa10.make_push_pull_output(&mut self.crh),
+            _ => return Err(PinError::NoSuchPin),
+        }
+        self.is_output[pin] = true;
+        Ok(())
+    }
+
+    fn is_output(&self, pin: usize) -> bool {
+        self.is_output.get(pin).copied().unwrap_or(false)
+    }
+
+    fn read(&self, pin: usize) -> Result<bool, PinError> {
+        match pin {
+            0 => Ok(self.pa8.is_high()?),
+            1 => Ok(self.pa9.is_high()?),
+            2 => Ok(self.pa10.is_high()?),
+            _ => Err(PinError::NoSuchPin),
+        }
+    }
+
+    fn write(&mut self, pin: usize, level: bool) -> Result<(), PinError> {
+        match (pin, level) {
+            (0, true) => Ok(self.pa8.set_high()?),
+            (0, false) => Ok(self.pa8.set_low()?),
+            (1, true) => Ok(self.pa9.set_high()?),
+            (1, false) => Ok(self.pa9.set_low()?),
+            (2, true) => Ok(self.pa10.set_high()?),
+            (2, false) => Ok(self.pa10.set_low()?),
+            _ => Err(PinError::NoSuchPin),
+        }
+    }
+}
+
+fn level_name(level: bool) -> &'static str {
+    match level {
+        true => "high",
+        false => "low",
+    }
+}
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_help_text(tx: &mut Tx<USART2>) {
+    let help_text = "\
+The following GPIO commands can be sent of USART, completed with enter:\r\n\
+in N - Make pin N a pull-up input\r\n\
+out N - Make pin N a push-pull output\r\n\
+hi N - Drive output pin N high\r\n\
+lo N - Drive output pin N low\r\n\
+rd N - Read pin N\r\n\
+cfg P, cfg P N - Decode the configuration of port P, or only of pin N, like cfg a 5\r\n\
+? - Display this help message\r\n\
+Pins:\
+";
+    send_string(tx, help_text);
+    for (pin, name) in PIN_NAMES.iter().enumerate() {
+        let mut buffer: String<BUFFER_SIZE> = String::new();
+        write!(buffer, "{} - {}", pin, name).unwrap();
+        send_string(tx, &buffer);
+    }
+}
+
+fn send_pin_config(tx: &mut Tx<USART2>, port: Port, config: &PortConfig, pin: u8) {
+    let desc = config.pin(pin);
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(
+        buffer,
+        "P{}{}: {}",
+        port.letter(),
+        pin,
+        desc.function.name()
+    )
+    .unwrap();
+    if PinFunction::PullInput == desc.function {
+        match config.is_pulled_up(pin) {
+            true => write!(buffer, ", pulled up").unwrap(),
+            false => write!(buffer, ", pulled down").unwrap(),
+        }
+    }
+    if let Some(speed_mhz) = desc.speed_mhz {
+        write!(buffer, ", {}MHz", speed_mhz).unwrap();
+    }
+    send_string(tx, &buffer);
+}
+
+fn run_cfg_command(tx: &mut Tx<USART2>, port: Port, pin: Option<u8>) {
+    let config = read_port_config(port);
+    match pin {
+        Some(pin) => send_pin_config(tx, port, &config, pin),
+        None => {
+            let mut buffer: String<BUFFER_SIZE> = String::new();
+            write!(
+                buffer,
+                "GPIO{} CRL 0x{:08X}, CRH 0x{:08X}, ODR 0x{:04X}",
+                port.letter(),
+                config.crl,
+                config.crh,
+                config.odr
+            )
+            .unwrap();
+            send_string(tx, &buffer);
+            for pin in 0..PINS_PER_PORT {
+                send_pin_config(tx, port, &config, pin);
+            }
+        }
+    }
+}
+
+fn run_pin_command(tx: &mut Tx<USART2>, pins: &mut DynPins, command: PinCommand) {
+    let pin = command.pin;
+    let result = match command.action {
+        PinAction::Input => pins.make_input_pullup(pin).map(|_| "is a pull-up input"),
+        PinAction::Output => pins.make_output(pin).map(|_| "is a push-pull output"),
+        PinAction::High => pins.write(pin, true).map(|_| "driven high"),
+        PinAction::Low => pins.write(pin, false).map(|_| "driven low"),
+        PinAction::Read => pins.read(pin).map(level_name),
+    };
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    match result {
+        Ok(state) => write!(buffer, "{} {}.", PIN_NAMES[pin], state).unwrap(),
+        Err(PinError::IncorrectMode) => {
+            write!(buffer, "{} is in the wrong mode.", PIN_NAMES[pin]).unwrap()
+        }
+        Err(PinError::NoSuchPin) => write!(buffer, "No such pin.").unwrap(),
+    }
+    send_string(tx, &buffer);
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, and acquire the GPIOA pins. The board splits the other
+    // ports too, which enables their clocks, so `cfg` can read them back.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut gpioa = board.gpioa;
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, mut rx) = serial.split();
+
+    // Hand the runtime configurable pins and their configuration register to `DynPins`.
+    let pa8 = gpioa.pa8.into_dynamic(&mut gpioa.crh);
+    let pa9 = gpioa.pa9.into_dynamic(&mut gpioa.crh);
+    let pa10 = gpioa.pa10.into_dynamic(&mut gpioa.crh);
+    let mut pins = DynPins::new(pa8, pa9, pa10, gpioa.crh);
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    send_start_message(&mut tx);
+    send_help_text(&mut tx);
+
+    let mut line: String<BUFFER_SIZE> = String::new();
+    let mut last_levels = [true; PIN_COUNT];
+    loop {
+        match rx.read() {
+            Ok(b'?') if line.is_empty() => {
+                send_help_text(&mut tx);
+            }
+            Ok(b'\r') => {
+                match parse_pin_command(&line, PIN_COUNT) {
+                    Some(command) => run_pin_command(&mut tx, &mut pins, command),
+                    None if line.is_empty() => (),
+                    None if line.starts_with("cfg") => match parse_cfg_command(&line) {
+                        Some((port, pin)) => run_cfg_command(&mut tx, port, pin),
+                        None => send_string(&mut tx, "Usage: cfg a|b|c|d [0-15]"),
+                    },
+                    None => send_string(&mut tx, "Unknown command."),
+                }
+                line.clear();
+            }
+            Ok(c) => {
+                if line.push(c as char).is_ok() {
+                    // Echo back the received character.
+                    block!(tx.write(c)).ok();
+                }
+            }
+            Err(nb::Error::WouldBlock) => (),
+            Err(_) => (),
+        }
+
+        // Poll input pins and report level changes.
+        for (pin, last_level) in last_levels.iter_mut().enumerate() {
+            if pins.is_output(pin) {
+                continue;
+            }
+            if let Ok(level) = pins.read(pin) {
+                if level != *last_level {
+                    let mut buffer: String<BUFFER_SIZE> = String::new();
+                    write!(
+                        buffer,
+                        "{} changed to {}.",
+                        PIN_NAMES[pin],
+                        level_name(level)
+                    )
+                    .unwrap();
+                    send_string(&mut tx, &buffer);
+                }
+                *last_level = level;
+            }
+        }
+    }
+}
@@ -0,0 +1,188 @@
+// examples/hsi_accuracy.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example measures the frequency of the internal 8MHz RC oscillator HSI,
+//! against the 8MHz HSE clock from the ST-Link that every other example uses.
+//!
+//! The datasheet only promises the HSI to within 1% at 25 C, and from -2% to
+//! +2.5% over the whole temperature range, which is why the examples run from HSE.
+//! A 1% error is already too much for reliable USART communication at some baud
+//! rates, once the other end's error is added in.
+//!
+//! The HSI cannot clock a timer directly, so it is sent out on the MCO pin, and
+//! back in on the external trigger input of TIM2. Connect a jumper wire as follows.
+//!   PA8 (Arduino D7, MCO) >--- PA0 (Arduino A0, TIM2_ETR)
+//!
+//! TIM2 counts the HSI edges divided by `ETR_DIV`, so the count fits in 16 bits.
+//! TIM3, which runs from the HSE through the PLL, times a window of `WINDOW_US`
+//! microseconds. The count, scaled back up, turns into a frequency with
+//! `estimate_freq` from `hello_nucleo_f103rb::timer`. Each count is good to about
+//! 1 part in 50000, or 20 ppm, and the HSE crystal on the ST-Link is good to tens
+//! of ppm, both far below the HSI tolerance.
+//!
+//! The HSI warms up along with the chip, so measure a few times over a few minutes,
+//! or after touching the chip, to watch it drift.
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use heapless::String;
+use hello_nucleo_f103rb::timer::estimate_freq;
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    pac,
+    pac::{TIM2, TIM3, USART2},
+    prelude::*,
+    serial::{Config, Serial, Tx},
+    timer::{CounterUs, Timer},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const HSI_NOMINAL_HZ: u32 = 8_000_000;
+/// The external trigger prescaler, the most the ETR input can divide by.
+const ETR_DIV: u32 = 8;
+/// 50ms of HSI edges divided by 8 is 50000 counts, within the 16-bit counter even
+/// for an HSI several percent fast.
+const WINDOW_US: u32 = 50_000;
+
+/// Difference of `measured_hz` from `nominal_hz`, in parts per million.
+fn ppm_error(measured_hz: u32, nominal_hz: u32) -> i32 {
+    match nominal_hz {
+        0 => 0,
+        nominal_hz => {
+            ((measured_hz as i64 - nominal_hz as i64) * 1_000_000 / nominal_hz as i64) as i32
+        }
+    }
+}
+
+/// Count the edges on the TIM2 external trigger input over one window.
+fn count_window(counter: &TIM2, window: &mut CounterUs<TIM3>) -> u32 {
+    counter.cnt.write(|w| w.cnt().bits(0));
+    window.start(WINDOW_US.micros()).unwrap();
+    block!(window.wait()).unwrap();
+    counter.cnt.read().cnt().bits() as u32
+}
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_help_text(tx: &mut Tx<USART2>) {
+    let help_text = "\
+Connect PA8 (D7) to PA0 (A0) with a jumper wire first.\r\n\
+The following commands can be sent of USART:\r\n\
+m - Measure the HSI frequency against HSE\r\n\
+? - Display this help message\
+";
+    send_string(tx, help_text);
+}
+
+fn send_measurement(tx: &mut Tx<USART2>, count: u32) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    match count {
+        0 => write!(buffer, "No HSI edges on PA0, check the jumper from PA8.").unwrap(),
+        count => {
+            let hsi_hz = estimate_freq(count * ETR_DIV, WINDOW_US);
+            write!(
+                buffer,
+                "HSI {} Hz, {:+} ppm from nominal, {} counts in {} us.",
+                hsi_hz,
+                ppm_error(hsi_hz, HSI_NOMINAL_HZ),
+                count,
+                WINDOW_US
+            )
+            .unwrap()
+        }
+    }
+    send_string(tx, &buffer);
+}
+
+#[entry]
+fn main() -> ! {
+    // This example does its own setup instead of using `Board`, because it selects
+    // the MCO output in RCC before the clock setup, and `Board` takes RCC and sets
+    // up the clocks in one go.
+
+    // Access device specific peripherals.
+    let dp = pac::Peripherals::take().unwrap();
+    let mut gpioa = dp.GPIOA.split();
+
+    // Send the HSI out on MCO. The clock setup below only changes the fields it
+    // needs, so the selection survives it. The HSI stays on after switching the
+    // system clock to HSE, because nothing turns it off.
+    dp.RCC.cfgr.modify(|_, w| w.mco().hsi());
+
+    // Take ownership of raw flash and rcc devices.
+    let mut flash = dp.FLASH.constrain();
+    let rcc = dp.RCC.constrain();
+
+    // Set up system clock.
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.MHz())
+        .sysclk(48.MHz())
+        .freeze(&mut flash.acr);
+
+    // MCO is the alternate function of PA8. PA0 stays a floating input, which is
+    // all the external trigger input needs.
+    let _mco = gpioa.pa8.into_alternate_push_pull(&mut gpioa.crh); // Arduino D7
+    let _etr = gpioa.pa0.into_floating_input(&mut gpioa.crl); // Arduino A0
+
+    // The HAL enables and resets TIM2, then hands it back for the external clock
+    // setup, which it has no API for. External clock mode 2 counts every rising
+    // edge on ETR, after the prescaler, over the full 16-bit range.
+    let counter = Timer::new(dp.TIM2, &clocks).release();
+    counter.arr.write(|w| w.arr().bits(u16::MAX));
+    counter.smcr.write(|w| w.etps().div8().ece().set_bit());
+    counter.cr1.modify(|_, w| w.cen().set_bit());
+    let mut window = dp.TIM3.counter_us(&clocks);
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = dp.AFIO.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        dp.USART2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, mut rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    send_start_message(&mut tx);
+    send_help_text(&mut tx);
+
+    loop {
+        match rx.read() {
+            Ok(b'm') => {
+                let count = count_window(&counter, &mut window);
+                send_measurement(&mut tx, count);
+            }
+            Ok(b'?') => send_help_text(&mut tx),
+            Ok(_) => (),
+            Err(nb::Error::WouldBlock) => (),
+            Err(_) => (),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+// examples/joystick.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example reads an analog joystick, and lights the LED that points the same
+//! way as the stick, out of the LEDs from the `button` example arranged in a ring.
+//!
+//! Wire a two axis joystick module, like the common KY-023, as follows. The module
+//! runs from 3.3V, so its outputs stay within the ADC range.
+//!   +5V  >--- 3.3V
+//!   GND  >--- GND
+//!   VRx  >--- PA0 (Arduino A0)
+//!   VRy  >--- PA1 (Arduino A1)
+//!   SW   >--- PA4 (Arduino A2)
+//!
+//! Place the LEDs in a circle in the order they are listed, clockwise, with the
+//! first one, the onboard LED LD2, at the top. `joystick_to_led` from
+//! `hello_nucleo_f103rb::joystick` turns the two readings into the LED nearest to
+//! the direction the stick is pushed. Near the center, within `DEAD_ZONE`, every
+//! LED is off, so the stick resting slightly
+//! off center does not light anything. If an axis comes out reversed, turn the
+//! module around or swap its wires.
+//!
+//! Pressing the stick down closes SW to ground, and spins a light once around the
+//! ring. SW has no pull-up of its own, so PA4 uses the internal one.
+//!
+//! The axes are read with `read_adc_timeout`, so a failed conversion is reported,
+//! and the LEDs left as they are, rather than the example hanging.
+
+use cortex_m_rt::{entry, exception};
+use hello_nucleo_f103rb::{
+    adc_timeout::read_adc_timeout,
+    board::Board,
+    joystick::joystick_to_led,
+    millis::{self, millis},
+    pins::{button_input, led_output, ButtonPull},
+};
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    adc::{Adc, SampleTime},
+    gpio::{ErasedPin, Output},
+    pac,
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const SAMPLE_MS: u32 = 10;
+const SPIN_STEP_MS: u32 = 40;
+/// PA0 and PA1 are ADC1 channels 0 and 1.
+const X_CHANNEL: u8 = 0;
+const Y_CHANNEL: u8 = 1;
+/// Polls allowed for each step of a conversion, far more than a working ADC needs.
+const ADC_MAX_SPINS: u32 = 10_000;
+
+/// Light only LED `index`, or none for `None`.
+fn show_led(led_set: &mut [ErasedPin<Output>], index: Option<usize>) {
+    for (i, led) in led_set.iter_mut().enumerate() {
+        match Some(i) == index {
+            true => led.set_high(),
+            false => led.set_low(),
+        }
+    }
+}
+
+#[exception]
+fn SysTick() {
+    millis::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks and the onboard LED LD2, and acquire GPIOA, GPIOB and GPIOC.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut gpioa = board.gpioa;
+    let mut gpiob = board.gpiob;
+    let mut gpioc = board.gpioc;
+
+    // The LEDs from the `button` example, in ring order.
+    let mut led_set = [
+        board.led, // On Board LED LD2
+        // Wire external LEDs as follows.
+        //   GPIO Pin >---|>|---[R]--- GND
+        //                LED   Resistor
+        led_output(gpioa.pa7, &mut gpioa.crl).erase(), // Arduino D11/PWM/MOSI
+        led_output(gpiob.pb6, &mut gpiob.crl).erase(), // Arduino D10/PWM/CS
+        led_output(gpioc.pc7, &mut gpioc.crl).erase(), // Arduino D9/PWM
+        led_output(gpioa.pa9, &mut gpioa.crh).erase(), // Arduino D8
+        led_output(gpioa.pa8, &mut gpioa.crh).erase(), // Arduino D7
+        led_output(gpiob.pb10, &mut gpiob.crh).erase(), // Arduino D6/PWM
+        led_output(gpiob.pb5, &mut gpiob.crl).erase(), // Arduino D4
+        led_output(gpioa.pa10, &mut gpioa.crh).erase(), // Arduino D2
+    ];
+
+    // The joystick switch pulls PA4 low when pressed.
+    let stick_button = button_input(gpioa.pa4, &mut gpioa.crl, ButtonPull::Up, true); // Arduino A2
+
+    // Start the millisecond time base used for sampling and the spin.
+    let cp = cortex_m::Peripherals::take().unwrap();
+    millis::init(cp.SYST, &clocks);
+
+    // Setup ADC1 with the two joystick axes as analog inputs. The potentiometers
+    // have a high source impedance, so take a long sample time.
+    // The pins are only configured here, `read_adc_timeout` selects the channels.
+    let mut adc1 = Adc::adc1(board.adc1, clocks);
+    let _x_axis = gpioa.pa0.into_analog(&mut gpioa.crl); // Arduino A0
+    let _y_axis = gpioa.pa1.into_analog(&mut gpioa.crl); // Arduino A1
+
+    rtt_init_print!();
+    rprintln!("Hello, {}!", BOARD);
+    rprintln!("Push the joystick to light an LED, press it to spin.");
+
+    let mut shown: Option<usize> = None;
+    let mut spin_start_ms: Option<u32> = None;
+    let mut was_pressed = false;
+    let mut last_sample_ms = millis();
+    loop {
+        let now_ms = millis();
+        if now_ms.wrapping_sub(last_sample_ms) < SAMPLE_MS {
+            continue;
+        }
+        last_sample_ms = now_ms;
+
+        let pressed = stick_button.is_pressed();
+        if pressed && !was_pressed {
+            rprintln!("Spin!");
+            spin_start_ms = Some(now_ms);
+        }
+        was_pressed = pressed;
+
+        // The spin lights each LED in turn once, and overrides the stick until done.
+        if let Some(start_ms) = spin_start_ms {
+            let step = (now_ms.wrapping_sub(start_ms) / SPIN_STEP_MS) as usize;
+            match step < led_set.len() {
+                true => show_led(&mut led_set, Some(step)),
+                false => spin_start_ms = None,
+            }
+            shown = None;
+            continue;
+        }
+
+        let read = |adc1: &mut Adc<pac::ADC1>, channel| {
+            read_adc_timeout(adc1, channel, SampleTime::T_71, ADC_MAX_SPINS)
+        };
+        let (x, y) = match (read(&mut adc1, X_CHANNEL), read(&mut adc1, Y_CHANNEL)) {
+            (Ok(x), Ok(y)) => (x, y),
+            (Err(error), _) | (_, Err(error)) => {
+                rprintln!("Joystick read failed, {}: {:?}.", error.describe(), error);
+                continue;
+            }
+        };
+        let led = joystick_to_led(x, y, led_set.len());
+        if led != shown {
+            match led {
+                Some(led) => rprintln!("x {}, y {}, LED {}.", x, y, led),
+                None => rprintln!("x {}, y {}, centered.", x, y),
+            }
+            show_led(&mut led_set, led);
+            shown = led;
+        }
+    }
+}
@@ -0,0 +1,142 @@
+// examples/output_benchmark.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example measures how long it takes to print the same line with
+//! `rprintln!` over RTT, and with `write!` to USART followed by a flush, so the
+//! cost of printing to both in a hot path is a number rather than a guess.
+//!
+//! TIM2 counts microseconds, and `time_us` from `hello_nucleo_f103rb::timer` times
+//! each call. Every measurement is repeated `RUNS` times, and the mean and the
+//! maximum are reported, along with the overhead of timing an empty closure.
+//! Press any key over USART to run the measurements again.
+//!
+//! Expect USART to be far slower. `write!` waits for every byte to go out, so the
+//! line takes about 87us per byte at 115200 baud, 10 bits per byte. RTT only
+//! copies the text into a buffer in RAM, which the debug probe reads whenever it
+//! likes. `rtt_init_print!` skips text that does not fit, rather than waiting, so
+//! RTT stays fast even with no probe attached, at the cost of losing output.
+//!
+//! The measured line really is printed, `RUNS` times on each output, before the
+//! results.
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use heapless::String;
+use hello_nucleo_f103rb::{
+    board::Board,
+    serial_config::{usart2_split, Usart2Pins},
+    timer::time_us,
+};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    pac::{TIM2, USART2},
+    prelude::*,
+    serial::{Config, Tx},
+    timer::CounterUs,
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const RUNS: u32 = 10;
+/// The line printed by every measurement, before the line ending.
+const MESSAGE: &str = "The quick brown fox jumps over.";
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+/// Time `f` over `RUNS` runs, and return the mean and maximum in microseconds.
+fn measure(timer: &mut CounterUs<TIM2>, mut f: impl FnMut()) -> (u32, u32) {
+    let mut total_us: u32 = 0;
+    let mut max_us: u32 = 0;
+    for _ in 0..RUNS {
+        let elapsed_us = time_us(timer, &mut f);
+        total_us = total_us.saturating_add(elapsed_us);
+        max_us = max_us.max(elapsed_us);
+    }
+    (total_us / RUNS, max_us)
+}
+
+fn send_result(tx: &mut Tx<USART2>, name: &str, (mean_us, max_us): (u32, u32)) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "{}: mean {} us, max {} us.", name, mean_us, max_us).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn run_benchmark(tx: &mut Tx<USART2>, timer: &mut CounterUs<TIM2>) {
+    let overhead = measure(timer, || ());
+    let rtt = measure(timer, || rprintln!("{}", MESSAGE));
+    let usart = measure(timer, || {
+        write!(tx, "\r{}\r\n", MESSAGE).unwrap();
+        block!(tx.flush()).unwrap();
+    });
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(
+        buffer,
+        "Printing {} characters, {} runs each.",
+        MESSAGE.len(),
+        RUNS
+    )
+    .unwrap();
+    send_string(tx, &buffer);
+    send_result(tx, "Timing overhead", overhead);
+    send_result(tx, "rprintln!", rtt);
+    send_result(tx, "write! and flush", usart);
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, and acquire the GPIOA pins.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut gpioa = board.gpioa;
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2. The pins choose
+    // the AFIO remap, see `usart2_split` for the alternative, and why it cannot be
+    // used on this board.
+    let pins = Usart2Pins::Default(
+        gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl),
+        gpioa.pa3,
+    );
+    let (mut tx, mut rx) = usart2_split(
+        board.usart2,
+        pins,
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    // TIM2 counts microseconds for `time_us`. The board started it as a delay, at
+    // the 1MHz a `CounterUs` needs.
+    let mut timer = board.delay.release().counter();
+
+    send_start_message(&mut tx);
+    run_benchmark(&mut tx, &mut timer);
+    send_string(&mut tx, "Press any key to run again.");
+    loop {
+        if rx.read().is_ok() {
+            run_benchmark(&mut tx, &mut timer);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+// examples/pwm_led.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example dims LEDs with hardware PWM, instead of just switching them on and
+//! off. TIM3 channel 1 drives an LED on PA6 (Arduino D12), and channel 2 drives one
+//! on PA7 (Arduino D11) with the opposite duty, so one fades in as the other fades
+//! out. Wire each LED with a series resistor to ground, as in `gpio_led`.
+//!
+//! The timer runs at `PWM_HZ`, far above the roughly 100Hz where flicker becomes
+//! visible, and the duty cycle is set in whole percent. The system clock stays at
+//! 48MHz from HSE, so the PWM frequency is predictable.
+//!
+//! At first, the LEDs breathe. Every `BREATHE_STEP_MS` milliseconds the duty moves
+//! one percent, from 0% up to 100% and back down. The following commands can be
+//! sent over USART.
+//!
+//! - `+` and `-` step the brightness by `BRIGHTNESS_STEP` percent.
+//! - `0` to `9` jump to 0% to 90%.
+//! - `b` starts breathing again, from the current duty.
+//! - `?` displays the help message.
+//!
+//! Changing the brightness by hand stops the breathing. The duty is printed over
+//! RTT whenever it is set by hand, and at every tenth percent while breathing.
+//!
+//! The duty is linear, so the LEDs seem to change quickly near 0% and hardly at all
+//! near 100%. `gamma_correct` from `hello_nucleo_f103rb::soft_pwm` evens that out,
+//! but is left out here so the duty printed is the duty on the pin.
+
+use core::fmt::Write;
+use cortex_m_rt::{entry, exception};
+use heapless::String;
+use hello_nucleo_f103rb::{
+    board::Board,
+    millis::{self, millis},
+};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    pac::USART2,
+    prelude::*,
+    serial::{Config, Serial, Tx},
+    timer::{Channel, Tim3NoRemap},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const PWM_HZ: u32 = 1_000;
+const DUTY_MAX_PERCENT: u8 = 100;
+const BRIGHTNESS_STEP: u8 = 10;
+/// A full breath, up and back down, takes 200 steps, or 4 seconds.
+const BREATHE_STEP_MS: u32 = 20;
+
+/// The compare value for `percent` duty, with `max_duty` being 100%.
+fn duty_for_percent(max_duty: u16, percent: u8) -> u16 {
+    let percent = percent.min(DUTY_MAX_PERCENT) as u32;
+    (max_duty as u32 * percent / DUTY_MAX_PERCENT as u32) as u16
+}
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_help_text(tx: &mut Tx<USART2>) {
+    let help_text = "\
+PWM LEDs on PA6 (Arduino D12) and PA7 (Arduino D11), with opposite duty.\r\n\
+The following commands can be sent of USART.\r\n\
++ - Brighter by 10%\r\n\
+- - Dimmer by 10%\r\n\
+0-9 - Set the duty cycle to 0% to 90%\r\n\
+b - Breathe, ramping from 0% to 100% and back\r\n\
+? - Display this help message\
+";
+    send_string(tx, help_text);
+}
+
+#[exception]
+fn SysTick() {
+    millis::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, at 48MHz from HSE, and acquire the GPIOA pins.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut gpioa = board.gpioa;
+
+    // Start the millisecond time base that paces the breathing.
+    let cp = cortex_m::Peripherals::take().unwrap();
+    millis::init(cp.SYST, &clocks);
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, mut rx) = serial.split();
+
+    // Configure PA6 and PA7 as the TIM3 channel 1 and 2 outputs, without remapping.
+    //   Wire external LEDs as follows.
+    //     GPIO Pin >---|>|---[R]--- GND
+    //                  LED   Resistor
+    let c1 = gpioa.pa6.into_alternate_push_pull(&mut gpioa.crl); // Arduino D12
+    let c2 = gpioa.pa7.into_alternate_push_pull(&mut gpioa.crl); // Arduino D11
+    let pins = (c1, c2);
+    let mut pwm =
+        board
+            .tim3
+            .pwm_hz::<Tim3NoRemap, _, _>(pins, &mut afio.mapr, PWM_HZ.Hz(), &clocks);
+    let max_duty = pwm.get_max_duty();
+    pwm.set_duty(Channel::C1, 0);
+    pwm.set_duty(Channel::C2, max_duty);
+    pwm.enable(Channel::C1);
+    pwm.enable(Channel::C2);
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    send_start_message(&mut tx);
+    send_help_text(&mut tx);
+
+    let mut duty: u8 = 0;
+    let mut breathing = true;
+    let mut rising = true;
+    let mut last_step_ms = millis();
+    loop {
+        let requested = match rx.read() {
+            Ok(b'?') => {
+                send_help_text(&mut tx);
+                None
+            }
+            Ok(b'+') => Some(duty.saturating_add(BRIGHTNESS_STEP).min(DUTY_MAX_PERCENT)),
+            Ok(b'-') => Some(duty.saturating_sub(BRIGHTNESS_STEP)),
+            Ok(c @ b'0'..=b'9') => Some((c - b'0') * BRIGHTNESS_STEP),
+            Ok(b'b') => {
+                breathing = true;
+                send_string(&mut tx, "Breathing.");
+                None
+            }
+            Ok(_) => None,
+            Err(nb::Error::WouldBlock) => None,
+            Err(_) => None,
+        };
+
+        let now_ms = millis();
+        let changed = match requested {
+            Some(percent) => {
+                breathing = false;
+                duty = percent;
+                true
+            }
+            None if breathing && BREATHE_STEP_MS <= now_ms.wrapping_sub(last_step_ms) => {
+                last_step_ms = now_ms;
+                if DUTY_MAX_PERCENT <= duty {
+                    rising = false;
+                } else if 0 == duty {
+                    rising = true;
+                }
+                duty = if rising { duty + 1 } else { duty - 1 };
+                true
+            }
+            None => false,
+        };
+
+        if changed {
+            pwm.set_duty(Channel::C1, duty_for_percent(max_duty, duty));
+            pwm.set_duty(
+                Channel::C2,
+                duty_for_percent(max_duty, DUTY_MAX_PERCENT - duty),
+            );
+            if !breathing || duty.is_multiple_of(BRIGHTNESS_STEP) {
+                rprintln!("Duty {}%.", duty);
+            }
+        }
+    }
+}
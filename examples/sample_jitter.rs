@@ -0,0 +1,162 @@
+// examples/sample_jitter.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example runs a fixed rate sampling loop from a timer interrupt, the way a
+//! control loop or DSP filter would, and measures how evenly spaced the samples
+//! really are.
+//!
+//! TIM3 interrupts at `SAMPLE_HZ`. The interrupt handler stands in for the sampling
+//! work, and timestamps each sample with the DWT cycle counter, which counts core
+//! clock cycles and so has a resolution far finer than a microsecond. The interval
+//! since the previous sample is rounded down to whole microseconds and added to
+//! the jitter statistics. Once a second, the main loop takes the statistics, resets
+//! them, and reports the minimum, maximum, mean and standard deviation of the
+//! interval over USART.
+//!
+//! Ideally every interval is exactly `1_000_000 / SAMPLE_HZ` microseconds. The timer
+//! itself does not drift, so the spread comes from interrupt latency, which varies
+//! with whatever the core was doing when the interrupt arrived. Reporting over
+//! USART from the main loop does not delay samples, because the interrupt preempts
+//! it, but critical sections do, because they hold off the interrupt.
+
+use core::fmt::Write;
+use cortex_m::peripheral::{DWT, NVIC};
+use cortex_m_rt::entry;
+use heapless::String;
+use hello_nucleo_f103rb::{
+    board::{Board, ClockProfile},
+    jitter::JitterStats,
+    shared::Shared,
+};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    pac::{interrupt, Interrupt, TIM3, USART2},
+    prelude::*,
+    serial::{Config, Serial, Tx},
+    timer::{CounterHz, Event},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const SAMPLE_HZ: u32 = 1_000;
+const PROFILE: ClockProfile = ClockProfile::Hse8Sysclk48;
+const CYCLES_PER_US: u32 = PROFILE.sysclk_hz() / 1_000_000;
+
+static G_TIMER: Shared<Option<CounterHz<TIM3>>> = Shared::new(None);
+static G_LAST_CYCLES: Shared<Option<u32>> = Shared::new(None);
+static G_STATS: Shared<JitterStats> = Shared::new(JitterStats::new());
+
+#[interrupt]
+fn TIM3() {
+    // Timestamp first, so the time taken by the rest of the handler does not count.
+    let now = DWT::cycle_count();
+    G_TIMER.with(|timer| {
+        if let Some(timer) = timer.as_mut() {
+            timer.clear_interrupt(Event::Update);
+        }
+    });
+    if let Some(last) = G_LAST_CYCLES.with(|last| last.replace(now)) {
+        let dt_us = now.wrapping_sub(last) / CYCLES_PER_US;
+        G_STATS.with(|stats| stats.update_jitter_stats(dt_us));
+    }
+}
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_stats(tx: &mut Tx<USART2>, stats: &JitterStats) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(
+        buffer,
+        "{} samples, interval min {} us, max {} us, mean {} us, std dev {} us.",
+        stats.count,
+        stats.min_us,
+        stats.max_us,
+        stats.mean_us(),
+        stats.std_dev_us()
+    )
+    .unwrap();
+    send_string(tx, &buffer);
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, and acquire the GPIOA pins.
+    let board = Board::take_with(PROFILE);
+    let clocks = board.clocks;
+    let mut gpioa = board.gpioa;
+
+    // The DWT cycle counter only runs while tracing is enabled.
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    cp.DCB.enable_trace();
+    cp.DWT.enable_cycle_counter();
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, _rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    send_start_message(&mut tx);
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(
+        buffer,
+        "Sampling at {} Hz, reporting once a second.",
+        SAMPLE_HZ
+    )
+    .unwrap();
+    send_string(&mut tx, &buffer);
+
+    // TIM3 sets the sample rate.
+    let mut timer = board.tim3.counter_hz(&clocks);
+    timer.start(SAMPLE_HZ.Hz()).unwrap();
+    timer.listen(Event::Update);
+    G_TIMER.lock_set(Some(timer));
+
+    // Unmasking an interrupt is unsafe because it can break critical sections,
+    // but all shared state is only accessed through `Shared`.
+    #[allow(unsafe_code)]
+    unsafe {
+        NVIC::unmask(Interrupt::TIM3);
+    }
+
+    loop {
+        cortex_m::asm::wfi();
+        // Take a full second of statistics at once, so the report is consistent.
+        let stats = G_STATS.with(|stats| match SAMPLE_HZ <= stats.count {
+            true => Some(core::mem::replace(stats, JitterStats::new())),
+            false => None,
+        });
+        if let Some(stats) = stats {
+            send_stats(&mut tx, &stats);
+        }
+    }
+}
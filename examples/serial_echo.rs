@@ -10,7 +10,7 @@
 ///
 /// 1. Reading from USART to receive commands from a serial terminal, responding to
 ///    commands to change text conversion modes (normal, upper case, lower case,
-///    inverted case), and displaying help information.
+///    inverted case, leetspeak), and displaying help information.
 ///
 /// 2. Using a push-button to cycle through text conversion modes, affecting how
 ///    text received from USART is echoed back:
@@ -18,12 +18,18 @@
 ///    - Force Upper Case: Converts all alphabetic characters to uppercase.
 ///    - Force Lower Case: Converts all alphabetic characters to lowercase.
 ///    - Inverted Case: Inverts the case of alphabetic characters.
+///    - Leetspeak: Replaces letters with similar looking digits, like `l337`.
+///    - ROT-N: Rotates alphabetic characters, chosen by the `&` command only.
+///    - Hex Dump: Shows bytes as hex digits, chosen by the `|` command only.
 ///
 /// 3. Controlling an LED based on the current text mode:
 ///    - Off in Normal Case mode.
 ///    - On in Force Upper Case mode.
 ///    - Blinking in Force Lower Case mode.
 ///    - Strobing in Inverted Case mode.
+///    - Flickering at 5 Hz in Leetspeak mode.
+///    - Blinking twice in quick succession, once a second, in ROT-N mode.
+///    - Blinking at 2 Hz in Hex Dump mode.
 ///
 /// 4. Implementing software-based delay to control execution rate and LED patterns.
 ///
@@ -33,34 +39,136 @@
 /// terminal, providing user feedback.
 ///
 /// Constants like BLINK_MS, STROBE_MS, DELAY_MS, and DELAY_COUNTER_MAX define the
-/// timing for LED control. Modular arithmetic determines the LED's state (on, off,
+/// default timing for LED control. Modular arithmetic determines the LED's state (on, off,
 /// blink, strobe) based on `counter`, while the main loop's timing and `counter`
 /// increment rely on the hardware timer (TIM2) for precise delay intervals. This
 /// ensures accurate control over periodic events like LED blinking and strobing,
 /// alongside the program's execution rate.
 ///
+/// An `@` sent at the start of a line, followed by a number of milliseconds and
+/// enter, changes the loop delay at runtime, trading CPU time against how often
+/// USART and the button are polled. The delay is clamped between `MIN_DELAY_MS`
+/// and `MAX_DELAY_MS`. DELAY_COUNTER_MAX is a multiple of both LED periods, so the
+/// blink and strobe rates stay the same, although a delay longer than a period
+/// makes that pattern irregular.
+///
+/// An `&` sent at the start of a line, followed by a number and enter, switches to
+/// a ROT-N cipher mode, which rotates letters N places through the alphabet and
+/// leaves everything else unchanged. ROT13 is its own inverse, so sending the
+/// echoed text back decodes it. Without a number, the last rotation is used again.
+/// ROT13 is `&13` rather than a command character of its own, because `@`, the
+/// obvious choice, already sets the loop delay.
+/// Larger rotations repeat, so they wrap around, and `&26` is the same as `&0`,
+/// the identity, while `&27` is the same as `&1`.
+///
+/// For automation, host tooling can send a boot configuration line within
+/// `BOOT_WINDOW_MS` of reset, like `boot mode=upper baud=57600 led=off echo=off`,
+/// to set the text mode, baud rate, mode indicator LED and local echo in one go.
+/// The line is applied before the greeting, so the greeting already arrives at the
+/// new baud rate. See `hello_nucleo_f103rb::boot_config` for the settings.
+///
+/// Mode change confirmations are shown in color using ANSI escape sequences.
+/// `#` turns color off, or back on, for terminals that print them literally.
+/// Ctrl-L clears the screen and redraws the line in progress, but only while color
+/// is on, because it is also an ANSI escape sequence.
+///
+/// `^` switches between verbose and terse confirmations. Verbose confirmations are
+/// full sentences. Terse confirmations are a short code instead, the command
+/// character followed by the new setting, like `+` or `!0`, which are easier for
+/// scripts to match. Error messages are always full sentences.
+///
+/// `!` turns local echo off, or back on, for terminals that echo typed characters
+/// themselves. With local echo off, characters are not echoed as they arrive, and
+/// only the converted line is sent when enter is pressed.
+///
+/// Backspace, sent as either 0x08 or 0x7F depending on the terminal, removes the
+/// last character of the line in progress, and erases it from the screen with
+/// `\x08 \x08` while local echo is on. On an empty line it does nothing, so it can
+/// never erase the text before the line.
+///
+/// `%` toggles counting mode. While counting, the cumulative number of characters,
+/// words, and lines received is reported after every completed line, like `wc`.
+///
+/// A `T` command sent at the start of a line transmits a fixed 1KB block as fast as
+/// the transmitter accepts it, and reports the measured throughput. This gives a
+/// concrete number to compare baud rates and transmit strategies against.
+///
+/// A `'` sent at the start of a line replays the last completed line, converted
+/// with the current text mode, so one line can be compared across modes.
+///
+/// An `x` sent at the start of a line, followed by a digit N, echoes every later
+/// completed line N times, separated by newlines. N is clamped to
+/// `MAX_REPETITIONS` so a single line cannot flood the terminal. With N set to 0,
+/// completed lines are not echoed at all, although local echo still shows the
+/// characters as they are typed. An `x` followed by anything other than a digit
+/// is ordinary text, so a line can still start with `x`, although the `x` only
+/// shows up once the next character arrives.
+///
+/// `*` toggles suppressing repeated lines. Each completed line is hashed with
+/// FNV-1a, see `hello_nucleo_f103rb::hash`, and a line with the same hash as the
+/// line before it is not echoed, like `uniq`. Only the hash of the previous line is
+/// kept, so this costs four bytes rather than a second line buffer. With local
+/// echo on, the characters of a repeated line still show as they are typed.
+///
+/// A `|` sent at the start of a line switches to hex dump mode, for devices that
+/// send binary data rather than text. Every received byte is shown as two hex
+/// digits as it arrives. Hex dump lines are `HEX_DUMP_ROW` bytes long rather than
+/// ended by enter, and each completed line is redrawn with a gutter with the bytes
+/// as ASCII and a `.` for anything unprintable, like `hexdump -C`. With local echo
+/// off, only the completed rows are shown. Every byte is shown, command characters
+/// included, so the mode is left with user button B1 instead, which finishes the
+/// last row and cycles back to normal case. The command is not a letter, so lines
+/// of text can start with anything but `|`.
+///
+/// User button B1 is debounced with `Debouncer`, see `hello_nucleo_f103rb::pins`.
+/// It is sampled once per pass, and told the time since the last sample as measured
+/// with `millis`, because a pass that sends text takes longer than the loop delay.
+/// A press has to be seen by two samples at least `DEBOUNCE_MS` apart, so it has to
+/// be held for a little longer than one pass, 50ms at the default loop delay, and
+/// longer if the loop delay is raised with `@`.
+///
 /// This example demonstrates handling of peripheral I/O (USART and GPIO), conditional
 /// logic based on external inputs (USART commands and button state), and basic use
 /// of Rust's type system (enums, match statements) in an embedded context without
 /// the standard library.
-
 use core::{fmt::Write, str};
-use cortex_m_rt::entry;
+use cortex_m_rt::{entry, exception};
 use heapless::String;
+use hello_nucleo_f103rb::{
+    ansi::{clear_screen, colored, AnsiColor},
+    board::Board,
+    boot_config::{parse_boot_config, BootConfig, BOOT_COMMAND},
+    hash::fnv1a,
+    line_buffer::{LastLine, LineBuffer},
+    millis::{self, millis},
+    parse::{parse_clamped, ParseErr},
+    pins::{ButtonEvent, Debouncer},
+    serial_config::reconfigure_serial,
+    text::{
+        convert_case, echo_byte, effective_limit, reduce_rotation, repeated, resolve_mode,
+        safe_str, LineCounts, TextMode, HEX_DUMP_ROW,
+    },
+    timer::throughput_bps,
+};
 use nb::block;
+#[cfg(not(feature = "panic-sos"))]
 use panic_halt as _;
 use rtt_target::{rprintln, rtt_init_print};
 use stm32f1xx_hal::{
     gpio::{ErasedPin, Output},
-    pac,
-    pac::USART2,
+    pac::{TIM2, USART2},
     prelude::*,
-    serial::{Config, Serial, Tx},
+    serial::{Config, Rx, Serial, Tx},
+    timer::Delay,
 };
 
 const BOARD: &str = "Nucleo-F103RB";
+// Ctrl-L, which clears the screen in most shells.
+const FORM_FEED: u8 = 0x0C;
+// Terminals send one of these for the backspace key.
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
 const BUFFER_SIZE: usize = 128;
-const CASE_OFFSET: u8 = 0x20;
 const BLINK_MS: u32 = 500;
 const STROBE_MS: u32 = 50;
 const DELAY_MS: u32 = if BLINK_MS < STROBE_MS {
@@ -68,18 +176,31 @@ const DELAY_MS: u32 = if BLINK_MS < STROBE_MS {
 } else {
     STROBE_MS
 };
+const MAX_REPETITIONS: u32 = 5;
+// More digits than any u32 has, so a long entry is clamped rather than cut short.
+const NUMBER_ENTRY_SIZE: usize = 16;
+const MIN_DELAY_MS: u32 = 1;
+const MAX_DELAY_MS: u32 = 1_000;
+const DEFAULT_ROTATION: u8 = 13;
+const BOOT_WINDOW_MS: u32 = 1_000;
+// Much shorter than one character at 115200 baud, so no byte is missed.
+const BOOT_POLL_US: u32 = 10;
+const BENCHMARK_BYTES: u32 = 1024;
+const BENCHMARK_LINE_LENGTH: u32 = 64;
+// TIM2 is 16 bits wide, so at 1MHz it wraps well before the benchmark finishes.
+const BENCHMARK_TIMER_PERIOD_US: u32 = 50_000;
 const DELAY_COUNTER_MAX: u32 = 2 * if STROBE_MS < BLINK_MS {
     BLINK_MS
 } else {
     STROBE_MS
 };
 
-#[derive(PartialEq)]
-enum TextMode {
-    NormalCase,
-    ForceUpper,
-    ForceLower,
-    InvertedCase,
+/// Commands that take a number, typed after the command character and completed
+/// with enter.
+#[derive(Clone, Copy)]
+enum NumberCommand {
+    Delay,
+    Rotation,
 }
 
 #[derive(PartialEq)]
@@ -87,6 +208,9 @@ enum LedMode {
     Off,
     On,
     Blink(u32), // Value represents the blink period in milliseconds
+    // Two flashes of the given length in milliseconds, then dark for the rest of
+    // the `DELAY_COUNTER_MAX` cycle.
+    DoubleBlink(u32),
 }
 
 impl LedMode {
@@ -95,7 +219,15 @@ impl LedMode {
             LedMode::Off => led.set_low(),
             LedMode::On => led.set_high(),
             LedMode::Blink(period) => {
-                if (counter / period) % 2 == 0 {
+                if (counter / period).is_multiple_of(2) {
+                    led.set_high();
+                } else {
+                    led.set_low();
+                }
+            }
+            LedMode::DoubleBlink(flash) => {
+                // Flash, pause, flash, each `flash` long.
+                if counter < 3 * flash && (counter / flash).is_multiple_of(2) {
                     led.set_high();
                 } else {
                     led.set_low();
@@ -112,31 +244,86 @@ impl From<&TextMode> for LedMode {
             TextMode::ForceUpper => LedMode::On,
             TextMode::ForceLower => LedMode::Blink(BLINK_MS),
             TextMode::InvertedCase => LedMode::Blink(STROBE_MS),
+            TextMode::Rot(_) => LedMode::DoubleBlink(2 * STROBE_MS),
+            TextMode::Leet => LedMode::Blink(2 * STROBE_MS),
+            TextMode::HexDump => LedMode::Blink(BLINK_MS / 2),
         }
     }
 }
 
-fn is_lowercase(c: u8) -> bool {
-    (b'a'..=b'z').contains(&c)
+/// Send `byte` as two hex digits and a space, as it is echoed in hex dump mode.
+fn send_hex_byte(tx: &mut Tx<USART2>, byte: u8) -> core::fmt::Result {
+    write!(tx, "{:02X} ", byte)
 }
 
-fn is_uppercase(c: u8) -> bool {
-    (b'A'..=b'Z').contains(&c)
+/// Send a row of hex dump mode, padded to full width if it is partial, followed by
+/// the gutter with the bytes as ASCII.
+fn send_hex_row(tx: &mut Tx<USART2>, row: &[u8]) -> core::fmt::Result {
+    for &byte in row {
+        send_hex_byte(tx, byte)?;
+    }
+    for _ in row.len()..HEX_DUMP_ROW {
+        write!(tx, "   ")?;
+    }
+    write!(tx, " |")?;
+    for &byte in row {
+        let c = match byte {
+            b' '..=b'~' => byte as char,
+            _ => '.',
+        };
+        write!(tx, "{}", c)?;
+    }
+    write!(tx, "|")
 }
 
-fn convert_case(c: u8, text_mode: &TextMode) -> u8 {
-    let mut result = c;
-    result = result
-        + match text_mode {
-            TextMode::ForceLower | TextMode::InvertedCase if is_uppercase(c) => CASE_OFFSET,
-            _ => 0,
-        };
-    result = result
-        - match text_mode {
-            TextMode::ForceUpper | TextMode::InvertedCase if is_lowercase(c) => CASE_OFFSET,
-            _ => 0,
-        };
-    result
+/// Byte `index` of the benchmark block. The block is printable digits broken
+/// into lines, so it does not upset the serial terminal.
+fn benchmark_byte(index: u32) -> u8 {
+    match index % BENCHMARK_LINE_LENGTH {
+        i if i == BENCHMARK_LINE_LENGTH - 2 => b'\r',
+        i if i == BENCHMARK_LINE_LENGTH - 1 => b'\n',
+        i => b'0' + (i % 10) as u8,
+    }
+}
+
+/// Transmit the benchmark block and return the elapsed time in microseconds.
+/// The delay timer is borrowed as a counter for the measurement and handed back
+/// afterwards. Timer wraps are counted while polling the transmitter.
+fn run_benchmark(
+    tx: &mut Tx<USART2>,
+    delay: Delay<TIM2, 1_000_000>,
+) -> (Delay<TIM2, 1_000_000>, u32) {
+    let mut timer = delay.release().counter();
+    timer.start(BENCHMARK_TIMER_PERIOD_US.micros()).ok();
+    let mut periods: u32 = 0;
+    let mut sent: u32 = 0;
+    loop {
+        if sent < BENCHMARK_BYTES {
+            if tx.write(benchmark_byte(sent)).is_ok() {
+                sent += 1;
+            }
+        } else if tx.flush().is_ok() {
+            break;
+        }
+        if timer.wait().is_ok() {
+            periods += 1;
+        }
+    }
+    let elapsed_us = periods * BENCHMARK_TIMER_PERIOD_US + timer.now().ticks();
+    (timer.release().delay(), elapsed_us)
+}
+
+fn send_benchmark_result(tx: &mut Tx<USART2>, elapsed_us: u32) -> nb::Result<(), core::fmt::Error> {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(
+        buffer,
+        "Sent {} bytes in {} us, {} bytes/s.",
+        BENCHMARK_BYTES,
+        elapsed_us,
+        throughput_bps(BENCHMARK_BYTES, elapsed_us)
+    )
+    .ok();
+    send_string(tx, &buffer)
 }
 
 fn flush_buffer(
@@ -144,10 +331,25 @@ fn flush_buffer(
     buffer: &[u8],
     index: usize,
     text_mode: &TextMode,
+    repetitions: usize,
 ) -> nb::Result<(), core::fmt::Error> {
+    // A hex dump row is redrawn once, from the start, with its gutter.
+    if TextMode::HexDump == *text_mode {
+        rprintln!("{:02X?}", &buffer[..index]);
+        block!(tx.write(b'\r')).ok();
+        send_hex_row(tx, &buffer[..index]).ok();
+        block!(tx.flush()).ok();
+        return Ok(());
+    }
+    let mut converted: [u8; BUFFER_SIZE] = [0; BUFFER_SIZE];
+    for (out, c) in converted.iter_mut().zip(&buffer[..index]) {
+        *out = convert_case(*c, text_mode);
+    }
+    // The buffer holds whatever bytes were received, so it may not be valid UTF-8.
+    rprintln!("{}", safe_str(&converted[..index]));
     block!(tx.write(b'\r')).ok();
-    for c in &buffer[..index] {
-        block!(tx.write(convert_case(*c, text_mode))).ok();
+    for c in repeated(&converted[..index], repetitions) {
+        block!(tx.write(c)).ok();
     }
     block!(tx.flush()).ok();
     Ok(())
@@ -160,59 +362,173 @@ fn send_string(tx: &mut Tx<USART2>, string: &str) -> nb::Result<(), core::fmt::E
     Ok(())
 }
 
+fn send_colored(
+    tx: &mut Tx<USART2>,
+    color: AnsiColor,
+    string: &str,
+    use_color: bool,
+) -> nb::Result<(), core::fmt::Error> {
+    if !use_color {
+        return send_string(tx, string);
+    }
+    rprintln!("{}", string);
+    write!(tx, "\r").ok();
+    colored(tx, color, string).ok();
+    write!(tx, "\r\n").ok();
+    block!(tx.flush()).ok();
+    Ok(())
+}
+
+/// The confirmation to show for the current verbosity.
+fn confirmation<'a>(verbose: bool, terse: &'a str, full: &'a str) -> &'a str {
+    match verbose {
+        true => full,
+        false => terse,
+    }
+}
+
+fn confirm(
+    tx: &mut Tx<USART2>,
+    verbose: bool,
+    terse: &str,
+    full: &str,
+) -> nb::Result<(), core::fmt::Error> {
+    send_string(tx, confirmation(verbose, terse, full))
+}
+
+fn confirm_colored(
+    tx: &mut Tx<USART2>,
+    verbose: bool,
+    terse: &str,
+    full: &str,
+    use_color: bool,
+) -> nb::Result<(), core::fmt::Error> {
+    let string = confirmation(verbose, terse, full);
+    send_colored(tx, AnsiColor::Green, string, use_color)
+}
+
+fn send_counts(tx: &mut Tx<USART2>, counts: &LineCounts) -> nb::Result<(), core::fmt::Error> {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(
+        buffer,
+        "{} characters, {} words, {} lines.",
+        counts.chars, counts.words, counts.lines
+    )
+    .ok();
+    send_string(tx, &buffer)
+}
+
+/// Wait up to `BOOT_WINDOW_MS` for a boot configuration line.
+/// Returns true if a complete line starting with `BOOT_COMMAND` was received.
+fn read_boot_line(
+    rx: &mut Rx<USART2>,
+    delay: &mut Delay<TIM2, 1_000_000>,
+    line: &mut String<BUFFER_SIZE>,
+) -> bool {
+    for _ in 0..BOOT_WINDOW_MS * 1_000 / BOOT_POLL_US {
+        match rx.read() {
+            Ok(b'\r') => return line.starts_with(BOOT_COMMAND),
+            Ok(c) => {
+                let _ = line.push(c as char);
+            }
+            Err(_) => (),
+        }
+        delay.delay_us(BOOT_POLL_US);
+    }
+    false
+}
+
 fn send_start_message(tx: &mut Tx<USART2>) -> nb::Result<(), core::fmt::Error> {
     let mut buffer: String<BUFFER_SIZE> = String::new();
     write!(buffer, "Hello, {}!", BOARD).ok();
     send_string(tx, &buffer)
 }
 
-fn send_help_text(tx: &mut Tx<USART2>) -> nb::Result<(), core::fmt::Error> {
+/// Write the terse and full confirmations for switching to `text_mode`.
+fn mode_confirmation(
+    text_mode: &TextMode,
+    terse: &mut String<BUFFER_SIZE>,
+    full: &mut String<BUFFER_SIZE>,
+) -> core::fmt::Result {
+    match text_mode {
+        TextMode::NormalCase => write!(terse, "=").and(write!(full, "Use normal case.")),
+        TextMode::ForceUpper => write!(terse, "+").and(write!(full, "Force upper case.")),
+        TextMode::ForceLower => write!(terse, "-").and(write!(full, "Force lower case.")),
+        TextMode::InvertedCase => write!(terse, "~").and(write!(full, "Use inverted case.")),
+        TextMode::Rot(n) => write!(terse, "&{}", n).and(write!(full, "Rotate letters by {}.", n)),
+        TextMode::Leet => write!(terse, "$").and(write!(full, "Use leetspeak.")),
+        TextMode::HexDump => write!(terse, "|").and(write!(full, "Hex dump, press B1 to stop.")),
+    }
+}
+
+fn send_help_text(
+    tx: &mut Tx<USART2>,
+    local_echo: bool,
+    rotation: u8,
+) -> nb::Result<(), core::fmt::Error> {
     let help_text = "\
 Press user button B1 to cycle through text conversion modes.\r\n\
+A command received at the same time as a button press takes precedence.\r\n\
 The following text conversion commands can be sent of USART:\r\n\
 = : Echo lines unchanged.\r\n\
 + : Echo lines in upper case.\r\n\
 - : Echo lines in lower case.\r\n\
 ~ : Echo lines in inverted case.\r\n\
+$ : Echo lines in leetspeak, with letters replaced by digits.\r\n\
+&N : Echo lines with letters rotated by N, then enter, at the start of a line.\r\n\
+&13 : Echo lines in ROT13, which decodes itself when the echo is sent back.\r\n\
+! : Toggle local echo for terminals that echo typed characters.\r\n\
+% : Toggle counting characters, words, and lines.\r\n\
+# : Toggle colored output for terminals without ANSI support.\r\n\
+Ctrl-L : Clear the screen, when colored output is on.\r\n\
+Backspace : Erase the last character of the line.\r\n\
+T : Measure transmit throughput with a 1KB block, at the start of a line.\r\n\
+| : Show received bytes in hex, at the start of a line, until B1 is pressed.\r\n\
+' : Replay the last line in the current mode, at the start of a line.\r\n\
+xN : Echo each line N times, up to 5, at the start of a line.\r\n\
+* : Toggle suppressing lines that repeat the line before.\r\n\
+@N : Set the loop delay to N ms, then enter, at the start of a line.\r\n\
+^ : Toggle terse confirmations for scripts.\r\n\
 ? : Display this help message.\
 ";
-    send_string(tx, help_text)
+    send_string(tx, help_text)?;
+    match local_echo {
+        true => send_string(tx, "Local echo is on."),
+        false => send_string(tx, "Local echo is off."),
+    }?;
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "ROT-N rotation is {}.", rotation).ok();
+    send_string(tx, &buffer)
+}
+
+#[exception]
+fn SysTick() {
+    millis::tick();
 }
 
 #[entry]
 fn main() -> ! {
-    // Access device specific peripherals.
-    let dp = pac::Peripherals::take().unwrap();
-
-    // Configure GPIO pins as push-pull output.
-    // For pins 0-7, use `crl`, and for pins 8-15, use `crh`.
-    let mut gpioa = dp.GPIOA.split();
-    let mut led = gpioa.pa5.into_push_pull_output(&mut gpioa.crl).erase(); // On Board LED LD2
-
-    // Acquire read-only user button B1, not mutable.
-    let gpioc = dp.GPIOC.split();
-    let button = gpioc.pc13;
-
-    // Take ownership of raw flash and rcc devices.
-    let mut flash = dp.FLASH.constrain();
-    let rcc = dp.RCC.constrain();
-
-    // Set up system clock and configure delay provider.
-    let clocks = rcc
-        .cfgr
-        .use_hse(8.MHz())
-        .sysclk(48.MHz())
-        .freeze(&mut flash.acr);
-    let mut delay = dp.TIM2.delay_us(&clocks);
+    // Set up the clocks, the delay provider, the onboard LED LD2 and user button B1.
+    // B1 has an external pull-up and reads low when pressed, see `board` and `pins`.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut delay = board.delay;
+    let mut led = board.led; // On Board LED LD2
+    let button = board.button;
+    let mut gpioa = board.gpioa;
+
+    // Start the millisecond time base that times the button samples.
+    let cp = cortex_m::Peripherals::take().unwrap();
+    millis::init(cp.SYST, &clocks);
 
     // Acquire alternate function input/output (AFIO).
-    let mut afio = dp.AFIO.constrain();
+    let mut afio = board.afio.constrain();
 
     // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
     let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
     let rx = gpioa.pa3;
     let serial = Serial::new(
-        dp.USART2,
+        board.usart2,
         (tx, rx),
         &mut afio.mapr,
         Config::default().baudrate(115200.bps()),
@@ -224,99 +540,317 @@ fn main() -> ! {
     // Also using RTT in when writing text to USART.
     rtt_init_print!();
 
+    // Host tooling can configure the board before anything else happens.
+    let mut boot_line: String<BUFFER_SIZE> = String::new();
+    let boot_config = match read_boot_line(&mut rx, &mut delay, &mut boot_line) {
+        true => parse_boot_config(&boot_line),
+        false => BootConfig::default(),
+    };
+    if let Some(baud) = boot_config.baud {
+        let config = Config::default().baudrate(baud.bps());
+        if reconfigure_serial(&mut tx, &mut rx, config, &clocks).is_err() {
+            rprintln!("Boot baud rate {} is not possible.", baud);
+        }
+    }
+
+    let mut local_echo = boot_config.local_echo;
+    let led_enabled = boot_config.led;
+    let mut rotation = match boot_config.text_mode {
+        TextMode::Rot(rotation) => rotation,
+        _ => DEFAULT_ROTATION,
+    };
     let _ = send_start_message(&mut tx);
-    let _ = send_help_text(&mut tx);
+    #[cfg(not(feature = "quiet-boot"))]
+    let _ = send_help_text(&mut tx, local_echo, rotation);
 
-    let mut buffer: [u8; BUFFER_SIZE] = [0; BUFFER_SIZE];
-    let mut index: usize = 0;
+    let mut line: LineBuffer<BUFFER_SIZE> = LineBuffer::new();
 
     let mut counter: u32 = 0;
-    let mut button_down = false;
-    let mut text_mode = TextMode::NormalCase;
-    let mut mode_change: bool = false;
+    let mut debouncer = Debouncer::default();
+    let mut last_sample_ms = millis();
+    let mut text_mode = boot_config.text_mode;
+    let mut use_color = true;
+    let mut counts: Option<LineCounts> = None;
+    let mut last_line: LastLine<BUFFER_SIZE> = LastLine::new();
+    let mut repetitions: usize = 1;
+    let mut repeat_pending = false;
+    // The hash of the last completed line, while repeated lines are suppressed.
+    let mut dedup: Option<Option<u32>> = None;
+    let mut delay_ms = DELAY_MS;
+    let mut number_entry: Option<(NumberCommand, String<NUMBER_ENTRY_SIZE>)> = None;
+    let mut verbose = true;
     let mut do_flush_buffer: bool = false;
     let mut reset_buffer: bool = false;
     loop {
-        match rx.read() {
-            Ok(b'?') => {
-                let _ = send_help_text(&mut tx);
+        let mut serial_cmd: Option<TextMode> = None;
+        let received = rx.read();
+        // An `x` without a count after it was text after all.
+        if let (true, &Ok(c)) = (repeat_pending, &received) {
+            if !c.is_ascii_digit() {
+                repeat_pending = false;
+                if line.push(b'x', effective_limit(&text_mode, BUFFER_SIZE)) {
+                    if let Some(echo) = echo_byte(b'x', &text_mode, local_echo) {
+                        block!(tx.write(echo)).ok();
+                    }
+                }
             }
-            Ok(b'=') => {
-                if TextMode::NormalCase != text_mode {
-                    text_mode = TextMode::NormalCase;
-                    mode_change = true;
+        }
+        match received {
+            // Every byte is data in hex dump mode, and a full row completes the line.
+            Ok(c) if TextMode::HexDump == text_mode => {
+                if line.push(c, effective_limit(&text_mode, BUFFER_SIZE)) && local_echo {
+                    send_hex_byte(&mut tx, c).ok();
+                }
+                if HEX_DUMP_ROW <= line.len() {
+                    do_flush_buffer = true;
+                    reset_buffer = true;
                 }
             }
-            Ok(b'+') => {
-                if TextMode::ForceUpper != text_mode {
-                    text_mode = TextMode::ForceUpper;
-                    mode_change = true;
+            Ok(c) if number_entry.is_some() => {
+                let Some((command, mut entered)) = number_entry.take() else {
+                    continue;
+                };
+                match (command, c) {
+                    (_, b'0'..=b'9') => {
+                        let _ = entered.push(c as char);
+                        number_entry = Some((command, entered));
+                        block!(tx.write(c)).ok();
+                    }
+                    (NumberCommand::Rotation, b'\r') => {
+                        match parse_clamped(&entered, 0, u32::MAX) {
+                            // Only a number too large for a `u32` is clamped.
+                            Ok(entered) if entered.clamped => {
+                                let _ = send_string(&mut tx, "Out of range.");
+                            }
+                            Ok(entered) => rotation = reduce_rotation(entered.value),
+                            // Without a number, the last rotation is used again.
+                            Err(ParseErr::Empty) => (),
+                            Err(ParseErr::NotANumber) => {
+                                let _ = send_string(&mut tx, "Rotation unchanged.");
+                            }
+                        }
+                        serial_cmd = Some(TextMode::Rot(rotation));
+                    }
+                    (NumberCommand::Rotation, _) => {
+                        let _ = send_string(&mut tx, "Rotation unchanged.");
+                    }
+                    (NumberCommand::Delay, b'\r') => {
+                        match parse_clamped(&entered, MIN_DELAY_MS, MAX_DELAY_MS) {
+                            Ok(entered) => {
+                                delay_ms = entered.value;
+                                let mut message: String<BUFFER_SIZE> = String::new();
+                                if entered.clamped {
+                                    write!(message, "Out of range. ").ok();
+                                }
+                                write!(
+                                    message,
+                                    "Loop delay {} ms, polling {} times a second.",
+                                    delay_ms,
+                                    1_000 / delay_ms
+                                )
+                                .ok();
+                                let mut code: String<BUFFER_SIZE> = String::new();
+                                write!(code, "@{}", delay_ms).ok();
+                                let _ = confirm(&mut tx, verbose, &code, &message);
+                            }
+                            Err(_) => {
+                                let _ = send_string(&mut tx, "Loop delay unchanged.");
+                            }
+                        }
+                    }
+                    (NumberCommand::Delay, _) => {
+                        let _ = send_string(&mut tx, "Loop delay unchanged.");
+                    }
                 }
             }
-            Ok(b'-') => {
-                if TextMode::ForceLower != text_mode {
-                    text_mode = TextMode::ForceLower;
-                    mode_change = true;
+            Ok(b'@') if line.is_empty() => {
+                number_entry = Some((NumberCommand::Delay, String::new()));
+                block!(tx.write(b'@')).ok();
+            }
+            Ok(b'&') if line.is_empty() => {
+                number_entry = Some((NumberCommand::Rotation, String::new()));
+                block!(tx.write(b'&')).ok();
+            }
+            Ok(c @ b'0'..=b'9') if repeat_pending => {
+                repeat_pending = false;
+                let mut digit = [0u8; 4];
+                match parse_clamped((c as char).encode_utf8(&mut digit), 0, MAX_REPETITIONS) {
+                    Ok(count) => {
+                        repetitions = count.value as usize;
+                        let mut message: String<BUFFER_SIZE> = String::new();
+                        if count.clamped {
+                            write!(message, "Out of range. ").ok();
+                        }
+                        write!(message, "Echo each line {} times.", repetitions).ok();
+                        let mut code: String<BUFFER_SIZE> = String::new();
+                        write!(code, "x{}", repetitions).ok();
+                        let _ = confirm(&mut tx, verbose, &code, &message);
+                    }
+                    Err(_) => {
+                        let _ = send_string(&mut tx, "Expected a repetition count after x.");
+                    }
                 }
             }
-            Ok(b'~') => {
-                if TextMode::InvertedCase != text_mode {
-                    text_mode = TextMode::InvertedCase;
-                    mode_change = true;
+            Ok(b'x') if line.is_empty() => repeat_pending = true,
+            Ok(b'?') => {
+                let _ = send_help_text(&mut tx, local_echo, rotation);
+            }
+            Ok(b'=') => serial_cmd = Some(TextMode::NormalCase),
+            Ok(b'+') => serial_cmd = Some(TextMode::ForceUpper),
+            Ok(b'-') => serial_cmd = Some(TextMode::ForceLower),
+            Ok(b'~') => serial_cmd = Some(TextMode::InvertedCase),
+            Ok(b'$') => serial_cmd = Some(TextMode::Leet),
+            Ok(b'!') => {
+                local_echo = !local_echo;
+                let _ = match local_echo {
+                    true => confirm(&mut tx, verbose, "!1", "Local echo on."),
+                    false => confirm(&mut tx, verbose, "!0", "Local echo off."),
+                };
+            }
+            Ok(b'%') => {
+                counts = match counts {
+                    Some(_) => {
+                        let _ = confirm(&mut tx, verbose, "%0", "Counting off.");
+                        None
+                    }
+                    None => {
+                        let _ = confirm(&mut tx, verbose, "%1", "Counting on.");
+                        Some(LineCounts::default())
+                    }
+                };
+            }
+            Ok(FORM_FEED) => match use_color {
+                true => {
+                    clear_screen(&mut tx).ok();
+                    do_flush_buffer = true;
+                }
+                false => {
+                    let _ = send_string(&mut tx, "Clearing the screen needs color on.");
+                }
+            },
+            Ok(b'*') => {
+                dedup = match dedup {
+                    Some(_) => {
+                        let _ = confirm(&mut tx, verbose, "*0", "Repeated lines echoed.");
+                        None
+                    }
+                    None => {
+                        let _ = confirm(&mut tx, verbose, "*1", "Repeated lines suppressed.");
+                        Some(None)
+                    }
+                };
+            }
+            Ok(b'#') => {
+                use_color = !use_color;
+                let _ = match use_color {
+                    true => confirm_colored(&mut tx, verbose, "#1", "Color on.", use_color),
+                    false => confirm(&mut tx, verbose, "#0", "Color off."),
+                };
+            }
+            Ok(b'^') => {
+                verbose = !verbose;
+                let _ = confirm(&mut tx, verbose, "^0", "Verbose confirmations.");
+            }
+            Ok(b'|') if line.is_empty() => serial_cmd = Some(TextMode::HexDump),
+            Ok(b'T') if line.is_empty() => {
+                let (released, elapsed_us) = run_benchmark(&mut tx, delay);
+                delay = released;
+                let _ = send_benchmark_result(&mut tx, elapsed_us);
+            }
+            Ok(b'\'') if line.is_empty() => {
+                let last = last_line.as_bytes();
+                if !last.is_empty() {
+                    let _ = flush_buffer(&mut tx, last, last.len(), &text_mode, repetitions);
+                    block!(tx.write(b'\r')).ok();
+                    block!(tx.write(b'\n')).ok();
                 }
             }
             Ok(b'\r') => {
                 do_flush_buffer = true;
                 reset_buffer = true;
             }
+            Ok(BACKSPACE | DELETE) => {
+                // Step back, overwrite the character with a space, and step back again.
+                if line.pop() && local_echo {
+                    for c in [BACKSPACE, b' ', BACKSPACE] {
+                        block!(tx.write(c)).ok();
+                    }
+                }
+            }
             Ok(c) => {
-                if index < BUFFER_SIZE {
-                    buffer[index] = c;
-                    index += 1;
+                if line.push(c, effective_limit(&text_mode, BUFFER_SIZE)) {
                     // Echo back the received character.
-                    block!(tx.write(convert_case(c, &text_mode))).ok();
+                    if let Some(echo) = echo_byte(c, &text_mode, local_echo) {
+                        block!(tx.write(echo)).ok();
+                    }
                 }
             }
             Err(nb::Error::WouldBlock) => (),
             Err(_) => (),
         }
-        let button_state = button.is_low();
-        if button_state && !button_down {
-            // Button was just pressed. Cycle through the text modes.
-            text_mode = match text_mode {
-                TextMode::NormalCase => TextMode::ForceUpper,
-                TextMode::ForceUpper => TextMode::ForceLower,
-                TextMode::ForceLower => TextMode::InvertedCase,
-                TextMode::InvertedCase => TextMode::NormalCase,
-            };
-            mode_change = true;
+        // The button is sampled once per pass, at least `delay_ms` apart.
+        let now_ms = millis();
+        let elapsed_ms = now_ms.wrapping_sub(last_sample_ms);
+        last_sample_ms = now_ms;
+        let button_event =
+            Some(ButtonEvent::Pressed) == debouncer.update(button.is_pressed(), elapsed_ms);
+        let (next_mode, mode_change) = resolve_mode(text_mode, serial_cmd, button_event);
+        // Leaving hex dump mode finishes the partial row, instead of redrawing its
+        // bytes as text in the next mode.
+        if mode_change && TextMode::HexDump == text_mode && !line.is_empty() {
+            let _ = flush_buffer(&mut tx, line.as_bytes(), line.len(), &text_mode, 1);
+            block!(tx.write(b'\r')).ok();
+            block!(tx.write(b'\n')).ok();
+            line.clear();
         }
-        button_down = button_state;
+        text_mode = next_mode;
         let led_mode: LedMode = (&text_mode).into();
-        led_mode.control_led(&mut led, counter);
+        match led_enabled {
+            true => led_mode.control_led(&mut led, counter),
+            false => led.set_low(),
+        }
         if mode_change {
-            let _ = match text_mode {
-                TextMode::NormalCase => send_string(&mut tx, "Use normal case."),
-                TextMode::ForceUpper => send_string(&mut tx, "Force upper case."),
-                TextMode::ForceLower => send_string(&mut tx, "Force lower case."),
-                TextMode::InvertedCase => send_string(&mut tx, "Use inverted case."),
-            };
-            mode_change = false;
+            let mut terse: String<BUFFER_SIZE> = String::new();
+            let mut full: String<BUFFER_SIZE> = String::new();
+            mode_confirmation(&text_mode, &mut terse, &mut full).ok();
+            let _ = confirm_colored(&mut tx, verbose, &terse, &full, use_color);
             do_flush_buffer = true;
         }
-        if do_flush_buffer && 0 < index {
-            let _ = flush_buffer(&mut tx, &buffer, index, &text_mode);
+        // A completed line with the same hash as the one before it is not echoed.
+        let mut repeated_line = false;
+        if let (Some(last_hash), true) = (dedup.as_mut(), reset_buffer && !line.is_empty()) {
+            let hash = fnv1a(line.as_bytes());
+            repeated_line = Some(hash) == *last_hash;
+            *last_hash = Some(hash);
+        }
+        if do_flush_buffer && !line.is_empty() {
+            // A mode change redraws the line in progress once, a completed line is
+            // echoed as many times as requested.
+            let times = match (reset_buffer, repeated_line) {
+                _ if TextMode::HexDump == text_mode => 1,
+                (true, true) => 0,
+                (true, false) => repetitions,
+                (false, _) => 1,
+            };
+            let _ = flush_buffer(&mut tx, line.as_bytes(), line.len(), &text_mode, times);
         }
         do_flush_buffer = false;
-        if reset_buffer && 0 < index {
-            index = 0; // Reset buffer index
+        if reset_buffer && !line.is_empty() {
+            last_line.store(line.as_bytes());
             block!(tx.write(b'\r')).ok();
             block!(tx.write(b'\n')).ok();
         }
+        if reset_buffer {
+            if let Some(counts) = counts.as_mut() {
+                counts.add_line(line.as_bytes());
+                let _ = send_counts(&mut tx, counts);
+            }
+            line.clear();
+        }
         reset_buffer = false;
 
         // Simple rate limiting
-        delay.delay_ms(DELAY_MS);
-        counter = (counter + DELAY_MS) % DELAY_COUNTER_MAX;
+        delay.delay_ms(delay_ms);
+        counter = (counter + delay_ms) % DELAY_COUNTER_MAX;
     }
 }
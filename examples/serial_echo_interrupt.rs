@@ -0,0 +1,141 @@
+// examples/serial_echo_interrupt.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example echoes text received over USART2 through `convert_case`, like
+//! `serial_echo`, but receives it with `SerialRx` from
+//! `hello_nucleo_f103rb::serial_rx`, so no character is missed while the main loop
+//! is stuck in a delay.
+//!
+//! The main loop blinks the onboard LED LD2 with a blocking `BLINK_MS` delay, far
+//! longer than the 87us between bytes at 115200 baud. The USART2 interrupt queues
+//! every byte as it arrives, and each pass echoes everything queued during the
+//! delay, so typed text shows up in bursts, but complete. Only a paste of more than
+//! `RX_QUEUE_SIZE - 1` bytes within one delay loses anything, the oldest bytes
+//! first, and the number lost is reported over RTT.
+//!
+//! User button B1 cycles the text conversion mode. The button is only read once
+//! per pass, so hold it until the LED toggles.
+
+use core::fmt::Write;
+use cortex_m::peripheral::NVIC;
+use cortex_m_rt::entry;
+use heapless::String;
+use hello_nucleo_f103rb::{
+    board::Board,
+    serial_rx::SerialRx,
+    text::{convert_case, TextMode},
+};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    pac::{interrupt, Interrupt, USART2},
+    prelude::*,
+    serial::{Config, Serial, Tx},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const BLINK_MS: u32 = 500;
+const RX_QUEUE_SIZE: usize = 256;
+
+static RX: SerialRx<USART2, RX_QUEUE_SIZE> = SerialRx::new();
+
+#[interrupt]
+fn USART2() {
+    RX.on_interrupt();
+}
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, the delay provider, the onboard LED LD2 and user button B1.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut delay = board.delay;
+    let mut led = board.led;
+    let button = board.button;
+    let mut gpioa = board.gpioa;
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    RX.init(rx);
+    // Unmasking an interrupt is unsafe because it can break critical sections,
+    // but all shared state is only accessed through `Shared`.
+    #[allow(unsafe_code)]
+    unsafe {
+        NVIC::unmask(Interrupt::USART2);
+    }
+
+    send_start_message(&mut tx);
+    send_string(
+        &mut tx,
+        "Type or paste text, it is echoed even during LED delays.",
+    );
+    send_string(
+        &mut tx,
+        "Hold user button B1 to cycle through text conversion modes.",
+    );
+
+    let mut button_down = false;
+    let mut text_mode = TextMode::NormalCase;
+    loop {
+        while let Some(c) = RX.dequeue() {
+            match c {
+                b'\r' => {
+                    block!(tx.write(b'\r')).ok();
+                    block!(tx.write(b'\n')).ok();
+                }
+                c => {
+                    block!(tx.write(convert_case(c, &text_mode))).ok();
+                }
+            }
+        }
+        match RX.take_dropped() {
+            0 => (),
+            dropped => rprintln!("Dropped {} received bytes.", dropped),
+        }
+
+        let button_state = button.is_pressed();
+        if button_state && !button_down {
+            text_mode = text_mode.next();
+            rprintln!("Text mode changed.");
+        }
+        button_down = button_state;
+
+        led.toggle();
+        delay.delay_ms(BLINK_MS);
+    }
+}
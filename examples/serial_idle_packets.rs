@@ -0,0 +1,185 @@
+// examples/serial_idle_packets.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example frames packets on USART2 by idle time instead of a delimiter byte,
+//! which suits binary protocols where any byte value can appear in a packet.
+//!
+//! The USART sets its IDLE flag once the line has stayed high for a whole frame
+//! after a received byte, about 87us at 115200 baud. The USART2 interrupt listens
+//! for both received bytes and the idle line. Received bytes are appended to the
+//! packet being assembled. When the line goes idle after a burst, the packet is
+//! complete, so the handler moves it into the ready slot, which flags it for the
+//! main loop, and starts a new packet. The main loop reports each packet as its
+//! length and the bytes in hexadecimal.
+//!
+//! The IDLE flag is cleared by reading SR and then DR. Reading a received byte does
+//! exactly that, so the handler always drains the received bytes before checking
+//! for idle, and only then clears the flag. Once the line is idle, no byte is
+//! waiting in DR, so the read that clears the flag does not lose data.
+//!
+//! Bytes beyond `PACKET_SIZE` are dropped and counted. A packet that completes
+//! while the previous one has not been reported yet replaces it in the ready slot,
+//! so the main loop always reports the latest packet, and the older one is counted
+//! as dropped. Pasting text
+//! into a terminal sends it in one burst, so it arrives as a single packet, and
+//! each typed key arrives as a packet of its own.
+
+use core::fmt::Write;
+use cortex_m::peripheral::NVIC;
+use cortex_m_rt::entry;
+use heapless::{String, Vec};
+use hello_nucleo_f103rb::{board::Board, shared::Shared};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    pac::{interrupt, Interrupt, USART2},
+    prelude::*,
+    serial::{Config, Rx, Serial, Tx},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const PACKET_SIZE: usize = 64;
+// Room for the packet length and three characters per byte.
+const REPORT_SIZE: usize = 32 + 3 * PACKET_SIZE;
+
+type Packet = Vec<u8, PACKET_SIZE>;
+
+/// Bytes and packets dropped because there was no room for them.
+#[derive(Clone, Copy, Default)]
+struct Dropped {
+    bytes: u32,
+    packets: u32,
+}
+
+/// Packet assembly state, owned by the interrupt handler.
+struct Assembler {
+    rx: Rx<USART2>,
+    packet: Packet,
+}
+
+static G_ASSEMBLER: Shared<Option<Assembler>> = Shared::new(None);
+static G_READY: Shared<Option<Packet>> = Shared::new(None);
+static G_DROPPED: Shared<Dropped> = Shared::new(Dropped {
+    bytes: 0,
+    packets: 0,
+});
+
+#[interrupt]
+fn USART2() {
+    G_ASSEMBLER.with(|assembler| {
+        let Some(assembler) = assembler.as_mut() else {
+            return;
+        };
+        // Reading a byte also clears an overrun, so errors are simply skipped.
+        while assembler.rx.is_rx_not_empty() {
+            if let Ok(byte) = assembler.rx.read() {
+                if assembler.packet.push(byte).is_err() {
+                    G_DROPPED.with(|dropped| dropped.bytes += 1);
+                }
+            }
+        }
+        if assembler.rx.is_idle() {
+            // Reads SR, then DR, which clears the IDLE flag.
+            assembler.rx.clear_idle_interrupt();
+            if !assembler.packet.is_empty() {
+                let packet = core::mem::take(&mut assembler.packet);
+                let replaced = G_READY.with(|ready| ready.replace(packet));
+                if replaced.is_some() {
+                    G_DROPPED.with(|dropped| dropped.packets += 1);
+                }
+            }
+        }
+    });
+}
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_packet(tx: &mut Tx<USART2>, packet: &[u8], dropped: Dropped) {
+    let mut buffer: String<REPORT_SIZE> = String::new();
+    write!(buffer, "Packet of {} bytes:", packet.len()).unwrap();
+    for byte in packet {
+        write!(buffer, " {:02X}", byte).unwrap();
+    }
+    send_string(tx, &buffer);
+    if 0 < dropped.bytes || 0 < dropped.packets {
+        let mut buffer: String<BUFFER_SIZE> = String::new();
+        write!(
+            buffer,
+            "Dropped {} bytes and {} packets so far.",
+            dropped.bytes, dropped.packets
+        )
+        .unwrap();
+        send_string(tx, &buffer);
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, and acquire the GPIOA pins.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut gpioa = board.gpioa;
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, mut rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    send_start_message(&mut tx);
+    send_string(
+        &mut tx,
+        "Send bytes over USART. Each burst is reported as one packet.",
+    );
+
+    // Interrupt on received bytes and on the idle line after them.
+    rx.listen();
+    rx.listen_idle();
+    G_ASSEMBLER.lock_set(Some(Assembler {
+        rx,
+        packet: Vec::new(),
+    }));
+
+    // Unmasking an interrupt is unsafe because it can break critical sections,
+    // but all shared state is only accessed through `Shared`.
+    #[allow(unsafe_code)]
+    unsafe {
+        NVIC::unmask(Interrupt::USART2);
+    }
+
+    loop {
+        cortex_m::asm::wfi();
+        if let Some(packet) = G_READY.with(|ready| ready.take()) {
+            send_packet(&mut tx, &packet, G_DROPPED.lock_get());
+        }
+    }
+}
@@ -5,51 +5,492 @@
 #![no_main]
 
 use core::fmt::Write;
-use cortex_m_rt::entry;
-use heapless::String;
-use nb::block;
+use cortex_m_rt::{entry, exception};
+use heapless::{String, Vec};
+use hello_nucleo_f103rb::{
+    adc::compute_vdda_mv,
+    base64::{base64_encode, encoded_len},
+    board::{Board, ClockProfile},
+    demo::{DemoPlayer, DemoStep},
+    device_id::device_id,
+    diag::{diag_digits, DiagPlayer, DiagScreen},
+    font::{message_column, GLYPH_HEIGHT, GLYPH_WIDTH},
+    heartbeat::{Heartbeat, HeartbeatMessage},
+    hour_clock::{in_quiet_hours, parse_hour, HourClock, HOUR_MS},
+    led_controller::{
+        physical_mask, Command, LedController, StrobeStyle, ALL_LEDS_MASK, BLINK_LEDS,
+        CONTROLLED_LEDS, LED_COUNT, STATIC_LEDS, STROBE_LEDS, STROBE_MS,
+    },
+    led_timing::{
+        parse_sequence, period_to_hz, FlashCounter, Monostable, SequencePlayer, StrobeSweep,
+        SweepStep,
+    },
+    memory_test::walking_bit_test,
+    menu::Menu,
+    millis::{self, micros, millis},
+    num_format::{format_num, NumFormat, NUM_FORMAT_SIZE},
+    parse::{parse_clamped, parse_index, ClampedArg},
+    pins::{led_output, BounceMeter},
+    report::{ack_line, write_selftest_report, CheckResult, CmdResult},
+    rng::{seed_from, xorshift32},
+    settings::{load_settings, save_settings},
+    soft_pwm::{gamma_correct, SoftPwm},
+    stack::{paint_stack, stack_size, unused_stack_words},
+    sysex::{
+        led_event, parse_sysex, SysexAssembler, SysexMsg, SYSEX_BLINK_BIT, SYSEX_START,
+        SYSEX_STATIC_BIT, SYSEX_STROBE_BIT,
+    },
+    tempo::{interval_to_bpm, TempoMeter},
+    text::{convert_case, ruler, TextMode},
+    tx_queue::{Throttle, TxQueue},
+};
+#[cfg(not(feature = "panic-sos"))]
 use panic_halt as _;
 use rtt_target::{rprintln, rtt_init_print};
 use stm32f1xx_hal::{
+    adc::{Adc, SampleTime},
+    flash::{FlashSize, SectorSize},
     gpio::{ErasedPin, Output},
-    pac,
-    pac::USART2,
+    pac::ADC1,
     prelude::*,
-    serial::{Config, Serial, Tx},
+    rcc::Clocks,
+    serial::{Config, Serial},
 };
 
 const BOARD: &str = "Nucleo-F103RB";
-const BLINK_MS: u32 = 500;
-const STROBE_MS: u32 = 50;
 const BUFFER_SIZE: usize = 128;
+// Large enough to queue the whole help text at once.
+const TX_QUEUE_SIZE: usize = 3584;
+const RULER_WIDTH: usize = 80;
+const PROMPT: &str = "> ";
+// A full test of this many words takes a few milliseconds, short enough not to
+// visibly disturb the LED patterns.
+const MEMORY_TEST_WORDS: usize = 256;
+const DEMO_STEP_MS: u32 = 3_000;
+// Sparkle picks a new random set of lit LEDs this often.
+const SPARKLE_MS: u32 = 100;
+const RX_PULSE_MS: u32 = 30;
+const HEARTBEAT_MIN_MS: u32 = 100;
+const HEARTBEAT_MAX_MS: u32 = HOUR_MS;
+const RX_PULSE_MAX_MS: u32 = 1_000;
+const MS_PER_SECOND: u32 = 1_000;
+// The onboard LED is the first controlled LED, and the last bit of `LedBanks::mask`.
+const ONBOARD_LED_BIT: u32 = 1 << (LED_COUNT - 1);
+// A tenth of a second per byte is slow enough for almost anything.
+const THROTTLE_MAX_US: u32 = 100_000;
+// The status blob is a format byte, the 12 byte device ID, and the major, minor and
+// patch version, 16 bytes that encode to 24 base64 characters.
+const STATUS_BLOB_FORMAT: u8 = 1;
+const STATUS_BLOB_SIZE: usize = 16;
+const FIRMWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+// The main loop runs many thousands of times a second, so even with this step the
+// software PWM period is short enough not to flicker.
+const SOFT_PWM_STEP: u8 = 16;
+// The onboard LED draws each POV column as one row at a time, and `millis()` ticks
+// once per millisecond, so a column needs at least one tick per row.
+const POV_COLUMN_MIN_MS: u32 = GLYPH_HEIGHT as u32;
+const POV_COLUMN_MAX_MS: u32 = 200;
+const POV_COLUMN_MS: u32 = 14;
+const POV_MESSAGE_SIZE: usize = 32;
+// Blank columns after the message, so the repeats can be told apart.
+const POV_GAP_COLUMNS: usize = 12;
+// VDDA is nominally 3.3V. The range allows for the supply tolerance, and for the
+// spread of VREFINT, which is uncalibrated on this part.
+const SELFTEST_VDDA_MIN_MV: u16 = 3_000;
+const SELFTEST_VDDA_MAX_MV: u16 = 3_600;
+// Long enough to see every LED light up during the self test.
+const SELFTEST_LED_MS: u32 = 200;
+// Holding B1 this long steps through the diagnostic screens. It is deliberately
+// longer than `pins::LONG_PRESS_MS`, because holding B1 is also how the controlled
+// LEDs are lit, and that should not step the screens after a moment.
+const DIAG_HOLD_MS: u32 = 1_500;
+// The software PWM period is 256 / `SOFT_PWM_STEP` passes, which has to stay above
+// about 100 Hz not to flicker.
+const HEALTHY_LOOP_HZ: u32 = 100 * (256 / SOFT_PWM_STEP as u32);
+/// Detects the button being held for `DIAG_HOLD_MS`, once per press.
+struct DiagHold {
+    pressed_ms: Option<u32>,
+    reported: bool,
+}
+
+impl DiagHold {
+    fn new() -> Self {
+        DiagHold {
+            pressed_ms: None,
+            reported: false,
+        }
+    }
 
-fn set_leds(led_set: &mut [ErasedPin<Output>], led_on: bool) {
-    if led_on {
-        for led in led_set {
-            led.set_high();
+    /// Sample the button. Returns true once the current press has been held long
+    /// enough.
+    fn update(&mut self, now_ms: u32, button_down: bool) -> bool {
+        if !button_down {
+            self.pressed_ms = None;
+            self.reported = false;
+            return false;
         }
-    } else {
-        for led in led_set {
-            led.set_low();
+        let pressed_ms = *self.pressed_ms.get_or_insert(now_ms);
+        if self.reported || now_ms.wrapping_sub(pressed_ms) < DIAG_HOLD_MS {
+            return false;
+        }
+        self.reported = true;
+        true
+    }
+}
+
+const DEMO_STEPS: [DemoStep; 5] = [
+    DemoStep {
+        enables: SYSEX_STATIC_BIT,
+        sparkle: false,
+        text_mode: TextMode::NormalCase,
+        duration_ms: DEMO_STEP_MS,
+        description: "Demo: Static LEDs, normal case text.",
+    },
+    DemoStep {
+        enables: SYSEX_BLINK_BIT,
+        sparkle: false,
+        text_mode: TextMode::ForceUpper,
+        duration_ms: DEMO_STEP_MS,
+        description: "Demo: Blinking LEDs, upper case text.",
+    },
+    DemoStep {
+        enables: SYSEX_STROBE_BIT,
+        sparkle: false,
+        text_mode: TextMode::ForceLower,
+        duration_ms: DEMO_STEP_MS,
+        description: "Demo: Strobing LEDs, lower case text.",
+    },
+    DemoStep {
+        enables: SYSEX_STATIC_BIT | SYSEX_BLINK_BIT | SYSEX_STROBE_BIT,
+        sparkle: false,
+        text_mode: TextMode::InvertedCase,
+        duration_ms: DEMO_STEP_MS,
+        description: "Demo: All LED patterns together, inverted case text.",
+    },
+    DemoStep {
+        enables: 0,
+        sparkle: true,
+        text_mode: TextMode::NormalCase,
+        duration_ms: DEMO_STEP_MS,
+        description: "Demo: Sparkling LEDs.",
+    },
+];
+
+/// Lights a random set of LEDs, chosen again every `SPARKLE_MS`.
+/// The pattern comes from `xorshift32`, so the same seed always sparkles the same way.
+struct Sparkle {
+    state: u32,
+    mask: u32,
+    last_ms: Option<u32>,
+    running: bool,
+}
+
+impl Sparkle {
+    fn new() -> Self {
+        Sparkle {
+            state: 1,
+            mask: 0,
+            last_ms: None,
+            running: false,
+        }
+    }
+
+    fn start(&mut self, seed: u32) {
+        self.state = seed;
+        self.last_ms = None;
+        self.running = true;
+    }
+
+    fn stop(&mut self) {
+        self.running = false;
+    }
+
+    fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Returns a bit mask of the LEDs lit at `now_ms`, or `None` if not running.
+    fn update(&mut self, now_ms: u32) -> Option<u32> {
+        if !self.running {
+            return None;
+        }
+        match self.last_ms {
+            Some(last_ms) if now_ms.wrapping_sub(last_ms) < SPARKLE_MS => (),
+            _ => {
+                self.mask = xorshift32(&mut self.state);
+                self.last_ms = Some(now_ms);
+            }
+        }
+        Some(self.mask)
+    }
+}
+
+/// Parse the index of an LED, 0 to `LED_COUNT - 1`, in the bit order of
+/// `LedBanks::mask`. It selects an LED, so out of range is rejected, not clamped.
+fn parse_led_index(s: &str) -> Option<usize> {
+    parse_index(s, LED_COUNT)
+}
+
+/// A persistence of vision message, repeated until stopped. Wave the board, and
+/// the message appears in the air, one column of the font every `column_ms`.
+///
+/// With LEDs 0 to 6 lined up from top to bottom, they show a whole column at once,
+/// so a sideways sweep draws the text. The onboard LED alone can only show one
+/// pixel at a time, so it draws each column top to bottom, one row at a time, and
+/// needs a quick up and down wave while sweeping sideways, or a long exposure photo.
+struct Pov {
+    message: String<POV_MESSAGE_SIZE>,
+    column_ms: u32,
+    start_ms: u32,
+}
+
+impl Pov {
+    fn new(now_ms: u32, message: String<POV_MESSAGE_SIZE>, column_ms: u32) -> Self {
+        Pov {
+            message,
+            column_ms,
+            start_ms: now_ms,
+        }
+    }
+
+    /// The column shown at `now_ms`, and whether the onboard LED is lit for its
+    /// current row.
+    fn frame(&self, now_ms: u32) -> (u8, bool) {
+        let width = GLYPH_WIDTH as usize + 1;
+        let columns = self.message.len() * width + POV_GAP_COLUMNS;
+        let elapsed_ms = now_ms.wrapping_sub(self.start_ms);
+        let column = message_column(
+            self.message.as_bytes(),
+            (elapsed_ms / self.column_ms) as usize % columns,
+        );
+        let row = elapsed_ms % self.column_ms * GLYPH_HEIGHT as u32 / self.column_ms;
+        (column, 0 != column & (1 << row))
+    }
+}
+
+/// Light every LED for `SELFTEST_LED_MS`, then turn them all off, checking each
+/// time that the output register reads back as written. This cannot tell if an
+/// LED is missing or broken, so watch them flash too. The main loop stops while
+/// the LEDs are lit.
+fn check_leds(banks: &mut LedBanks) -> CheckResult {
+    let all = ALL_LEDS_MASK;
+    banks.set_mask(all);
+    let lit = banks.mask();
+    let lit_ms = millis();
+    while millis().wrapping_sub(lit_ms) < SELFTEST_LED_MS {}
+    banks.set_mask(0);
+    match (lit, banks.mask()) {
+        (lit, _) if lit != all => Err("an LED output did not turn on"),
+        (_, 0) => Ok(()),
+        _ => Err("an LED output did not turn off"),
+    }
+}
+
+/// Checks that the system clock runs at the frequency `profile` asked for.
+fn check_clock(clocks: &Clocks, profile: ClockProfile) -> CheckResult {
+    match clocks.sysclk().raw() == profile.sysclk_hz() {
+        true => Ok(()),
+        false => Err(match profile {
+            ClockProfile::Hsi8 => "system clock is not 8MHz",
+            ClockProfile::Hse8Sysclk48 => "system clock is not 48MHz",
+            ClockProfile::Hse8Sysclk72 => "system clock is not 72MHz",
+        }),
+    }
+}
+
+fn check_vrefint(adc: &mut Adc<ADC1>) -> CheckResult {
+    match compute_vdda_mv(adc.read_vref()) {
+        SELFTEST_VDDA_MIN_MV..=SELFTEST_VDDA_MAX_MV => Ok(()),
+        _ => Err("VDDA measured with VREFINT is out of range"),
+    }
+}
+
+fn check_button(button_down: bool) -> CheckResult {
+    match button_down {
+        false => Ok(()),
+        true => Err("button B1 reads pressed, release it and try again"),
+    }
+}
+
+/// What a menu selection does.
+#[derive(Clone, Copy, PartialEq)]
+enum MenuAction {
+    Led(Command),
+    DeviceId,
+    Exit,
+}
+
+const MENU_ITEMS: [(char, &str, MenuAction); 9] = [
+    ('1', "Enable all LEDs", MenuAction::Led(Command::EnableAll)),
+    (
+        '2',
+        "Disable all LEDs",
+        MenuAction::Led(Command::DisableAll),
+    ),
+    (
+        '3',
+        "Toggle static LED",
+        MenuAction::Led(Command::ToggleStatic),
+    ),
+    (
+        '4',
+        "Toggle blinking LED",
+        MenuAction::Led(Command::ToggleBlink),
+    ),
+    (
+        '5',
+        "Toggle strobing LED",
+        MenuAction::Led(Command::ToggleStrobe),
+    ),
+    (
+        '6',
+        "Toggle controlled LED",
+        MenuAction::Led(Command::ToggleControlled),
+    ),
+    (
+        '7',
+        "Toggle LED control inversion",
+        MenuAction::Led(Command::ToggleInversion),
+    ),
+    ('8', "Display the unique device ID", MenuAction::DeviceId),
+    ('0', "Leave the menu", MenuAction::Exit),
+];
+
+/// The LED groups driven by `LedController`.
+struct LedBanks {
+    static_leds: [ErasedPin<Output>; STATIC_LEDS],
+    blink: [ErasedPin<Output>; BLINK_LEDS],
+    strobe: [ErasedPin<Output>; STROBE_LEDS],
+    controlled: [ErasedPin<Output>; CONTROLLED_LEDS],
+}
+
+impl LedBanks {
+    /// Light the LEDs whose bits are set in `mask`, in bank order, the onboard LED last.
+    fn set_mask(&mut self, mask: u32) {
+        let leds = self
+            .static_leds
+            .iter_mut()
+            .chain(self.blink.iter_mut())
+            .chain(self.strobe.iter_mut())
+            .chain(self.controlled.iter_mut().rev());
+        for (bit, led) in leds.enumerate() {
+            if 0 != mask & (1 << bit) {
+                led.set_high();
+            } else {
+                led.set_low();
+            }
         }
     }
+
+    /// The LEDs that are currently lit, in the same bit order as `set_mask`.
+    fn mask(&self) -> u32 {
+        let leds = self
+            .static_leds
+            .iter()
+            .chain(self.blink.iter())
+            .chain(self.strobe.iter())
+            .chain(self.controlled.iter().rev());
+        leds.enumerate()
+            .filter(|(_, led)| led.is_set_high())
+            .fold(0, |mask, (bit, _)| mask | (1 << bit))
+    }
+}
+
+/// `mask` with the LEDs in `bits` lit or unlit, and the others left as they are.
+fn with_leds(mask: u32, bits: u32, level: bool) -> u32 {
+    match level {
+        true => mask | bits,
+        false => mask & !bits,
+    }
 }
 
-fn send_string(tx: &mut Tx<USART2>, string: &str) {
+// Text is queued rather than written directly, so LED timing is not disrupted
+// while long messages are transmitted. Text that does not fit is dropped.
+fn send_string(tx: &mut TxQueue<TX_QUEUE_SIZE>, string: &str) {
     rprintln!("{}", string);
-    write!(tx, "\r{}\r\n", string).unwrap();
-    block!(tx.flush()).unwrap();
+    write!(tx, "\r{}\r\n", string).ok();
 }
 
-fn send_start_message(tx: &mut Tx<USART2>) {
+fn send_start_message(tx: &mut TxQueue<TX_QUEUE_SIZE>) {
     let mut buffer: String<BUFFER_SIZE> = String::new();
     write!(buffer, "Hello, {}!", BOARD).unwrap();
     send_string(tx, &buffer);
 }
 
-fn send_help_text(tx: &mut Tx<USART2>) {
+fn send_device_id(tx: &mut TxQueue<TX_QUEUE_SIZE>, num_format: NumFormat) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Device ID:").unwrap();
+    let mut separator = ' ';
+    for word in device_id() {
+        let mut number: String<NUM_FORMAT_SIZE> = String::new();
+        format_num(word, num_format, &mut number);
+        write!(buffer, "{}{}", separator, number).unwrap();
+        separator = '-';
+    }
+    send_string(tx, &buffer);
+}
+
+/// The device ID and firmware version packed for a provisioning app. The ID words
+/// are little endian, and a version part that is not a number below 256 is 0.
+fn status_blob(id: [u32; 3], version: &str) -> [u8; STATUS_BLOB_SIZE] {
+    let mut blob = [0; STATUS_BLOB_SIZE];
+    blob[0] = STATUS_BLOB_FORMAT;
+    for (bytes, word) in blob[1..13].chunks_exact_mut(4).zip(id) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    for (byte, part) in blob[13..].iter_mut().zip(version.split('.')) {
+        *byte = part.parse().unwrap_or(0);
+    }
+    blob
+}
+
+fn send_status_blob(tx: &mut TxQueue<TX_QUEUE_SIZE>) {
+    let mut encoded = [0; encoded_len(STATUS_BLOB_SIZE)];
+    let len = base64_encode(&status_blob(device_id(), FIRMWARE_VERSION), &mut encoded);
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    // Base64 is always ASCII.
+    let blob = core::str::from_utf8(&encoded[..len]).unwrap_or_default();
+    write!(buffer, "Status blob: {}", blob).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_num_format(tx: &mut TxQueue<TX_QUEUE_SIZE>, num_format: NumFormat) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Numbers are displayed in {}.", num_format.name()).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_stack_usage(tx: &mut TxQueue<TX_QUEUE_SIZE>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(
+        buffer,
+        "Stack: {} of {} bytes never used.",
+        4 * unused_stack_words(),
+        stack_size()
+    )
+    .unwrap();
+    send_string(tx, &buffer);
+}
+
+/// Warn that a numeric argument was out of range and has been clamped.
+fn send_clamped_warning(tx: &mut TxQueue<TX_QUEUE_SIZE>, arg: ClampedArg) {
+    if arg.clamped {
+        let mut buffer: String<BUFFER_SIZE> = String::new();
+        write!(buffer, "Out of range, using {}.", arg.value).unwrap();
+        send_string(tx, &buffer);
+    }
+}
+
+fn send_ruler(tx: &mut TxQueue<TX_QUEUE_SIZE>, width: usize) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    ruler(width, &mut buffer);
+    send_string(tx, &buffer);
+}
+
+fn send_help_text(tx: &mut TxQueue<TX_QUEUE_SIZE>) {
     let help_text = "\
 Hold user button B1 to activate controlled LED when enabled.\r\n\
+Hold B1 for 1.5 s to step through diagnostics flashed on the onboard LED.\r\n\
 The following LED control commands can be sent of USART:\r\n\
 0 - Disable all LEDs\r\n\
 1 - Enable all LEDs\r\n\
@@ -58,66 +499,134 @@ The following LED control commands can be sent of USART:\r\n\
 4 - Toggle strobing LED\r\n\
 5 - Toggle controlled LED\r\n\
 9 - Toggle LED control inversion\r\n\
-? - Display this help message\
+M - Run a walking bit RAM test\r\n\
+? - Display this help message\r\n\
+Commands starting with a letter are completed with enter, even a single letter,\r\n\
+like d, because longer commands start with the same letter:\r\n\
+ack - Toggle OK or ERR after every command, for scripts\r\n\
+b - Measure the bounce of the next button B1 press\r\n\
+d - Display the unique device ID\r\n\
+dim 30 - Dim the external LEDs to a perceived brightness from 0 to 100\r\n\
+dim - Restore the external LEDs to full brightness\r\n\
+demo - Cycle through LED patterns and text modes until a key is pressed\r\n\
+drops - Display and reset the number of bytes dropped by a full transmit queue\r\n\
+events - Toggle sending binary MIDI style note events when LEDs change\r\n\
+f 3 - Repeatedly flash a digit on the onboard LED, as a status code\r\n\
+f - Stop flashing the status code\r\n\
+hb 1000 - Send . every 1000 ms, so a host can tell the board is alive\r\n\
+hb 1000 alive - Send alive ms= with the uptime every 1000 ms instead\r\n\
+hb - Stop the heartbeat message\r\n\
+hb led 8 - Also pulse LED 0 to 8 on every heartbeat, 8 is the onboard LED\r\n\
+hb led off - Stop pulsing an LED with the heartbeat\r\n\
+i? - Explain when the controlled LED lights, with the current inversion\r\n\
+invert - Toggle inverting every LED output, for LEDs wired active low, saved\r\n\
+l on, l off - Force the onboard LED on or off, overriding everything else\r\n\
+l - Release the onboard LED\r\n\
+m - Choose commands from a numbered menu\r\n\
+n - Cycle numbers between decimal, hexadecimal and binary\r\n\
+pov HELLO - Repeat a message on LEDs 0 to 6 and the onboard LED, to wave in the air\r\n\
+pov ms 20 - Show each column of the message for 7 to 200 ms, 14 by default\r\n\
+pov - Stop the message\r\n\
+prompt - Toggle a > prompt after every command\r\n\
+qr - Display the device ID and firmware version as base64, to show as a QR code\r\n\
+quiet 22 6 - Force all LEDs off from 22:00 until 06:00, once time is set\r\n\
+quiet - Turn quiet hours off\r\n\
+rx - Toggle pulsing the onboard LED on every received byte, overriding patterns\r\n\
+rx 100 - Pulse the onboard LED for a number of ms on every received byte\r\n\
+selftest - Check the LEDs, clock, ADC and button, and report PASS or FAIL\r\n\
+seq 100,200,... - Loop the onboard LED through on/off durations in ms\r\n\
+seq - Stop the onboard LED sequence\r\n\
+stack - Display how much of the stack has never been used\r\n\
+sparkle - Toggle lighting random LEDs, overriding the LED groups\r\n\
+strobe all, strobe chase - Toggle the strobing LEDs together, or one at a time\r\n\
+sweep - Toggle sweeping the strobe from 5 Hz to 100 Hz, to find where flicker stops\r\n\
+tempo - Toggle measuring the tempo of B1 presses, flashing the onboard LED to it\r\n\
+throttle 1000 - Send at most one byte every 1000 us, for slow devices\r\n\
+throttle - Send at the full baud rate again\r\n\
+time 14 - Set the current hour of the day, for quiet hours\r\n\
+w, w 120 - Display a ruler to check the terminal width, 80 columns by default\r\n\
+Binary 0xF0 ... 0xF7 frames can also set all LED states at once.\
 ";
     send_string(tx, help_text);
 }
 
+#[exception]
+fn SysTick() {
+    millis::tick();
+}
+
 #[entry]
 fn main() -> ! {
-    // Access device specific peripherals, and acquire GPIOA, GPIOB GPIOC.
-    let dp = pac::Peripherals::take().unwrap();
-    let mut gpioa = dp.GPIOA.split();
-    let mut gpiob = dp.GPIOB.split();
-    let mut gpioc = dp.GPIOC.split();
+    // Paint the unused stack before any interrupt can use it, for the stack command.
+    paint_stack();
+
+    // Set up the clocks, the onboard LED LD2 and user button B1, and acquire GPIOA,
+    // GPIOB and GPIOC. The board also reads the reset flags for the diagnostics,
+    // then clears them, so the next reset is not mistaken for this one.
+    let board = Board::take();
+    let profile = board.profile;
+    let clocks = board.clocks;
+    let mut gpioa = board.gpioa;
+    let mut gpiob = board.gpiob;
+    let mut gpioc = board.gpioc;
+    let reset_cause = board.reset_cause;
 
-    // Configure GPIO pins as push-pull output.
+    // Configure GPIO pins as push-pull output, with the slow slew rate used for LEDs.
     // For pins 0-7, use `crl`, and for pins 8-15, use `crh`.
     // `erase()` removes the type so different pins can be collected in an array.
     //   Wire external LEDs as follows.
     //     GPIO Pin >---|>|---[R]--- GND
     //                  LED   Resistor
-    let mut leds_static = [
-        gpioa.pa7.into_push_pull_output(&mut gpioa.crl).erase(), // Arduino D11/PWM/MOSI
-        gpiob.pb6.into_push_pull_output(&mut gpiob.crl).erase(), // Arduino D10/PWM/CS
-        gpioc.pc7.into_push_pull_output(&mut gpioc.crl).erase(), // Arduino D9/PWM
+    let static_leds = [
+        led_output(gpioa.pa7, &mut gpioa.crl).erase(), // Arduino D11/PWM/MOSI
+        led_output(gpiob.pb6, &mut gpiob.crl).erase(), // Arduino D10/PWM/CS
+        led_output(gpioc.pc7, &mut gpioc.crl).erase(), // Arduino D9/PWM
     ];
-    let mut leds_blink = [
-        gpiob.pb10.into_push_pull_output(&mut gpiob.crh).erase(), // Arduino D6/PWM
-        gpioa.pa8.into_push_pull_output(&mut gpioa.crh).erase(),  // Arduino D7
+    let blink = [
+        led_output(gpiob.pb10, &mut gpiob.crh).erase(), // Arduino D6/PWM
+        led_output(gpioa.pa8, &mut gpioa.crh).erase(),  // Arduino D7
     ];
-    let mut leds_strobe = [
-        gpioa.pa9.into_push_pull_output(&mut gpioa.crh).erase(), // Arduino D8
-        gpiob.pb5.into_push_pull_output(&mut gpiob.crl).erase(), // Arduino D4
+    let strobe = [
+        led_output(gpioa.pa9, &mut gpioa.crh).erase(), // Arduino D8
+        led_output(gpiob.pb5, &mut gpiob.crl).erase(), // Arduino D4
     ];
-    let mut leds_controlled = [
-        gpioa.pa5.into_push_pull_output(&mut gpioa.crl).erase(), // On Board LED LD2
-        gpioa.pa10.into_push_pull_output(&mut gpioa.crh).erase(), // Arduino D2
+    let controlled = [
+        board.led,                                      // On Board LED LD2
+        led_output(gpioa.pa10, &mut gpioa.crh).erase(), // Arduino D2
     ];
+    let mut banks = LedBanks {
+        static_leds,
+        blink,
+        strobe,
+        controlled,
+    };
 
     // Acquire read-only user button B1, not mutable.
-    let button = gpioc.pc13;
+    let button = board.button;
 
-    // Take ownership of raw flash and rcc devices.
-    let mut flash = dp.FLASH.constrain();
-    let rcc = dp.RCC.constrain();
+    // Load the saved settings, falling back to the defaults for blank flash. Only
+    // the LED inversion is used here, the rest belongs to `shell`.
+    let mut flash = board.flash;
+    let mut flash_writer = flash.writer(SectorSize::Sz1K, FlashSize::Sz128K);
+    let mut settings = load_settings(&flash_writer).unwrap_or_default();
 
-    // Set up system clock and configure delay provider.
-    let clocks = rcc
-        .cfgr
-        .use_hse(8.MHz())
-        .sysclk(48.MHz())
-        .freeze(&mut flash.acr);
-    let mut delay = dp.TIM2.delay_us(&clocks);
+    // ADC1 is only used by the self test, to measure VREFINT, which needs a long
+    // sample time, see section 5.3.4 of the datasheet.
+    let mut adc1 = Adc::adc1(board.adc1, clocks);
+    adc1.set_sample_time(SampleTime::T_239);
+
+    // Start the millisecond time base used for LED timing.
+    let cp = cortex_m::Peripherals::take().unwrap();
+    millis::init(cp.SYST, &clocks);
 
     // Acquire alternate function input/output (AFIO).
-    let mut afio = dp.AFIO.constrain();
+    let mut afio = board.afio.constrain();
 
     // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
     let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
     let rx = gpioa.pa3;
     let serial = Serial::new(
-        dp.USART2,
+        board.usart2,
         (tx, rx),
         &mut afio.mapr,
         Config::default().baudrate(115200.bps()),
@@ -129,118 +638,738 @@ fn main() -> ! {
     // Also using RTT in when writing text to USART.
     rtt_init_print!();
 
-    send_start_message(&mut tx);
-    send_help_text(&mut tx);
-
-    let mut counter: u32 = 0;
-    let static_on: bool = true;
-    let mut static_enable: bool = true;
-    let mut blink_on: bool;
-    let mut blink_enable: bool = true;
-    let mut strobe_on: bool = false;
-    let mut strobe_enable: bool = true;
-    let mut controlled_on: bool = false;
-    let mut controlled_enable: bool = true;
-    let mut controlled_inversion: bool = false;
+    let mut tx_queue: TxQueue<TX_QUEUE_SIZE> = TxQueue::new();
+    send_start_message(&mut tx_queue);
+    #[cfg(not(feature = "quiet-boot"))]
+    send_help_text(&mut tx_queue);
+
+    let mut controller = LedController::new();
+    let mut sysex = SysexAssembler::new();
+    let mut command_line: String<BUFFER_SIZE> = String::new();
+    let mut sequence = SequencePlayer::new();
+    let mut flash_counter = FlashCounter::new();
+    let mut onboard_override: Option<bool> = None;
+    let mut rx_activity: Option<Monostable> = None;
+    let mut heartbeat: Option<Heartbeat> = None;
+    let mut sparkle = Sparkle::new();
+    let mut pov: Option<Pov> = None;
+    let mut pov_column_ms = POV_COLUMN_MS;
+    let mut demo = DemoPlayer::new(&DEMO_STEPS);
+    let menu = Menu::new(&MENU_ITEMS);
+    let mut in_menu = false;
+    let mut bounce_meter = BounceMeter::new();
+    let mut tempo = TempoMeter::new();
+    let mut sweep = StrobeSweep::new();
+    let mut num_format = NumFormat::Hex;
+    let mut acknowledge = false;
+    let mut prompt = false;
+    let mut hour_clock: Option<HourClock> = None;
+    let mut quiet_hours: Option<(u8, u8)> = None;
+    let mut led_events = false;
+    let mut global_invert = settings.invert_leds;
+    let mut throttle = Throttle::new(0);
+    let mut last_mask: u32 = 0;
+    // Which LEDs are lit, before dimming and the global inversion touch the pins.
+    let mut logical_mask: u32 = 0;
+    let mut dimmer = SoftPwm::new(SOFT_PWM_STEP, u8::MAX);
+    let mut diag_hold = DiagHold::new();
+    let mut diag: Option<DiagPlayer> = None;
+    let mut loops: u32 = 0;
+    let mut loop_window_ms = millis();
+    let mut loop_hz: u32 = 0;
     loop {
-        match rx.read() {
-            Ok(b'?') => {
-                send_help_text(&mut tx);
-            }
-            Ok(b'0') => {
-                if static_enable || blink_enable || strobe_enable || controlled_enable {
-                    static_enable = false;
-                    blink_enable = false;
-                    strobe_enable = false;
-                    controlled_enable = false;
-                    send_string(&mut tx, "All LEDs disabled.");
-                }
+        let mut result: Option<CmdResult> = None;
+        let received = rx.read();
+        if let (Ok(_), Some(rx_activity)) = (&received, rx_activity.as_mut()) {
+            rx_activity.trigger(millis());
+        }
+        match received {
+            Ok(_) if demo.is_running() => {
+                result = Some(CmdResult::Ok);
+                demo.stop();
+                sparkle.stop();
+                controller.handle_command(Command::EnableAll);
+                send_string(&mut tx_queue, "Demo stopped.");
             }
-            Ok(b'1') => {
-                if !static_enable || !blink_enable || !strobe_enable || !controlled_enable {
-                    static_enable = true;
-                    blink_enable = true;
-                    strobe_enable = true;
-                    controlled_enable = true;
-                    send_string(&mut tx, "All LEDs enabled.");
+            Ok(c) if sysex.is_receiving() || SYSEX_START == c => {
+                match sysex.feed(c).map(parse_sysex) {
+                    Some(Ok(SysexMsg::SetEnables(mask))) => {
+                        result = Some(CmdResult::Ok);
+                        let message = controller.handle_command(Command::SetEnables(mask));
+                        if let Some(message) = message {
+                            send_string(&mut tx_queue, message);
+                        }
+                    }
+                    Some(Err(_)) => {
+                        result = Some(CmdResult::Err);
+                        send_string(&mut tx_queue, "Invalid LED state frame.");
+                    }
+                    None => (),
                 }
             }
-            Ok(b'2') => {
-                static_enable = !static_enable;
-                if static_enable {
-                    send_string(&mut tx, "Static enabled.");
-                } else {
-                    send_string(&mut tx, "Static disabled.");
+            Ok(c) if in_menu => match menu.select(c as char) {
+                Some(MenuAction::Exit) => {
+                    result = Some(CmdResult::Ok);
+                    in_menu = false;
+                    send_string(&mut tx_queue, "Left the menu.");
                 }
-            }
-            Ok(b'3') => {
-                blink_enable = !blink_enable;
-                if blink_enable {
-                    send_string(&mut tx, "Blink enabled.");
-                } else {
-                    send_string(&mut tx, "Blink disabled.");
+                Some(action) => {
+                    result = Some(CmdResult::Ok);
+                    match action {
+                        MenuAction::Led(command) => {
+                            if let Some(message) = controller.handle_command(command) {
+                                send_string(&mut tx_queue, message);
+                            }
+                            if matches!(command, Command::ToggleInversion) {
+                                send_string(&mut tx_queue, controller.describe_controlled());
+                            }
+                        }
+                        MenuAction::DeviceId => send_device_id(&mut tx_queue, num_format),
+                        MenuAction::Exit => (),
+                    }
+                    menu.render(&mut tx_queue).ok();
                 }
-            }
-            Ok(b'4') => {
-                strobe_enable = !strobe_enable;
-                if strobe_enable {
-                    send_string(&mut tx, "Strobe enabled.");
-                } else {
-                    send_string(&mut tx, "Strobe disabled.");
+                None => {
+                    result = Some(CmdResult::Err);
+                    send_string(&mut tx_queue, "Invalid selection.");
+                }
+            },
+            Ok(b'\r') if !command_line.is_empty() => {
+                let line = command_line.as_str();
+                match line.split_once(' ') {
+                    None if "ack" == line => {
+                        acknowledge = !acknowledge;
+                        match acknowledge {
+                            true => send_string(&mut tx_queue, "Acknowledgments on."),
+                            false => send_string(&mut tx_queue, "Acknowledgments off."),
+                        }
+                    }
+                    None if "demo" == line => demo.start(millis()),
+                    None if "dim" == line => {
+                        dimmer.set_duty(u8::MAX);
+                        send_string(&mut tx_queue, "External LEDs at full brightness.");
+                    }
+                    None if "events" == line => {
+                        led_events = !led_events;
+                        // The pins are already dimmed and inverted by now, so start
+                        // from last pass's logical state instead.
+                        last_mask = logical_mask;
+                        match led_events {
+                            true => send_string(&mut tx_queue, "LED events on."),
+                            false => send_string(&mut tx_queue, "LED events off."),
+                        }
+                    }
+                    None if "prompt" == line => {
+                        prompt = !prompt;
+                        match prompt {
+                            true => send_string(&mut tx_queue, "Prompt on."),
+                            false => send_string(&mut tx_queue, "Prompt off."),
+                        }
+                    }
+                    None if "b" == line => {
+                        bounce_meter.arm(button.is_pressed());
+                        send_string(&mut tx_queue, "Press user button B1.");
+                    }
+                    None if "d" == line => send_device_id(&mut tx_queue, num_format),
+                    None if "drops" == line => {
+                        let dropped = tx_queue.take_dropped();
+                        let mut buffer: String<BUFFER_SIZE> = String::new();
+                        write!(buffer, "{} bytes dropped by the transmit queue.", dropped).unwrap();
+                        send_string(&mut tx_queue, &buffer);
+                    }
+                    None if "n" == line => {
+                        num_format = num_format.next();
+                        send_num_format(&mut tx_queue, num_format);
+                    }
+                    None if "f" == line => {
+                        flash_counter.stop();
+                        send_string(&mut tx_queue, "Status code stopped.");
+                    }
+                    None if "l" == line => {
+                        onboard_override = None;
+                        send_string(&mut tx_queue, "Onboard LED released.");
+                    }
+                    None if "m" == line => {
+                        in_menu = true;
+                        menu.render(&mut tx_queue).ok();
+                    }
+                    None if "seq" == line => {
+                        sequence.stop();
+                        send_string(&mut tx_queue, "LED sequence stopped.");
+                    }
+                    None if "pov" == line => {
+                        pov = None;
+                        send_string(&mut tx_queue, "POV message stopped.");
+                    }
+                    None if "hb" == line => {
+                        heartbeat = None;
+                        send_string(&mut tx_queue, "Heartbeat stopped.");
+                    }
+                    None if "i?" == line => {
+                        send_string(&mut tx_queue, controller.describe_controlled());
+                    }
+                    None if "invert" == line => {
+                        global_invert = !global_invert;
+                        match global_invert {
+                            true => send_string(&mut tx_queue, "LED outputs inverted, active low."),
+                            false => send_string(&mut tx_queue, "LED outputs normal, active high."),
+                        }
+                        // The inversion matches the wiring, so it survives a reset.
+                        let mut changed = settings.clone();
+                        changed.invert_leds = global_invert;
+                        match save_settings(&mut flash_writer, &changed) {
+                            Ok(()) => settings = changed,
+                            Err(_) => {
+                                result = Some(CmdResult::Err);
+                                send_string(&mut tx_queue, "Inversion could not be saved.");
+                            }
+                        }
+                    }
+                    None if "rx" == line => {
+                        rx_activity = match rx_activity {
+                            Some(_) => {
+                                send_string(&mut tx_queue, "RX activity light off.");
+                                None
+                            }
+                            None => {
+                                send_string(&mut tx_queue, "RX activity light on.");
+                                Some(Monostable::new(RX_PULSE_MS))
+                            }
+                        };
+                    }
+                    None if "qr" == line => send_status_blob(&mut tx_queue),
+                    None if "quiet" == line => {
+                        quiet_hours = None;
+                        send_string(&mut tx_queue, "Quiet hours off.");
+                    }
+                    None if "selftest" == line => {
+                        let checks = [
+                            ("LEDs", check_leds(&mut banks)),
+                            ("clock", check_clock(&clocks, profile)),
+                            ("VREFINT", check_vrefint(&mut adc1)),
+                            ("button", check_button(button.is_pressed())),
+                        ];
+                        write_selftest_report(&mut tx_queue, &checks).ok();
+                        if checks.iter().any(|(_, check)| check.is_err()) {
+                            result = Some(CmdResult::Err);
+                        }
+                    }
+                    None if "stack" == line => send_stack_usage(&mut tx_queue),
+                    None if "sparkle" == line => {
+                        if sparkle.is_running() {
+                            sparkle.stop();
+                            send_string(&mut tx_queue, "Sparkle stopped.");
+                        } else {
+                            let [id0, id1, id2] = device_id();
+                            sparkle.start(seed_from(&[id0, id1, id2, millis()]));
+                            send_string(&mut tx_queue, "Sparkle started.");
+                        }
+                    }
+                    None if "throttle" == line => {
+                        throttle.set_min_gap_us(0);
+                        send_string(&mut tx_queue, "Throttle off.");
+                    }
+                    None if "sweep" == line => {
+                        if sweep.is_running() {
+                            sweep.stop();
+                            controller.set_strobe_ms(STROBE_MS);
+                            send_string(&mut tx_queue, "Strobe sweep stopped.");
+                        } else {
+                            sweep.start(millis());
+                            send_string(&mut tx_queue, "Strobe sweep started.");
+                        }
+                    }
+                    None if "tempo" == line => {
+                        if tempo.is_running() {
+                            tempo.stop();
+                            send_string(&mut tx_queue, "Tempo stopped.");
+                        } else {
+                            tempo.start(button.is_pressed());
+                            send_string(&mut tx_queue, "Tap user button B1 to the beat.");
+                        }
+                    }
+                    None if "w" == line => send_ruler(&mut tx_queue, RULER_WIDTH),
+                    Some(("w", width)) => match parse_clamped(width, 1, BUFFER_SIZE as u32) {
+                        Ok(width) => {
+                            send_clamped_warning(&mut tx_queue, width);
+                            send_ruler(&mut tx_queue, width.value as usize);
+                        }
+                        Err(_) => {
+                            result = Some(CmdResult::Err);
+                            send_string(&mut tx_queue, "Invalid ruler width.");
+                        }
+                    },
+                    Some(("f", digit)) => match parse_clamped(digit, 0, 9) {
+                        // The digit selects a code, so out of range is an error.
+                        Ok(digit) if !digit.clamped => {
+                            sequence.stop();
+                            flash_counter.start(digit.value);
+                            send_string(&mut tx_queue, "Status code started.");
+                        }
+                        _ => {
+                            result = Some(CmdResult::Err);
+                            send_string(&mut tx_queue, "Invalid status code digit.");
+                        }
+                    },
+                    Some(("strobe", style)) => match style.trim() {
+                        "all" => {
+                            controller.set_strobe_style(StrobeStyle::All);
+                            send_string(&mut tx_queue, "Strobing LEDs toggle together.");
+                        }
+                        "chase" => {
+                            controller.set_strobe_style(StrobeStyle::Chase);
+                            send_string(&mut tx_queue, "Strobing LEDs chase in sequence.");
+                        }
+                        _ => {
+                            result = Some(CmdResult::Err);
+                            send_string(&mut tx_queue, "Invalid strobe style.");
+                        }
+                    },
+                    Some(("l", state)) => match state.trim() {
+                        "on" => {
+                            onboard_override = Some(true);
+                            send_string(&mut tx_queue, "Onboard LED forced on.");
+                        }
+                        "off" => {
+                            onboard_override = Some(false);
+                            send_string(&mut tx_queue, "Onboard LED forced off.");
+                        }
+                        _ => {
+                            result = Some(CmdResult::Err);
+                            send_string(&mut tx_queue, "Invalid onboard LED state.");
+                        }
+                    },
+                    Some(("dim", percent)) => match parse_clamped(percent, 0, 100) {
+                        Ok(percent) => {
+                            send_clamped_warning(&mut tx_queue, percent);
+                            let level = (percent.value * u8::MAX as u32 + 50) / 100;
+                            dimmer.set_duty(gamma_correct(level as u8));
+                            let mut buffer: String<BUFFER_SIZE> = String::new();
+                            write!(
+                                buffer,
+                                "External LEDs at {}% brightness, {}/255 duty.",
+                                percent.value,
+                                dimmer.duty()
+                            )
+                            .unwrap();
+                            send_string(&mut tx_queue, &buffer);
+                        }
+                        Err(_) => {
+                            result = Some(CmdResult::Err);
+                            send_string(&mut tx_queue, "Invalid brightness.");
+                        }
+                    },
+                    Some(("pov", args)) if args.trim_start().starts_with("ms ") => {
+                        let column_ms = args.trim_start().trim_start_matches("ms").trim();
+                        match parse_clamped(column_ms, POV_COLUMN_MIN_MS, POV_COLUMN_MAX_MS) {
+                            Ok(column_ms) => {
+                                send_clamped_warning(&mut tx_queue, column_ms);
+                                pov_column_ms = column_ms.value;
+                                if let Some(pov) = pov.as_mut() {
+                                    pov.column_ms = pov_column_ms;
+                                }
+                                let mut buffer: String<BUFFER_SIZE> = String::new();
+                                write!(buffer, "POV columns every {} ms.", pov_column_ms).unwrap();
+                                send_string(&mut tx_queue, &buffer);
+                            }
+                            Err(_) => {
+                                result = Some(CmdResult::Err);
+                                send_string(&mut tx_queue, "Invalid POV column time.");
+                            }
+                        }
+                    }
+                    Some(("pov", text)) => {
+                        // A long message is cut short rather than rejected, like a
+                        // clamped argument.
+                        let mut message: String<POV_MESSAGE_SIZE> = String::new();
+                        for c in text.trim().chars().take(POV_MESSAGE_SIZE) {
+                            message.push(c).ok();
+                        }
+                        if message.len() < text.trim().len() {
+                            let mut buffer: String<BUFFER_SIZE> = String::new();
+                            write!(buffer, "Too long, using {}.", message).unwrap();
+                            send_string(&mut tx_queue, &buffer);
+                        }
+                        pov = Some(Pov::new(millis(), message, pov_column_ms));
+                        send_string(&mut tx_queue, "POV message started.");
+                    }
+                    Some(("hb", args)) if args.trim_start().starts_with("led") => {
+                        let led = args.trim_start().trim_start_matches("led").trim();
+                        let led = match led {
+                            "off" => Ok(None),
+                            led => parse_led_index(led).map(Some).ok_or(()),
+                        };
+                        match (heartbeat.as_mut(), led) {
+                            (Some(heartbeat), Ok(led)) => {
+                                let previous = heartbeat.set_led(led);
+                                let mut buffer: String<BUFFER_SIZE> = String::new();
+                                match led {
+                                    Some(led) => write!(buffer, "Heartbeat LED {}", led).unwrap(),
+                                    None => write!(buffer, "Heartbeat LED off").unwrap(),
+                                }
+                                match previous {
+                                    Some(previous) => write!(buffer, ", was {}.", previous),
+                                    None => write!(buffer, "."),
+                                }
+                                .unwrap();
+                                send_string(&mut tx_queue, &buffer);
+                            }
+                            (None, Ok(_)) => {
+                                result = Some(CmdResult::Err);
+                                send_string(&mut tx_queue, "No heartbeat, start one with hb 1000.");
+                            }
+                            (_, Err(())) => {
+                                result = Some(CmdResult::Err);
+                                send_string(&mut tx_queue, "Invalid heartbeat LED.");
+                            }
+                        }
+                    }
+                    Some(("hb", args)) => {
+                        let (interval, message) = match args.trim().split_once(' ') {
+                            Some((interval, "alive")) => (interval, Some(HeartbeatMessage::Alive)),
+                            Some((interval, _)) => (interval, None),
+                            None => (args, Some(HeartbeatMessage::Dot)),
+                        };
+                        match (
+                            parse_clamped(interval, HEARTBEAT_MIN_MS, HEARTBEAT_MAX_MS),
+                            message,
+                        ) {
+                            (Ok(interval), Some(message)) => {
+                                send_clamped_warning(&mut tx_queue, interval);
+                                // Restarting the heartbeat keeps the LED it pulses.
+                                let led = heartbeat.as_ref().and_then(Heartbeat::led);
+                                let mut restarted =
+                                    Heartbeat::new(millis(), interval.value, message);
+                                restarted.set_led(led);
+                                heartbeat = Some(restarted);
+                                let mut buffer: String<BUFFER_SIZE> = String::new();
+                                write!(buffer, "Heartbeat every {} ms.", interval.value).unwrap();
+                                send_string(&mut tx_queue, &buffer);
+                            }
+                            _ => {
+                                result = Some(CmdResult::Err);
+                                send_string(&mut tx_queue, "Invalid heartbeat.");
+                            }
+                        }
+                    }
+                    Some(("rx", duration)) => match parse_clamped(duration, 1, RX_PULSE_MAX_MS) {
+                        Ok(duration) => {
+                            send_clamped_warning(&mut tx_queue, duration);
+                            rx_activity = Some(Monostable::new(duration.value));
+                            let mut buffer: String<BUFFER_SIZE> = String::new();
+                            write!(
+                                buffer,
+                                "RX activity light on, {} ms pulses.",
+                                duration.value
+                            )
+                            .unwrap();
+                            send_string(&mut tx_queue, &buffer);
+                        }
+                        Err(_) => {
+                            result = Some(CmdResult::Err);
+                            send_string(&mut tx_queue, "Invalid pulse duration.");
+                        }
+                    },
+                    Some(("quiet", window)) => {
+                        let window = window.trim().split_once(' ');
+                        match window.map(|(start, end)| (parse_hour(start), parse_hour(end))) {
+                            Some((Some(start), Some(end))) => {
+                                quiet_hours = Some((start, end));
+                                let mut buffer: String<BUFFER_SIZE> = String::new();
+                                write!(buffer, "Quiet hours {:02}:00 to {:02}:00.", start, end)
+                                    .unwrap();
+                                send_string(&mut tx_queue, &buffer);
+                                if hour_clock.is_none() {
+                                    send_string(&mut tx_queue, "Set the time to use quiet hours.");
+                                }
+                            }
+                            _ => {
+                                result = Some(CmdResult::Err);
+                                send_string(&mut tx_queue, "Invalid quiet hours.");
+                            }
+                        }
+                    }
+                    Some(("throttle", gap)) => match parse_clamped(gap, 0, THROTTLE_MAX_US) {
+                        Ok(gap) => {
+                            send_clamped_warning(&mut tx_queue, gap);
+                            throttle.set_min_gap_us(gap.value);
+                            let mut buffer: String<BUFFER_SIZE> = String::new();
+                            write!(buffer, "At least {} us between bytes.", gap.value).unwrap();
+                            send_string(&mut tx_queue, &buffer);
+                        }
+                        Err(_) => {
+                            result = Some(CmdResult::Err);
+                            send_string(&mut tx_queue, "Invalid throttle gap.");
+                        }
+                    },
+                    Some(("time", hour)) => match parse_hour(hour) {
+                        Some(hour) => {
+                            hour_clock = Some(HourClock::new(millis(), hour));
+                            let mut buffer: String<BUFFER_SIZE> = String::new();
+                            write!(buffer, "Time set to {:02}:00.", hour).unwrap();
+                            send_string(&mut tx_queue, &buffer);
+                        }
+                        None => {
+                            result = Some(CmdResult::Err);
+                            send_string(&mut tx_queue, "Invalid hour.");
+                        }
+                    },
+                    Some(("seq", list)) => {
+                        let mut durations = Vec::new();
+                        match parse_sequence(list, &mut durations) {
+                            Ok(clamped) => {
+                                if clamped {
+                                    send_string(
+                                        &mut tx_queue,
+                                        "Durations out of range were clamped.",
+                                    );
+                                }
+                                flash_counter.stop();
+                                sequence.start(durations, millis());
+                                send_string(&mut tx_queue, "LED sequence started.");
+                            }
+                            Err(_) => {
+                                result = Some(CmdResult::Err);
+                                send_string(&mut tx_queue, "Invalid LED sequence.");
+                            }
+                        }
+                    }
+                    _ => {
+                        result = Some(CmdResult::Err);
+                        send_string(&mut tx_queue, "Unknown command.");
+                    }
                 }
+                command_line.clear();
+                result.get_or_insert(CmdResult::Ok);
             }
-            Ok(b'5') => {
-                controlled_enable = !controlled_enable;
-                if controlled_enable {
-                    send_string(&mut tx, "Controlled enabled.");
-                } else {
-                    send_string(&mut tx, "Controlled disabled.");
+            Ok(c) if !command_line.is_empty() || c.is_ascii_lowercase() => {
+                if command_line.push(c as char).is_ok() {
+                    // Echo back the received character.
+                    let _ = tx_queue.enqueue(c);
                 }
             }
-            Ok(b'9') => {
-                controlled_inversion = !controlled_inversion;
-                if controlled_inversion {
-                    send_string(&mut tx, "LED control inversion enabled.");
-                } else {
-                    send_string(&mut tx, "LED control inversion disabled.");
+            Ok(b'?') => {
+                result = Some(CmdResult::Ok);
+                send_help_text(&mut tx_queue);
+            }
+            Ok(b'M') => {
+                result = Some(CmdResult::Ok);
+                let mut scratch = [0u32; MEMORY_TEST_WORDS];
+                let mut buffer: String<BUFFER_SIZE> = String::new();
+                match walking_bit_test(&mut scratch) {
+                    Ok(()) => write!(buffer, "RAM test PASS.").unwrap(),
+                    Err(address) => write!(buffer, "RAM test FAIL at 0x{:08X}.", address).unwrap(),
                 }
+                send_string(&mut tx_queue, &buffer);
             }
-            Ok(_) => (),
+            Ok(c) => match Command::from_byte(c) {
+                Some(command) => {
+                    result = Some(CmdResult::Ok);
+                    if let Some(message) = controller.handle_command(command) {
+                        send_string(&mut tx_queue, message);
+                    }
+                    if matches!(command, Command::ToggleInversion) {
+                        send_string(&mut tx_queue, controller.describe_controlled());
+                    }
+                }
+                // Control characters, like the line feed after a carriage return, are ignored.
+                None if c.is_ascii_graphic() => {
+                    result = Some(CmdResult::Err);
+                    send_string(&mut tx_queue, "Unknown command.");
+                }
+                None => (),
+            },
             Err(nb::Error::WouldBlock) => (),
             Err(_) => (),
         }
-        delay.delay_ms(STROBE_MS);
-        counter = counter + STROBE_MS;
-        if 2 * BLINK_MS < counter {
-            counter = 0;
+        if let Some(result) = result {
+            if acknowledge {
+                let mut ack: String<16> = String::new();
+                ack_line(result, &mut ack);
+                tx_queue.write_str(&ack).ok();
+            }
+            if prompt {
+                tx_queue.write_str(PROMPT).ok();
+            }
+        }
+        // Transmit queued text between LED updates. `micros` costs a division, so
+        // it is only read while throttled.
+        match throttle.min_gap_us() {
+            0 => tx_queue.pump(&mut tx),
+            _ => tx_queue.pump_throttled(&mut tx, &mut throttle, micros()),
+        };
+
+        let now_ms = millis();
+        loops += 1;
+        let loop_elapsed_ms = now_ms.wrapping_sub(loop_window_ms);
+        if MS_PER_SECOND <= loop_elapsed_ms {
+            loop_hz = loops * MS_PER_SECOND / loop_elapsed_ms;
+            loops = 0;
+            loop_window_ms = now_ms;
+        }
+        if diag_hold.update(now_ms, button.is_pressed()) {
+            let screen = match &diag {
+                Some(diag) => diag.screen().next(),
+                None => Some(DiagScreen::FIRST),
+            };
+            diag = screen.map(|screen| {
+                let digits = diag_digits(
+                    screen,
+                    reset_cause,
+                    HEALTHY_LOOP_HZ <= loop_hz,
+                    tx_queue.dropped(),
+                );
+                DiagPlayer::new(screen, digits, now_ms)
+            });
+            let mut buffer: String<BUFFER_SIZE> = String::new();
+            match screen {
+                Some(DiagScreen::ResetCause) => {
+                    write!(buffer, "Diagnostics: reset cause, {}.", reset_cause.name())
+                }
+                Some(DiagScreen::LoopHealth) => write!(
+                    buffer,
+                    "Diagnostics: loop health, {} loops per second.",
+                    loop_hz
+                ),
+                Some(screen) => write!(buffer, "Diagnostics: {}.", screen.name()),
+                None => write!(buffer, "Diagnostics off."),
+            }
+            .unwrap();
+            send_string(&mut tx_queue, &buffer);
+        }
+        if let Some((window_ms, edges)) = bounce_meter.update(now_ms, button.is_pressed()) {
+            let mut buffer: String<BUFFER_SIZE> = String::new();
+            write!(
+                buffer,
+                "Button bounced for {} ms over {} edges.",
+                window_ms, edges
+            )
+            .unwrap();
+            send_string(&mut tx_queue, &buffer);
+        }
+        match heartbeat
+            .as_mut()
+            .and_then(|heartbeat| heartbeat.update(now_ms))
+        {
+            Some(HeartbeatMessage::Dot) => send_string(&mut tx_queue, "."),
+            Some(HeartbeatMessage::Alive) => {
+                let mut buffer: String<BUFFER_SIZE> = String::new();
+                write!(buffer, "alive ms={}", now_ms).unwrap();
+                send_string(&mut tx_queue, &buffer);
+            }
+            None => (),
+        }
+        if let Some(interval_ms) = tempo.update(now_ms, button.is_pressed()) {
+            let mut buffer: String<BUFFER_SIZE> = String::new();
+            write!(
+                buffer,
+                "Tempo {} BPM, {} ms between presses.",
+                interval_to_bpm(interval_ms),
+                interval_ms
+            )
+            .unwrap();
+            send_string(&mut tx_queue, &buffer);
+        }
+        match sweep.update(now_ms) {
+            Some(SweepStep::HalfPeriod(half_period_ms)) => {
+                controller.set_strobe_ms(half_period_ms);
+                let mut buffer: String<BUFFER_SIZE> = String::new();
+                write!(buffer, "Strobe {} Hz.", period_to_hz(2 * half_period_ms)).unwrap();
+                send_string(&mut tx_queue, &buffer);
+            }
+            Some(SweepStep::Done) => {
+                controller.set_strobe_ms(STROBE_MS);
+                send_string(&mut tx_queue, "Strobe sweep done.");
+            }
+            None => (),
+        }
+        if let Some(message) = controller.update(now_ms, button.is_pressed()) {
+            send_string(&mut tx_queue, message);
+        }
+        // The LEDs are composed into one mask, and the pins written once per pass, so
+        // an LED that ends up off is never lit partway through the pass.
+        let mut mask = controller.mask();
+        if let Some(step) = demo.update(now_ms) {
+            controller.handle_command(Command::SetEnables(step.enables));
+            if step.sparkle {
+                sparkle.start(seed_from(&[now_ms]));
+            } else {
+                sparkle.stop();
+            }
+            let mut buffer: String<BUFFER_SIZE> = String::new();
+            for c in step.description.bytes() {
+                let _ = buffer.push(convert_case(c, &step.text_mode) as char);
+            }
+            send_string(&mut tx_queue, &buffer);
+        }
+        if let Some(sparkle_mask) = sparkle.update(now_ms) {
+            mask = sparkle_mask;
+        }
+        // The message replaces the LED groups, like sparkle, and is in turn
+        // overridden on the onboard LED by the patterns below.
+        if let Some((column, onboard)) = pov.as_ref().map(|pov| pov.frame(now_ms)) {
+            mask = with_leds(column as u32, ONBOARD_LED_BIT, onboard);
         }
-        strobe_on = !strobe_on;
-        blink_on = BLINK_MS <= counter;
 
-        // Apply inversion logic here.
-        let button_state = button.is_low() != controlled_inversion;
-        if button_state {
-            if controlled_enable && !controlled_on {
-                send_string(&mut tx, "Controlled LED on.");
+        // A running LED sequence or status code overrides the onboard LED, which is
+        // the first controlled LED. Only one of them runs at a time, and both take
+        // precedence over the tempo flash. A diagnostic screen takes precedence over
+        // all of them, since it is only ever started from the button.
+        let diag_level = diag.as_ref().map(|diag| diag.level(now_ms));
+        let pattern = diag_level
+            .or(sequence.update(now_ms))
+            .or(flash_counter.tick(now_ms));
+        if let Some(level) = pattern.or(tempo.flash(now_ms)) {
+            mask = with_leds(mask, ONBOARD_LED_BIT, level);
+        }
+        // The RX activity light shows nothing else, so idle is off rather than the pattern.
+        if let Some(rx_activity) = rx_activity.as_mut() {
+            mask = with_leds(mask, ONBOARD_LED_BIT, rx_activity.is_on(now_ms));
+        }
+        // The heartbeat pulse replaces whatever its LED would otherwise show.
+        if let Some((led, level)) = heartbeat
+            .as_ref()
+            .and_then(|heartbeat| heartbeat.led_level(now_ms))
+        {
+            mask = with_leds(mask, 1 << led, level);
+        }
+        // A forced onboard LED state is applied after the patterns, so it takes precedence.
+        if let Some(level) = onboard_override {
+            mask = with_leds(mask, ONBOARD_LED_BIT, level);
+        }
+        // Quiet hours are the final override, and force every LED off.
+        if let (Some(clock), Some((start, end))) = (&hour_clock, quiet_hours) {
+            if in_quiet_hours(clock.hour(now_ms), start, end) {
+                mask = 0;
             }
-            controlled_on = true;
-        } else {
-            if controlled_enable && controlled_on {
-                send_string(&mut tx, "Controlled LED off.");
+        }
+
+        logical_mask = mask;
+        // Only transitions are sent, so a steady LED costs no bandwidth.
+        if led_events {
+            let mask = logical_mask;
+            let changed = mask ^ last_mask;
+            for id in (0..u32::BITS).filter(|id| 0 != changed & (1 << id)) {
+                // A whole event or nothing is queued, so a full queue cannot leave
+                // a partial event that would misalign the ones after it.
+                let event = led_event(id as u8, 0 != mask & (1 << id));
+                if event.len() <= tx_queue.capacity() - tx_queue.len() {
+                    for byte in event {
+                        let _ = tx_queue.enqueue(byte);
+                    }
+                }
             }
-            controlled_on = false;
+            last_mask = mask;
         }
-        set_leds(&mut leds_static, static_enable && static_on);
-        set_leds(&mut leds_blink, blink_enable && blink_on);
-        set_leds(&mut leds_strobe, strobe_enable && strobe_on);
 
-        // The LED is inverted even if disabled, but the inverted state is baked in above.
-        // This results in boolean logic that is hard to follow.
-        if !controlled_inversion {
-            set_leds(&mut leds_controlled, controlled_enable && controlled_on);
-        } else {
-            // Note that inversion logic is applied above, so this may appear wrong at first glance.
-            set_leds(&mut leds_controlled, !controlled_enable || controlled_on);
+        // Dimming only ever turns the external LEDs off for part of the PWM period. It
+        // comes after the events, so the PWM itself is not reported as LED changes.
+        if !dimmer.update() {
+            mask &= ONBOARD_LED_BIT;
         }
+
+        // The global inversion is the very last step, so everything above, events
+        // included, works in terms of lit and unlit, and only the pins are inverted.
+        banks.set_mask(physical_mask(mask, global_invert));
     }
 }
@@ -0,0 +1,240 @@
+// examples/serial_rx_modes.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example switches USART2 reception between polling and interrupts at
+//! runtime, to show why interrupts matter when the main loop is busy.
+//!
+//! Each pass through the main loop simulates `WORK_MS` milliseconds of other work.
+//! At 115200 baud a byte arrives roughly every 87us, and the USART only holds one
+//! received byte, so in polling mode most of a pasted line is lost to overruns.
+//! In interrupt mode, the USART2 interrupt moves every byte into a ring buffer
+//! as it arrives, and the main loop echoes everything in the buffer on each pass.
+//!
+//! `!` switches between the modes, and reports how many bytes were lost so far.
+//! Switching to polling mode echoes anything still in the ring buffer before the
+//! USART is polled again, so no bytes are lost or reordered by the switch.
+//!
+//! Output goes through a `TxQueue`, which is sent at the end of each pass. `q`
+//! reports how many bytes are waiting in the ring buffer and in the transmit queue
+//! at the moment it is read, next to their capacities. Paste a long line with a
+//! `q` in it, in interrupt mode, to see the bytes that piled up during the work.
+
+use core::fmt::Write;
+use cortex_m::peripheral::NVIC;
+use cortex_m_rt::entry;
+use heapless::{spsc::Queue, String};
+use hello_nucleo_f103rb::{board::Board, shared::Shared, tx_queue::TxQueue};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    pac::{interrupt, Interrupt, USART2},
+    prelude::*,
+    serial::{Config, Error, Rx, Serial, Tx},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const RX_QUEUE_SIZE: usize = 256;
+const TX_QUEUE_SIZE: usize = 512;
+const WORK_MS: u32 = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum RxMode {
+    Polling,
+    Interrupt,
+}
+
+/// Bytes lost in each mode.
+#[derive(Clone, Copy, Default)]
+struct LostBytes {
+    overruns: u32,
+    queue_full: u32,
+}
+
+static G_RX: Shared<Option<Rx<USART2>>> = Shared::new(None);
+static G_RX_QUEUE: Shared<Queue<u8, RX_QUEUE_SIZE>> = Shared::new(Queue::new());
+static G_LOST: Shared<LostBytes> = Shared::new(LostBytes {
+    overruns: 0,
+    queue_full: 0,
+});
+
+#[interrupt]
+fn USART2() {
+    let received = G_RX.with(|rx| rx.as_mut().map(|rx| rx.read()));
+    match received {
+        Some(Ok(byte)) if G_RX_QUEUE.with(|queue| queue.enqueue(byte)).is_err() => {
+            G_LOST.with(|lost| lost.queue_full += 1);
+        }
+        Some(Err(nb::Error::Other(Error::Overrun))) => {
+            G_LOST.with(|lost| lost.overruns += 1);
+        }
+        _ => (),
+    }
+}
+
+/// Turn the USART2 receive interrupt on or off.
+fn set_mode(mode: RxMode) {
+    match mode {
+        RxMode::Polling => {
+            NVIC::mask(Interrupt::USART2);
+            G_RX.with(|rx| rx.as_mut().map(|rx| rx.unlisten()));
+        }
+        RxMode::Interrupt => {
+            G_RX.with(|rx| rx.as_mut().map(|rx| rx.listen()));
+            // Unmasking an interrupt is unsafe because it can break critical sections,
+            // but all shared state is only accessed through `Shared`.
+            #[allow(unsafe_code)]
+            unsafe {
+                NVIC::unmask(Interrupt::USART2);
+            }
+        }
+    }
+}
+
+/// The next received byte, if any. Bytes already in the ring buffer come first,
+/// then the USART is read directly in polling mode.
+fn next_byte(mode: RxMode) -> Option<u8> {
+    if let Some(byte) = G_RX_QUEUE.with(|queue| queue.dequeue()) {
+        return Some(byte);
+    }
+    if RxMode::Interrupt == mode {
+        return None;
+    }
+    match G_RX.with(|rx| rx.as_mut().map(|rx| rx.read())) {
+        Some(Ok(byte)) => Some(byte),
+        Some(Err(nb::Error::Other(Error::Overrun))) => {
+            G_LOST.with(|lost| lost.overruns += 1);
+            None
+        }
+        _ => None,
+    }
+}
+
+fn send_string(tx: &mut TxQueue<TX_QUEUE_SIZE>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).ok();
+}
+
+/// Send everything in the queue, waiting for the USART as needed.
+fn drain(tx_queue: &mut TxQueue<TX_QUEUE_SIZE>, tx: &mut Tx<USART2>) {
+    while !tx_queue.is_empty() {
+        tx_queue.pump(tx);
+    }
+    block!(tx.flush()).ok();
+}
+
+fn send_start_message(tx: &mut TxQueue<TX_QUEUE_SIZE>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_help_text(tx: &mut TxQueue<TX_QUEUE_SIZE>) {
+    let help_text = "\
+Received text is echoed back. Paste a long line to compare the modes.\r\n\
+! - Switch between polling and interrupt reception\r\n\
+q - Report the bytes waiting in the receive and transmit queues\r\n\
+? - Display this help message\
+";
+    send_string(tx, help_text);
+}
+
+fn send_mode(tx: &mut TxQueue<TX_QUEUE_SIZE>, mode: RxMode) {
+    let lost = G_LOST.lock_get();
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    let name = match mode {
+        RxMode::Polling => "Polling",
+        RxMode::Interrupt => "Interrupt",
+    };
+    write!(
+        buffer,
+        "{} mode. Lost {} bytes to overruns, {} to a full buffer.",
+        name, lost.overruns, lost.queue_full
+    )
+    .unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_queue_depths(tx: &mut TxQueue<TX_QUEUE_SIZE>) {
+    let (rx_len, rx_capacity) = G_RX_QUEUE.with(|queue| (queue.len(), queue.capacity()));
+    let (tx_len, tx_capacity) = (tx.len(), tx.capacity());
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(
+        buffer,
+        "RX queue {}/{} bytes, TX queue {}/{} bytes.",
+        rx_len, rx_capacity, tx_len, tx_capacity
+    )
+    .unwrap();
+    send_string(tx, &buffer);
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, and acquire the GPIOA pins.
+    let board = Board::take();
+    let clocks = board.clocks;
+    // The simulated work is a busy wait, counted in system clock cycles.
+    let work_cycles = board.sysclk_hz() / 1_000 * WORK_MS;
+    let mut gpioa = board.gpioa;
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    let mut tx_queue: TxQueue<TX_QUEUE_SIZE> = TxQueue::new();
+    send_start_message(&mut tx_queue);
+    send_help_text(&mut tx_queue);
+
+    // Move the receiver into shared storage for the interrupt handler.
+    G_RX.lock_set(Some(rx));
+    let mut mode = RxMode::Polling;
+    set_mode(mode);
+    send_mode(&mut tx_queue, mode);
+
+    loop {
+        while let Some(byte) = next_byte(mode) {
+            match byte {
+                b'!' => {
+                    mode = match mode {
+                        RxMode::Polling => RxMode::Interrupt,
+                        RxMode::Interrupt => RxMode::Polling,
+                    };
+                    set_mode(mode);
+                    send_mode(&mut tx_queue, mode);
+                }
+                b'?' => send_help_text(&mut tx_queue),
+                b'q' => send_queue_depths(&mut tx_queue),
+                b'\r' => {
+                    tx_queue.enqueue_str("\r\n").ok();
+                }
+                c => {
+                    tx_queue.enqueue(c).ok();
+                }
+            }
+        }
+        drain(&mut tx_queue, &mut tx);
+
+        // Simulate other work that keeps the main loop busy.
+        cortex_m::asm::delay(work_cycles);
+    }
+}
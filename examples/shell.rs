@@ -0,0 +1,296 @@
+// examples/shell.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example is a small line oriented shell, and a starting point for any
+//! program controlled by typed commands over USART.
+//!
+//! Each line is collected with echo and backspace, then `dispatch` from
+//! `hello_nucleo_f103rb::shell` turns it into a `ShellResponse` without touching
+//! any hardware, and the main loop carries the response out. Keeping the parsing separate from its effects makes it easy to
+//! add commands. Parsing is allocation free, and `echo` borrows its text straight
+//! from the line buffer.
+//!
+//! Built in commands are completed with enter.
+//! - `help` lists the commands.
+//! - `led on` and `led off` switch the onboard LED LD2.
+//! - `echo <text>` prints the text back.
+//! - `clock` reports the system clock and the uptime.
+//! - `reset` restarts the microcontroller.
+//! - `pulse <high ms> <period ms>` outputs a repeating pulse on PA6 (Arduino D12),
+//!   for checking timing with a logic analyzer, and `pulse off` stops it.
+//! - `banner <text>` and `prompt <text>` set the greeting shown at boot and the
+//!   prompt. Both are saved in flash, so they survive a reset. Without any text,
+//!   the built in default is restored.
+//!
+//! Anything else prints a usage line, and an empty line just shows a new prompt.
+//!
+//! Pulse edges are scheduled on the `millis()` time base, with rising edges on
+//! whole multiples of the period, and the first few are printed, so the analyzer
+//! capture can be checked against them. The main loop polls the schedule, so an
+//! edge comes a few microseconds after the millisecond it is due, except while a
+//! message is being sent, which delays it by as long as the message takes. The
+//! first edge is far enough ahead for the report to go out first.
+//! See `hello_nucleo_f103rb::settings` for how the settings are stored.
+
+use core::fmt::Write;
+use cortex_m::peripheral::SCB;
+use cortex_m_rt::{entry, exception};
+use heapless::String;
+use hello_nucleo_f103rb::{
+    board::Board,
+    millis::{self, millis},
+    pins::led_output,
+    settings::{load_settings, save_settings, Settings, BANNER_SIZE, PROMPT_SIZE},
+    shell::{dispatch, PulseSchedule, ShellResponse},
+};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    flash::{FlashSize, FlashWriter, SectorSize},
+    pac::USART2,
+    prelude::*,
+    serial::{Config, Serial, Tx},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const PROMPT: &str = ">";
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+// Far enough ahead for the edge report to be sent before the first edge.
+const PULSE_LEAD_MS: u32 = 50;
+const PULSE_EDGES_REPORTED: u32 = 4;
+
+fn send_pulse_schedule(tx: &mut Tx<USART2>, schedule: &PulseSchedule, clamped: bool) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    if clamped {
+        write!(buffer, "Out of range. ").unwrap();
+    }
+    write!(
+        buffer,
+        "Pulse high {} ms every {} ms on PA6, rising edges at",
+        schedule.high_ms(),
+        schedule.period_ms()
+    )
+    .unwrap();
+    for n in 0..PULSE_EDGES_REPORTED {
+        write!(buffer, " {}", schedule.rising_edge_ms(n)).unwrap();
+    }
+    write!(buffer, " ms and so on.").unwrap();
+    send_string(tx, &buffer);
+}
+
+#[exception]
+fn SysTick() {
+    millis::tick();
+}
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+/// Send the saved banner, or the usual greeting if none is saved.
+fn send_start_message(tx: &mut Tx<USART2>, settings: &Settings) {
+    if !settings.banner.is_empty() {
+        send_string(tx, &settings.banner);
+        return;
+    }
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_prompt(tx: &mut Tx<USART2>, settings: &Settings) {
+    let prompt = match settings.prompt.as_str() {
+        "" => PROMPT,
+        prompt => prompt,
+    };
+    write!(tx, "\r{} ", prompt).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_help_text(tx: &mut Tx<USART2>) {
+    let help_text = "\
+The following shell commands can be sent of USART, completed with enter:\r\n\
+help - Display this help message\r\n\
+led on, led off - Switch the onboard LED\r\n\
+echo <text> - Print the text back\r\n\
+clock - Display the system clock and uptime\r\n\
+reset - Restart the microcontroller\r\n\
+banner <text> - Save the greeting shown at boot, or restore the default\r\n\
+prompt <text> - Save the prompt, or restore the default\r\n\
+pulse 10 100 - Pulse PA6 (Arduino D12) high for 10 ms every 100 ms\r\n\
+pulse off - Stop the pulse\
+";
+    send_string(tx, help_text);
+}
+
+fn send_clock(tx: &mut Tx<USART2>, sysclk_hz: u32, uptime_ms: u32) {
+    let seconds = uptime_ms / 1_000;
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(
+        buffer,
+        "System clock {} Hz, up {}:{:02}:{:02}.",
+        sysclk_hz,
+        seconds / 3_600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+    .unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_too_long(tx: &mut Tx<USART2>, name: &str, size: usize) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "{} is limited to {} bytes.", name, size).unwrap();
+    send_string(tx, &buffer);
+}
+
+/// Save `changed` to flash, and make it current if that worked.
+fn save(
+    tx: &mut Tx<USART2>,
+    flash_writer: &mut FlashWriter,
+    settings: &mut Settings,
+    changed: Settings,
+) {
+    match save_settings(flash_writer, &changed) {
+        Ok(()) => {
+            *settings = changed;
+            send_string(tx, "Settings saved.");
+        }
+        Err(_) => send_string(tx, "Settings could not be saved."),
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks and the onboard LED LD2, and acquire the GPIOA pins.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut led = board.led; // On Board LED LD2
+    let mut gpioa = board.gpioa;
+    let mut pulse_pin = led_output(gpioa.pa6, &mut gpioa.crl); // Arduino D12
+
+    // Load the saved settings, falling back to the defaults for blank flash.
+    let mut flash = board.flash;
+    let mut flash_writer = flash.writer(SectorSize::Sz1K, FlashSize::Sz128K);
+    let mut settings = load_settings(&flash_writer).unwrap_or_default();
+
+    // Start the millisecond time base used for the uptime.
+    let cp = cortex_m::Peripherals::take().unwrap();
+    millis::init(cp.SYST, &clocks);
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, mut rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    send_start_message(&mut tx, &settings);
+    send_help_text(&mut tx);
+    send_prompt(&mut tx, &settings);
+
+    let mut line: String<BUFFER_SIZE> = String::new();
+    let mut pulse: Option<PulseSchedule> = None;
+    loop {
+        match pulse.map(|pulse| pulse.level(millis())) {
+            Some(true) => pulse_pin.set_high(),
+            Some(false) | None => pulse_pin.set_low(),
+        }
+        match rx.read() {
+            Ok(b'\r') => {
+                write!(tx, "\r\n").unwrap();
+                match dispatch(&line) {
+                    ShellResponse::Empty => (),
+                    ShellResponse::Help => send_help_text(&mut tx),
+                    ShellResponse::Led(true) => {
+                        led.set_high();
+                        send_string(&mut tx, "LED on.");
+                    }
+                    ShellResponse::Led(false) => {
+                        led.set_low();
+                        send_string(&mut tx, "LED off.");
+                    }
+                    ShellResponse::Echo(text) => send_string(&mut tx, text),
+                    ShellResponse::Clock => send_clock(&mut tx, clocks.sysclk().raw(), millis()),
+                    ShellResponse::Reset => {
+                        send_string(&mut tx, "Resetting.");
+                        SCB::sys_reset();
+                    }
+                    ShellResponse::SetBanner(text) => {
+                        let mut changed = settings.clone();
+                        changed.banner.clear();
+                        match changed.banner.push_str(text) {
+                            Ok(()) => save(&mut tx, &mut flash_writer, &mut settings, changed),
+                            Err(()) => send_too_long(&mut tx, "Banner", BANNER_SIZE),
+                        }
+                    }
+                    ShellResponse::SetPrompt(text) => {
+                        let mut changed = settings.clone();
+                        changed.prompt.clear();
+                        match changed.prompt.push_str(text) {
+                            Ok(()) => save(&mut tx, &mut flash_writer, &mut settings, changed),
+                            Err(()) => send_too_long(&mut tx, "Prompt", PROMPT_SIZE),
+                        }
+                    }
+                    ShellResponse::Pulse { high_ms, period_ms } => {
+                        let schedule = PulseSchedule::new(
+                            millis(),
+                            PULSE_LEAD_MS,
+                            high_ms.value,
+                            period_ms.value,
+                        );
+                        pulse = Some(schedule);
+                        send_pulse_schedule(
+                            &mut tx,
+                            &schedule,
+                            high_ms.clamped || period_ms.clamped,
+                        );
+                    }
+                    ShellResponse::PulseOff => {
+                        pulse = None;
+                        send_string(&mut tx, "Pulse off.");
+                    }
+                    ShellResponse::Usage(usage) => send_string(&mut tx, usage),
+                }
+                line.clear();
+                send_prompt(&mut tx, &settings);
+            }
+            Ok(BACKSPACE | DELETE) => {
+                if line.pop().is_some() {
+                    write!(tx, "\x08 \x08").unwrap();
+                }
+            }
+            // Control characters, like the line feed after a carriage return, are ignored.
+            Ok(c) if c.is_ascii_graphic() || b' ' == c => {
+                if line.push(c as char).is_ok() {
+                    // Echo back the received character.
+                    block!(tx.write(c)).ok();
+                }
+            }
+            Ok(_) => (),
+            Err(nb::Error::WouldBlock) => (),
+            Err(_) => (),
+        }
+    }
+}
@@ -0,0 +1,199 @@
+// examples/sht3x.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example reads temperature and relative humidity from a Sensirion SHT3x
+//! sensor over I2C, and prints them over USART every two seconds.
+//!
+//! Wire the sensor to the Arduino I2C header pins, which are I2C1 remapped.
+//!   SHT3x SCL >--- PB8 (Arduino D15)
+//!   SHT3x SDA >--- PB9 (Arduino D14)
+//!   SHT3x VDD >--- 3V3, ADDR and GND >--- GND
+//! Breakout boards normally include the I2C pull-up resistors. A bare sensor needs
+//! external pull-ups, around 4.7k ohms from SCL and SDA to 3V3.
+//!
+//! Each reading is a single shot, high repeatability measurement without clock
+//! stretching. The sensor answers with a 16-bit temperature and a 16-bit humidity
+//! word, each followed by a CRC-8, and a reading is only reported if both CRCs
+//! match. A missing sensor, bus timeout or CRC mismatch is reported, and the next
+//! reading is tried as usual, so the sensor can be connected while running.
+//!
+//! Values are kept in hundredths of a degree Celsius and of a percent, so the
+//! datasheet conversion formulas in `hello_nucleo_f103rb::sht3x` run in integer
+//! arithmetic.
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use heapless::String;
+use hello_nucleo_f103rb::{
+    board::Board,
+    sht3x::{checked_word, convert_humidity, convert_temp},
+};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    i2c::{BlockingI2c, Error as I2cError, Mode},
+    pac::USART2,
+    prelude::*,
+    serial::{Config, Serial, Tx},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const SHT3X_ADDRESS: u8 = 0x44; // 0x45 with ADDR pulled high
+const SHT3X_MEASURE_HIGH: [u8; 2] = [0x24, 0x00];
+// The datasheet gives a maximum of 15.5ms for a high repeatability measurement.
+const SHT3X_MEASURE_MS: u32 = 16;
+const READING_INTERVAL_MS: u32 = 2_000;
+
+enum SensorError {
+    NoResponse,
+    Timeout,
+    Bus,
+    Crc,
+}
+
+impl From<I2cError> for SensorError {
+    fn from(error: I2cError) -> Self {
+        match error {
+            I2cError::Acknowledge => SensorError::NoResponse,
+            I2cError::Timeout => SensorError::Timeout,
+            _ => SensorError::Bus,
+        }
+    }
+}
+
+fn start_measurement<I>(i2c: &mut I) -> Result<(), SensorError>
+where
+    I: embedded_hal_02::blocking::i2c::Write<Error = I2cError>,
+{
+    Ok(i2c.write(SHT3X_ADDRESS, &SHT3X_MEASURE_HIGH)?)
+}
+
+/// Read the finished measurement, as raw temperature and humidity words.
+fn read_measurement<I>(i2c: &mut I) -> Result<(u16, u16), SensorError>
+where
+    I: embedded_hal_02::blocking::i2c::Read<Error = I2cError>,
+{
+    let mut response = [0u8; 6];
+    i2c.read(SHT3X_ADDRESS, &mut response)?;
+    let temp = checked_word(&response[..3]).ok_or(SensorError::Crc)?;
+    let humidity = checked_word(&response[3..]).ok_or(SensorError::Crc)?;
+    Ok((temp, humidity))
+}
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_reading(tx: &mut Tx<USART2>, temp: i32, humidity: u16) {
+    let sign = if temp < 0 { "-" } else { "" };
+    let temp = temp.unsigned_abs();
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(
+        buffer,
+        "Temperature {}{}.{:02} C, humidity {}.{:02} %RH.",
+        sign,
+        temp / 100,
+        temp % 100,
+        humidity / 100,
+        humidity % 100
+    )
+    .unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_error(tx: &mut Tx<USART2>, error: SensorError) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    match error {
+        SensorError::NoResponse => write!(
+            buffer,
+            "No SHT3x response at address 0x{:02X}.",
+            SHT3X_ADDRESS
+        )
+        .unwrap(),
+        SensorError::Timeout => write!(buffer, "I2C bus timeout.").unwrap(),
+        SensorError::Bus => write!(buffer, "I2C bus error.").unwrap(),
+        SensorError::Crc => write!(buffer, "SHT3x CRC mismatch, reading discarded.").unwrap(),
+    }
+    send_string(tx, &buffer);
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks and the delay provider, and acquire GPIOA and GPIOB.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut delay = board.delay;
+    let mut gpioa = board.gpioa;
+    let mut gpiob = board.gpiob;
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, _rx) = serial.split();
+
+    // I2C needs open drain pins. The timeouts are in microseconds, and keep a
+    // missing or stuck sensor from hanging the bus forever.
+    let scl = gpiob.pb8.into_alternate_open_drain(&mut gpiob.crh);
+    let sda = gpiob.pb9.into_alternate_open_drain(&mut gpiob.crh);
+    let mut i2c = BlockingI2c::i2c1(
+        board.i2c1,
+        (scl, sda),
+        &mut afio.mapr,
+        Mode::standard(100.kHz()),
+        clocks,
+        1_000,
+        10,
+        1_000,
+        1_000,
+    );
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    send_start_message(&mut tx);
+    send_string(
+        &mut tx,
+        "Reading an SHT3x sensor on PB8 (SCL) and PB9 (SDA).",
+    );
+
+    loop {
+        let reading = start_measurement(&mut i2c).and_then(|_| {
+            delay.delay_ms(SHT3X_MEASURE_MS);
+            read_measurement(&mut i2c)
+        });
+        match reading {
+            Ok((raw_temp, raw_humidity)) => send_reading(
+                &mut tx,
+                convert_temp(raw_temp),
+                convert_humidity(raw_humidity),
+            ),
+            Err(error) => send_error(&mut tx, error),
+        }
+        delay.delay_ms(READING_INTERVAL_MS);
+    }
+}
@@ -0,0 +1,222 @@
+// examples/sine_dds.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example generates a sine wave on PA6 (Arduino D12) with direct digital
+//! synthesis (DDS), for audio or for trying out waveform generation.
+//!
+//! The STM32F103RB has no DAC, so TIM3 channel 1 outputs PWM, with an auto-reload
+//! value of 255, so the duty cycle takes one byte. At a 48MHz timer clock, the PWM
+//! runs at 187.5kHz, far above the audio band. An RC low pass filter turns the duty
+//! cycle into a voltage, for example 1k ohm in series and 100nF to ground, which
+//! cuts off around 1.6kHz. Use a larger capacitor for a smoother, quieter output,
+//! and a smaller one for higher tones. A speaker needs an amplifier after the filter.
+//!
+//! TIM2 interrupts at `SAMPLE_HZ`, and the interrupt handler takes the next sample
+//! from the sine table with `next_sample`, and writes it to the compare register.
+//! See `hello_nucleo_f103rb::dds` for how the phase accumulator works.
+//!
+//! Type a frequency in Hz over USART and press enter to change it, for example
+//! `440` followed by enter. `?` displays the help message.
+
+use core::fmt::Write;
+use cortex_m::peripheral::NVIC;
+use cortex_m_rt::entry;
+use heapless::String;
+use hello_nucleo_f103rb::{
+    board::Board,
+    dds::{next_sample, output_hz, phase_increment, sine_table, SINE_TABLE_SIZE},
+    parse::parse_clamped,
+    shared::Shared,
+};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    gpio::{IOPinSpeed, OutputSpeed},
+    pac::{interrupt, Interrupt, TIM2, TIM3, USART2},
+    prelude::*,
+    serial::{Config, Serial, Tx},
+    timer::{CounterHz, Event, Timer},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const SAMPLE_HZ: u32 = 16_000;
+const DEFAULT_HZ: u32 = 440;
+const MIN_HZ: u32 = 1;
+// At least four samples per cycle, so the output still resembles a sine wave.
+const MAX_HZ: u32 = SAMPLE_HZ / 4;
+// More digits than any u32 has, so a long entry is clamped rather than cut short.
+const ENTRY_SIZE: usize = 16;
+const PWM_MAX_DUTY: u16 = u8::MAX as u16;
+
+static SINE_TABLE: [u8; SINE_TABLE_SIZE] = sine_table();
+
+/// Everything the sample interrupt needs, so it takes a single critical section.
+struct SineOutput {
+    pwm: TIM3,
+    phase: u32,
+    increment: u32,
+}
+
+static G_TIMER: Shared<Option<CounterHz<TIM2>>> = Shared::new(None);
+static G_OUTPUT: Shared<Option<SineOutput>> = Shared::new(None);
+
+#[interrupt]
+fn TIM2() {
+    G_TIMER.with(|timer| {
+        if let Some(timer) = timer.as_mut() {
+            timer.clear_interrupt(Event::Update);
+        }
+    });
+    G_OUTPUT.with(|output| {
+        if let Some(output) = output.as_mut() {
+            let sample = next_sample(&mut output.phase, output.increment, &SINE_TABLE);
+            output.pwm.ccr1().write(|w| w.ccr().bits(sample as u16));
+        }
+    });
+}
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_help_text(tx: &mut Tx<USART2>) {
+    let help_text = "\
+A sine wave is output as PWM on PA6 (Arduino D12), filter it with an RC low pass.\r\n\
+Type a frequency from 1 Hz to 4000 Hz and press enter to change it.\r\n\
+? - Display this help message\
+";
+    send_string(tx, help_text);
+}
+
+fn send_frequency(tx: &mut Tx<USART2>, increment: u32, clamped: bool) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    if clamped {
+        write!(buffer, "Out of range. ").unwrap();
+    }
+    write!(
+        buffer,
+        "Sine wave {} Hz, {} samples per second.",
+        output_hz(increment, SAMPLE_HZ),
+        SAMPLE_HZ
+    )
+    .unwrap();
+    send_string(tx, &buffer);
+}
+
+/// Change the frequency, keeping the phase so the wave stays continuous.
+fn set_increment(increment: u32) {
+    G_OUTPUT.with(|output| {
+        if let Some(output) = output.as_mut() {
+            output.increment = increment;
+        }
+    });
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, and acquire the GPIOA pins.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut gpioa = board.gpioa;
+
+    // Configure PA6 as the TIM3 channel 1 output. The filter removes the edges
+    // anyway, so a slow slew rate is plenty.
+    let mut pin = gpioa.pa6.into_alternate_push_pull(&mut gpioa.crl); // Arduino D12
+    pin.set_speed(&mut gpioa.crl, IOPinSpeed::Mhz2);
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, mut rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    send_start_message(&mut tx);
+    send_help_text(&mut tx);
+
+    // The HAL enables and resets TIM3, then the registers are programmed directly.
+    // PWM mode 1 drives the output high while the count is below CCR1. The compare
+    // register is preloaded, so each sample takes effect at the end of a PWM period.
+    let pwm = Timer::new(board.tim3, &clocks).release();
+    pwm.psc.write(|w| w.psc().bits(0));
+    pwm.arr.write(|w| w.arr().bits(PWM_MAX_DUTY));
+    pwm.ccr1().write(|w| w.ccr().bits(SINE_TABLE[0] as u16));
+    pwm.ccmr1_output()
+        .modify(|_, w| w.oc1m().pwm_mode1().oc1pe().set_bit());
+    pwm.ccer.modify(|_, w| w.cc1e().set_bit());
+    pwm.egr.write(|w| w.ug().set_bit());
+    pwm.cr1.modify(|_, w| w.arpe().set_bit().cen().set_bit());
+
+    let increment = phase_increment(DEFAULT_HZ, SAMPLE_HZ);
+    G_OUTPUT.lock_set(Some(SineOutput {
+        pwm,
+        phase: 0,
+        increment,
+    }));
+    send_frequency(&mut tx, increment, false);
+
+    // TIM2 sets the sample rate. The board started it as a delay, so take the raw
+    // timer back first.
+    let mut timer = board.delay.release().release().counter_hz(&clocks);
+    timer.start(SAMPLE_HZ.Hz()).unwrap();
+    timer.listen(Event::Update);
+    G_TIMER.lock_set(Some(timer));
+
+    // Unmasking an interrupt is unsafe because it can break critical sections,
+    // but all shared state is only accessed through `Shared`.
+    #[allow(unsafe_code)]
+    unsafe {
+        NVIC::unmask(Interrupt::TIM2);
+    }
+
+    let mut entry: String<ENTRY_SIZE> = String::new();
+    loop {
+        match rx.read() {
+            Ok(b'?') => {
+                send_help_text(&mut tx);
+            }
+            Ok(c @ b'0'..=b'9') => {
+                if entry.push(c as char).is_ok() {
+                    block!(tx.write(c)).ok();
+                }
+            }
+            Ok(b'\r') => {
+                if let Ok(hz) = parse_clamped(&entry, MIN_HZ, MAX_HZ) {
+                    let increment = phase_increment(hz.value, SAMPLE_HZ);
+                    set_increment(increment);
+                    send_frequency(&mut tx, increment, hz.clamped);
+                }
+                entry.clear();
+            }
+            Ok(_) => (),
+            Err(nb::Error::WouldBlock) => (),
+            Err(_) => (),
+        }
+    }
+}
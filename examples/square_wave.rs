@@ -0,0 +1,255 @@
+// examples/square_wave.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example outputs a 50% duty cycle square wave on PA6 (Arduino D12) for
+//! oscilloscope practice and calibration. The pin is toggled from the TIM3 update
+//! interrupt, so the timer fires at twice the output frequency.
+//!
+//! The output starts at 1kHz. Type a frequency in Hz over USART and press enter to
+//! change it, for example `2500` followed by enter. `?` displays the help message.
+//!
+//! `timer_reload_for_hz` from `hello_nucleo_f103rb::timer` computes the prescaler
+//! (PSC) and auto-reload (ARR) values written to TIM3. The timer runs at
+//! `(PSC + 1) * (ARR + 1)` timer clock ticks per update event, so not every
+//! frequency can be hit exactly. The achieved frequency is reported after every
+//! change so it can be compared against the scope reading.
+//! `t` reports the PSC and ARR values currently in TIM3, to check the timer math,
+//! and `n` cycles those values between decimal, hexadecimal and binary.
+
+use core::fmt::Write;
+use cortex_m_rt::entry;
+use heapless::String;
+use hello_nucleo_f103rb::{
+    board::Board,
+    num_format::{format_num, NumFormat, NUM_FORMAT_SIZE},
+    parse::parse_clamped,
+    pins::push_pull_output,
+    shared::Shared,
+    timer::{tick_hz, timer_reload_for_hz},
+};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    gpio::{ErasedPin, IOPinSpeed, Output},
+    pac::{interrupt, Interrupt, TIM3, USART2},
+    prelude::*,
+    serial::{Config, Serial, Tx},
+    timer::Timer,
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const DEFAULT_HZ: u32 = 1_000;
+const MIN_HZ: u32 = 1;
+const MAX_HZ: u32 = 50_000;
+// Enough digits for any `u32`, and a few more that are only ever clamped.
+const ENTRY_SIZE: usize = 16;
+
+static G_PIN: Shared<Option<ErasedPin<Output>>> = Shared::new(None);
+static G_TIM: Shared<Option<TIM3>> = Shared::new(None);
+
+fn apply_frequency(tim: &TIM3, pclk_hz: u32, hz: u32) -> u32 {
+    // Toggling on every update event halves the frequency at the pin.
+    let (psc, arr) = timer_reload_for_hz(pclk_hz, 2 * hz);
+    tim.cr1.modify(|_, w| w.cen().clear_bit());
+    tim.psc.write(|w| w.psc().bits(psc));
+    tim.arr.write(|w| w.arr().bits(arr));
+    // Load the new values immediately, and discard the update event this generates.
+    tim.egr.write(|w| w.ug().set_bit());
+    tim.sr.modify(|_, w| w.uif().clear_bit());
+    tim.cr1.modify(|_, w| w.cen().set_bit());
+    tick_hz(pclk_hz, psc, arr) / 2
+}
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_help_text(tx: &mut Tx<USART2>) {
+    let help_text = "\
+A square wave is output on PA6 (Arduino D12).\r\n\
+Type a frequency in Hz and press enter to change it.\r\n\
+t - Display the TIM3 prescaler and auto-reload values\r\n\
+n - Cycle numbers between decimal, hexadecimal and binary\r\n\
+? - Display this help message\
+";
+    send_string(tx, help_text);
+}
+
+fn send_out_of_range(tx: &mut Tx<USART2>, entered: &str, hz: u32) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(
+        buffer,
+        "{} Hz is out of range, {} to {} Hz, using {} Hz.",
+        entered, MIN_HZ, MAX_HZ, hz
+    )
+    .unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_frequency(tx: &mut Tx<USART2>, requested: u32, achieved: u32) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(
+        buffer,
+        "Requested {} Hz, output {} Hz.",
+        requested, achieved
+    )
+    .unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_timer_registers(
+    tx: &mut Tx<USART2>,
+    num_format: NumFormat,
+    pclk_hz: u32,
+    psc: u16,
+    arr: u16,
+) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    let mut number: String<NUM_FORMAT_SIZE> = String::new();
+    format_num(psc as u32, num_format, &mut number);
+    write!(buffer, "TIM3 PSC {}", number).unwrap();
+    send_string(tx, &buffer);
+    buffer.clear();
+    format_num(arr as u32, num_format, &mut number);
+    write!(buffer, "TIM3 ARR {}", number).unwrap();
+    send_string(tx, &buffer);
+    buffer.clear();
+    format_num(pclk_hz, num_format, &mut number);
+    write!(
+        buffer,
+        "Clock {} Hz, update {} Hz.",
+        number,
+        tick_hz(pclk_hz, psc, arr)
+    )
+    .unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_num_format(tx: &mut Tx<USART2>, num_format: NumFormat) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Numbers are displayed in {}.", num_format.name()).unwrap();
+    send_string(tx, &buffer);
+}
+
+#[interrupt]
+fn TIM3() {
+    G_TIM.with(|tim| {
+        if let Some(tim) = tim.as_ref() {
+            tim.sr.modify(|_, w| w.uif().clear_bit());
+        }
+    });
+    G_PIN.with(|pin| {
+        if let Some(pin) = pin.as_mut() {
+            pin.toggle();
+        }
+    });
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, and acquire the GPIOA pins.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut gpioa = board.gpioa;
+
+    // Configure the square wave output pin as push-pull output.
+    // Use the fastest slew rate for clean edges on the oscilloscope.
+    let pin = push_pull_output(gpioa.pa6, &mut gpioa.crl, IOPinSpeed::Mhz50).erase(); // Arduino D12
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, mut rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    send_start_message(&mut tx);
+    send_help_text(&mut tx);
+
+    // The HAL enables and resets TIM3, then the registers are programmed directly.
+    let pclk_hz = clocks.pclk1_tim().raw();
+    let tim = Timer::new(board.tim3, &clocks).release();
+    let achieved = apply_frequency(&tim, pclk_hz, DEFAULT_HZ);
+    tim.dier.modify(|_, w| w.uie().set_bit());
+    send_frequency(&mut tx, DEFAULT_HZ, achieved);
+
+    // Move the pin and timer into global storage for the interrupt handler.
+    G_PIN.lock_set(Some(pin));
+    G_TIM.lock_set(Some(tim));
+
+    // Unmasking an interrupt is unsafe because it can break critical sections,
+    // but all shared state is only accessed through `Shared`.
+    #[allow(unsafe_code)]
+    unsafe {
+        cortex_m::peripheral::NVIC::unmask(Interrupt::TIM3);
+    }
+
+    let mut entered: String<ENTRY_SIZE> = String::new();
+    let mut num_format = NumFormat::Dec;
+    loop {
+        match rx.read() {
+            Ok(b'?') => {
+                send_help_text(&mut tx);
+            }
+            Ok(b't') => {
+                let registers = G_TIM.with(|tim| {
+                    tim.as_ref()
+                        .map(|tim| (tim.psc.read().psc().bits(), tim.arr.read().arr().bits()))
+                });
+                if let Some((psc, arr)) = registers {
+                    send_timer_registers(&mut tx, num_format, pclk_hz, psc, arr);
+                }
+            }
+            Ok(b'n') => {
+                num_format = num_format.next();
+                send_num_format(&mut tx, num_format);
+            }
+            Ok(c @ b'0'..=b'9') => {
+                if entered.push(c as char).is_ok() {
+                    block!(tx.write(c)).ok();
+                }
+            }
+            Ok(b'\r') => {
+                if let Ok(requested) = parse_clamped(&entered, MIN_HZ, MAX_HZ) {
+                    let hz = requested.value;
+                    if requested.clamped {
+                        send_out_of_range(&mut tx, &entered, hz);
+                    }
+                    let achieved =
+                        G_TIM.with(|tim| tim.as_ref().map(|tim| apply_frequency(tim, pclk_hz, hz)));
+                    send_frequency(&mut tx, hz, achieved.unwrap_or(0));
+                }
+                entered.clear();
+            }
+            Ok(_) => (),
+            Err(nb::Error::WouldBlock) => (),
+            Err(_) => (),
+        }
+    }
+}
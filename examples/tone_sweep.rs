@@ -0,0 +1,174 @@
+// examples/tone_sweep.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example sweeps a PWM tone from 200Hz up to 2kHz and back down, over and
+//! over, to test a speaker or buzzer connected to PA6 (Arduino D12) and ground.
+//! A small speaker needs a series resistor, around 100 ohms, to limit the current.
+//!
+//! TIM3 channel 1 generates the tone in hardware at 50% duty cycle. Every
+//! `STEP_MS` milliseconds, `sweep_freq` picks the frequency for the current time,
+//! and `timer_reload_for_hz` reprograms the timer. The prescaler, auto-reload and
+//! compare registers are preloaded, so new values take effect at the end of the
+//! current period, without glitches.
+//!
+//! Type a sweep period in milliseconds over USART and press enter to change the
+//! sweep rate, for example `1000` followed by enter. `?` displays the help message.
+
+use core::fmt::Write;
+use cortex_m_rt::{entry, exception};
+use heapless::String;
+use hello_nucleo_f103rb::{
+    board::Board,
+    millis::{self, millis},
+    timer::{sweep_freq, timer_reload_for_hz},
+};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    gpio::{IOPinSpeed, OutputSpeed},
+    pac::{TIM3, USART2},
+    prelude::*,
+    serial::{Config, Serial, Tx},
+    timer::Timer,
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const LOW_HZ: u32 = 200;
+const HIGH_HZ: u32 = 2_000;
+const DEFAULT_PERIOD_MS: u32 = 4_000;
+const MIN_PERIOD_MS: u32 = 100;
+const MAX_PERIOD_MS: u32 = 60_000;
+const STEP_MS: u32 = 10;
+
+fn set_tone(tim: &TIM3, pclk_hz: u32, hz: u32) {
+    let (psc, arr) = timer_reload_for_hz(pclk_hz, hz);
+    tim.psc.write(|w| w.psc().bits(psc));
+    tim.arr.write(|w| w.arr().bits(arr));
+    tim.ccr1().write(|w| w.ccr().bits(arr / 2));
+}
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_help_text(tx: &mut Tx<USART2>) {
+    let help_text = "\
+A tone sweeping from 200Hz to 2kHz and back is output on PA6 (Arduino D12).\r\n\
+Type a sweep period in ms and press enter to change the sweep rate.\r\n\
+? - Display this help message\
+";
+    send_string(tx, help_text);
+}
+
+fn send_period(tx: &mut Tx<USART2>, period_ms: u32) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Sweep period {} ms.", period_ms).unwrap();
+    send_string(tx, &buffer);
+}
+
+#[exception]
+fn SysTick() {
+    millis::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, and acquire the GPIOA pins.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut gpioa = board.gpioa;
+
+    // Configure PA6 as the TIM3 channel 1 output. The tone is audio, so a slow
+    // slew rate is plenty.
+    let mut pin = gpioa.pa6.into_alternate_push_pull(&mut gpioa.crl); // Arduino D12
+    pin.set_speed(&mut gpioa.crl, IOPinSpeed::Mhz2);
+
+    // Start the millisecond time base that drives the sweep.
+    let cp = cortex_m::Peripherals::take().unwrap();
+    millis::init(cp.SYST, &clocks);
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, mut rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    send_start_message(&mut tx);
+    send_help_text(&mut tx);
+
+    // The HAL enables and resets TIM3, then the registers are programmed directly.
+    // PWM mode 1 drives the output high while the count is below CCR1.
+    let pclk_hz = clocks.pclk1_tim().raw();
+    let tim = Timer::new(board.tim3, &clocks).release();
+    set_tone(&tim, pclk_hz, LOW_HZ);
+    tim.ccmr1_output()
+        .modify(|_, w| w.oc1m().pwm_mode1().oc1pe().set_bit());
+    tim.ccer.modify(|_, w| w.cc1e().set_bit());
+    tim.egr.write(|w| w.ug().set_bit());
+    tim.cr1.modify(|_, w| w.arpe().set_bit().cen().set_bit());
+
+    let mut period_ms = DEFAULT_PERIOD_MS;
+    send_period(&mut tx, period_ms);
+    let mut requested: u32 = 0;
+    let mut last_step_ms = millis();
+    loop {
+        match rx.read() {
+            Ok(b'?') => {
+                send_help_text(&mut tx);
+            }
+            Ok(c @ b'0'..=b'9') => {
+                requested = requested
+                    .saturating_mul(10)
+                    .saturating_add((c - b'0') as u32);
+                block!(tx.write(c)).ok();
+            }
+            Ok(b'\r') => {
+                if 0 < requested {
+                    period_ms = requested.clamp(MIN_PERIOD_MS, MAX_PERIOD_MS);
+                    send_period(&mut tx, period_ms);
+                }
+                requested = 0;
+            }
+            Ok(_) => (),
+            Err(nb::Error::WouldBlock) => (),
+            Err(_) => (),
+        }
+
+        let now_ms = millis();
+        if STEP_MS <= now_ms.wrapping_sub(last_step_ms) {
+            last_step_ms = now_ms;
+            set_tone(
+                &tim,
+                pclk_hz,
+                sweep_freq(now_ms, LOW_HZ, HIGH_HZ, period_ms),
+            );
+        }
+    }
+}
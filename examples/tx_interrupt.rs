@@ -0,0 +1,198 @@
+// examples/tx_interrupt.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example sends text from the USART2 interrupt, with `InterruptTx` from
+//! `hello_nucleo_f103rb::tx_interrupt`, so queuing text returns immediately, and
+//! shows that the main loop keeps its timing while a large block goes out.
+//!
+//! The onboard LED LD2 toggles every `TOGGLE_MS` milliseconds, timed with `millis`
+//! in the main loop. The main loop also keeps track of the longest pass through
+//! it, which is how late a toggle can be.
+//!
+//! `i` queues a block of `BLOCK_LINES` lines, about 1KB, all at once, and the
+//! interrupt handler sends it in the background, roughly 85ms at 115200 baud. The
+//! loop keeps running the whole time, and the LED keeps its rhythm. `b` sends the
+//! same block the way `send_string` does in the other examples, waiting for every
+//! line to go out before moving on, so the loop stalls until the whole block is
+//! sent. The LED visibly stutters. Either way, the longest pass is reported once
+//! the block is out.
+
+use core::fmt::Write;
+use cortex_m::peripheral::NVIC;
+use cortex_m_rt::{entry, exception};
+use heapless::String;
+use hello_nucleo_f103rb::{
+    board::Board,
+    millis::{self, millis},
+    tx_interrupt::InterruptTx,
+};
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    pac::{interrupt, Interrupt, USART2},
+    prelude::*,
+    serial::{Config, Serial},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const TX_QUEUE_SIZE: usize = 1_024;
+const TOGGLE_MS: u32 = 50;
+/// 12 lines of 76 characters, 79 bytes each with the line endings, fit in the
+/// queue at once.
+const BLOCK_LINES: u32 = 12;
+const BLOCK_LINE_LENGTH: usize = 76;
+
+static TX: InterruptTx<USART2, TX_QUEUE_SIZE> = InterruptTx::new();
+
+#[interrupt]
+fn USART2() {
+    TX.on_interrupt();
+}
+
+#[exception]
+fn SysTick() {
+    millis::tick();
+}
+
+#[derive(Clone, Copy)]
+enum SendMode {
+    Interrupt,
+    Blocking,
+}
+
+/// Queue `string` and a line ending, returning at once.
+fn send_string(string: &str) {
+    rprintln!("{}", string);
+    write!(&TX, "\r{}\r\n", string).ok();
+}
+
+/// Queue `string` like `send_string`, then wait until it has all been sent.
+fn send_string_blocking(string: &str) {
+    send_string(string);
+    while !TX.is_empty() {}
+}
+
+fn send_start_message() {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(&buffer);
+}
+
+fn send_help_text() {
+    let help_text = "\
+The following commands can be sent of USART:\r\n\
+i - Send a 1KB block from the interrupt, while the LED keeps time\r\n\
+b - Send the same block, waiting for each line, so the LED stalls\r\n\
+? - Display this help message\
+";
+    send_string(help_text);
+}
+
+/// Line `n` of the test block, a line number followed by printable filler.
+fn block_line(n: u32) -> String<BUFFER_SIZE> {
+    let mut line: String<BUFFER_SIZE> = String::new();
+    write!(line, "{:02} ", n).unwrap();
+    let filler = (b'A'..=b'Z').cycle().skip(n as usize);
+    for c in filler.take(BLOCK_LINE_LENGTH - line.len()) {
+        line.push(c as char).unwrap();
+    }
+    line
+}
+
+fn send_block(mode: SendMode) {
+    for n in 0..BLOCK_LINES {
+        let line = block_line(n);
+        match mode {
+            SendMode::Interrupt => send_string(&line),
+            SendMode::Blocking => send_string_blocking(&line),
+        }
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks and the onboard LED LD2, and acquire the GPIOA pins.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut led = board.led; // On Board LED LD2
+    let mut gpioa = board.gpioa;
+
+    // Start the millisecond time base used for the LED.
+    let cp = cortex_m::Peripherals::take().unwrap();
+    millis::init(cp.SYST, &clocks);
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (tx, mut rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    // The receiver stays in the main loop, only transmission uses the interrupt.
+    TX.init(tx);
+    // Unmasking an interrupt is unsafe because it can break critical sections,
+    // but all shared state is only accessed through `Shared`.
+    #[allow(unsafe_code)]
+    unsafe {
+        NVIC::unmask(Interrupt::USART2);
+    }
+
+    send_start_message();
+    send_help_text();
+
+    let mut last_toggle_ms = millis();
+    let mut last_pass_ms = millis();
+    let mut longest_pass_ms: u32 = 0;
+    let mut sending = false;
+    loop {
+        let now_ms = millis();
+        longest_pass_ms = longest_pass_ms.max(now_ms.wrapping_sub(last_pass_ms));
+        last_pass_ms = now_ms;
+
+        // Report once the block is out, so the report itself is not measured. The
+        // check follows the measurement, so a pass that blocked is counted first.
+        if sending && TX.is_empty() {
+            sending = false;
+            let mut buffer: String<BUFFER_SIZE> = String::new();
+            write!(buffer, "Block sent. Longest pass {} ms.", longest_pass_ms).unwrap();
+            send_string(&buffer);
+        }
+
+        if TOGGLE_MS <= now_ms.wrapping_sub(last_toggle_ms) {
+            last_toggle_ms = now_ms;
+            led.toggle();
+        }
+
+        let mode = match rx.read() {
+            Ok(b'i') => Some(SendMode::Interrupt),
+            Ok(b'b') => Some(SendMode::Blocking),
+            Ok(b'?') => {
+                send_help_text();
+                None
+            }
+            _ => None,
+        };
+        if let Some(mode) = mode {
+            longest_pass_ms = 0;
+            send_block(mode);
+            sending = true;
+        }
+    }
+}
@@ -0,0 +1,164 @@
+// examples/uart_bridge.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example bridges two USARTs, forwarding bytes in both directions.
+//!
+//! - USART2 on PA2/PA3 is connected to the host through the ST-Link.
+//! - USART1 on PA9 (TX, Arduino D8) and PA10 (RX, Arduino D2) is connected to a
+//!   downstream device. Remember to connect the grounds.
+//!
+//! Bytes flowing from the host to the device pass through `convert_case`, and user
+//! button B1 cycles the text conversion mode. Bytes flowing from the device to the
+//! host are forwarded unchanged.
+//!
+//! Each direction is buffered in a bounded `TxQueue`, so a burst from the faster
+//! side does not stall the other direction. If one side produces data faster than
+//! the other can accept it, bytes that do not fit in the queue are dropped and
+//! counted. So are bytes lost to a USART overrun, when the main loop falls behind
+//! the incoming data. Drop counts and mode changes are reported over RTT so diagnostics never
+//! mix with the bridged data.
+//!
+//! With the `flow-control` feature, USART1 also uses RTS/CTS hardware flow control,
+//! with CTS on PA11 and RTS on PA12. The device must support flow control, with its
+//! RTS wired to PA11 and its CTS wired to PA12. CTS is pulled down, so an unconnected
+//! CTS never stops transmission. See `hello_nucleo_f103rb::flow_control`.
+
+use cortex_m_rt::entry;
+#[cfg(feature = "flow-control")]
+use hello_nucleo_f103rb::flow_control::enable_rts_cts;
+use hello_nucleo_f103rb::{
+    board::Board,
+    text::{convert_case, TextMode},
+    tx_queue::TxQueue,
+};
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    prelude::*,
+    serial::{Config, Error, Instance, Rx, Serial},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BAUD_RATE: u32 = 115200;
+const QUEUE_SIZE: usize = 256;
+
+/// Move one received byte, if any, into `queue`.
+/// Returns false if a byte was lost, either received but did not fit, or
+/// overwritten in the USART before it was read. An overrun loses at least one
+/// byte, and is counted as one.
+fn forward<USART: Instance>(
+    rx: &mut Rx<USART>,
+    queue: &mut TxQueue<QUEUE_SIZE>,
+    text_mode: &TextMode,
+) -> bool {
+    match rx.read() {
+        Ok(c) => queue.enqueue(convert_case(c, text_mode)).is_ok(),
+        Err(nb::Error::WouldBlock) => true,
+        Err(nb::Error::Other(Error::Overrun)) => false,
+        Err(_) => true,
+    }
+}
+
+fn text_mode_name(text_mode: &TextMode) -> &'static str {
+    match text_mode {
+        TextMode::NormalCase => "normal case",
+        TextMode::ForceUpper => "upper case",
+        TextMode::ForceLower => "lower case",
+        TextMode::InvertedCase => "inverted case",
+        TextMode::Rot(_) => "rotated letters",
+        TextMode::Leet => "leetspeak",
+        TextMode::HexDump => "hex dump",
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks and user button B1, and acquire the GPIOA pins.
+    // B1 has an external pull-up and reads low when pressed, see `board` and `pins`.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let button = board.button;
+    let mut gpioa = board.gpioa;
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let host = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(BAUD_RATE.bps()),
+        &clocks,
+    );
+    let (mut host_tx, mut host_rx) = host.split();
+
+    // Prepare Tx and Rx pins, and setup USART1 for the downstream device.
+    let tx = gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh);
+    let rx = gpioa.pa10;
+    let device = Serial::new(
+        board.usart1,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(BAUD_RATE.bps()),
+        &clocks,
+    );
+    let (mut device_tx, mut device_rx) = device.split();
+
+    // Optionally, add RTS/CTS flow control to USART1.
+    #[cfg(feature = "flow-control")]
+    {
+        let _cts = gpioa.pa11.into_pull_down_input(&mut gpioa.crh);
+        let _rts = gpioa.pa12.into_alternate_push_pull(&mut gpioa.crh);
+        enable_rts_cts(&mut device_tx);
+    }
+
+    rtt_init_print!();
+    rprintln!("Hello, {}!", BOARD);
+    rprintln!("Bridging USART2 (host) and USART1 (device).");
+    #[cfg(feature = "flow-control")]
+    rprintln!("USART1 uses RTS/CTS flow control.");
+    rprintln!("Press user button B1 to cycle the host to device text conversion.");
+
+    let mut to_device: TxQueue<QUEUE_SIZE> = TxQueue::new();
+    let mut to_host: TxQueue<QUEUE_SIZE> = TxQueue::new();
+    let mut dropped_to_device: u32 = 0;
+    let mut dropped_to_host: u32 = 0;
+    let mut reported_drops: u32 = 0;
+    let mut button_down = false;
+    let mut text_mode = TextMode::NormalCase;
+    loop {
+        if !forward(&mut host_rx, &mut to_device, &text_mode) {
+            dropped_to_device += 1;
+        }
+        if !forward(&mut device_rx, &mut to_host, &TextMode::NormalCase) {
+            dropped_to_host += 1;
+        }
+        to_device.pump(&mut device_tx);
+        to_host.pump(&mut host_tx);
+
+        // Report drops once the burst that caused them has drained.
+        let drops = dropped_to_device + dropped_to_host;
+        if reported_drops != drops && to_device.is_empty() && to_host.is_empty() {
+            rprintln!(
+                "Dropped {} bytes to device, {} bytes to host.",
+                dropped_to_device,
+                dropped_to_host
+            );
+            reported_drops = drops;
+        }
+
+        let button_state = button.is_pressed();
+        if button_state && !button_down {
+            text_mode = text_mode.next();
+            rprintln!("Host to device text uses {}.", text_mode_name(&text_mode));
+        }
+        button_down = button_state;
+    }
+}
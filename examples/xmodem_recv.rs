@@ -0,0 +1,189 @@
+// examples/xmodem_recv.rs
+
+#![deny(unsafe_code)]
+#![no_std]
+#![no_main]
+
+//! This example receives a file into RAM over USART2 using the XMODEM/CRC protocol.
+//!
+//! Start an XMODEM send from the serial terminal, for example with `sx` or the
+//! terminal's file transfer menu. The board sends `C` every few seconds until the
+//! transfer starts. Each 128 byte block is checked with its CRC and answered with
+//! ACK or NAK. Only CRC mode is supported, not the older checksum mode.
+//!
+//! Up to `BLOB_MAX` bytes are stored. A larger file cancels the transfer. When the
+//! transfer ends, its size and the CRC of the received data are reported, and the
+//! board waits for the next transfer. Progress is reported over RTT only, because
+//! any text sent over USART during a transfer would corrupt it.
+
+use core::fmt::Write;
+use cortex_m_rt::{entry, exception};
+use heapless::String;
+use hello_nucleo_f103rb::{
+    board::Board,
+    millis::{self, millis},
+    xmodem::{xmodem_crc, Receiver, XmodemEvent, ACK, BLOCK_SIZE, CAN, CRC_MODE, NAK},
+};
+use nb::block;
+#[cfg(not(feature = "panic-sos"))]
+use panic_halt as _;
+use rtt_target::{rprintln, rtt_init_print};
+use stm32f1xx_hal::{
+    pac::USART2,
+    prelude::*,
+    serial::{Config, Serial, Tx},
+};
+
+const BOARD: &str = "Nucleo-F103RB";
+const BUFFER_SIZE: usize = 128;
+const BLOB_MAX: usize = 4 * 1024;
+const CRC_MODE_INTERVAL_MS: u32 = 3_000;
+const PACKET_TIMEOUT_MS: u32 = 1_000;
+
+fn send_string(tx: &mut Tx<USART2>, string: &str) {
+    rprintln!("{}", string);
+    write!(tx, "\r{}\r\n", string).unwrap();
+    block!(tx.flush()).unwrap();
+}
+
+fn send_start_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(buffer, "Hello, {}!", BOARD).unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_ready_message(tx: &mut Tx<USART2>) {
+    let mut buffer: String<BUFFER_SIZE> = String::new();
+    write!(
+        buffer,
+        "Ready to receive up to {} bytes with XMODEM/CRC.",
+        BLOB_MAX
+    )
+    .unwrap();
+    send_string(tx, &buffer);
+}
+
+fn send_reply(tx: &mut Tx<USART2>, reply: u8) {
+    block!(tx.write(reply)).ok();
+}
+
+fn cancel_transfer(tx: &mut Tx<USART2>) {
+    send_reply(tx, CAN);
+    send_reply(tx, CAN);
+    block!(tx.flush()).ok();
+}
+
+#[exception]
+fn SysTick() {
+    millis::tick();
+}
+
+#[entry]
+fn main() -> ! {
+    // Set up the clocks, and acquire the GPIOA pins.
+    let board = Board::take();
+    let clocks = board.clocks;
+    let mut gpioa = board.gpioa;
+
+    // Start the millisecond time base used for protocol timeouts.
+    let cp = cortex_m::Peripherals::take().unwrap();
+    millis::init(cp.SYST, &clocks);
+
+    // Acquire alternate function input/output (AFIO).
+    let mut afio = board.afio.constrain();
+
+    // Prepare Tx and Rx pins, and setup ST-Link connected USART2.
+    let tx = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+    let rx = gpioa.pa3;
+    let serial = Serial::new(
+        board.usart2,
+        (tx, rx),
+        &mut afio.mapr,
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    );
+    let (mut tx, mut rx) = serial.split();
+
+    // Use RTT because `cargo embed` expects it.
+    // Also using RTT in when writing text to USART.
+    rtt_init_print!();
+
+    send_start_message(&mut tx);
+    send_ready_message(&mut tx);
+
+    let mut blob = [0u8; BLOB_MAX];
+    let mut blob_len: usize = 0;
+    let mut receiver = Receiver::new();
+    let mut last_byte_ms = millis();
+    let mut last_crc_mode_ms = last_byte_ms.wrapping_sub(CRC_MODE_INTERVAL_MS);
+    loop {
+        let now_ms = millis();
+        let mut finished: Option<&str> = None;
+        match rx.read() {
+            Ok(byte) => {
+                last_byte_ms = now_ms;
+                match receiver.feed(byte) {
+                    XmodemEvent::Pending => (),
+                    XmodemEvent::Block if blob_len + BLOCK_SIZE <= BLOB_MAX => {
+                        blob[blob_len..blob_len + BLOCK_SIZE].copy_from_slice(receiver.block());
+                        blob_len += BLOCK_SIZE;
+                        receiver.accept();
+                        send_reply(&mut tx, ACK);
+                        rprintln!("Block {} received.", receiver.blocks());
+                    }
+                    XmodemEvent::Block => {
+                        cancel_transfer(&mut tx);
+                        finished = Some("File too large, transfer cancelled.");
+                    }
+                    XmodemEvent::Repeated => send_reply(&mut tx, ACK),
+                    XmodemEvent::Corrupt => {
+                        send_reply(&mut tx, NAK);
+                        rprintln!("Corrupt packet, NAK sent.");
+                    }
+                    XmodemEvent::Done => {
+                        send_reply(&mut tx, ACK);
+                        finished = Some("Transfer complete.");
+                    }
+                    XmodemEvent::Cancelled => {
+                        cancel_transfer(&mut tx);
+                        finished = Some("Transfer cancelled.");
+                    }
+                }
+            }
+            Err(nb::Error::WouldBlock) => (),
+            Err(_) => (),
+        }
+
+        if !receiver.has_started() {
+            // Keep asking for a CRC mode transfer until the sender responds.
+            if CRC_MODE_INTERVAL_MS <= now_ms.wrapping_sub(last_crc_mode_ms) {
+                send_reply(&mut tx, CRC_MODE);
+                last_crc_mode_ms = now_ms;
+            }
+        } else if receiver.is_receiving_packet()
+            && PACKET_TIMEOUT_MS <= now_ms.wrapping_sub(last_byte_ms)
+        {
+            receiver.abandon_packet();
+            send_reply(&mut tx, NAK);
+            rprintln!("Packet timed out, NAK sent.");
+        }
+
+        if let Some(message) = finished {
+            send_string(&mut tx, message);
+            let mut buffer: String<BUFFER_SIZE> = String::new();
+            write!(
+                buffer,
+                "Received {} bytes in {} blocks, CRC {:04X}.",
+                blob_len,
+                receiver.blocks(),
+                xmodem_crc(&blob[..blob_len])
+            )
+            .unwrap();
+            send_string(&mut tx, &buffer);
+            send_ready_message(&mut tx);
+            blob_len = 0;
+            receiver = Receiver::new();
+            last_crc_mode_ms = millis();
+        }
+    }
+}
@@ -2,7 +2,8 @@
 /* Linker script for STM32F103RB */
 MEMORY
 {
-  FLASH : ORIGIN = 0x08000000, LENGTH = 128K
+  /* The last 1K page of the 128K flash is reserved for src/settings.rs. */
+  FLASH : ORIGIN = 0x08000000, LENGTH = 127K
   RAM : ORIGIN = 0x20000000, LENGTH = 20K
 }
 
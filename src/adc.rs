@@ -0,0 +1,199 @@
+// src/adc.rs
+
+//! Helpers for converting raw 12-bit ADC readings into millivolts, and smoothing them.
+//!
+//! Raw readings are relative to VDDA, which on the Nucleo is nominally 3.3V but
+//! varies with the supply. The internal reference voltage VREFINT on ADC1 channel 17
+//! is fixed, so reading it reveals the actual VDDA. The STM32F103 has no factory
+//! calibration value for VREFINT, so the datasheet typical value of 1.20V is used.
+//! The datasheet allows 1.16V to 1.24V, so expect up to roughly 3% error.
+//!
+//! The internal temperature sensor on ADC1 channel 16 is converted with the typical
+//! values from the datasheet too, and is far less accurate. Its voltage at 25C
+//! varies by up to 45C worth from chip to chip, so a reading is only good for
+//! watching the temperature change, not for its absolute value. The chip also runs
+//! a few degrees warmer than the air around it.
+
+/// Largest value a 12-bit conversion can return.
+pub const ADC_MAX: u16 = 4095;
+
+/// Typical VREFINT voltage, see section 5.3.4 of the STM32F103xB datasheet.
+pub const VREFINT_MV: u16 = 1200;
+
+/// Nominal VDDA, for use before VREFINT has been measured.
+pub const VDDA_NOMINAL_MV: u16 = 3300;
+
+/// Compute VDDA in millivolts from a raw VREFINT reading.
+/// Returns 0 if the reading is 0, which can only happen if the ADC is misconfigured.
+pub fn compute_vdda_mv(vrefint_raw: u16) -> u16 {
+    if 0 == vrefint_raw {
+        return 0;
+    }
+    let vdda_mv = VREFINT_MV as u32 * ADC_MAX as u32 / vrefint_raw as u32;
+    vdda_mv.min(u16::MAX as u32) as u16
+}
+
+/// Typical temperature sensor voltage at 25C, from the datasheet.
+pub const TEMP_V25_MV: i32 = 1430;
+
+/// Typical temperature sensor slope, in microvolts per degree Celsius. The sensor
+/// voltage falls as the temperature rises.
+pub const TEMP_AVG_SLOPE_UV: i32 = 4300;
+
+/// Compute the temperature in tenths of a degree Celsius from a raw temperature
+/// sensor reading, given VDDA in millivolts, with the formula from section 11.10 of
+/// the reference manual, `(V25 - VSENSE) / Avg_Slope + 25`. The tenths only show
+/// small changes, see the module documentation for the accuracy.
+pub fn compute_temp_decidegrees(temp_raw: u16, vdda_mv: u16) -> i32 {
+    let sense_uv = temp_raw.min(ADC_MAX) as i64 * vdda_mv as i64 * 1000 / ADC_MAX as i64;
+    let offset_uv = TEMP_V25_MV as i64 * 1000 - sense_uv;
+    250 + (offset_uv * 10 / TEMP_AVG_SLOPE_UV as i64) as i32
+}
+
+/// Convert a raw reading into millivolts, given VDDA in millivolts.
+pub fn scale_adc(raw: u16, vdda_mv: u16) -> u16 {
+    (raw.min(ADC_MAX) as u32 * vdda_mv as u32 / ADC_MAX as u32) as u16
+}
+
+/// Mean of `samples`, rounded down, or 0 if there are none.
+/// The sum is kept in 64 bits, so it cannot overflow for any buffer that fits in RAM.
+pub fn mean_u16(samples: &[u16]) -> u16 {
+    if samples.is_empty() {
+        return 0;
+    }
+    let sum: u64 = samples.iter().map(|sample| *sample as u64).sum();
+    (sum / samples.len() as u64) as u16
+}
+
+/// Largest `alpha_shift` for which the scaled average of any `u16` fits in a `u32`.
+pub const EMA_SHIFT_MAX: u8 = 16;
+
+/// Add `sample` to an exponential moving average, for smoothing noisy readings
+/// without floating point.
+///
+/// The average is kept scaled up by `2^alpha_shift`, so the fraction lost to
+/// integer division is carried forward instead of biasing the result. Each update
+/// moves the average toward the sample by a smoothing factor of `1 / 2^alpha_shift`,
+/// so a shift of 0 just follows the samples, and every extra bit halves the weight
+/// of each new sample and doubles the time it takes to settle, to roughly
+/// `2^alpha_shift` samples. Shifts above `EMA_SHIFT_MAX` are treated as the maximum,
+/// so the scaled average cannot overflow. The average is `prev >> alpha_shift`.
+pub fn ema_update(prev: u32, sample: u16, alpha_shift: u8) -> u32 {
+    let alpha_shift = alpha_shift.min(EMA_SHIFT_MAX);
+    prev - (prev >> alpha_shift) + sample as u32
+}
+
+/// An exponential moving average of ADC readings, see `ema_update`.
+pub struct Ema {
+    scaled: Option<u32>,
+    alpha_shift: u8,
+}
+
+impl Ema {
+    pub const fn new(alpha_shift: u8) -> Self {
+        Ema {
+            scaled: None,
+            alpha_shift,
+        }
+    }
+
+    /// Add a sample, and return the new average. The first sample sets the average
+    /// directly, so it does not have to climb up from zero.
+    pub fn update(&mut self, sample: u16) -> u16 {
+        let shift = self.alpha_shift.min(EMA_SHIFT_MAX);
+        let scaled = match self.scaled {
+            Some(prev) => ema_update(prev, sample, shift),
+            None => (sample as u32) << shift,
+        };
+        self.scaled = Some(scaled);
+        self.value()
+    }
+
+    /// The current average, or 0 before the first sample.
+    pub fn value(&self) -> u16 {
+        let shift = self.alpha_shift.min(EMA_SHIFT_MAX);
+        self.scaled.map_or(0, |scaled| (scaled >> shift) as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vdda_from_vrefint() {
+        // VREFINT reads lower as VDDA rises.
+        assert_eq!(compute_vdda_mv(1489), 3300);
+        assert_eq!(compute_vdda_mv(1365), 3600);
+        assert_eq!(compute_vdda_mv(1638), 3000);
+        // A reading of full scale means VDDA is no higher than VREFINT.
+        assert_eq!(compute_vdda_mv(ADC_MAX), VREFINT_MV);
+    }
+
+    #[test]
+    fn vdda_from_bad_readings() {
+        assert_eq!(compute_vdda_mv(0), 0);
+        assert_eq!(compute_vdda_mv(1), u16::MAX);
+    }
+
+    #[test]
+    fn scaling_with_the_measured_vdda() {
+        let vdda_mv = compute_vdda_mv(1365);
+        assert_eq!(scale_adc(ADC_MAX, vdda_mv), vdda_mv);
+        assert_eq!(scale_adc(ADC_MAX / 2, vdda_mv), vdda_mv / 2 - 1);
+        assert_eq!(scale_adc(0, vdda_mv), 0);
+    }
+
+    #[test]
+    fn mean_rounds_down() {
+        assert_eq!(mean_u16(&[]), 0);
+        assert_eq!(mean_u16(&[1234]), 1234);
+        assert_eq!(mean_u16(&[1, 2]), 1);
+        assert_eq!(mean_u16(&[10, 20, 30, 41]), 25);
+    }
+
+    #[test]
+    fn mean_does_not_overflow() {
+        assert_eq!(mean_u16(&[u16::MAX; 1024]), u16::MAX);
+        assert_eq!(mean_u16(&[u16::MAX, u16::MAX - 1]), u16::MAX - 1);
+    }
+
+    #[test]
+    fn ema_update_carries_the_fraction() {
+        // With a shift of 0 the average just follows the samples.
+        assert_eq!(ema_update(100, 7, 0), 7);
+        // A constant input is a fixed point of the scaled average.
+        assert_eq!(ema_update(1_000 << 4, 1_000, 4), 1_000 << 4);
+        // A step of 8 moves the average by half a count, which is kept in the scaling.
+        assert_eq!(ema_update(1_000 << 4, 1_008, 4) >> 4, 1_000);
+        assert_eq!(ema_update(1_000 << 4, 1_008, 4), (1_000 << 4) + 8);
+        // Shifts above the maximum do not overflow.
+        let full = (u16::MAX as u32) << EMA_SHIFT_MAX;
+        assert_eq!(ema_update(full, u16::MAX, u8::MAX), full);
+    }
+
+    #[test]
+    fn ema_starts_at_the_first_sample_and_settles() {
+        let mut ema = Ema::new(4);
+        assert_eq!(ema.value(), 0);
+        assert_eq!(ema.update(2_000), 2_000);
+        let mut value = 0;
+        for _ in 0..16 {
+            value = ema.update(1_000);
+        }
+        // After 16 samples about 1 / e of the step remains.
+        assert!((1_300..1_400).contains(&value), "{}", value);
+        for _ in 0..200 {
+            value = ema.update(1_000);
+        }
+        assert_eq!(value, 1_000);
+    }
+
+    #[test]
+    fn ema_with_the_largest_shift() {
+        let mut ema = Ema::new(u8::MAX);
+        assert_eq!(ema.update(u16::MAX), u16::MAX);
+        assert_eq!(ema.update(u16::MAX), u16::MAX);
+        assert_eq!(ema.update(0), u16::MAX - 1);
+    }
+}
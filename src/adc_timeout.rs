@@ -0,0 +1,94 @@
+// src/adc_timeout.rs
+
+//! ADC conversions that give up instead of hanging.
+//!
+//! The HAL's `OneShot::read` starts a conversion and spins until the end of
+//! conversion flag is set, with no limit. If the ADC is misconfigured, or its clock
+//! is off, the flag never comes, and the program hangs with nothing to show why.
+//! `read_adc_timeout` runs the same steps, but counts the polls, and returns
+//! `AdcError::Timeout` once `max_spins` have passed. The result is also checked
+//! against the 12-bit range, because a value above `ADC_MAX` means the data is
+//! left aligned, or was not an ADC result at all.
+//!
+//! The HAL has no way to start a conversion without waiting for it, so the start
+//! bit and the status and data registers are accessed directly, and `unsafe` is
+//! allowed in this module only. The accesses are sound because the `Adc` is
+//! borrowed mutably for the whole conversion, so nothing else can use ADC1 at the
+//! same time, and the same register writes are what the HAL itself does.
+//!
+//! `enable_temp_sensor` also sets a bit directly, for the same reason. The HAL only
+//! powers the temperature sensor for the length of its own `read_temp`, so there is
+//! no other way to sample channel 16 with `read_adc_timeout`.
+
+#![allow(unsafe_code)]
+
+use crate::adc::ADC_MAX;
+use stm32f1xx_hal::{
+    adc::{Adc, ChannelTimeSequence, SampleTime},
+    pac::{self, ADC1},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AdcError {
+    /// The conversion did not finish within the allowed number of polls.
+    Timeout,
+    /// The conversion returned a value that a right aligned 12-bit result cannot have.
+    OutOfRange(u16),
+}
+
+impl AdcError {
+    pub fn describe(self) -> &'static str {
+        match self {
+            AdcError::Timeout => "conversion timed out",
+            AdcError::OutOfRange(_) => "result out of the 12-bit range",
+        }
+    }
+}
+
+/// Check that `raw` is a possible 12-bit reading.
+pub fn check_adc_range(raw: u16) -> Result<u16, AdcError> {
+    match raw {
+        0..=ADC_MAX => Ok(raw),
+        raw => Err(AdcError::OutOfRange(raw)),
+    }
+}
+
+/// Poll `done` until it returns true, at most `max_spins` times.
+pub fn wait_bounded(max_spins: u32, mut done: impl FnMut() -> bool) -> Result<(), AdcError> {
+    match (0..max_spins).any(|_| done()) {
+        true => Ok(()),
+        false => Err(AdcError::Timeout),
+    }
+}
+
+/// Convert ADC1 `channel`, sampled for `sample_time`, giving up after `max_spins`
+/// polls. At 48MHz a poll takes well under a microsecond, and the slowest sample
+/// time converts in about 21us, so a few thousand spins is plenty.
+pub fn read_adc_timeout(
+    adc: &mut Adc<ADC1>,
+    channel: u8,
+    sample_time: SampleTime,
+    max_spins: u32,
+) -> Result<u16, AdcError> {
+    adc.set_channel_sample_time(channel, sample_time);
+    adc.set_regular_sequence(&[channel]);
+    // SAFETY: See the module documentation.
+    let registers = unsafe { &*pac::ADC1::ptr() };
+    // Discard any stale result, like the HAL does, so it cannot be mistaken for this one.
+    registers.dr.read();
+    registers.cr2.modify(|_, w| w.swstart().set_bit());
+    wait_bounded(max_spins, || registers.cr2.read().swstart().bit_is_clear())?;
+    wait_bounded(max_spins, || registers.sr.read().eoc().bit_is_set())?;
+    check_adc_range(registers.dr.read().data().bits())
+}
+
+/// Power up the temperature sensor on ADC1 channel 16, along with VREFINT on
+/// channel 17, which share the TSVREFE bit. The sensor needs up to 10us to start up
+/// before the first reading. They stay powered, and the HAL's `read_vref` and
+/// `read_temp` leave them that way, because they only clear the bit if it was clear
+/// before.
+pub fn enable_temp_sensor(_adc: &mut Adc<ADC1>) {
+    // SAFETY: See the module documentation.
+    let registers = unsafe { &*pac::ADC1::ptr() };
+    registers.cr2.modify(|_, w| w.tsvrefe().set_bit());
+}
@@ -0,0 +1,90 @@
+// src/ansi.rs
+
+//! ANSI SGR color codes and screen control for text sent to a serial terminal.
+//!
+//! Most terminal emulators understand these escape sequences, but some do not and
+//! print them literally, so examples that use color let it be turned off at runtime.
+//! The same setting should gate any other escape sequence, like `CLEAR_SCREEN`.
+
+use core::fmt::{self, Write};
+
+/// Erase the whole screen, then move the cursor to the top left corner.
+pub const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnsiColor {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+}
+
+impl AnsiColor {
+    /// SGR parameter that selects this color as the foreground color.
+    pub fn code(self) -> u8 {
+        match self {
+            AnsiColor::Red => 31,
+            AnsiColor::Green => 32,
+            AnsiColor::Yellow => 33,
+            AnsiColor::Blue => 34,
+            AnsiColor::Magenta => 35,
+            AnsiColor::Cyan => 36,
+        }
+    }
+}
+
+/// Write `text` in `color`, then reset the terminal to its default attributes.
+pub fn colored<W: Write>(out: &mut W, color: AnsiColor, text: &str) -> fmt::Result {
+    write!(out, "\x1b[{}m{}\x1b[0m", color.code(), text)
+}
+
+/// Write `CLEAR_SCREEN`, for a terminal that understands ANSI escape sequences.
+pub fn clear_screen<W: Write>(out: &mut W) -> fmt::Result {
+    out.write_str(CLEAR_SCREEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapless::String;
+
+    #[test]
+    fn colored_text_is_reset() {
+        let mut out: String<32> = String::new();
+        colored(&mut out, AnsiColor::Red, "error").unwrap();
+        assert_eq!(out.as_str(), "\x1b[31merror\x1b[0m");
+        out.clear();
+        colored(&mut out, AnsiColor::Cyan, "").unwrap();
+        assert_eq!(out.as_str(), "\x1b[36m\x1b[0m");
+    }
+
+    #[test]
+    fn color_codes() {
+        let colors = [
+            AnsiColor::Red,
+            AnsiColor::Green,
+            AnsiColor::Yellow,
+            AnsiColor::Blue,
+            AnsiColor::Magenta,
+            AnsiColor::Cyan,
+        ];
+        for (color, code) in colors.iter().zip(31..) {
+            assert_eq!(color.code(), code);
+        }
+    }
+
+    #[test]
+    fn clear_screen_sequence() {
+        let mut out: String<16> = String::new();
+        clear_screen(&mut out).unwrap();
+        assert_eq!(out.as_str(), "\x1b[2J\x1b[H");
+    }
+
+    #[test]
+    fn full_buffer_is_an_error() {
+        let mut out: String<8> = String::new();
+        assert!(colored(&mut out, AnsiColor::Green, "too long").is_err());
+    }
+}
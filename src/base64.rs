@@ -0,0 +1,92 @@
+// src/base64.rs
+
+//! Standard base64 encoding, RFC 4648 section 4, for sending binary data as text
+//! that survives any terminal, and fits in a QR code.
+//!
+//! Every three bytes become four characters. A final group of one or two bytes is
+//! padded with `=` to four characters, so the encoded length is always a multiple
+//! of four, see `encoded_len`.
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const PAD: u8 = b'=';
+const GROUP_BYTES: usize = 3;
+const GROUP_CHARS: usize = 4;
+
+/// Number of characters needed to encode `len` bytes, including padding.
+pub const fn encoded_len(len: usize) -> usize {
+    len.div_ceil(GROUP_BYTES) * GROUP_CHARS
+}
+
+/// Encode `data` into `out`, returning the number of characters written.
+/// When `out` is shorter than `encoded_len(data.len())`, only the groups that fit
+/// whole are written, so the output is always valid base64.
+pub fn base64_encode(data: &[u8], out: &mut [u8]) -> usize {
+    let groups = data
+        .chunks(GROUP_BYTES)
+        .zip(out.chunks_exact_mut(GROUP_CHARS));
+    let mut written = 0;
+    for (bytes, chars) in groups {
+        let b = [
+            bytes[0],
+            bytes.get(1).copied().unwrap_or(0),
+            bytes.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for (i, c) in chars.iter_mut().enumerate() {
+            // One byte fills two characters, two bytes fill three.
+            *c = match i <= bytes.len() {
+                true => ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize],
+                false => PAD,
+            };
+        }
+        written += GROUP_CHARS;
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(data: &[u8]) -> std::string::String {
+        let mut out = [0; 64];
+        let written = base64_encode(data, &mut out);
+        assert_eq!(written, encoded_len(data.len()));
+        std::str::from_utf8(&out[..written]).unwrap().into()
+    }
+
+    #[test]
+    fn rfc_4648_test_vectors() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg==");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(b"foo"), "Zm9v");
+        assert_eq!(encode(b"foob"), "Zm9vYg==");
+        assert_eq!(encode(b"fooba"), "Zm9vYmE=");
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn every_character_of_the_alphabet() {
+        assert_eq!(encode(&[0x00, 0x10, 0x83]), "ABCD");
+        assert_eq!(encode(&[0xFB, 0xFF, 0xBF]), "+/+/");
+        assert_eq!(encode(&[0xFF]), "/w==");
+    }
+
+    #[test]
+    fn encoded_lengths() {
+        assert_eq!(encoded_len(0), 0);
+        assert_eq!(encoded_len(1), 4);
+        assert_eq!(encoded_len(3), 4);
+        assert_eq!(encoded_len(4), 8);
+        assert_eq!(encoded_len(16), 24);
+    }
+
+    #[test]
+    fn short_output_gets_whole_groups_only() {
+        let mut out = [b'.'; 7];
+        assert_eq!(base64_encode(b"foobar", &mut out), 4);
+        assert_eq!(&out, b"Zm9v...");
+        assert_eq!(base64_encode(b"foobar", &mut []), 0);
+    }
+}
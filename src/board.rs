@@ -0,0 +1,242 @@
+// src/board.rs
+
+//! The setup that every example starts with, done once.
+//!
+//! `Board::take` takes the device peripherals, runs the system clock at 48MHz from
+//! the 8MHz HSE clock that the ST-Link provides, and starts a microsecond delay on
+//! TIM2. `Board::take_with` picks another `ClockProfile` instead. It also sets up
+//! the onboard LED LD2 on PA5 with `led_output`, erased so it can go in an array
+//! with external LEDs, and user button B1 on PC13 with `button_input`.
+//!
+//! ```ignore
+//! let mut board = Board::take();
+//! board.led.set_high();
+//! board.delay.delay_ms(500_u32);
+//! ```
+//!
+//! Timing that follows the system clock, like a busy wait counted in cycles,
+//! should be computed from `Board::sysclk_hz` rather than assuming 48MHz. The
+//! delay provider and the peripheral drivers already take the clocks into account.
+//!
+//! Everything else is handed back for the caller to claim.
+//!
+//! - `gpioa` and `gpioc` have every pin except PA5 and PC13, along with `crl` and
+//!   `crh` for configuring them. `gpiob` and `gpiod` are split as usual.
+//! - `flash` is what is left of FLASH after setting the wait states, for a
+//!   `FlashWriter`.
+//! - `reset_cause` is why the board last reset. The reset flags are cleared after
+//!   reading them, so the next reset is not mistaken for this one.
+//! - `afio`, `adc1`, `dma1`, `exti`, `i2c1`, `tim3`, `usart1` and `usart2` are the
+//!   raw peripherals, as they come from `pac::Peripherals`. `usart2` is the one
+//!   connected to the ST-Link.
+//!
+//! The device peripherals can only be taken once, so any other peripheral is out of
+//! reach after `Board::take`. An example that needs another one does its own setup
+//! instead. An example that needs TIM2 for something other than a delay gets it
+//! back with `delay.release()`, still counting at 1MHz. The core peripherals,
+//! like SysTick for `millis`, are separate, and still come from
+//! `cortex_m::Peripherals::take`.
+
+use crate::diag::ResetCause;
+use crate::pins::{button_input, led_output, Button, ButtonPull};
+use stm32f1xx_hal::{
+    flash,
+    gpio::{gpioa, gpiob, gpioc, gpiod, Cr, Debugger, ErasedPin, Output},
+    pac,
+    prelude::*,
+    rcc::Clocks,
+    timer::DelayUs,
+};
+
+/// How the system clock is set up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockProfile {
+    /// The internal 8MHz RC oscillator alone, without the PLL, for the lowest
+    /// power. The HSI is only accurate to within a few percent, which may be too
+    /// much for reliable USART communication.
+    Hsi8,
+    /// 48MHz from the 8MHz HSE clock, what every example uses.
+    Hse8Sysclk48,
+    /// The full 72MHz from the 8MHz HSE clock. APB1 runs at half of that, the most
+    /// it allows.
+    Hse8Sysclk72,
+}
+
+impl ClockProfile {
+    /// The system clock frequency this profile runs at.
+    pub const fn sysclk_hz(self) -> u32 {
+        match self {
+            ClockProfile::Hsi8 => 8_000_000,
+            ClockProfile::Hse8Sysclk48 => 48_000_000,
+            ClockProfile::Hse8Sysclk72 => 72_000_000,
+        }
+    }
+}
+
+/// GPIOA with PA5, the onboard LED, already taken.
+pub struct GpioA {
+    pub crl: Cr<'A', false>,
+    pub crh: Cr<'A', true>,
+    pub pa0: gpioa::PA0,
+    pub pa1: gpioa::PA1,
+    pub pa2: gpioa::PA2,
+    pub pa3: gpioa::PA3,
+    pub pa4: gpioa::PA4,
+    pub pa6: gpioa::PA6,
+    pub pa7: gpioa::PA7,
+    pub pa8: gpioa::PA8,
+    pub pa9: gpioa::PA9,
+    pub pa10: gpioa::PA10,
+    pub pa11: gpioa::PA11,
+    pub pa12: gpioa::PA12,
+    pub pa13: gpioa::PA13<Debugger>,
+    pub pa14: gpioa::PA14<Debugger>,
+    pub pa15: gpioa::PA15<Debugger>,
+}
+
+/// GPIOC with PC13, user button B1, already taken.
+pub struct GpioC {
+    pub crl: Cr<'C', false>,
+    pub crh: Cr<'C', true>,
+    pub pc0: gpioc::PC0,
+    pub pc1: gpioc::PC1,
+    pub pc2: gpioc::PC2,
+    pub pc3: gpioc::PC3,
+    pub pc4: gpioc::PC4,
+    pub pc5: gpioc::PC5,
+    pub pc6: gpioc::PC6,
+    pub pc7: gpioc::PC7,
+    pub pc8: gpioc::PC8,
+    pub pc9: gpioc::PC9,
+    pub pc10: gpioc::PC10,
+    pub pc11: gpioc::PC11,
+    pub pc12: gpioc::PC12,
+    pub pc14: gpioc::PC14,
+    pub pc15: gpioc::PC15,
+}
+
+/// The Nucleo-F103RB, set up the way the examples expect.
+pub struct Board {
+    pub profile: ClockProfile,
+    pub clocks: Clocks,
+    /// TIM2 as a microsecond delay provider.
+    pub delay: DelayUs<pac::TIM2>,
+    /// The onboard LED LD2 on PA5.
+    pub led: ErasedPin<Output>,
+    /// User button B1 on PC13, which has an external pull-up and is active low.
+    pub button: Button<'C', 13>,
+    pub gpioa: GpioA,
+    pub gpiob: gpiob::Parts,
+    pub gpioc: GpioC,
+    pub gpiod: gpiod::Parts,
+    pub flash: flash::Parts,
+    pub reset_cause: ResetCause,
+    pub afio: pac::AFIO,
+    pub adc1: pac::ADC1,
+    pub dma1: pac::DMA1,
+    pub exti: pac::EXTI,
+    pub i2c1: pac::I2C1,
+    pub tim3: pac::TIM3,
+    pub usart1: pac::USART1,
+    pub usart2: pac::USART2,
+}
+
+impl Board {
+    /// Take and set up the board, running at 48MHz from HSE.
+    /// Panics if the device peripherals were taken before.
+    pub fn take() -> Board {
+        Board::take_with(ClockProfile::Hse8Sysclk48)
+    }
+
+    /// Take and set up the board with the system clock from `profile`.
+    /// Panics if the device peripherals were taken before.
+    pub fn take_with(profile: ClockProfile) -> Board {
+        let dp = pac::Peripherals::take().unwrap();
+
+        let mut gpioa = dp.GPIOA.split();
+        let led = led_output(gpioa.pa5, &mut gpioa.crl).erase(); // On Board LED LD2
+        let mut gpioc = dp.GPIOC.split();
+        let button = button_input(gpioc.pc13, &mut gpioc.crh, ButtonPull::Floating, true);
+
+        // Keep the reset flags, then clear them, before RCC is constrained.
+        let reset_cause = ResetCause::from_csr(dp.RCC.csr.read().bits());
+        dp.RCC.csr.modify(|_, w| w.rmvf().set_bit());
+
+        // Take ownership of raw flash and rcc devices.
+        let mut flash = dp.FLASH.constrain();
+        let rcc = dp.RCC.constrain();
+
+        // Set up system clock and configure delay provider. The HAL picks the bus
+        // prescalers, keeping APB1 within its 36MHz limit.
+        let cfgr = match profile {
+            ClockProfile::Hsi8 => rcc.cfgr,
+            ClockProfile::Hse8Sysclk48 | ClockProfile::Hse8Sysclk72 => rcc.cfgr.use_hse(8.MHz()),
+        };
+        let clocks = cfgr.sysclk(profile.sysclk_hz().Hz()).freeze(&mut flash.acr);
+        let delay = dp.TIM2.delay_us(&clocks);
+
+        Board {
+            profile,
+            clocks,
+            delay,
+            led,
+            button,
+            gpioa: GpioA {
+                crl: gpioa.crl,
+                crh: gpioa.crh,
+                pa0: gpioa.pa0,
+                pa1: gpioa.pa1,
+                pa2: gpioa.pa2,
+                pa3: gpioa.pa3,
+                pa4: gpioa.pa4,
+                pa6: gpioa.pa6,
+                pa7: gpioa.pa7,
+                pa8: gpioa.pa8,
+                pa9: gpioa.pa9,
+                pa10: gpioa.pa10,
+                pa11: gpioa.pa11,
+                pa12: gpioa.pa12,
+                pa13: gpioa.pa13,
+                pa14: gpioa.pa14,
+                pa15: gpioa.pa15,
+            },
+            gpiob: dp.GPIOB.split(),
+            gpioc: GpioC {
+                crl: gpioc.crl,
+                crh: gpioc.crh,
+                pc0: gpioc.pc0,
+                pc1: gpioc.pc1,
+                pc2: gpioc.pc2,
+                pc3: gpioc.pc3,
+                pc4: gpioc.pc4,
+                pc5: gpioc.pc5,
+                pc6: gpioc.pc6,
+                pc7: gpioc.pc7,
+                pc8: gpioc.pc8,
+                pc9: gpioc.pc9,
+                pc10: gpioc.pc10,
+                pc11: gpioc.pc11,
+                pc12: gpioc.pc12,
+                pc14: gpioc.pc14,
+                pc15: gpioc.pc15,
+            },
+            gpiod: dp.GPIOD.split(),
+            flash,
+            reset_cause,
+            afio: dp.AFIO,
+            adc1: dp.ADC1,
+            dma1: dp.DMA1,
+            exti: dp.EXTI,
+            i2c1: dp.I2C1,
+            tim3: dp.TIM3,
+            usart1: dp.USART1,
+            usart2: dp.USART2,
+        }
+    }
+
+    /// The system clock frequency the board actually runs at, for recomputing
+    /// timing constants.
+    pub fn sysclk_hz(&self) -> u32 {
+        self.clocks.sysclk().raw()
+    }
+}
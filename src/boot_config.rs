@@ -0,0 +1,163 @@
+// src/boot_config.rs
+
+//! A one line startup configuration, sent by host tooling right after reset.
+//!
+//! The line is a list of `key=value` settings separated by spaces, and may start
+//! with the word `boot`, for example `boot mode=upper baud=57600 led=off echo=off`.
+//! Settings may come in any order. A setting that is left out, or has a value that
+//! is not understood, keeps its default, so a script only has to send what it
+//! wants to change, and a typo cannot leave the board in an unexpected state.
+//!
+//! - `mode` is the text mode, one of `normal`, `upper`, `lower`, `invert`, `leet`,
+//!   or `rot` followed by a rotation, like `rot13`. Rotations above 25 wrap around,
+//!   so `rot27` is the same as `rot1`.
+//! - `baud` is a baud rate to switch to once the configuration is applied, from
+//!   `MIN_BOOT_BAUD` to `MAX_BOOT_BAUD`. The default is to keep the current rate,
+//!   and so is a rate outside that range, because the host would be left talking
+//!   at a rate the board is not using.
+//! - `led` is `on` for the usual mode indicator LED, or `off` to keep it dark.
+//! - `echo` is `on` or `off`, for local echo.
+
+use crate::{
+    parse::parse_clamped,
+    text::{reduce_rotation, TextMode},
+};
+
+/// Word that may start a boot configuration line.
+pub const BOOT_COMMAND: &str = "boot";
+pub const MIN_BOOT_BAUD: u32 = 1_200;
+pub const MAX_BOOT_BAUD: u32 = 1_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BootConfig {
+    pub text_mode: TextMode,
+    /// `None` keeps the current baud rate.
+    pub baud: Option<u32>,
+    pub led: bool,
+    pub local_echo: bool,
+}
+
+impl Default for BootConfig {
+    fn default() -> Self {
+        BootConfig {
+            text_mode: TextMode::NormalCase,
+            baud: None,
+            led: true,
+            local_echo: true,
+        }
+    }
+}
+
+fn parse_text_mode(value: &str) -> Option<TextMode> {
+    match value {
+        "normal" => Some(TextMode::NormalCase),
+        "upper" => Some(TextMode::ForceUpper),
+        "lower" => Some(TextMode::ForceLower),
+        "invert" => Some(TextMode::InvertedCase),
+        "leet" => Some(TextMode::Leet),
+        _ => {
+            let rotation = parse_clamped(value.strip_prefix("rot")?, 0, u32::MAX).ok()?;
+            Some(TextMode::Rot(reduce_rotation(rotation.value)))
+        }
+    }
+}
+
+fn parse_on_off(value: &str) -> Option<bool> {
+    match value {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse a boot configuration line. Anything not understood is ignored.
+pub fn parse_boot_config(line: &str) -> BootConfig {
+    let mut config = BootConfig::default();
+    let settings = line.trim();
+    let settings = settings.strip_prefix(BOOT_COMMAND).unwrap_or(settings);
+    for (key, value) in settings
+        .split_whitespace()
+        .filter_map(|s| s.split_once('='))
+    {
+        match key {
+            "mode" => config.text_mode = parse_text_mode(value).unwrap_or(config.text_mode),
+            "baud" => {
+                config.baud = parse_clamped(value, MIN_BOOT_BAUD, MAX_BOOT_BAUD)
+                    .ok()
+                    .filter(|baud| !baud.clamped)
+                    .map(|baud| baud.value)
+                    .or(config.baud)
+            }
+            "led" => config.led = parse_on_off(value).unwrap_or(config.led),
+            "echo" => config.local_echo = parse_on_off(value).unwrap_or(config.local_echo),
+            _ => (),
+        }
+    }
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_line() {
+        assert_eq!(
+            parse_boot_config("boot mode=upper baud=57600 led=off echo=off"),
+            BootConfig {
+                text_mode: TextMode::ForceUpper,
+                baud: Some(57_600),
+                led: false,
+                local_echo: false,
+            }
+        );
+    }
+
+    #[test]
+    fn any_order_and_without_the_command() {
+        let config = parse_boot_config("  echo=off   mode=rot13 ");
+        assert_eq!(config.text_mode, TextMode::Rot(13));
+        assert!(!config.local_echo);
+        assert!(config.led);
+        assert_eq!(config.baud, None);
+    }
+
+    #[test]
+    fn empty_lines_keep_the_defaults() {
+        assert_eq!(parse_boot_config(""), BootConfig::default());
+        assert_eq!(parse_boot_config("boot"), BootConfig::default());
+    }
+
+    #[test]
+    fn rotations_wrap() {
+        assert_eq!(parse_boot_config("mode=rot27").text_mode, TextMode::Rot(1));
+        assert_eq!(parse_boot_config("mode=rot0").text_mode, TextMode::Rot(0));
+    }
+
+    #[test]
+    fn values_not_understood_keep_their_defaults() {
+        let defaults = BootConfig::default();
+        for line in [
+            "mode=shout",
+            "mode=rot",
+            "mode=rot-1",
+            "led=maybe",
+            "echo=",
+            "baud=fast",
+            "baud=300",
+            "baud=2000000",
+            "volume=11",
+            "mode",
+        ] {
+            assert_eq!(parse_boot_config(line), defaults, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn baud_limits() {
+        assert_eq!(parse_boot_config("baud=1200").baud, Some(MIN_BOOT_BAUD));
+        assert_eq!(parse_boot_config("baud=1000000").baud, Some(MAX_BOOT_BAUD));
+        // A later bad value does not undo an earlier good one.
+        assert_eq!(parse_boot_config("baud=9600 baud=1").baud, Some(9_600));
+    }
+}
@@ -0,0 +1,214 @@
+// src/calc.rs
+
+//! Integer expression evaluation for the `calc` example.
+//!
+//! `+ - * /` follow the usual precedence, `*` and `/` before `+` and `-`, and
+//! parentheses group. A `-` in front of a number or parenthesis negates it.
+//! Arithmetic is 32 bit signed and division truncates toward zero. An overflow or
+//! division by zero is an error instead of wrapping or panicking, and so is a
+//! literal too large for an `i32`, which means the most negative `i32` has to be
+//! written as a subtraction.
+//!
+//! `eval` uses the shunting-yard algorithm with fixed-size value and operator
+//! stacks, so no heap is needed.
+
+use heapless::Vec;
+
+/// Values and operators each stack, beyond which an expression is too complex.
+pub const STACK_DEPTH: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CalcError {
+    Syntax,
+    DivideByZero,
+    Overflow,
+    TooComplex,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+    Open,
+}
+
+impl Op {
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Open => 0,
+            Op::Add | Op::Sub => 1,
+            Op::Mul | Op::Div => 2,
+            Op::Neg => 3,
+        }
+    }
+}
+
+type Values = Vec<i32, STACK_DEPTH>;
+type Ops = Vec<Op, STACK_DEPTH>;
+
+/// Pop the operands of `op` from `values` and push the result.
+fn apply(op: Op, values: &mut Values) -> Result<(), CalcError> {
+    let right = values.pop().ok_or(CalcError::Syntax)?;
+    let result = match op {
+        Op::Neg => right.checked_neg().ok_or(CalcError::Overflow)?,
+        Op::Open => return Err(CalcError::Syntax),
+        _ => {
+            let left = values.pop().ok_or(CalcError::Syntax)?;
+            match op {
+                Op::Add => left.checked_add(right),
+                Op::Sub => left.checked_sub(right),
+                Op::Mul => left.checked_mul(right),
+                _ if 0 == right => return Err(CalcError::DivideByZero),
+                _ => left.checked_div(right),
+            }
+            .ok_or(CalcError::Overflow)?
+        }
+    };
+    values.push(result).map_err(|_| CalcError::TooComplex)
+}
+
+/// Push a binary operator, first applying stacked operators that bind at least as tightly.
+fn push_binary(op: Op, values: &mut Values, ops: &mut Ops) -> Result<(), CalcError> {
+    while let Some(&top) = ops.last() {
+        if top.precedence() < op.precedence() {
+            break;
+        }
+        ops.pop();
+        apply(top, values)?;
+    }
+    ops.push(op).map_err(|_| CalcError::TooComplex)
+}
+
+/// Evaluate an integer expression with `+ - * /`, parentheses and unary minus.
+pub fn eval(expr: &str) -> Result<i32, CalcError> {
+    let bytes = expr.as_bytes();
+    let mut values = Values::new();
+    let mut ops = Ops::new();
+    // An operand is expected at the start, and after an operator or `(`.
+    let mut expect_operand = true;
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        i += 1;
+        match c {
+            b' ' | b'\t' => (),
+            b'0'..=b'9' if expect_operand => {
+                let mut n = (c - b'0') as i32;
+                while let Some(&d @ b'0'..=b'9') = bytes.get(i) {
+                    n = n
+                        .checked_mul(10)
+                        .and_then(|n| n.checked_add((d - b'0') as i32))
+                        .ok_or(CalcError::Overflow)?;
+                    i += 1;
+                }
+                values.push(n).map_err(|_| CalcError::TooComplex)?;
+                expect_operand = false;
+            }
+            b'(' if expect_operand => ops.push(Op::Open).map_err(|_| CalcError::TooComplex)?,
+            b'-' if expect_operand => ops.push(Op::Neg).map_err(|_| CalcError::TooComplex)?,
+            b')' if !expect_operand => loop {
+                match ops.pop() {
+                    Some(Op::Open) => break,
+                    Some(op) => apply(op, &mut values)?,
+                    None => return Err(CalcError::Syntax),
+                }
+            },
+            b'+' if !expect_operand => {
+                push_binary(Op::Add, &mut values, &mut ops)?;
+                expect_operand = true;
+            }
+            b'-' => {
+                push_binary(Op::Sub, &mut values, &mut ops)?;
+                expect_operand = true;
+            }
+            b'*' if !expect_operand => {
+                push_binary(Op::Mul, &mut values, &mut ops)?;
+                expect_operand = true;
+            }
+            b'/' if !expect_operand => {
+                push_binary(Op::Div, &mut values, &mut ops)?;
+                expect_operand = true;
+            }
+            _ => return Err(CalcError::Syntax),
+        }
+    }
+    if expect_operand {
+        return Err(CalcError::Syntax);
+    }
+    while let Some(op) = ops.pop() {
+        apply(op, &mut values)?;
+    }
+    match values.as_slice() {
+        [result] => Ok(*result),
+        _ => Err(CalcError::Syntax),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precedence_and_grouping() {
+        assert_eq!(eval("12 + 30 * 2"), Ok(72));
+        assert_eq!(eval("(12 + 30) * 2"), Ok(84));
+        assert_eq!(eval("10 - 4 - 3"), Ok(3));
+        assert_eq!(eval("100 / 10 / 5"), Ok(2));
+        assert_eq!(eval(" 7\t"), Ok(7));
+    }
+
+    #[test]
+    fn negation() {
+        assert_eq!(eval("-5"), Ok(-5));
+        assert_eq!(eval("-2 * 3"), Ok(-6));
+        assert_eq!(eval("2 * -3"), Ok(-6));
+        assert_eq!(eval("2 - -3"), Ok(5));
+        assert_eq!(eval("--4"), Ok(4));
+        assert_eq!(eval("-(1 + 2)"), Ok(-3));
+    }
+
+    #[test]
+    fn division_truncates_toward_zero() {
+        assert_eq!(eval("7 / 2"), Ok(3));
+        assert_eq!(eval("-7 / 2"), Ok(-3));
+        assert_eq!(eval("7 / 0"), Err(CalcError::DivideByZero));
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        assert_eq!(eval("2147483647"), Ok(i32::MAX));
+        assert_eq!(eval("2147483648"), Err(CalcError::Overflow));
+        assert_eq!(eval("2147483647 + 1"), Err(CalcError::Overflow));
+        assert_eq!(eval("65536 * 65536"), Err(CalcError::Overflow));
+        // The most negative value needs a subtraction, and cannot be negated.
+        assert_eq!(eval("-2147483648"), Err(CalcError::Overflow));
+        assert_eq!(eval("-2147483647 - 1"), Ok(i32::MIN));
+        assert_eq!(eval("-(-2147483647 - 1)"), Err(CalcError::Overflow));
+        assert_eq!(eval("(-2147483647 - 1) / -1"), Err(CalcError::Overflow));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        for expr in [
+            "", " ", "1 +", "* 2", "(1", "1)", "()", "1 2", "1 + x", "(1 + 2))",
+        ] {
+            assert_eq!(eval(expr), Err(CalcError::Syntax), "{:?}", expr);
+        }
+    }
+
+    #[test]
+    fn deep_nesting_is_too_complex() {
+        let nested = |depth| {
+            let mut expr: heapless::String<64> = heapless::String::new();
+            (0..depth).for_each(|_| expr.push('(').unwrap());
+            expr.push('1').unwrap();
+            (0..depth).for_each(|_| expr.push(')').unwrap());
+            eval(&expr)
+        };
+        assert_eq!(nested(STACK_DEPTH), Ok(1));
+        assert_eq!(nested(STACK_DEPTH + 1), Err(CalcError::TooComplex));
+    }
+}
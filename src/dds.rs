@@ -0,0 +1,126 @@
+// src/dds.rs
+
+//! Direct digital synthesis (DDS) of waveforms from a lookup table.
+//!
+//! A 32 bit phase accumulator advances by a fixed increment at every sample, and
+//! wraps around once per output cycle, so the output frequency is
+//! `increment * sample_hz / 2^32`. The top bits of the phase select the table
+//! entry. The frequency resolution is the sample rate divided by 2^32, far finer
+//! than any timer reload value allows, and the frequency can change between samples
+//! without a glitch, because the phase carries on from where it was.
+//!
+//! The Cortex-M3 has no FPU, so `sine_table` is a `const fn`. Stored in a `static`,
+//! the table is computed by the compiler, and no floating point math runs on the
+//! chip.
+
+pub const SINE_TABLE_SIZE: usize = 256;
+const PI: f32 = core::f32::consts::PI;
+/// Sample value at the zero crossings, halfway between 0 and `u8::MAX`.
+const SINE_MIDPOINT: f32 = 128.0;
+const SINE_AMPLITUDE: f32 = 127.0;
+
+/// Sine of `x` for `-PI / 2 <= x <= PI / 2`, from the Taylor series up to the x^9
+/// term, which is accurate to well under one part in 127 over that range.
+const fn sin_quarter(x: f32) -> f32 {
+    let x2 = x * x;
+    x * (1.0 - x2 / 6.0 * (1.0 - x2 / 20.0 * (1.0 - x2 / 42.0 * (1.0 - x2 / 72.0))))
+}
+
+/// One cycle of a sine wave, centered on 128 with an amplitude of 127, so every
+/// sample is in `1..=255`.
+pub const fn sine_table() -> [u8; SINE_TABLE_SIZE] {
+    let mut table = [0; SINE_TABLE_SIZE];
+    let mut i = 0;
+    while i < SINE_TABLE_SIZE {
+        let angle = 2.0 * PI * i as f32 / SINE_TABLE_SIZE as f32;
+        // Fold the angle into the range where the series is accurate.
+        let x = if i < SINE_TABLE_SIZE / 4 {
+            angle
+        } else if i < 3 * SINE_TABLE_SIZE / 4 {
+            PI - angle
+        } else {
+            angle - 2.0 * PI
+        };
+        // Round to the nearest step. The value is always positive, so adding a
+        // half and truncating rounds correctly.
+        table[i] = (SINE_MIDPOINT + SINE_AMPLITUDE * sin_quarter(x) + 0.5) as u8;
+        i += 1;
+    }
+    table
+}
+
+/// Return the table entry for the current phase, then advance the phase by
+/// `increment`. The table can have any length.
+pub fn next_sample(phase: &mut u32, increment: u32, table: &[u8]) -> u8 {
+    let index = ((*phase as u64 * table.len() as u64) >> 32) as usize;
+    *phase = phase.wrapping_add(increment);
+    table[index]
+}
+
+/// Phase increment that produces `hz` at a sample rate of `sample_hz`, rounded to
+/// the nearest step. Only frequencies below half the sample rate can be reproduced.
+pub fn phase_increment(hz: u32, sample_hz: u32) -> u32 {
+    match sample_hz {
+        0 => 0,
+        sample_hz => {
+            let sample_hz = sample_hz as u64;
+            ((((hz as u64) << 32) + sample_hz / 2) / sample_hz) as u32
+        }
+    }
+}
+
+/// Output frequency for a phase increment, rounded to the nearest whole Hz, so it
+/// reports the frequency that was asked of `phase_increment`.
+pub fn output_hz(increment: u32, sample_hz: u32) -> u32 {
+    ((increment as u64 * sample_hz as u64 + (1 << 31)) >> 32) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static TABLE: [u8; SINE_TABLE_SIZE] = sine_table();
+
+    #[test]
+    fn sine_table_matches_sin() {
+        for (i, &sample) in TABLE.iter().enumerate() {
+            let angle = 2.0 * std::f64::consts::PI * i as f64 / SINE_TABLE_SIZE as f64;
+            let exact = 128.0 + 127.0 * angle.sin();
+            assert!(
+                (sample as f64 - exact).abs() <= 0.5 + 1e-3,
+                "{} {}",
+                i,
+                sample
+            );
+        }
+        assert_eq!(TABLE[0], 128);
+        assert_eq!(TABLE[SINE_TABLE_SIZE / 4], 255);
+        assert_eq!(TABLE[SINE_TABLE_SIZE / 2], 128);
+        assert_eq!(TABLE[3 * SINE_TABLE_SIZE / 4], 1);
+        assert!(TABLE.iter().all(|&sample| (1..=255).contains(&sample)));
+    }
+
+    #[test]
+    fn samples_follow_the_phase() {
+        // A quarter cycle per sample visits every fourth entry.
+        let mut phase = 0;
+        let samples: [u8; 5] = core::array::from_fn(|_| next_sample(&mut phase, 1 << 30, &TABLE));
+        assert_eq!(samples, [128, 255, 128, 1, 128]);
+        assert_eq!(phase, 1 << 30);
+        // Any table length works, and the last entry is reached just before wrapping.
+        let mut phase = u32::MAX;
+        assert_eq!(next_sample(&mut phase, 1, &[10, 20, 30]), 30);
+        assert_eq!(phase, 0);
+    }
+
+    #[test]
+    fn increments_and_frequencies() {
+        assert_eq!(phase_increment(1_000, 0), 0);
+        assert_eq!(phase_increment(0, 48_000), 0);
+        // A quarter of the sample rate is a quarter of the phase range.
+        assert_eq!(phase_increment(12_000, 48_000), 1 << 30);
+        for hz in [1, 440, 1_000, 12_345, 23_999] {
+            assert_eq!(output_hz(phase_increment(hz, 48_000), 48_000), hz);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+// src/demo.rs
+
+//! A scripted demo that steps through the LED patterns and text modes on its own.
+//!
+//! `DemoPlayer` is timed against `millis` like the players in `led_timing`, and
+//! only says which step is current. Applying a step is up to the caller.
+
+use crate::text::TextMode;
+
+/// One step of the scripted demo.
+/// One step of the scripted demo.
+pub struct DemoStep {
+    /// Bitmask of the `SYSEX_*_BIT` flags, for the LED patterns to enable.
+    pub enables: u8,
+    pub sparkle: bool,
+    pub text_mode: TextMode,
+    pub duration_ms: u32,
+    pub description: &'static str,
+}
+
+/// Plays a list of demo steps in a loop, each for its own duration.
+pub struct DemoPlayer {
+    steps: &'static [DemoStep],
+    index: usize,
+    step_start_ms: u32,
+    running: bool,
+    entered: bool,
+}
+
+impl DemoPlayer {
+    pub fn new(steps: &'static [DemoStep]) -> Self {
+        DemoPlayer {
+            steps,
+            index: 0,
+            step_start_ms: 0,
+            running: false,
+            entered: false,
+        }
+    }
+
+    pub fn start(&mut self, now_ms: u32) {
+        self.index = 0;
+        self.step_start_ms = now_ms;
+        self.running = !self.steps.is_empty();
+        self.entered = false;
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Advance to `now_ms`. Returns the current step when it has just been
+    /// entered, so it can be applied once, and `None` otherwise.
+    pub fn update(&mut self, now_ms: u32) -> Option<&'static DemoStep> {
+        if !self.running {
+            return None;
+        }
+        let steps = self.steps;
+        // Catch up on every step that has elapsed since the last update.
+        while steps[self.index].duration_ms <= now_ms.wrapping_sub(self.step_start_ms) {
+            self.step_start_ms = self
+                .step_start_ms
+                .wrapping_add(steps[self.index].duration_ms);
+            self.index = (self.index + 1) % steps.len();
+            self.entered = false;
+        }
+        match self.entered {
+            true => None,
+            false => {
+                self.entered = true;
+                Some(&steps[self.index])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn step(duration_ms: u32, description: &'static str) -> DemoStep {
+        DemoStep {
+            enables: 0,
+            sparkle: false,
+            text_mode: TextMode::NormalCase,
+            duration_ms,
+            description,
+        }
+    }
+
+    static STEPS: [DemoStep; 3] = [step(100, "a"), step(200, "b"), step(300, "c")];
+
+    /// The description of the step entered by `update`, if any.
+    fn entered(demo: &mut DemoPlayer, now_ms: u32) -> Option<&'static str> {
+        demo.update(now_ms).map(|step| step.description)
+    }
+
+    #[test]
+    fn each_step_is_entered_once() {
+        let mut demo = DemoPlayer::new(&STEPS);
+        assert!(!demo.is_running());
+        assert_eq!(entered(&mut demo, 0), None);
+        demo.start(1_000);
+        assert!(demo.is_running());
+        assert_eq!(entered(&mut demo, 1_000), Some("a"));
+        assert_eq!(entered(&mut demo, 1_099), None);
+        assert_eq!(entered(&mut demo, 1_100), Some("b"));
+        assert_eq!(entered(&mut demo, 1_299), None);
+        assert_eq!(entered(&mut demo, 1_300), Some("c"));
+        // The demo loops back to the first step.
+        assert_eq!(entered(&mut demo, 1_600), Some("a"));
+    }
+
+    #[test]
+    fn missed_steps_are_skipped() {
+        let mut demo = DemoPlayer::new(&STEPS);
+        demo.start(0);
+        assert_eq!(entered(&mut demo, 0), Some("a"));
+        // Only the step that is current now is reported.
+        assert_eq!(entered(&mut demo, 350), Some("c"));
+        assert_eq!(entered(&mut demo, 599), None);
+    }
+
+    #[test]
+    fn stop_and_restart() {
+        let mut demo = DemoPlayer::new(&STEPS);
+        demo.start(0);
+        assert_eq!(entered(&mut demo, 150), Some("b"));
+        demo.stop();
+        assert!(!demo.is_running());
+        assert_eq!(entered(&mut demo, 400), None);
+        demo.start(400);
+        assert_eq!(entered(&mut demo, 400), Some("a"));
+    }
+
+    #[test]
+    fn no_steps_never_runs() {
+        let mut demo = DemoPlayer::new(&[]);
+        demo.start(0);
+        assert!(!demo.is_running());
+        assert_eq!(entered(&mut demo, 0), None);
+    }
+
+    #[test]
+    fn clock_wrapping() {
+        let mut demo = DemoPlayer::new(&STEPS);
+        demo.start(u32::MAX - 49);
+        assert_eq!(entered(&mut demo, u32::MAX), Some("a"));
+        assert_eq!(entered(&mut demo, 49), None);
+        assert_eq!(entered(&mut demo, 50), Some("b"));
+    }
+}
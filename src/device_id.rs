@@ -0,0 +1,26 @@
+// src/device_id.rs
+
+//! The STM32F103 stores a factory programmed 96-bit unique device ID in system
+//! memory. The HAL does not provide an accessor for it, so it is read directly.
+//!
+//! Reading the ID requires dereferencing raw pointers, so `unsafe` is allowed in
+//! this module only. The reads are sound because the ID registers are always
+//! mapped, read-only, word aligned, and have no side effects when read.
+
+#![allow(unsafe_code)]
+
+/// Base address of the unique device ID registers, see RM0008 section 30.2.
+const DEVICE_ID_ADDRESS: usize = 0x1FFF_F7E8;
+
+/// Read the three 32-bit words of the unique device ID, lowest address first.
+pub fn device_id() -> [u32; 3] {
+    let base = DEVICE_ID_ADDRESS as *const u32;
+    // SAFETY: See the module documentation.
+    unsafe {
+        [
+            core::ptr::read_volatile(base),
+            core::ptr::read_volatile(base.add(1)),
+            core::ptr::read_volatile(base.add(2)),
+        ]
+    }
+}
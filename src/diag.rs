@@ -0,0 +1,310 @@
+// src/diag.rs
+
+//! Diagnostic screens flashed on the onboard LED, for a board without a terminal.
+//!
+//! Each screen strobes its number, then flashes one or more digits like
+//! `FlashCounter`: why the board last reset, the firmware version, and whether the
+//! main loop is keeping up. The level is worked out from the time since the screen
+//! started, so `DiagPlayer` never blocks.
+
+use crate::led_timing::{FLASH_GAP_MS, FLASH_OFF_MS, FLASH_ON_MS};
+use heapless::Vec;
+
+const FIRMWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Each diagnostic screen starts with a burst of quick strobes, one per screen
+// number, so the screens can be told apart, then flashes its digits.
+const DIAG_STROBE_MS: u32 = 60;
+const DIAG_GAP_MS: u32 = 800;
+pub const DIAG_DIGITS_MAX: usize = 3;
+
+// The RCC_CSR reset flags, see RM0008 section 7.3.10.
+const CSR_PINRSTF: u32 = 1 << 26;
+const CSR_PORRSTF: u32 = 1 << 27;
+const CSR_SFTRSTF: u32 = 1 << 28;
+const CSR_IWDGRSTF: u32 = 1 << 29;
+const CSR_WWDGRSTF: u32 = 1 << 30;
+const CSR_LPWRRSTF: u32 = 1 << 31;
+
+/// Why the board last reset, from the RCC_CSR flags.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResetCause {
+    PowerOn,
+    Pin,
+    Software,
+    IndependentWatchdog,
+    WindowWatchdog,
+    LowPower,
+    Unknown,
+}
+
+impl ResetCause {
+    /// A power on reset also sets the pin reset flag, and watchdog and software
+    /// resets pulse the reset pin too, so the most specific flag wins.
+    pub fn from_csr(csr: u32) -> Self {
+        [
+            (CSR_LPWRRSTF, ResetCause::LowPower),
+            (CSR_WWDGRSTF, ResetCause::WindowWatchdog),
+            (CSR_IWDGRSTF, ResetCause::IndependentWatchdog),
+            (CSR_SFTRSTF, ResetCause::Software),
+            (CSR_PORRSTF, ResetCause::PowerOn),
+            (CSR_PINRSTF, ResetCause::Pin),
+        ]
+        .into_iter()
+        .find(|(flag, _)| 0 != csr & flag)
+        .map_or(ResetCause::Unknown, |(_, cause)| cause)
+    }
+
+    /// The digit flashed for this cause.
+    pub fn code(self) -> u32 {
+        match self {
+            ResetCause::PowerOn => 1,
+            ResetCause::Pin => 2,
+            ResetCause::Software => 3,
+            ResetCause::IndependentWatchdog => 4,
+            ResetCause::WindowWatchdog => 5,
+            ResetCause::LowPower => 6,
+            ResetCause::Unknown => 9,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ResetCause::PowerOn => "power on",
+            ResetCause::Pin => "reset pin",
+            ResetCause::Software => "software",
+            ResetCause::IndependentWatchdog => "independent watchdog",
+            ResetCause::WindowWatchdog => "window watchdog",
+            ResetCause::LowPower => "low power",
+            ResetCause::Unknown => "unknown",
+        }
+    }
+}
+
+/// Diagnostic information that can be flashed on the onboard LED, for use
+/// without a terminal. A long press of B1 steps through the screens in order,
+/// and a long press on the last screen goes back to the usual LED behavior.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiagScreen {
+    /// One digit, see `ResetCause::code`.
+    ResetCause,
+    /// The major, minor and patch version, one digit each.
+    Version,
+    /// 1 if the main loop is fast enough, 2 if it is too slow for the software
+    /// PWM, and 3 if transmitted text has been dropped.
+    LoopHealth,
+}
+
+impl DiagScreen {
+    pub const FIRST: DiagScreen = DiagScreen::ResetCause;
+
+    pub fn next(self) -> Option<DiagScreen> {
+        match self {
+            DiagScreen::ResetCause => Some(DiagScreen::Version),
+            DiagScreen::Version => Some(DiagScreen::LoopHealth),
+            DiagScreen::LoopHealth => None,
+        }
+    }
+
+    /// The number of strobes that introduce the screen.
+    pub fn number(self) -> u32 {
+        match self {
+            DiagScreen::ResetCause => 1,
+            DiagScreen::Version => 2,
+            DiagScreen::LoopHealth => 3,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DiagScreen::ResetCause => "reset cause",
+            DiagScreen::Version => "firmware version",
+            DiagScreen::LoopHealth => "loop health",
+        }
+    }
+}
+
+/// The LED level `elapsed_ms` into a diagnostic screen. The screen strobes its
+/// number, then flashes each digit like `FlashCounter`, with a pause after each
+/// group, and repeats after a longer gap.
+pub fn diag_level(screen_number: u32, digits: &[u32], elapsed_ms: u32) -> bool {
+    let flashes = |digit: u32| match digit % 10 {
+        0 => 10,
+        digit => digit,
+    };
+    let groups = || {
+        core::iter::once((screen_number, DIAG_STROBE_MS, DIAG_STROBE_MS)).chain(
+            digits
+                .iter()
+                .map(|&digit| (flashes(digit), FLASH_ON_MS, FLASH_OFF_MS)),
+        )
+    };
+    let cycle_ms: u32 = groups()
+        .map(|(count, on_ms, off_ms)| count * (on_ms + off_ms) + DIAG_GAP_MS)
+        .sum::<u32>()
+        + FLASH_GAP_MS;
+    let mut t = elapsed_ms % cycle_ms;
+    for (count, on_ms, off_ms) in groups() {
+        let group_ms = count * (on_ms + off_ms);
+        if t < group_ms {
+            return t % (on_ms + off_ms) < on_ms;
+        }
+        t -= group_ms;
+        if t < DIAG_GAP_MS {
+            return false;
+        }
+        t -= DIAG_GAP_MS;
+    }
+    false
+}
+
+/// The digits shown by `screen`.
+pub fn diag_digits(
+    screen: DiagScreen,
+    reset_cause: ResetCause,
+    loop_healthy: bool,
+    dropped: u32,
+) -> Vec<u32, DIAG_DIGITS_MAX> {
+    let mut digits = Vec::new();
+    match screen {
+        DiagScreen::ResetCause => digits.push(reset_cause.code()).ok(),
+        DiagScreen::Version => {
+            for part in FIRMWARE_VERSION.split('.').take(DIAG_DIGITS_MAX) {
+                digits.push(part.parse().unwrap_or(0)).ok();
+            }
+            None
+        }
+        DiagScreen::LoopHealth => {
+            let health = match (dropped, loop_healthy) {
+                (1.., _) => 3,
+                (0, false) => 2,
+                (0, true) => 1,
+            };
+            digits.push(health).ok()
+        }
+    };
+    digits
+}
+
+/// Plays one diagnostic screen on the onboard LED, without blocking.
+pub struct DiagPlayer {
+    screen: DiagScreen,
+    digits: Vec<u32, DIAG_DIGITS_MAX>,
+    start_ms: u32,
+}
+
+impl DiagPlayer {
+    pub fn new(screen: DiagScreen, digits: Vec<u32, DIAG_DIGITS_MAX>, now_ms: u32) -> Self {
+        DiagPlayer {
+            screen,
+            digits,
+            start_ms: now_ms,
+        }
+    }
+
+    pub fn screen(&self) -> DiagScreen {
+        self.screen
+    }
+
+    pub fn level(&self, now_ms: u32) -> bool {
+        let elapsed_ms = now_ms.wrapping_sub(self.start_ms);
+        diag_level(self.screen.number(), &self.digits, elapsed_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screens_cycle_in_order_then_stop() {
+        let mut order = Vec::<DiagScreen, 4>::new();
+        let mut screen = Some(DiagScreen::FIRST);
+        while let Some(current) = screen {
+            order.push(current).unwrap();
+            screen = current.next();
+        }
+        assert_eq!(
+            order,
+            [
+                DiagScreen::ResetCause,
+                DiagScreen::Version,
+                DiagScreen::LoopHealth
+            ]
+        );
+        // Each screen strobes a different number, counting up.
+        let numbers: Vec<u32, 4> = order.iter().map(|screen| screen.number()).collect();
+        assert_eq!(numbers, [1, 2, 3]);
+    }
+
+    #[test]
+    fn most_specific_reset_flag_wins() {
+        assert_eq!(ResetCause::from_csr(0), ResetCause::Unknown);
+        assert_eq!(ResetCause::from_csr(CSR_PINRSTF), ResetCause::Pin);
+        assert_eq!(
+            ResetCause::from_csr(CSR_PORRSTF | CSR_PINRSTF),
+            ResetCause::PowerOn
+        );
+        assert_eq!(
+            ResetCause::from_csr(CSR_IWDGRSTF | CSR_PINRSTF),
+            ResetCause::IndependentWatchdog
+        );
+        assert_eq!(
+            ResetCause::from_csr(CSR_SFTRSTF | CSR_PINRSTF),
+            ResetCause::Software
+        );
+        assert_eq!(ResetCause::from_csr(u32::MAX), ResetCause::LowPower);
+        // Bits other than the reset flags are ignored.
+        assert_eq!(ResetCause::from_csr(0x0C00_0003), ResetCause::PowerOn);
+    }
+
+    #[test]
+    fn screen_digits() {
+        let digits = diag_digits(DiagScreen::ResetCause, ResetCause::Software, true, 0);
+        assert_eq!(digits, [3]);
+        let health = |loop_healthy, dropped| {
+            diag_digits(
+                DiagScreen::LoopHealth,
+                ResetCause::Pin,
+                loop_healthy,
+                dropped,
+            )
+        };
+        assert_eq!(health(true, 0), [1]);
+        assert_eq!(health(false, 0), [2]);
+        assert_eq!(health(true, 5), [3]);
+        let version = diag_digits(DiagScreen::Version, ResetCause::Pin, true, 0);
+        let expected: Vec<u32, DIAG_DIGITS_MAX> = FIRMWARE_VERSION
+            .split('.')
+            .map(|part| part.parse().unwrap())
+            .collect();
+        assert_eq!(version, expected);
+    }
+
+    #[test]
+    fn level_strobes_the_number_then_flashes_the_digits() {
+        let period_ms = DIAG_STROBE_MS + DIAG_STROBE_MS;
+        // Two strobes for screen 2, then the gap.
+        assert!(diag_level(2, &[1], 0));
+        assert!(!diag_level(2, &[1], DIAG_STROBE_MS));
+        assert!(diag_level(2, &[1], period_ms));
+        assert!(!diag_level(2, &[1], 2 * period_ms));
+        // One flash for the digit 1, then the long gap, then it repeats.
+        let digit_ms = 2 * period_ms + DIAG_GAP_MS;
+        assert!(diag_level(2, &[1], digit_ms));
+        assert!(!diag_level(2, &[1], digit_ms + FLASH_ON_MS));
+        let cycle_ms = digit_ms + FLASH_ON_MS + FLASH_OFF_MS + DIAG_GAP_MS + FLASH_GAP_MS;
+        assert!(!diag_level(2, &[1], cycle_ms - 1));
+        assert!(diag_level(2, &[1], cycle_ms));
+    }
+
+    #[test]
+    fn player_counts_from_its_start() {
+        let mut digits = Vec::new();
+        digits.push(1).unwrap();
+        let player = DiagPlayer::new(DiagScreen::ResetCause, digits, u32::MAX);
+        assert_eq!(player.screen(), DiagScreen::ResetCause);
+        assert!(player.level(u32::MAX));
+        assert!(!player.level(DIAG_STROBE_MS - 1));
+    }
+}
@@ -0,0 +1,90 @@
+// src/ds18b20.rs
+
+//! Data checks and conversions for the Maxim DS18B20 1-Wire temperature sensor.
+//!
+//! The scratchpad is nine bytes, ending with a CRC-8 of the other eight, and starts
+//! with the temperature in sixteenths of a degree, least significant byte first.
+
+/// Length of the scratchpad, including its CRC.
+pub const SCRATCHPAD_SIZE: usize = 9;
+const DS18B20_CRC_POLYNOMIAL: u8 = 0x8C; // x^8 + x^5 + x^4 + 1, bit reversed
+
+/// Dallas/Maxim CRC-8, over the ROM code or the scratchpad, least significant bit
+/// first. Running it over data that ends with its own CRC gives 0. The example
+/// from Maxim application note 27 is `ds18b20_crc8(&[0x02, 0x1C, 0xB8, 0x01, 0x00,
+/// 0x00, 0x00]) == 0xA2`.
+pub fn ds18b20_crc8(data: &[u8]) -> u8 {
+    let mut crc = 0;
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = match crc & 0x01 {
+                0 => crc >> 1,
+                _ => (crc >> 1) ^ DS18B20_CRC_POLYNOMIAL,
+            };
+        }
+    }
+    crc
+}
+
+/// Temperature in hundredths of a degree Celsius, from the raw 12-bit reading in
+/// sixteenths of a degree. The result is truncated toward zero, so 0xFF5E,
+/// -10.125 C, gives -1012.
+pub fn raw_to_celsius(raw: i16) -> i32 {
+    raw as i32 * 100 / 16
+}
+
+/// The raw temperature from `scratchpad`, or `None` if its CRC does not match.
+pub fn scratchpad_temperature(scratchpad: &[u8; SCRATCHPAD_SIZE]) -> Option<i16> {
+    match ds18b20_crc8(scratchpad) {
+        0 => Some(i16::from_le_bytes([scratchpad[0], scratchpad[1]])),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratchpad holding `raw`, with the power up values after it and its CRC.
+    fn scratchpad(raw: i16) -> [u8; SCRATCHPAD_SIZE] {
+        let [lsb, msb] = raw.to_le_bytes();
+        let mut scratchpad = [lsb, msb, 0x4B, 0x46, 0x7F, 0xFF, 0x0C, 0x10, 0];
+        scratchpad[8] = ds18b20_crc8(&scratchpad[..8]);
+        scratchpad
+    }
+
+    #[test]
+    fn crc_application_note_example() {
+        let rom = [0x02, 0x1C, 0xB8, 0x01, 0x00, 0x00, 0x00];
+        assert_eq!(ds18b20_crc8(&rom), 0xA2);
+        assert_eq!(
+            ds18b20_crc8(&[0x02, 0x1C, 0xB8, 0x01, 0x00, 0x00, 0x00, 0xA2]),
+            0
+        );
+        assert_eq!(ds18b20_crc8(&[]), 0);
+    }
+
+    #[test]
+    fn datasheet_temperatures() {
+        assert_eq!(raw_to_celsius(0x07D0), 12_500);
+        assert_eq!(raw_to_celsius(0x0550), 8_500);
+        assert_eq!(raw_to_celsius(0x0191), 2_506);
+        assert_eq!(raw_to_celsius(0x0008), 50);
+        assert_eq!(raw_to_celsius(0x0000), 0);
+        assert_eq!(raw_to_celsius(0xFFF8_u16 as i16), -50);
+        assert_eq!(raw_to_celsius(0xFF5E_u16 as i16), -1_012);
+        assert_eq!(raw_to_celsius(0xFC90_u16 as i16), -5_500);
+    }
+
+    #[test]
+    fn scratchpads_are_checked() {
+        assert_eq!(scratchpad_temperature(&scratchpad(0x0191)), Some(0x0191));
+        assert_eq!(scratchpad_temperature(&scratchpad(-880)), Some(-880));
+        let mut corrupt = scratchpad(0x0191);
+        corrupt[0] ^= 0x01;
+        assert_eq!(scratchpad_temperature(&corrupt), None);
+        // A bus with nothing on it reads all ones, which fails the CRC.
+        assert_eq!(scratchpad_temperature(&[0xFF; SCRATCHPAD_SIZE]), None);
+    }
+}
@@ -0,0 +1,39 @@
+// src/flow_control.rs
+
+//! RTS/CTS hardware flow control for USART1, USART2 and USART3.
+//!
+//! With flow control on, the USART only transmits while its CTS input is low, and
+//! drives its RTS output high while a received byte is waiting to be read, which
+//! tells the other side to pause. This prevents receive overruns at high baud
+//! rates, but only if the other side also honors RTS/CTS. Cross the wires, so
+//! each side's RTS drives the other side's CTS.
+//!
+//! The default pin mapping is shown below. The HAL does not configure these pins,
+//! so the example must. CTS is an input, and RTS is an alternate function output.
+//! - USART1: CTS on PA11, RTS on PA12
+//! - USART2: CTS on PA0, RTS on PA1
+//! - USART3: CTS on PB13, RTS on PB14
+//!
+//! The ST-Link virtual COM port only connects TX and RX of USART2, so flow control
+//! is only useful on a USART wired to a device that supports it.
+//!
+//! The HAL serial `Config` has no flow control setting, and the USART registers are
+//! owned by the `Serial` once it is created, so the control register is written
+//! through a raw pointer. `unsafe` is allowed in this module only. The write is
+//! sound because the `Tx` half proves the USART was configured by `Serial::new`,
+//! and the HAL only modifies CR3 when DMA is started or stopped, which must not
+//! happen at the same time as `enable_rts_cts`.
+
+#![allow(unsafe_code)]
+
+use stm32f1xx_hal::serial::{Instance, Tx};
+
+/// Turn on RTS/CTS hardware flow control for the USART that `tx` belongs to.
+pub fn enable_rts_cts<USART: Instance>(_tx: &mut Tx<USART>) {
+    // SAFETY: See the module documentation.
+    unsafe {
+        (*USART::ptr())
+            .cr3
+            .modify(|_, w| w.rtse().set_bit().ctse().set_bit());
+    }
+}
@@ -0,0 +1,151 @@
+// src/font.rs
+
+//! A 5x7 pixel font, for drawing text one column at a time on a row of LEDs.
+//!
+//! Each glyph is five columns wide. A column is one byte, with bit 0 the top row
+//! and bit 6 the bottom row, and bit 7 always clear. The glyphs are the classic
+//! 5x7 LCD font, for the space, digits, upper case letters, and a little
+//! punctuation. Lower case letters are drawn in upper case, and any other
+//! character is blank, so unexpected input shows as a gap rather than garbage.
+
+/// Columns in a glyph.
+pub const GLYPH_WIDTH: u8 = 5;
+/// Rows in a glyph, the low bits of a column.
+pub const GLYPH_HEIGHT: u8 = 7;
+
+const BLANK: [u8; 5] = [0x00, 0x00, 0x00, 0x00, 0x00];
+
+const DIGITS: [[u8; 5]; 10] = [
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // 0
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4B, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1E], // 9
+];
+
+const LETTERS: [[u8; 5]; 26] = [
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], // A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], // D
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7F, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3E, 0x41, 0x49, 0x49, 0x7A], // G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // H
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // J
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F], // M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // N
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7F, 0x01, 0x01], // T
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // V
+    [0x3F, 0x40, 0x38, 0x40, 0x3F], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+];
+
+fn glyph(c: u8) -> &'static [u8; 5] {
+    match c.to_ascii_uppercase() {
+        c @ b'0'..=b'9' => &DIGITS[(c - b'0') as usize],
+        c @ b'A'..=b'Z' => &LETTERS[(c - b'A') as usize],
+        b'!' => &[0x00, 0x00, 0x5F, 0x00, 0x00],
+        b'\'' => &[0x00, 0x05, 0x03, 0x00, 0x00],
+        b',' => &[0x00, 0x50, 0x30, 0x00, 0x00],
+        b'-' => &[0x08, 0x08, 0x08, 0x08, 0x08],
+        b'.' => &[0x00, 0x60, 0x60, 0x00, 0x00],
+        b':' => &[0x00, 0x36, 0x36, 0x00, 0x00],
+        b'?' => &[0x02, 0x01, 0x51, 0x09, 0x06],
+        _ => &BLANK,
+    }
+}
+
+/// The pixels in column `col` of the glyph for `c`, bit 0 at the top.
+/// Columns past the glyph width are blank.
+pub fn font_column(c: u8, col: u8) -> u8 {
+    glyph(c).get(col as usize).copied().unwrap_or(0)
+}
+
+/// Column `index` of `message` drawn in the 5x7 font, with a blank column after
+/// each character. Past the end of the message, the columns are blank.
+pub fn message_column(message: &[u8], index: usize) -> u8 {
+    let width = GLYPH_WIDTH as usize + 1;
+    match message.get(index / width) {
+        Some(&c) => font_column(c, (index % width) as u8),
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The glyph for `c` as rows of `#` and `.`, top row first.
+    fn draw(c: u8) -> [std::string::String; GLYPH_HEIGHT as usize] {
+        core::array::from_fn(|row| {
+            (0..GLYPH_WIDTH)
+                .map(|col| match font_column(c, col) & (1 << row) {
+                    0 => '.',
+                    _ => '#',
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn glyphs_are_drawn_top_down() {
+        assert_eq!(
+            draw(b'L'),
+            ["#....", "#....", "#....", "#....", "#....", "#....", "#####"]
+        );
+        assert_eq!(
+            draw(b'7'),
+            ["#####", "....#", "...#.", "..#..", ".#...", ".#...", ".#..."]
+        );
+    }
+
+    #[test]
+    fn lower_case_is_drawn_as_upper_case() {
+        for c in b'a'..=b'z' {
+            assert_eq!(draw(c), draw(c.to_ascii_uppercase()));
+        }
+    }
+
+    #[test]
+    fn unknown_characters_and_columns_are_blank() {
+        for c in [b' ', b'#', b'~', 0x00, 0xFF] {
+            assert!((0..GLYPH_WIDTH).all(|col| 0 == font_column(c, col)));
+        }
+        assert_eq!(font_column(b'A', GLYPH_WIDTH), 0);
+        assert_eq!(font_column(b'A', u8::MAX), 0);
+    }
+
+    #[test]
+    fn every_glyph_fits_in_seven_rows() {
+        for c in 0..=u8::MAX {
+            assert!((0..GLYPH_WIDTH).all(|col| font_column(c, col) < 1 << GLYPH_HEIGHT));
+        }
+    }
+
+    #[test]
+    fn message_columns_have_a_gap_after_each_character() {
+        let columns: Vec<u8> = (0..14).map(|index| message_column(b"HI", index)).collect();
+        assert_eq!(
+            columns,
+            [0x7F, 0x08, 0x08, 0x08, 0x7F, 0, 0x00, 0x41, 0x7F, 0x41, 0x00, 0, 0, 0]
+        );
+        assert_eq!(message_column(b"", 0), 0);
+    }
+}
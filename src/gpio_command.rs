@@ -0,0 +1,83 @@
+// src/gpio_command.rs
+
+//! Parsing of the pin commands in `gpio_repl`, like `out 2` or `rd 0`.
+//!
+//! The pins are numbered from 0 within the fixed set the example can reconfigure,
+//! rather than named by port and pin, so the set can change without touching the
+//! parser.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PinAction {
+    Input,
+    Output,
+    High,
+    Low,
+    Read,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PinCommand {
+    pub action: PinAction,
+    pub pin: usize,
+}
+
+/// Parse a command line like `out 2`, for a set of `pin_count` pins. Returns `None`
+/// for anything unrecognized, including pin numbers outside the set.
+pub fn parse_pin_command(line: &str, pin_count: usize) -> Option<PinCommand> {
+    let (action, pin) = line.trim().split_once(' ')?;
+    let action = match action {
+        "in" => PinAction::Input,
+        "out" => PinAction::Output,
+        "hi" => PinAction::High,
+        "lo" => PinAction::Low,
+        "rd" => PinAction::Read,
+        _ => return None,
+    };
+    let pin: usize = pin.trim().parse().ok()?;
+    if pin_count <= pin {
+        return None;
+    }
+    Some(PinCommand { action, pin })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(action: PinAction, pin: usize) -> Option<PinCommand> {
+        Some(PinCommand { action, pin })
+    }
+
+    #[test]
+    fn every_action() {
+        assert_eq!(parse_pin_command("in 0", 3), command(PinAction::Input, 0));
+        assert_eq!(parse_pin_command("out 1", 3), command(PinAction::Output, 1));
+        assert_eq!(parse_pin_command("hi 2", 3), command(PinAction::High, 2));
+        assert_eq!(parse_pin_command("lo 2", 3), command(PinAction::Low, 2));
+        assert_eq!(parse_pin_command("rd 0", 3), command(PinAction::Read, 0));
+    }
+
+    #[test]
+    fn surrounding_whitespace() {
+        assert_eq!(
+            parse_pin_command("  rd  1 ", 3),
+            command(PinAction::Read, 1)
+        );
+    }
+
+    #[test]
+    fn pins_outside_the_set() {
+        assert_eq!(parse_pin_command("out 3", 3), None);
+        assert_eq!(parse_pin_command("out 0", 0), None);
+        assert_eq!(parse_pin_command("out -1", 3), None);
+    }
+
+    #[test]
+    fn unrecognized_lines() {
+        for line in [
+            "", "in", "out", "rd x", "read 1", "IN 1", "in 1 2", "cfg a 5",
+        ] {
+            assert_eq!(parse_pin_command(line, 3), None, "{:?}", line);
+        }
+    }
+}
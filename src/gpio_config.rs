@@ -0,0 +1,273 @@
+// src/gpio_config.rs
+
+//! Reading back and decoding the GPIO port configuration registers, to check that
+//! pins are set up as intended.
+//!
+//! Every pin has four configuration bits, in CRL for pins 0-7 and CRH for pins
+//! 8-15, see RM0008 section 9.2.1. The low two bits, MODE, are 00 for an input,
+//! or the maximum output speed. The high two bits, CNF, select the kind of input
+//! or output. A pulled input is pulled up when its ODR bit is set, and down when
+//! it is clear. A port whose clock is off reads as all zeros, which decodes as
+//! analog inputs.
+//!
+//! The HAL gives no way to read these registers once a port has been split, so they
+//! are read directly, and `unsafe` is allowed in this module only. The reads are
+//! sound because the GPIO registers are always mapped, word aligned, and have no
+//! side effects when read. Nothing is written.
+
+#![allow(unsafe_code)]
+
+use stm32f1xx_hal::pac;
+
+const PIN_CONFIG_BITS: u32 = 4;
+const PIN_CONFIG_MASK: u32 = 0b1111;
+const PINS_PER_REGISTER: u8 = 8;
+pub const PINS_PER_PORT: u8 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PinFunction {
+    Analog,
+    FloatingInput,
+    /// Pulled up or down, depending on the ODR bit.
+    PullInput,
+    PushPull,
+    OpenDrain,
+    AlternatePushPull,
+    AlternateOpenDrain,
+    /// CNF 11 with MODE 00 is reserved.
+    Reserved,
+}
+
+impl PinFunction {
+    pub fn name(self) -> &'static str {
+        match self {
+            PinFunction::Analog => "analog input",
+            PinFunction::FloatingInput => "floating input",
+            PinFunction::PullInput => "pull-up/pull-down input",
+            PinFunction::PushPull => "push-pull output",
+            PinFunction::OpenDrain => "open-drain output",
+            PinFunction::AlternatePushPull => "alternate function push-pull output",
+            PinFunction::AlternateOpenDrain => "alternate function open-drain output",
+            PinFunction::Reserved => "reserved",
+        }
+    }
+}
+
+/// The decoded configuration of one pin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PinConfigDesc {
+    pub function: PinFunction,
+    /// Maximum output speed, or `None` for an input.
+    pub speed_mhz: Option<u8>,
+}
+
+/// Decode the configuration of `pin` from the values of CRL and CRH.
+/// Only the low four bits of `pin` are used.
+pub fn decode_pin_config(crl: u32, crh: u32, pin: u8) -> PinConfigDesc {
+    let pin = pin % PINS_PER_PORT;
+    let register = match pin < PINS_PER_REGISTER {
+        true => crl,
+        false => crh,
+    };
+    let shift = (pin % PINS_PER_REGISTER) as u32 * PIN_CONFIG_BITS;
+    let bits = (register >> shift) & PIN_CONFIG_MASK;
+    let (mode, cnf) = (bits & 0b11, bits >> 2);
+    let speed_mhz = match mode {
+        0b01 => Some(10),
+        0b10 => Some(2),
+        0b11 => Some(50),
+        _ => None,
+    };
+    let function = match (speed_mhz.is_some(), cnf) {
+        (false, 0b00) => PinFunction::Analog,
+        (false, 0b01) => PinFunction::FloatingInput,
+        (false, 0b10) => PinFunction::PullInput,
+        (false, _) => PinFunction::Reserved,
+        (true, 0b00) => PinFunction::PushPull,
+        (true, 0b01) => PinFunction::OpenDrain,
+        (true, 0b10) => PinFunction::AlternatePushPull,
+        (true, _) => PinFunction::AlternateOpenDrain,
+    };
+    PinConfigDesc {
+        function,
+        speed_mhz,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Port {
+    A,
+    B,
+    C,
+    D,
+}
+
+impl Port {
+    /// The port for a letter, in either case.
+    pub fn from_letter(letter: char) -> Option<Port> {
+        match letter.to_ascii_uppercase() {
+            'A' => Some(Port::A),
+            'B' => Some(Port::B),
+            'C' => Some(Port::C),
+            'D' => Some(Port::D),
+            _ => None,
+        }
+    }
+
+    pub fn letter(self) -> char {
+        match self {
+            Port::A => 'A',
+            Port::B => 'B',
+            Port::C => 'C',
+            Port::D => 'D',
+        }
+    }
+}
+
+/// Parse a command line like `cfg a` or `cfg a 5`. The pin is optional, but must
+/// be on the port when given.
+pub fn parse_cfg_command(line: &str) -> Option<(Port, Option<u8>)> {
+    let mut args = line.trim().strip_prefix("cfg ")?.split_whitespace();
+    let mut letters = args.next()?.chars();
+    let port = Port::from_letter(letters.next()?)?;
+    if letters.next().is_some() {
+        return None;
+    }
+    let pin = match args.next() {
+        Some(pin) => Some(pin.parse().ok().filter(|pin| *pin < PINS_PER_PORT)?),
+        None => None,
+    };
+    match args.next() {
+        Some(_) => None,
+        None => Some((port, pin)),
+    }
+}
+
+/// Register values read back from one port.
+#[derive(Clone, Copy)]
+pub struct PortConfig {
+    pub crl: u32,
+    pub crh: u32,
+    pub odr: u32,
+}
+
+impl PortConfig {
+    pub fn pin(&self, pin: u8) -> PinConfigDesc {
+        decode_pin_config(self.crl, self.crh, pin)
+    }
+
+    /// Whether a pulled input on `pin` is pulled up, rather than down.
+    pub fn is_pulled_up(&self, pin: u8) -> bool {
+        0 != self.odr & (1 << (pin % PINS_PER_PORT))
+    }
+}
+
+/// Read the configuration registers of `port`.
+pub fn read_port_config(port: Port) -> PortConfig {
+    // Every port has the same register block layout.
+    let registers = match port {
+        Port::A => pac::GPIOA::ptr(),
+        Port::B => pac::GPIOB::ptr(),
+        Port::C => pac::GPIOC::ptr(),
+        Port::D => pac::GPIOD::ptr(),
+    };
+    // SAFETY: See the module documentation.
+    let registers = unsafe { &*registers };
+    PortConfig {
+        crl: registers.crl.read().bits(),
+        crh: registers.crh.read().bits(),
+        odr: registers.odr.read().bits(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A register with `bits` in the slot for `pin`, and every other pin analog.
+    fn with_pin(pin: u8, bits: u32) -> u32 {
+        bits << ((pin % PINS_PER_REGISTER) as u32 * PIN_CONFIG_BITS)
+    }
+
+    #[test]
+    fn every_mode_and_cnf_decodes() {
+        let cases = [
+            (0b0000, PinFunction::Analog, None),
+            (0b0100, PinFunction::FloatingInput, None),
+            (0b1000, PinFunction::PullInput, None),
+            (0b1100, PinFunction::Reserved, None),
+            (0b0001, PinFunction::PushPull, Some(10)),
+            (0b0010, PinFunction::PushPull, Some(2)),
+            (0b0011, PinFunction::PushPull, Some(50)),
+            (0b0110, PinFunction::OpenDrain, Some(2)),
+            (0b1011, PinFunction::AlternatePushPull, Some(50)),
+            (0b1101, PinFunction::AlternateOpenDrain, Some(10)),
+        ];
+        for (bits, function, speed_mhz) in cases {
+            let expected = PinConfigDesc {
+                function,
+                speed_mhz,
+            };
+            assert_eq!(decode_pin_config(bits, 0, 0), expected, "{:04b}", bits);
+        }
+    }
+
+    #[test]
+    fn pins_are_read_from_the_right_register_and_slot() {
+        // The reset value of CRL and CRH, every pin a floating input.
+        const RESET: u32 = 0x4444_4444;
+        let push_pull = PinConfigDesc {
+            function: PinFunction::PushPull,
+            speed_mhz: Some(2),
+        };
+        for pin in 0..PINS_PER_PORT {
+            let register = (RESET & !with_pin(pin, PIN_CONFIG_MASK)) | with_pin(pin, 0b0010);
+            let (crl, crh) = match pin < PINS_PER_REGISTER {
+                true => (register, RESET),
+                false => (RESET, register),
+            };
+            for other in 0..PINS_PER_PORT {
+                let expected = match other == pin {
+                    true => push_pull,
+                    false => PinConfigDesc {
+                        function: PinFunction::FloatingInput,
+                        speed_mhz: None,
+                    },
+                };
+                assert_eq!(decode_pin_config(crl, crh, other), expected);
+            }
+        }
+        // Only the low four bits of the pin are used.
+        assert_eq!(decode_pin_config(0, with_pin(13, 0b0010), 29), push_pull);
+    }
+
+    #[test]
+    fn pull_direction_comes_from_odr() {
+        let port = PortConfig {
+            crl: with_pin(3, 0b1000),
+            crh: 0,
+            odr: 1 << 3,
+        };
+        assert_eq!(port.pin(3).function, PinFunction::PullInput);
+        assert!(port.is_pulled_up(3));
+        assert!(!port.is_pulled_up(4));
+    }
+
+    #[test]
+    fn cfg_commands() {
+        assert_eq!(parse_cfg_command("cfg a"), Some((Port::A, None)));
+        assert_eq!(parse_cfg_command(" cfg D 15 "), Some((Port::D, Some(15))));
+        assert_eq!(parse_cfg_command("cfg b 0"), Some((Port::B, Some(0))));
+        for line in [
+            "cfg",
+            "cfg ",
+            "cfg e",
+            "cfg ab",
+            "cfg a 16",
+            "cfg a x",
+            "cfg a 1 2",
+        ] {
+            assert_eq!(parse_cfg_command(line), None, "{:?}", line);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+// src/hash.rs
+
+//! The 32-bit FNV-1a hash, a tiny non-cryptographic hash for telling data apart.
+//!
+//! Each byte is XORed into the hash, which is then multiplied by the FNV prime.
+//! It needs no table and no state beyond the hash itself, and a change to any
+//! byte changes the result, which is all that spotting repeated data needs. It is
+//! easy to construct collisions on purpose, so never use it for anything that an
+//! attacker could exploit.
+
+const FNV_OFFSET_BASIS: u32 = 0x811C_9DC5;
+const FNV_PRIME: u32 = 0x0100_0193;
+
+/// The FNV-1a hash of `data`. An empty slice hashes to the offset basis.
+pub fn fnv1a(data: &[u8]) -> u32 {
+    data.iter().fold(FNV_OFFSET_BASIS, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(FNV_PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reference_values() {
+        assert_eq!(fnv1a(b""), FNV_OFFSET_BASIS);
+        assert_eq!(fnv1a(b"a"), 0xE40C_292C);
+        assert_eq!(fnv1a(b"foobar"), 0xBF9C_F968);
+    }
+
+    #[test]
+    fn order_and_every_byte_matter() {
+        assert_ne!(fnv1a(b"ab"), fnv1a(b"ba"));
+        assert_ne!(fnv1a(b"a"), fnv1a(b"a\0"));
+        let line = *b"Hello, Nucleo!";
+        for i in 0..line.len() {
+            let mut changed = line;
+            changed[i] ^= 0x01;
+            assert_ne!(fnv1a(&changed), fnv1a(&line));
+        }
+    }
+}
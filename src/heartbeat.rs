@@ -0,0 +1,127 @@
+// src/heartbeat.rs
+
+//! A liveness message sent over USART at a fixed interval, for a host monitoring
+//! the port to detect a hang.
+//!
+//! The interval is measured from the previous message, not from when it was due,
+//! so a message sent late pushes the next one back rather than bunching up.
+
+/// How long the heartbeat LED stays lit after each message.
+pub const HEARTBEAT_PULSE_MS: u32 = 50;
+
+/// Whether a heartbeat sent at `last_ms` is due again at `now_ms`.
+pub fn heartbeat_due(now_ms: u32, last_ms: u32, interval_ms: u32) -> bool {
+    interval_ms <= now_ms.wrapping_sub(last_ms)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HeartbeatMessage {
+    /// A single `.`, the least clutter for a person watching the terminal.
+    Dot,
+    /// `alive ms=NNN` with the uptime, which a script can parse.
+    Alive,
+}
+
+/// A liveness message sent over USART at a fixed interval, so a host can detect
+/// a hang without watching the LEDs. It can also pulse one LED with every message,
+/// for a board that is not connected to a host.
+pub struct Heartbeat {
+    interval_ms: u32,
+    message: HeartbeatMessage,
+    last_ms: u32,
+    /// The LED pulsed on every heartbeat, by its bit in the LED mask.
+    led: Option<usize>,
+}
+
+impl Heartbeat {
+    /// The first heartbeat is sent one interval after `now_ms`.
+    pub fn new(now_ms: u32, interval_ms: u32, message: HeartbeatMessage) -> Self {
+        Heartbeat {
+            interval_ms,
+            message,
+            last_ms: now_ms,
+            led: None,
+        }
+    }
+
+    /// The LED pulsed on every heartbeat, if any.
+    pub fn led(&self) -> Option<usize> {
+        self.led
+    }
+
+    /// Move the heartbeat pulse to `led`, or stop pulsing for `None`, and return
+    /// the LED it was on before.
+    pub fn set_led(&mut self, led: Option<usize>) -> Option<usize> {
+        core::mem::replace(&mut self.led, led)
+    }
+
+    /// The assigned LED, and whether it is lit at `now_ms`, shortly after each message.
+    pub fn led_level(&self, now_ms: u32) -> Option<(usize, bool)> {
+        let lit = now_ms.wrapping_sub(self.last_ms) < HEARTBEAT_PULSE_MS;
+        self.led.map(|led| (led, lit))
+    }
+
+    /// Returns the message to send, if a heartbeat is due at `now_ms`.
+    pub fn update(&mut self, now_ms: u32) -> Option<HeartbeatMessage> {
+        if !heartbeat_due(now_ms, self.last_ms, self.interval_ms) {
+            return None;
+        }
+        self.last_ms = now_ms;
+        Some(self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn due_after_a_whole_interval() {
+        assert!(!heartbeat_due(999, 0, 1_000));
+        assert!(heartbeat_due(1_000, 0, 1_000));
+        assert!(heartbeat_due(5_000, 0, 1_000));
+        // The uptime wrapping does not delay or hurry the next message.
+        assert!(!heartbeat_due(498, u32::MAX - 500, 1_000));
+        assert!(heartbeat_due(499, u32::MAX - 500, 1_000));
+    }
+
+    #[test]
+    fn messages_are_sent_once_per_interval() {
+        let mut heartbeat = Heartbeat::new(100, 1_000, HeartbeatMessage::Alive);
+        assert_eq!(heartbeat.update(100), None);
+        assert_eq!(heartbeat.update(1_099), None);
+        assert_eq!(heartbeat.update(1_100), Some(HeartbeatMessage::Alive));
+        assert_eq!(heartbeat.update(1_100), None);
+        // A late message moves the schedule back, so the next is one interval later.
+        assert_eq!(heartbeat.update(2_600), Some(HeartbeatMessage::Alive));
+        assert_eq!(heartbeat.update(3_599), None);
+        assert_eq!(heartbeat.update(3_600), Some(HeartbeatMessage::Alive));
+    }
+
+    #[test]
+    fn led_can_be_moved_and_removed() {
+        let mut heartbeat = Heartbeat::new(0, 1_000, HeartbeatMessage::Dot);
+        assert_eq!(heartbeat.led(), None);
+        assert_eq!(heartbeat.led_level(0), None);
+        assert_eq!(heartbeat.set_led(Some(3)), None);
+        assert_eq!(heartbeat.led(), Some(3));
+        // Moving the pulse reports where it was, so that LED can be released.
+        assert_eq!(heartbeat.set_led(Some(7)), Some(3));
+        assert_eq!(heartbeat.set_led(None), Some(7));
+        assert_eq!(heartbeat.led_level(0), None);
+    }
+
+    #[test]
+    fn led_pulses_after_each_message() {
+        let mut heartbeat = Heartbeat::new(0, 1_000, HeartbeatMessage::Dot);
+        heartbeat.set_led(Some(2));
+        assert_eq!(heartbeat.led_level(HEARTBEAT_PULSE_MS - 1), Some((2, true)));
+        assert_eq!(heartbeat.led_level(HEARTBEAT_PULSE_MS), Some((2, false)));
+        heartbeat.update(1_000);
+        assert_eq!(heartbeat.led_level(1_000), Some((2, true)));
+        assert_eq!(
+            heartbeat.led_level(1_000 + HEARTBEAT_PULSE_MS),
+            Some((2, false))
+        );
+    }
+}
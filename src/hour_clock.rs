@@ -0,0 +1,105 @@
+// src/hour_clock.rs
+
+//! An hour of the day kept in software, and quiet hours that follow it.
+
+use crate::parse::parse_clamped;
+
+pub const HOUR_MS: u32 = 60 * 60 * 1_000;
+pub const HOURS_PER_DAY: u8 = 24;
+
+/// Whether `hour` falls in the quiet hours from `start` up to, but not including,
+/// `end`. A window whose end is before its start wraps past midnight, so 22 to 6
+/// is quiet from 22:00 until 06:00. An empty window, with equal start and end, is
+/// never quiet.
+pub fn in_quiet_hours(hour: u8, start: u8, end: u8) -> bool {
+    match start <= end {
+        true => start <= hour && hour < end,
+        false => start <= hour || hour < end,
+    }
+}
+
+/// Parse an hour of the day, 0 to 23. An hour outside that range is rejected
+/// rather than clamped, because the nearest hour is not the one asked for.
+pub fn parse_hour(s: &str) -> Option<u8> {
+    match parse_clamped(s, 0, HOURS_PER_DAY as u32 - 1) {
+        Ok(hour) if !hour.clamped => Some(hour.value as u8),
+        _ => None,
+    }
+}
+
+/// Hour of the day kept in software from `millis`, because the RTC is not set up.
+/// The hour is lost on reset, and drifts with the HSE crystal. `millis` wraps after
+/// about 49 days, so the clock stays correct for that long after it is set.
+pub struct HourClock {
+    set_ms: u32,
+    set_hour: u8,
+}
+
+impl HourClock {
+    pub fn new(now_ms: u32, hour: u8) -> Self {
+        HourClock {
+            set_ms: now_ms,
+            set_hour: hour,
+        }
+    }
+
+    /// The hour of the day at `now_ms`.
+    pub fn hour(&self, now_ms: u32) -> u8 {
+        let elapsed_hours = now_ms.wrapping_sub(self.set_ms) / HOUR_MS;
+        ((self.set_hour as u32 + elapsed_hours) % HOURS_PER_DAY as u32) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_within_a_day() {
+        let quiet: std::vec::Vec<u8> = (0..HOURS_PER_DAY)
+            .filter(|&hour| in_quiet_hours(hour, 9, 12))
+            .collect();
+        assert_eq!(quiet, [9, 10, 11]);
+    }
+
+    #[test]
+    fn window_past_midnight() {
+        let quiet: std::vec::Vec<u8> = (0..HOURS_PER_DAY)
+            .filter(|&hour| in_quiet_hours(hour, 22, 6))
+            .collect();
+        assert_eq!(quiet, [0, 1, 2, 3, 4, 5, 22, 23]);
+    }
+
+    #[test]
+    fn empty_window_is_never_quiet() {
+        assert!((0..HOURS_PER_DAY).all(|hour| !in_quiet_hours(hour, 7, 7)));
+        assert!((0..HOURS_PER_DAY).all(|hour| !in_quiet_hours(hour, 0, 0)));
+    }
+
+    #[test]
+    fn hours_are_rejected_out_of_range() {
+        assert_eq!(parse_hour("0"), Some(0));
+        assert_eq!(parse_hour(" 23 "), Some(23));
+        assert_eq!(parse_hour("24"), None);
+        assert_eq!(parse_hour("99999999999"), None);
+        assert_eq!(parse_hour(""), None);
+        assert_eq!(parse_hour("-1"), None);
+    }
+
+    #[test]
+    fn clock_advances_and_wraps_at_midnight() {
+        let clock = HourClock::new(1_000, 22);
+        assert_eq!(clock.hour(1_000), 22);
+        assert_eq!(clock.hour(1_000 + HOUR_MS - 1), 22);
+        assert_eq!(clock.hour(1_000 + HOUR_MS), 23);
+        assert_eq!(clock.hour(1_000 + 2 * HOUR_MS), 0);
+        assert_eq!(clock.hour(1_000 + 26 * HOUR_MS), 0);
+    }
+
+    #[test]
+    fn clock_across_millis_wrap() {
+        let clock = HourClock::new(u32::MAX - HOUR_MS / 2, 5);
+        assert_eq!(clock.hour(HOUR_MS / 2 - 2), 5);
+        assert_eq!(clock.hour(HOUR_MS / 2 - 1), 6);
+    }
+}
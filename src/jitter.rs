@@ -0,0 +1,120 @@
+// src/jitter.rs
+
+//! Statistics of the interval between periodic samples, to measure jitter.
+//!
+//! Only running sums are kept, so the statistics take the same few words of
+//! memory however many samples are added, and are cheap enough to update from an
+//! interrupt handler. The standard deviation is computed from the sum of
+//! squares, in integer arithmetic.
+
+/// Running statistics of the interval between samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JitterStats {
+    pub count: u32,
+    pub min_us: u32,
+    pub max_us: u32,
+    sum_us: u64,
+    sum_sq_us: u64,
+}
+
+impl JitterStats {
+    pub const fn new() -> Self {
+        JitterStats {
+            count: 0,
+            min_us: u32::MAX,
+            max_us: 0,
+            sum_us: 0,
+            sum_sq_us: 0,
+        }
+    }
+
+    pub fn update_jitter_stats(&mut self, dt_us: u32) {
+        self.count += 1;
+        self.min_us = self.min_us.min(dt_us);
+        self.max_us = self.max_us.max(dt_us);
+        self.sum_us += dt_us as u64;
+        self.sum_sq_us += dt_us as u64 * dt_us as u64;
+    }
+
+    pub fn mean_us(&self) -> u32 {
+        match self.count {
+            0 => 0,
+            count => (self.sum_us / count as u64) as u32,
+        }
+    }
+
+    /// Population standard deviation, rounded down to a whole microsecond.
+    pub fn std_dev_us(&self) -> u32 {
+        if 0 == self.count {
+            return 0;
+        }
+        let count = self.count as u64;
+        let mean_sq = self.sum_us * self.sum_us / count;
+        isqrt(self.sum_sq_us.saturating_sub(mean_sq) / count)
+    }
+}
+
+impl Default for JitterStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Integer square root, rounded down.
+fn isqrt(n: u64) -> u32 {
+    // Newton's method, starting from an estimate that is never too small.
+    let mut x = n;
+    let mut y = x.div_ceil(2);
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integer_square_roots() {
+        for (n, root) in [(0, 0), (1, 1), (2, 1), (3, 1), (4, 2), (99, 9), (100, 10)] {
+            assert_eq!(isqrt(n), root, "{}", n);
+        }
+        assert_eq!(isqrt(u32::MAX as u64 * u32::MAX as u64), u32::MAX);
+    }
+
+    #[test]
+    fn empty_stats() {
+        let stats = JitterStats::new();
+        assert_eq!(stats.count, 0);
+        assert_eq!(stats.mean_us(), 0);
+        assert_eq!(stats.std_dev_us(), 0);
+    }
+
+    #[test]
+    fn steady_intervals_have_no_spread() {
+        let mut stats = JitterStats::new();
+        for _ in 0..1_000 {
+            stats.update_jitter_stats(1_000);
+        }
+        assert_eq!(
+            (stats.count, stats.min_us, stats.max_us),
+            (1_000, 1_000, 1_000)
+        );
+        assert_eq!(stats.mean_us(), 1_000);
+        assert_eq!(stats.std_dev_us(), 0);
+    }
+
+    #[test]
+    fn spread_intervals() {
+        let mut stats = JitterStats::new();
+        // Mean 1000, and every interval 2 away from it.
+        for dt_us in [998, 1_002, 998, 1_002] {
+            stats.update_jitter_stats(dt_us);
+        }
+        assert_eq!((stats.min_us, stats.max_us), (998, 1_002));
+        assert_eq!(stats.mean_us(), 1_000);
+        assert_eq!(stats.std_dev_us(), 2);
+    }
+}
@@ -0,0 +1,106 @@
+// src/joystick.rs
+
+//! Turning the two axes of an analog joystick into a direction, and into one LED
+//! out of a ring.
+//!
+//! `atan` is not available without `std`, so the direction is worked out with a
+//! polynomial approximation, which is far closer than the LEDs are apart.
+
+/// The middle of the 12-bit ADC range, where a centered stick reads.
+pub const ADC_CENTER: i32 = 2_048;
+/// Readings this close to the center, in ADC counts, count as centered.
+pub const DEAD_ZONE: i32 = 400;
+
+/// `atan(z)` in turns, for `z` from 0 to 1, within about 0.001 of a turn.
+fn atan_turns(z: f32) -> f32 {
+    (z * core::f32::consts::FRAC_PI_4 + 0.273 * z * (1.0 - z)) / (2.0 * core::f32::consts::PI)
+}
+
+/// The direction of (`dx`, `dy`) in turns, clockwise from straight up, from 0 up to 1.
+fn direction_turns(dx: i32, dy: i32) -> f32 {
+    let (ax, ay) = (dx.unsigned_abs() as f32, dy.unsigned_abs() as f32);
+    // Angle from the nearer axis, kept below 1/8 of a turn where the
+    // approximation holds.
+    let from_up = match ax <= ay {
+        true => atan_turns(ax / ay),
+        false => 0.25 - atan_turns(ay / ax),
+    };
+    match (0 <= dx, 0 <= dy) {
+        (true, true) => from_up,
+        (true, false) => 0.5 - from_up,
+        (false, false) => 0.5 + from_up,
+        (false, true) => 1.0 - from_up,
+    }
+}
+
+/// The LED, out of `n` in a ring clockwise from the top, nearest to the direction
+/// of joystick readings `x` and `y`, or `None` while the stick is centered.
+/// `x` grows to the right and `y` grows upward, from 0 to 4095.
+pub fn joystick_to_led(x: u16, y: u16, n: usize) -> Option<usize> {
+    let (dx, dy) = (x as i32 - ADC_CENTER, y as i32 - ADC_CENTER);
+    if 0 == n || dx * dx + dy * dy < DEAD_ZONE * DEAD_ZONE {
+        return None;
+    }
+    let nearest = (direction_turns(dx, dy) * n as f32 + 0.5) as usize;
+    Some(nearest % n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FULL: u16 = 4_095;
+    const MID: u16 = ADC_CENTER as u16;
+
+    #[test]
+    fn atan_is_close() {
+        for i in 0..=100 {
+            let z = i as f32 / 100.0;
+            let exact = (z as f64).atan() / core::f64::consts::TAU;
+            assert!((atan_turns(z) as f64 - exact).abs() < 0.001, "{}", z);
+        }
+    }
+
+    #[test]
+    fn directions_run_clockwise_from_up() {
+        let cases = [
+            ((0, 100), 0.0),
+            ((100, 100), 0.125),
+            ((100, 0), 0.25),
+            ((100, -100), 0.375),
+            ((0, -100), 0.5),
+            ((-100, -100), 0.625),
+            ((-100, 0), 0.75),
+            ((-100, 100), 0.875),
+        ];
+        for ((dx, dy), turns) in cases {
+            assert!(
+                (direction_turns(dx, dy) - turns).abs() < 0.001,
+                "{} {}",
+                dx,
+                dy
+            );
+        }
+    }
+
+    #[test]
+    fn leds_follow_the_stick() {
+        assert_eq!(joystick_to_led(MID, FULL, 8), Some(0));
+        assert_eq!(joystick_to_led(FULL, FULL, 8), Some(1));
+        assert_eq!(joystick_to_led(FULL, MID, 8), Some(2));
+        assert_eq!(joystick_to_led(MID, 0, 8), Some(4));
+        assert_eq!(joystick_to_led(0, MID, 8), Some(6));
+        // Just left of straight up rounds back around to the first LED.
+        assert_eq!(joystick_to_led(MID - 50, FULL, 8), Some(0));
+        assert_eq!(joystick_to_led(FULL, MID, 3), Some(1));
+    }
+
+    #[test]
+    fn centered_stick_lights_nothing() {
+        assert_eq!(joystick_to_led(MID, MID, 8), None);
+        let edge = MID + DEAD_ZONE as u16;
+        assert_eq!(joystick_to_led(edge - 1, MID, 8), None);
+        assert_eq!(joystick_to_led(edge, MID, 8), Some(2));
+        assert_eq!(joystick_to_led(FULL, FULL, 0), None);
+    }
+}
@@ -0,0 +1,482 @@
+// src/led_controller.rs
+
+//! The LED group state behind `serial_led_control`, kept apart from the pins.
+//!
+//! The example drives four banks of LEDs. The static LEDs stay lit, the blinking
+//! LEDs flash every `BLINK_MS`, the strobing LEDs flash faster, and the
+//! controlled LEDs follow user button B1. `LedController` holds what is enabled
+//! for each bank, takes a `Command` from the terminal or a binary frame, and works
+//! out which LEDs are lit at a given time. The example then writes that mask to
+//! the pins, so everything here can be checked on the host.
+
+use crate::sysex::{
+    SYSEX_BLINK_BIT, SYSEX_CONTROLLED_BIT, SYSEX_INVERSION_BIT, SYSEX_STATIC_BIT, SYSEX_STROBE_BIT,
+};
+
+/// Time the blinking LEDs spend on, and off.
+pub const BLINK_MS: u32 = 500;
+/// Default time the strobing LEDs spend on, and off.
+pub const STROBE_MS: u32 = 50;
+/// Number of LEDs in each bank.
+pub const STATIC_LEDS: usize = 3;
+pub const BLINK_LEDS: usize = 2;
+pub const STROBE_LEDS: usize = 2;
+pub const CONTROLLED_LEDS: usize = 2;
+pub const LED_COUNT: usize = STATIC_LEDS + BLINK_LEDS + STROBE_LEDS + CONTROLLED_LEDS;
+/// Every LED, in the bit order of `LedController::mask`.
+pub const ALL_LEDS_MASK: u32 = (1 << LED_COUNT) - 1;
+
+/// A mask with the low `count` bits set.
+fn bank_bits(count: usize) -> u32 {
+    (1 << count) - 1
+}
+
+/// Commands that change the LED group state.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    DisableAll,
+    EnableAll,
+    ToggleStatic,
+    ToggleBlink,
+    ToggleStrobe,
+    ToggleControlled,
+    ToggleInversion,
+    SetEnables(u8), // Bitmask of SYSEX_*_BIT flags
+}
+
+impl Command {
+    pub fn from_byte(c: u8) -> Option<Command> {
+        match c {
+            b'0' => Some(Command::DisableAll),
+            b'1' => Some(Command::EnableAll),
+            b'2' => Some(Command::ToggleStatic),
+            b'3' => Some(Command::ToggleBlink),
+            b'4' => Some(Command::ToggleStrobe),
+            b'5' => Some(Command::ToggleControlled),
+            b'9' => Some(Command::ToggleInversion),
+            _ => None,
+        }
+    }
+}
+
+/// The level of the controlled LED, the single truth table for both driving the
+/// LED and describing it. Inversion swaps which button state lights the LED, and
+/// also keeps it lit while it is disabled, so disabling it is visible either way.
+pub fn controlled_led_level(enable: bool, inversion: bool, button_down: bool) -> bool {
+    match enable {
+        true => button_down != inversion,
+        false => inversion,
+    }
+}
+
+/// Describe the behavior of the controlled LED, by evaluating
+/// `controlled_led_level` for both button states.
+pub fn describe_controlled_led(enable: bool, inversion: bool) -> &'static str {
+    match (
+        controlled_led_level(enable, inversion, true),
+        controlled_led_level(enable, inversion, false),
+    ) {
+        (true, false) => "Controlled LED now ON when button pressed.",
+        (false, true) => "Controlled LED now ON when button released.",
+        (true, true) => "Controlled LED now always ON.",
+        (false, false) => "Controlled LED now always OFF.",
+    }
+}
+
+/// The pin levels that show the lit LEDs in `mask`. An LED wired active low, from
+/// 3.3V to the pin, lights when its pin is low, so the global inversion flips every
+/// bit. It comes after the per group settings, like the controlled LED inversion,
+/// which decide whether an LED is lit in the first place.
+pub fn physical_mask(mask: u32, global_invert: bool) -> u32 {
+    match global_invert {
+        true => !mask & ALL_LEDS_MASK,
+        false => mask,
+    }
+}
+
+/// How the strobing LEDs light on each strobe tick.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StrobeStyle {
+    /// Every strobing LED toggles together.
+    All,
+    /// One strobing LED is lit at a time, advancing every tick.
+    Chase,
+}
+
+/// The LED lit on `tick` of a chase through `count` LEDs. The chase advances one
+/// LED per tick, and wraps around to the first after the last.
+pub fn chase_index(tick: u32, count: usize) -> usize {
+    match count {
+        0 => 0,
+        count => (tick % count as u32) as usize,
+    }
+}
+
+/// Owns all of the enable, toggle and inversion state for the LED groups.
+/// Commands change what is enabled, `update` advances the patterns, and `mask`
+/// gives the resulting LED levels, to be written to the pins.
+pub struct LedController {
+    static_enable: bool,
+    blink_enable: bool,
+    strobe_enable: bool,
+    controlled_enable: bool,
+    controlled_inversion: bool,
+    blink_on: bool,
+    strobe_on: bool,
+    strobe_ms: u32,
+    strobe_style: StrobeStyle,
+    strobe_tick: u32,
+    controlled_on: bool,
+    button_down: bool,
+}
+
+impl LedController {
+    pub fn new() -> Self {
+        LedController {
+            static_enable: true,
+            blink_enable: true,
+            strobe_enable: true,
+            controlled_enable: true,
+            controlled_inversion: false,
+            blink_on: false,
+            strobe_on: false,
+            strobe_ms: STROBE_MS,
+            strobe_style: StrobeStyle::All,
+            strobe_tick: 0,
+            controlled_on: false,
+            button_down: false,
+        }
+    }
+
+    pub fn any_enabled(&self) -> bool {
+        self.static_enable || self.blink_enable || self.strobe_enable || self.controlled_enable
+    }
+
+    pub fn all_enabled(&self) -> bool {
+        self.static_enable && self.blink_enable && self.strobe_enable && self.controlled_enable
+    }
+
+    pub fn set_all(&mut self, enable: bool) {
+        self.static_enable = enable;
+        self.blink_enable = enable;
+        self.strobe_enable = enable;
+        self.controlled_enable = enable;
+    }
+
+    /// Change the time the strobing LEDs spend on, and off.
+    pub fn set_strobe_ms(&mut self, strobe_ms: u32) {
+        self.strobe_ms = strobe_ms.max(1);
+    }
+
+    pub fn set_strobe_style(&mut self, strobe_style: StrobeStyle) {
+        self.strobe_style = strobe_style;
+    }
+
+    pub fn describe_controlled(&self) -> &'static str {
+        describe_controlled_led(self.controlled_enable, self.controlled_inversion)
+    }
+
+    /// Apply a command, returning a confirmation message if anything changed.
+    pub fn handle_command(&mut self, command: Command) -> Option<&'static str> {
+        match command {
+            Command::DisableAll if self.any_enabled() => {
+                self.set_all(false);
+                Some("All LEDs disabled.")
+            }
+            Command::EnableAll if !self.all_enabled() => {
+                self.set_all(true);
+                Some("All LEDs enabled.")
+            }
+            Command::DisableAll | Command::EnableAll => None,
+            Command::ToggleStatic => {
+                self.static_enable = !self.static_enable;
+                Some(match self.static_enable {
+                    true => "Static enabled.",
+                    false => "Static disabled.",
+                })
+            }
+            Command::ToggleBlink => {
+                self.blink_enable = !self.blink_enable;
+                Some(match self.blink_enable {
+                    true => "Blink enabled.",
+                    false => "Blink disabled.",
+                })
+            }
+            Command::ToggleStrobe => {
+                self.strobe_enable = !self.strobe_enable;
+                Some(match self.strobe_enable {
+                    true => "Strobe enabled.",
+                    false => "Strobe disabled.",
+                })
+            }
+            Command::ToggleControlled => {
+                self.controlled_enable = !self.controlled_enable;
+                Some(match self.controlled_enable {
+                    true => "Controlled enabled.",
+                    false => "Controlled disabled.",
+                })
+            }
+            Command::ToggleInversion => {
+                self.controlled_inversion = !self.controlled_inversion;
+                Some(match self.controlled_inversion {
+                    true => "LED control inversion enabled.",
+                    false => "LED control inversion disabled.",
+                })
+            }
+            Command::SetEnables(mask) => {
+                self.static_enable = 0 != mask & SYSEX_STATIC_BIT;
+                self.blink_enable = 0 != mask & SYSEX_BLINK_BIT;
+                self.strobe_enable = 0 != mask & SYSEX_STROBE_BIT;
+                self.controlled_enable = 0 != mask & SYSEX_CONTROLLED_BIT;
+                self.controlled_inversion = 0 != mask & SYSEX_INVERSION_BIT;
+                Some("LED states set.")
+            }
+        }
+    }
+
+    /// Advance the blink and strobe patterns to `now_ms` and sample the button.
+    /// Returns a message when the controlled LED changes while enabled.
+    pub fn update(&mut self, now_ms: u32, button_down: bool) -> Option<&'static str> {
+        self.strobe_tick = now_ms / self.strobe_ms;
+        self.strobe_on = !self.strobe_tick.is_multiple_of(2);
+        self.blink_on = BLINK_MS <= now_ms % (2 * BLINK_MS);
+
+        self.button_down = button_down;
+        let button_state = button_down != self.controlled_inversion;
+        let changed = button_state != self.controlled_on;
+        self.controlled_on = button_state;
+        match (changed && self.controlled_enable, self.controlled_on) {
+            (true, true) => Some("Controlled LED on."),
+            (true, false) => Some("Controlled LED off."),
+            (false, _) => None,
+        }
+    }
+
+    /// The LEDs that are lit, with the bits in bank order: `STATIC_LEDS` static LEDs
+    /// from bit 0, then the blinking, strobing and controlled LEDs.
+    pub fn mask(&self) -> u32 {
+        let strobe = match self.strobe_style {
+            StrobeStyle::All => match self.strobe_enable && self.strobe_on {
+                true => bank_bits(STROBE_LEDS),
+                false => 0,
+            },
+            StrobeStyle::Chase => match self.strobe_enable {
+                true => 1 << chase_index(self.strobe_tick, STROBE_LEDS),
+                false => 0,
+            },
+        };
+        let controlled = controlled_led_level(
+            self.controlled_enable,
+            self.controlled_inversion,
+            self.button_down,
+        );
+        let banks = [
+            (
+                STATIC_LEDS,
+                self.static_enable as u32 * bank_bits(STATIC_LEDS),
+            ),
+            (
+                BLINK_LEDS,
+                (self.blink_enable && self.blink_on) as u32 * bank_bits(BLINK_LEDS),
+            ),
+            (STROBE_LEDS, strobe),
+            (
+                CONTROLLED_LEDS,
+                controlled as u32 * bank_bits(CONTROLLED_LEDS),
+            ),
+        ];
+        banks
+            .iter()
+            .rev()
+            .fold(0, |mask, (count, bits)| mask << count | bits)
+    }
+}
+
+impl Default for LedController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The enables in `SetEnables` bit order, to compare in one go.
+    fn enables(controller: &LedController) -> u8 {
+        [
+            (controller.static_enable, SYSEX_STATIC_BIT),
+            (controller.blink_enable, SYSEX_BLINK_BIT),
+            (controller.strobe_enable, SYSEX_STROBE_BIT),
+            (controller.controlled_enable, SYSEX_CONTROLLED_BIT),
+            (controller.controlled_inversion, SYSEX_INVERSION_BIT),
+        ]
+        .iter()
+        .filter(|(enable, _)| *enable)
+        .fold(0, |mask, (_, bit)| mask | bit)
+    }
+
+    const ALL: u8 = SYSEX_STATIC_BIT | SYSEX_BLINK_BIT | SYSEX_STROBE_BIT | SYSEX_CONTROLLED_BIT;
+
+    #[test]
+    fn commands_from_keys() {
+        assert_eq!(Command::from_byte(b'0'), Some(Command::DisableAll));
+        assert_eq!(Command::from_byte(b'1'), Some(Command::EnableAll));
+        assert_eq!(Command::from_byte(b'4'), Some(Command::ToggleStrobe));
+        assert_eq!(Command::from_byte(b'9'), Some(Command::ToggleInversion));
+        assert_eq!(Command::from_byte(b'6'), None);
+        assert_eq!(Command::from_byte(b'a'), None);
+    }
+
+    #[test]
+    fn command_sequence() {
+        let mut controller = LedController::new();
+        assert_eq!(enables(&controller), ALL);
+        assert!(controller.handle_command(Command::DisableAll).is_some());
+        assert_eq!(enables(&controller), 0);
+        // Nothing left to disable, so nothing is confirmed.
+        assert_eq!(controller.handle_command(Command::DisableAll), None);
+        controller.handle_command(Command::ToggleBlink);
+        controller.handle_command(Command::ToggleInversion);
+        assert_eq!(enables(&controller), SYSEX_BLINK_BIT | SYSEX_INVERSION_BIT);
+        controller.handle_command(Command::EnableAll);
+        assert_eq!(enables(&controller), ALL | SYSEX_INVERSION_BIT);
+        assert_eq!(controller.handle_command(Command::EnableAll), None);
+        controller.handle_command(Command::ToggleStatic);
+        controller.handle_command(Command::ToggleControlled);
+        assert_eq!(
+            enables(&controller),
+            SYSEX_BLINK_BIT | SYSEX_STROBE_BIT | SYSEX_INVERSION_BIT
+        );
+    }
+
+    #[test]
+    fn set_enables_replaces_everything() {
+        let mut controller = LedController::new();
+        let mask = SYSEX_STROBE_BIT | SYSEX_INVERSION_BIT;
+        assert!(controller
+            .handle_command(Command::SetEnables(mask))
+            .is_some());
+        assert_eq!(enables(&controller), mask);
+    }
+
+    #[test]
+    fn mask_follows_the_patterns() {
+        let mut controller = LedController::new();
+        // Bits 0 to 2 are static, 3 and 4 blink, 5 and 6 strobe, 7 and 8 controlled.
+        // At 0ms the blinking and strobing LEDs are off, and the button is up.
+        controller.update(0, false);
+        assert_eq!(controller.mask(), 0x007);
+        // At 550ms the blinking LEDs are on, and so are the strobing LEDs.
+        controller.update(550, true);
+        assert_eq!(controller.mask(), 0x1FF);
+        controller.handle_command(Command::ToggleStatic);
+        controller.handle_command(Command::ToggleBlink);
+        assert_eq!(controller.mask(), 0x1E0);
+    }
+
+    #[test]
+    fn update_reports_controlled_changes() {
+        let mut controller = LedController::new();
+        assert_eq!(controller.update(0, false), None);
+        assert_eq!(controller.update(1, true), Some("Controlled LED on."));
+        assert_eq!(controller.update(2, true), None);
+        assert_eq!(controller.update(3, false), Some("Controlled LED off."));
+        controller.handle_command(Command::ToggleControlled);
+        assert_eq!(controller.update(4, true), None);
+    }
+
+    #[test]
+    fn controlled_led_truth_table() {
+        // (enable, inversion, pressed level, released level)
+        let cases = [
+            (true, false, true, false),
+            (true, true, false, true),
+            (false, false, false, false),
+            (false, true, true, true),
+        ];
+        for (enable, inversion, pressed, released) in cases {
+            assert_eq!(controlled_led_level(enable, inversion, true), pressed);
+            assert_eq!(controlled_led_level(enable, inversion, false), released);
+        }
+    }
+
+    #[test]
+    fn controlled_led_descriptions() {
+        assert_eq!(
+            describe_controlled_led(true, false),
+            "Controlled LED now ON when button pressed."
+        );
+        assert_eq!(
+            describe_controlled_led(true, true),
+            "Controlled LED now ON when button released."
+        );
+        assert_eq!(
+            describe_controlled_led(false, false),
+            "Controlled LED now always OFF."
+        );
+        assert_eq!(
+            describe_controlled_led(false, true),
+            "Controlled LED now always ON."
+        );
+        let mut controller = LedController::new();
+        controller.handle_command(Command::ToggleInversion);
+        assert_eq!(
+            controller.describe_controlled(),
+            describe_controlled_led(true, true)
+        );
+    }
+
+    #[test]
+    fn chase_advances_and_wraps() {
+        let order: Vec<usize> = (0..7).map(|tick| chase_index(tick, 3)).collect();
+        assert_eq!(order, [0, 1, 2, 0, 1, 2, 0]);
+        assert_eq!(chase_index(u32::MAX, 2), 1);
+        assert_eq!(chase_index(5, 1), 0);
+        assert_eq!(chase_index(5, 0), 0);
+    }
+
+    #[test]
+    fn chase_lights_one_strobe_led_at_a_time() {
+        let mut controller = LedController::new();
+        controller.set_strobe_style(StrobeStyle::Chase);
+        // The strobing LEDs are bits 5 and 6, after the static and blinking ones.
+        controller.update(0, false);
+        assert_eq!(controller.mask(), 0x027);
+        controller.update(STROBE_MS, false);
+        assert_eq!(controller.mask(), 0x047);
+        controller.update(2 * STROBE_MS, false);
+        assert_eq!(controller.mask(), 0x027);
+        controller.handle_command(Command::ToggleStrobe);
+        assert_eq!(controller.mask(), 0x007);
+    }
+
+    #[test]
+    fn global_invert_flips_every_led() {
+        assert_eq!(physical_mask(0x0A5, false), 0x0A5);
+        assert_eq!(physical_mask(0x0A5, true), 0x15A);
+        assert_eq!(physical_mask(0, true), ALL_LEDS_MASK);
+        assert_eq!(physical_mask(ALL_LEDS_MASK, true), 0);
+        // Bits past the last LED never reach a pin.
+        assert_eq!(physical_mask(!0, false) & !ALL_LEDS_MASK, !ALL_LEDS_MASK);
+        assert_eq!(physical_mask(!0, true), 0);
+    }
+
+    #[test]
+    fn global_invert_comes_after_the_controlled_inversion() {
+        let mut controller = LedController::new();
+        controller.handle_command(Command::SetEnables(SYSEX_CONTROLLED_BIT));
+        // The controlled LEDs are bits 7 and 8, lit while the button is down.
+        controller.update(0, true);
+        assert_eq!(physical_mask(controller.mask(), false), 0x180);
+        assert_eq!(physical_mask(controller.mask(), true), 0x07F);
+        // Inverting the controlled LEDs first lights them with the button up, and
+        // the global inversion then drives those pins low.
+        controller.handle_command(Command::ToggleInversion);
+        controller.update(1, false);
+        assert_eq!(physical_mask(controller.mask(), false), 0x180);
+        assert_eq!(physical_mask(controller.mask(), true), 0x07F);
+        controller.update(2, true);
+        assert_eq!(physical_mask(controller.mask(), true), ALL_LEDS_MASK);
+    }
+}
@@ -0,0 +1,509 @@
+// src/led_timing.rs
+
+//! LED patterns that are timed against `millis`, and keep no clock of their own.
+//! Each is handed the current time in milliseconds when it is updated, so the
+//! timing can be checked on the host without a timer.
+//!
+//! `SequencePlayer` loops through a list of on and off durations, which
+//! `parse_sequence` reads from a command argument like `100,200,100,500`.
+//! `FlashCounter` flashes a single digit status code over and over, and
+//! `Monostable` stays on for a while after every trigger, to show activity.
+//! `StrobeSweep` steps a strobe up through a range of rates.
+
+use crate::parse::{parse_clamped, ParseErr};
+use heapless::Vec;
+
+/// Most durations in one sequence.
+pub const SEQUENCE_MAX: usize = 16;
+/// Longest single step of a sequence, a minute.
+pub const SEQUENCE_STEP_MAX_MS: u32 = 60_000;
+const MS_PER_SECOND: u32 = 1_000;
+// The strobe sweep climbs from a visible flicker to well past flicker fusion, which
+// for most people is somewhere around 50-90 Hz, one step every `SWEEP_STEP_MS`.
+pub const SWEEP_MIN_HZ: u32 = 5;
+pub const SWEEP_MAX_HZ: u32 = 100;
+pub const SWEEP_HZ_STEP: u32 = 5;
+pub const SWEEP_STEP_MS: u32 = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SequenceErr {
+    /// A duration that is missing or not a number.
+    BadDuration(ParseErr),
+    /// More than `SEQUENCE_MAX` durations.
+    TooLong,
+}
+
+/// Parse a comma separated list of durations in milliseconds, like `100,200,100,500`.
+/// Returns whether any duration had to be clamped into range.
+pub fn parse_sequence(s: &str, out: &mut Vec<u32, SEQUENCE_MAX>) -> Result<bool, SequenceErr> {
+    out.clear();
+    let mut clamped = false;
+    for item in s.split(',') {
+        let duration =
+            parse_clamped(item, 1, SEQUENCE_STEP_MAX_MS).map_err(SequenceErr::BadDuration)?;
+        clamped |= duration.clamped;
+        out.push(duration.value).map_err(|_| SequenceErr::TooLong)?;
+    }
+    Ok(clamped)
+}
+
+/// Loops through a list of durations, alternating between on and off.
+/// Even steps are on and odd steps are off, so with an odd number of durations
+/// the last on step runs straight into the first.
+pub struct SequencePlayer {
+    durations: Vec<u32, SEQUENCE_MAX>,
+    index: usize,
+    step_start_ms: u32,
+}
+
+impl SequencePlayer {
+    pub fn new() -> Self {
+        SequencePlayer {
+            durations: Vec::new(),
+            index: 0,
+            step_start_ms: 0,
+        }
+    }
+
+    pub fn start(&mut self, durations: Vec<u32, SEQUENCE_MAX>, now_ms: u32) {
+        self.durations = durations;
+        self.index = 0;
+        self.step_start_ms = now_ms;
+    }
+
+    pub fn stop(&mut self) {
+        self.durations.clear();
+    }
+
+    /// Returns the LED level at `now_ms`, or `None` if no sequence is running.
+    pub fn update(&mut self, now_ms: u32) -> Option<bool> {
+        if self.durations.is_empty() {
+            return None;
+        }
+        // Catch up on every step that has elapsed since the last update.
+        while self.durations[self.index] <= now_ms.wrapping_sub(self.step_start_ms) {
+            self.step_start_ms = self.step_start_ms.wrapping_add(self.durations[self.index]);
+            self.index = (self.index + 1) % self.durations.len();
+        }
+        Some(self.index.is_multiple_of(2))
+    }
+}
+
+impl Default for SequencePlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Status code flashes are long enough to count by eye, with a longer gap
+// between repeats of the code.
+pub const FLASH_ON_MS: u32 = 200;
+pub const FLASH_OFF_MS: u32 = 300;
+pub const FLASH_GAP_MS: u32 = 1_500;
+
+/// Repeatedly flashes a single digit status code, like a BIOS beep code.
+/// Each repeat is `n` flashes followed by a gap. Zero is shown as ten flashes,
+/// so that every code is visible.
+pub struct FlashCounter {
+    flashes: u32,
+    start_ms: Option<u32>,
+}
+
+impl FlashCounter {
+    pub fn new() -> Self {
+        FlashCounter {
+            flashes: 0,
+            start_ms: None,
+        }
+    }
+
+    /// Start flashing `n`, which is a single digit. The sequence is timed from
+    /// the next `tick`.
+    pub fn start(&mut self, n: u32) {
+        self.flashes = match n % 10 {
+            0 => 10,
+            n => n,
+        };
+        self.start_ms = None;
+    }
+
+    pub fn stop(&mut self) {
+        self.flashes = 0;
+    }
+
+    /// Returns the LED level at `now_ms`, or `None` if no code is being flashed.
+    pub fn tick(&mut self, now_ms: u32) -> Option<bool> {
+        if 0 == self.flashes {
+            return None;
+        }
+        let start_ms = *self.start_ms.get_or_insert(now_ms);
+        let flash_ms = FLASH_ON_MS + FLASH_OFF_MS;
+        let cycle_ms = self.flashes * flash_ms + FLASH_GAP_MS;
+        let phase = now_ms.wrapping_sub(start_ms) % cycle_ms;
+        Some(phase < self.flashes * flash_ms && phase % flash_ms < FLASH_ON_MS)
+    }
+}
+
+impl Default for FlashCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A retriggerable one shot timer. The output turns on when triggered, and stays on
+/// until `duration_ms` after the latest trigger, so a steady stream of triggers
+/// keeps it on.
+pub struct Monostable {
+    duration_ms: u32,
+    triggered_ms: Option<u32>,
+}
+
+impl Monostable {
+    pub fn new(duration_ms: u32) -> Self {
+        Monostable {
+            duration_ms,
+            triggered_ms: None,
+        }
+    }
+
+    pub fn trigger(&mut self, now_ms: u32) {
+        self.triggered_ms = Some(now_ms);
+    }
+
+    /// Whether the output is on at `now_ms`. Once it times out, the trigger is
+    /// forgotten, so the output cannot turn on again when `millis()` wraps.
+    pub fn is_on(&mut self, now_ms: u32) -> bool {
+        match self.triggered_ms {
+            Some(triggered_ms) if now_ms.wrapping_sub(triggered_ms) < self.duration_ms => true,
+            _ => {
+                self.triggered_ms = None;
+                false
+            }
+        }
+    }
+}
+
+/// Frequency in Hz of a cycle lasting `period_ms`, rounded to the nearest Hz.
+/// A period of zero gives 0.
+pub fn period_to_hz(period_ms: u32) -> u32 {
+    match period_ms {
+        0 => 0,
+        period_ms => (MS_PER_SECOND + period_ms / 2) / period_ms,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SweepStep {
+    /// Strobe with this half period, the time on and the time off, in ms.
+    HalfPeriod(u32),
+    Done,
+}
+
+/// Steps the strobe rate from `SWEEP_MIN_HZ` to `SWEEP_MAX_HZ`, to find the rate
+/// at which the flicker can no longer be seen.
+pub struct StrobeSweep {
+    start_ms: Option<u32>,
+    step: Option<u32>,
+    half_period_ms: Option<u32>,
+}
+
+impl StrobeSweep {
+    pub const fn new() -> Self {
+        StrobeSweep {
+            start_ms: None,
+            step: None,
+            half_period_ms: None,
+        }
+    }
+
+    pub fn start(&mut self, now_ms: u32) {
+        self.start_ms = Some(now_ms);
+        self.step = None;
+        self.half_period_ms = None;
+    }
+
+    pub fn stop(&mut self) {
+        self.start_ms = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.start_ms.is_some()
+    }
+
+    /// Returns the next step when the sweep moves on from the current one.
+    /// The strobe is toggled every whole ms, so each target rate is rounded to the
+    /// nearest half period, and the rate actually used, `period_to_hz` of twice the
+    /// half period, is what should be reported. The half periods get short at the
+    /// fast end, where several targets round to the same one, so the rate moves in
+    /// coarse jumps there, and a target that would repeat the current half period is
+    /// skipped.
+    pub fn update(&mut self, now_ms: u32) -> Option<SweepStep> {
+        let start_ms = self.start_ms?;
+        let step = now_ms.wrapping_sub(start_ms) / SWEEP_STEP_MS;
+        if Some(step) == self.step {
+            return None;
+        }
+        self.step = Some(step);
+        let hz = SWEEP_MIN_HZ + step * SWEEP_HZ_STEP;
+        if SWEEP_MAX_HZ < hz {
+            self.stop();
+            return Some(SweepStep::Done);
+        }
+        let half_period_ms = (MS_PER_SECOND + hz) / (2 * hz);
+        if Some(half_period_ms) == self.half_period_ms {
+            return None;
+        }
+        self.half_period_ms = Some(half_period_ms);
+        Some(SweepStep::HalfPeriod(half_period_ms))
+    }
+}
+
+impl Default for StrobeSweep {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn durations(list: &[u32]) -> Vec<u32, SEQUENCE_MAX> {
+        Vec::from_slice(list).unwrap()
+    }
+
+    #[test]
+    fn parse_valid_sequences() {
+        let mut out = Vec::new();
+        assert_eq!(parse_sequence("100,200,100,500", &mut out), Ok(false));
+        assert_eq!(out, durations(&[100, 200, 100, 500]));
+        assert_eq!(parse_sequence("7", &mut out), Ok(false));
+        assert_eq!(out, durations(&[7]));
+    }
+
+    #[test]
+    fn parse_clamps_durations() {
+        let mut out = Vec::new();
+        assert_eq!(parse_sequence("0,70000", &mut out), Ok(true));
+        assert_eq!(out, durations(&[1, SEQUENCE_STEP_MAX_MS]));
+    }
+
+    #[test]
+    fn parse_rejects_malformed_lists() {
+        let mut out = Vec::new();
+        for list in ["", "100,", ",100", "100,,200"] {
+            let result = parse_sequence(list, &mut out);
+            assert_eq!(
+                result,
+                Err(SequenceErr::BadDuration(ParseErr::Empty)),
+                "{:?}",
+                list
+            );
+        }
+        for list in ["100;200", "fast", "-5"] {
+            let result = parse_sequence(list, &mut out);
+            assert_eq!(
+                result,
+                Err(SequenceErr::BadDuration(ParseErr::NotANumber)),
+                "{:?}",
+                list
+            );
+        }
+        let too_long = "1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16,17";
+        assert_eq!(
+            parse_sequence(too_long, &mut out),
+            Err(SequenceErr::TooLong)
+        );
+    }
+
+    #[test]
+    fn player_alternates_on_and_off() {
+        let mut player = SequencePlayer::new();
+        assert_eq!(player.update(0), None);
+        player.start(durations(&[100, 200]), 1_000);
+        assert_eq!(player.update(1_000), Some(true));
+        assert_eq!(player.update(1_099), Some(true));
+        assert_eq!(player.update(1_100), Some(false));
+        assert_eq!(player.update(1_299), Some(false));
+        assert_eq!(player.update(1_300), Some(true));
+        player.stop();
+        assert_eq!(player.update(1_300), None);
+    }
+
+    #[test]
+    fn player_catches_up_on_missed_steps() {
+        let mut player = SequencePlayer::new();
+        player.start(durations(&[10, 20, 30]), 0);
+        // 10 + 20 + 30 + 10 = 70, so the second step of the second loop is running.
+        assert_eq!(player.update(75), Some(false));
+        // With three steps, the last on step runs into the first.
+        assert_eq!(player.update(120), Some(true));
+    }
+
+    #[test]
+    fn player_handles_the_clock_wrapping() {
+        let mut player = SequencePlayer::new();
+        player.start(durations(&[100, 100]), u32::MAX - 50);
+        assert_eq!(player.update(u32::MAX), Some(true));
+        assert_eq!(player.update(49), Some(false));
+        assert_eq!(player.update(149), Some(true));
+    }
+
+    /// The times in `0..until_ms` at which the LED level changes, and to what.
+    fn flash_edges(
+        counter: &mut FlashCounter,
+        start_ms: u32,
+        until_ms: u32,
+    ) -> std::vec::Vec<(u32, bool)> {
+        let mut edges = std::vec::Vec::new();
+        let mut level = false;
+        for t in 0..until_ms {
+            let now = counter.tick(start_ms.wrapping_add(t)).unwrap();
+            if now != level {
+                edges.push((t, now));
+                level = now;
+            }
+        }
+        edges
+    }
+
+    #[test]
+    fn flash_counter_timing() {
+        let mut counter = FlashCounter::new();
+        assert_eq!(counter.tick(0), None);
+        counter.start(2);
+        let flash_ms = FLASH_ON_MS + FLASH_OFF_MS;
+        let cycle_ms = 2 * flash_ms + FLASH_GAP_MS;
+        assert_eq!(
+            flash_edges(&mut counter, 1_000, cycle_ms + 1),
+            [
+                (0, true),
+                (FLASH_ON_MS, false),
+                (flash_ms, true),
+                (flash_ms + FLASH_ON_MS, false),
+                (cycle_ms, true),
+            ]
+        );
+        counter.stop();
+        assert_eq!(counter.tick(5_000), None);
+    }
+
+    #[test]
+    fn flash_counter_digits() {
+        let mut counter = FlashCounter::new();
+        // Zero is ten flashes, and only the last digit counts.
+        for (code, flashes) in [(0, 10), (7, 7), (13, 3)] {
+            counter.start(code);
+            let cycle_ms = flashes * (FLASH_ON_MS + FLASH_OFF_MS) + FLASH_GAP_MS;
+            let ons = flash_edges(&mut counter, 0, cycle_ms)
+                .iter()
+                .filter(|(_, level)| *level)
+                .count();
+            assert_eq!(ons as u32, flashes);
+        }
+    }
+
+    #[test]
+    fn flash_counter_across_millis_wrap() {
+        let mut counter = FlashCounter::new();
+        counter.start(1);
+        assert_eq!(counter.tick(u32::MAX - 10), Some(true));
+        assert_eq!(counter.tick(FLASH_ON_MS - 12), Some(true));
+        assert_eq!(counter.tick(FLASH_ON_MS - 11), Some(false));
+    }
+
+    #[test]
+    fn monostable_pulse() {
+        let mut pulse = Monostable::new(30);
+        assert!(!pulse.is_on(0));
+        pulse.trigger(100);
+        assert!(pulse.is_on(100));
+        assert!(pulse.is_on(129));
+        assert!(!pulse.is_on(130));
+    }
+
+    #[test]
+    fn monostable_retriggers() {
+        let mut pulse = Monostable::new(30);
+        // A steady stream of triggers keeps it on.
+        for now_ms in (0..300).step_by(20) {
+            pulse.trigger(now_ms);
+            assert!(pulse.is_on(now_ms + 19));
+        }
+        assert!(pulse.is_on(309));
+        assert!(!pulse.is_on(310));
+    }
+
+    #[test]
+    fn monostable_forgets_a_timed_out_trigger() {
+        let mut pulse = Monostable::new(30);
+        pulse.trigger(100);
+        assert!(!pulse.is_on(200));
+        // Once `millis()` wraps back around to the trigger time, it stays off.
+        assert!(!pulse.is_on(100));
+        // A trigger just before the wrap still times out after it.
+        pulse.trigger(u32::MAX - 9);
+        assert!(pulse.is_on(19));
+        assert!(!pulse.is_on(20));
+    }
+
+    #[test]
+    fn periods_to_hz() {
+        assert_eq!(period_to_hz(0), 0);
+        assert_eq!(period_to_hz(1), 1_000);
+        assert_eq!(period_to_hz(100), 10);
+        assert_eq!(period_to_hz(1_000), 1);
+        // Rounded to the nearest Hz, halves rounding up.
+        assert_eq!(period_to_hz(3), 333);
+        assert_eq!(period_to_hz(6), 167);
+        assert_eq!(period_to_hz(2_000), 1);
+        assert_eq!(period_to_hz(2_001), 0);
+    }
+
+    #[test]
+    fn sweep_steps_through_the_rates() {
+        let mut sweep = StrobeSweep::new();
+        assert!(!sweep.is_running());
+        assert_eq!(sweep.update(0), None);
+        sweep.start(1_000);
+        assert!(sweep.is_running());
+        let mut steps = Vec::<SweepStep, 32>::new();
+        let mut now_ms = 1_000;
+        while sweep.is_running() {
+            if let Some(step) = sweep.update(now_ms) {
+                steps.push(step).unwrap();
+            }
+            now_ms += 1;
+        }
+        assert_eq!(steps.first(), Some(&SweepStep::HalfPeriod(100)));
+        assert_eq!(steps.last(), Some(&SweepStep::Done));
+        // Done comes one step after the last rate, the step past `SWEEP_MAX_HZ`.
+        let steps_run = (SWEEP_MAX_HZ - SWEEP_MIN_HZ) / SWEEP_HZ_STEP + 1;
+        assert_eq!(now_ms - 1, 1_000 + steps_run * SWEEP_STEP_MS);
+        let half_periods: Vec<u32, 32> = steps
+            .iter()
+            .filter_map(|step| match step {
+                SweepStep::HalfPeriod(half_period_ms) => Some(*half_period_ms),
+                SweepStep::Done => None,
+            })
+            .collect();
+        // The rate only ever climbs, and no half period is reported twice.
+        assert!(half_periods.windows(2).all(|pair| pair[1] < pair[0]));
+        assert_eq!(half_periods.last(), Some(&5));
+        assert_eq!(period_to_hz(2 * half_periods[0]), SWEEP_MIN_HZ);
+    }
+
+    #[test]
+    fn sweep_can_be_stopped_and_restarted() {
+        let mut sweep = StrobeSweep::default();
+        sweep.start(0);
+        assert_eq!(sweep.update(0), Some(SweepStep::HalfPeriod(100)));
+        assert_eq!(sweep.update(SWEEP_STEP_MS - 1), None);
+        assert_eq!(sweep.update(SWEEP_STEP_MS), Some(SweepStep::HalfPeriod(50)));
+        sweep.stop();
+        assert_eq!(sweep.update(2 * SWEEP_STEP_MS), None);
+        // Starting again begins from the slowest rate.
+        sweep.start(5_000);
+        assert_eq!(sweep.update(5_000), Some(SweepStep::HalfPeriod(100)));
+    }
+}
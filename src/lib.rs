@@ -0,0 +1,62 @@
+// src/lib.rs
+
+#![deny(unsafe_code)]
+#![cfg_attr(not(test), no_std)]
+
+//! Board support code shared by the examples.
+//!
+//! Unit tests run on the host with `cargo test-host`, so the crate only links
+//! `std` for tests. Nothing under test may touch the hardware.
+
+pub mod adc;
+pub mod adc_timeout;
+pub mod ansi;
+pub mod base64;
+pub mod board;
+pub mod boot_config;
+pub mod calc;
+pub mod dds;
+pub mod demo;
+pub mod device_id;
+pub mod diag;
+pub mod ds18b20;
+#[cfg(feature = "flow-control")]
+pub mod flow_control;
+pub mod font;
+pub mod gpio_command;
+pub mod gpio_config;
+pub mod hash;
+pub mod heartbeat;
+pub mod hour_clock;
+pub mod jitter;
+pub mod joystick;
+pub mod led_controller;
+pub mod led_timing;
+pub mod line_buffer;
+pub mod log;
+pub mod memory_test;
+pub mod menu;
+pub mod millis;
+pub mod num_format;
+#[cfg(feature = "panic-sos")]
+mod panic_sos;
+pub mod parse;
+pub mod pins;
+pub mod report;
+pub mod rng;
+pub mod scheduler;
+pub mod serial_config;
+pub mod serial_rx;
+pub mod settings;
+pub mod shared;
+pub mod shell;
+pub mod sht3x;
+pub mod soft_pwm;
+pub mod stack;
+pub mod sysex;
+pub mod tempo;
+pub mod text;
+pub mod timer;
+pub mod tx_interrupt;
+pub mod tx_queue;
+pub mod xmodem;
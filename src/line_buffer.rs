@@ -0,0 +1,182 @@
+// src/line_buffer.rs
+
+//! Fixed size buffers for lines of text received over USART.
+
+/// The line being typed. The length is private, and every change goes through
+/// methods that keep it in bounds.
+pub struct LineBuffer<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> LineBuffer<N> {
+    pub fn new() -> Self {
+        LineBuffer {
+            bytes: [0; N],
+            len: 0,
+        }
+    }
+
+    /// Append `c` if the line is shorter than `limit`, which is capped at the
+    /// buffer size. Returns false, and leaves the line as it is, otherwise.
+    pub fn push(&mut self, c: u8, limit: usize) -> bool {
+        debug_assert!(self.len <= N);
+        if limit.min(N) <= self.len {
+            return false;
+        }
+        self.bytes[self.len] = c;
+        self.len += 1;
+        true
+    }
+
+    /// Remove the last character. Returns false if the line was already empty.
+    pub fn pop(&mut self) -> bool {
+        match self.len {
+            0 => false,
+            _ => {
+                self.len -= 1;
+                true
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        0 == self.len
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl<const N: usize> Default for LineBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A copy of the most recently completed line, kept for replay after the line
+/// buffer is reset. The raw received bytes are stored, so a replay is converted
+/// with whatever text mode is current at that time.
+pub struct LastLine<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> LastLine<N> {
+    pub fn new() -> Self {
+        LastLine {
+            bytes: [0; N],
+            len: 0,
+        }
+    }
+
+    /// Replace the stored line with `line`, cut to the first `N` bytes.
+    pub fn store(&mut self, line: &[u8]) {
+        self.len = line.len().min(N);
+        self.bytes[..self.len].copy_from_slice(&line[..self.len]);
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl<const N: usize> Default for LastLine<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replays_the_latest_line() {
+        let mut last: LastLine<8> = LastLine::new();
+        assert_eq!(last.as_bytes(), b"");
+        last.store(b"first");
+        assert_eq!(last.as_bytes(), b"first");
+        last.store(b"two");
+        assert_eq!(last.as_bytes(), b"two");
+        last.store(b"");
+        assert_eq!(last.as_bytes(), b"");
+    }
+
+    #[test]
+    fn long_lines_are_cut_to_capacity() {
+        let mut last: LastLine<4> = LastLine::new();
+        last.store(b"too long");
+        assert_eq!(last.as_bytes(), b"too ");
+    }
+
+    #[test]
+    fn push_stops_at_capacity() {
+        let mut line: LineBuffer<4> = LineBuffer::new();
+        for &c in b"abcd" {
+            assert!(line.push(c, usize::MAX));
+        }
+        // A full buffer refuses more, and keeps what it has.
+        assert!(!line.push(b'e', usize::MAX));
+        assert_eq!(line.as_bytes(), b"abcd");
+        assert_eq!(line.len(), 4);
+        assert!(line.pop());
+        assert!(line.push(b'e', usize::MAX));
+        assert_eq!(line.as_bytes(), b"abce");
+    }
+
+    #[test]
+    fn push_stops_at_the_limit() {
+        let mut line: LineBuffer<8> = LineBuffer::new();
+        assert!(line.push(b'a', 2));
+        assert!(line.push(b'b', 2));
+        assert!(!line.push(b'c', 2));
+        // A lower limit does not cut a line that is already longer.
+        assert!(!line.push(b'c', 1));
+        assert_eq!(line.as_bytes(), b"ab");
+        assert!(!line.push(b'c', 0));
+    }
+
+    #[test]
+    fn pop_and_clear() {
+        let mut line: LineBuffer<4> = LineBuffer::default();
+        assert!(line.is_empty());
+        assert!(!line.pop());
+        line.push(b'x', 4);
+        line.push(b'y', 4);
+        assert!(line.pop());
+        assert_eq!(line.as_bytes(), b"x");
+        line.clear();
+        assert!(line.is_empty());
+        assert_eq!(line.as_bytes(), b"");
+    }
+
+    #[test]
+    fn replay_uses_the_current_text_mode() {
+        use crate::text::{convert_case, TextMode};
+        let replay = |last: &LastLine<8>, mode: &TextMode| -> Vec<u8> {
+            last.as_bytes()
+                .iter()
+                .map(|&c| convert_case(c, mode))
+                .collect()
+        };
+        let mut last: LastLine<8> = LastLine::new();
+        let mut mode = TextMode::ForceUpper;
+        last.store(b"Hi there");
+        assert_eq!(replay(&last, &mode), b"HI THERE");
+        mode = TextMode::ForceLower;
+        assert_eq!(replay(&last, &mode), b"hi there");
+        mode = TextMode::InvertedCase;
+        assert_eq!(replay(&last, &mode), b"hI THERE");
+        assert_eq!(last.as_bytes(), b"Hi there");
+    }
+}
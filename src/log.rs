@@ -0,0 +1,216 @@
+// src/log.rs
+
+//! Line buffered logging to RTT and USART at once.
+//!
+//! `send_string` in the examples writes straight to both sinks, which is fine
+//! while only the main loop prints. Once an interrupt handler prints too, it can
+//! run partway through a message and leave half of each line on the terminal.
+//! Here, each source writes through its own `LineWriter`, which assembles a whole
+//! line before handing it to the shared `Log`. The `Log` then writes the line to
+//! RTT and to its transmit queue inside a single critical section, so lines from
+//! different sources never interleave, on either sink.
+//!
+//! ```ignore
+//! static LOG: Log<512> = Log::new();
+//!
+//! // In an interrupt handler, or in the main loop.
+//! let mut log: LineWriter<64, 512> = LOG.writer();
+//! write!(log, "Sample {}", sample).ok();
+//! writeln!(log, " done.").ok();
+//!
+//! // Once per pass through the main loop.
+//! LOG.pump(&mut tx);
+//! ```
+//!
+//! `button_interrupt` logs from both its EXTI handler and its main loop this way.
+//!
+//! Text still waits in the transmit queue afterwards, so the USART is never
+//! written from an interrupt handler. A line longer than the buffer is split, and
+//! a partial line is emitted when its writer is dropped, so no text is lost.
+
+use crate::{shared::Shared, tx_queue::TxQueue};
+use core::fmt::{self, Write};
+use heapless::String;
+use rtt_target::rprintln;
+use stm32f1xx_hal::serial::{Instance, Tx};
+
+/// Collects text until a full line is available.
+pub struct LineAssembler<const N: usize> {
+    line: String<N>,
+}
+
+impl<const N: usize> LineAssembler<N> {
+    pub const fn new() -> Self {
+        LineAssembler {
+            line: String::new(),
+        }
+    }
+
+    /// Append `string`, calling `emit` with every line it completes, without the
+    /// line ending. A carriage return before the newline is dropped too. When the
+    /// buffer fills up, the text so far is emitted as a line of its own.
+    pub fn push_str(&mut self, string: &str, mut emit: impl FnMut(&str)) {
+        for c in string.chars() {
+            if '\n' == c {
+                emit(self.line.trim_end_matches('\r'));
+                self.line.clear();
+                continue;
+            }
+            if self.line.push(c).is_err() {
+                emit(&self.line);
+                self.line.clear();
+                self.line.push(c).ok();
+            }
+        }
+    }
+
+    /// Emit any partial line, and empty the buffer.
+    pub fn flush(&mut self, mut emit: impl FnMut(&str)) {
+        if !self.line.is_empty() {
+            emit(&self.line);
+            self.line.clear();
+        }
+    }
+
+    /// The partial line waiting for a newline.
+    pub fn pending(&self) -> &str {
+        &self.line
+    }
+}
+
+impl<const N: usize> Default for LineAssembler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The shared sinks, meant to be a `static`.
+/// `Q` is the size of the transmit queue.
+pub struct Log<const Q: usize> {
+    tx_queue: Shared<TxQueue<Q>>,
+}
+
+impl<const Q: usize> Log<Q> {
+    pub const fn new() -> Self {
+        Log {
+            tx_queue: Shared::new(TxQueue::new()),
+        }
+    }
+
+    /// Write a whole line to both sinks, without anything else in between.
+    pub fn line(&self, line: &str) {
+        self.tx_queue.with(|tx_queue| {
+            rprintln!("{}", line);
+            write!(tx_queue, "\r{}\r\n", line).ok();
+        });
+    }
+
+    /// A new writer for one source of log lines.
+    /// `N` is the longest line it assembles.
+    pub fn writer<const N: usize>(&self) -> LineWriter<'_, N, Q> {
+        LineWriter {
+            log: self,
+            buffer: LineAssembler::new(),
+        }
+    }
+
+    /// Write the next queued byte if the USART is ready for it.
+    /// Returns true if a byte was written.
+    pub fn pump<USART: Instance>(&self, tx: &mut Tx<USART>) -> bool {
+        self.tx_queue.with(|tx_queue| tx_queue.pump(tx))
+    }
+
+    /// Returns true once every queued line has been handed to the USART.
+    pub fn is_empty(&self) -> bool {
+        self.tx_queue.with(|tx_queue| tx_queue.is_empty())
+    }
+
+    /// Return the number of bytes dropped because the queue was full, and reset it.
+    pub fn take_dropped(&self) -> u32 {
+        self.tx_queue.with(|tx_queue| tx_queue.take_dropped())
+    }
+}
+
+impl<const Q: usize> Default for Log<Q> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Buffers the text from one source, and passes complete lines to its `Log`.
+pub struct LineWriter<'a, const N: usize, const Q: usize> {
+    log: &'a Log<Q>,
+    buffer: LineAssembler<N>,
+}
+
+impl<const N: usize, const Q: usize> LineWriter<'_, N, Q> {
+    /// Emit any partial line now, rather than waiting for a newline.
+    pub fn flush(&mut self) {
+        let log = self.log;
+        self.buffer.flush(|line| log.line(line));
+    }
+}
+
+impl<const N: usize, const Q: usize> fmt::Write for LineWriter<'_, N, Q> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        let log = self.log;
+        self.buffer.push_str(string, |line| log.line(line));
+        Ok(())
+    }
+}
+
+impl<const N: usize, const Q: usize> Drop for LineWriter<'_, N, Q> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Push each piece in turn, and collect every line emitted.
+    fn assemble<const N: usize>(
+        assembler: &mut LineAssembler<N>,
+        pieces: &[&str],
+    ) -> Vec<std::string::String> {
+        let mut lines = Vec::new();
+        for piece in pieces {
+            assembler.push_str(piece, |line| lines.push(line.into()));
+        }
+        lines
+    }
+
+    #[test]
+    fn lines_are_joined_across_partial_writes() {
+        let mut assembler: LineAssembler<32> = LineAssembler::new();
+        let lines = assemble(&mut assembler, &["Sam", "ple ", "1", " done.\r", "\nNext"]);
+        assert_eq!(lines, ["Sample 1 done."]);
+        assert_eq!(assembler.pending(), "Next");
+        let lines = assemble(&mut assembler, &["\n\n", "a\nb\r\n"]);
+        assert_eq!(lines, ["Next", "", "a", "b"]);
+        assert_eq!(assembler.pending(), "");
+    }
+
+    #[test]
+    fn full_buffer_is_split() {
+        let mut assembler: LineAssembler<4> = LineAssembler::new();
+        let lines = assemble(&mut assembler, &["abcdefghij"]);
+        assert_eq!(lines, ["abcd", "efgh"]);
+        assert_eq!(assembler.pending(), "ij");
+        // A newline right after a full buffer emits the rest as a line of its own.
+        let lines = assemble(&mut assembler, &["kl", "\n"]);
+        assert_eq!(lines, ["ijkl"]);
+    }
+
+    #[test]
+    fn flush_emits_a_partial_line_once() {
+        let mut assembler: LineAssembler<16> = LineAssembler::new();
+        assemble(&mut assembler, &["partial"]);
+        let mut lines = Vec::new();
+        assembler.flush(|line| lines.push(std::string::String::from(line)));
+        assembler.flush(|line| lines.push(std::string::String::from(line)));
+        assert_eq!(lines, ["partial"]);
+        assert_eq!(assembler.pending(), "");
+    }
+}
@@ -6,6 +6,9 @@
 
 use cortex_m::asm::nop;
 use cortex_m_rt::entry;
+#[cfg(feature = "panic-sos")]
+use hello_nucleo_f103rb as _;
+#[cfg(not(feature = "panic-sos"))]
 use panic_halt as _;
 use rtt_target::{rprintln, rtt_init_print};
 
@@ -0,0 +1,72 @@
+// src/menu.rs
+
+//! A list of numbered choices, for users who would rather not memorize commands.
+//!
+//! Each item is a key, a label and an action. The menu only renders the items and
+//! finds the action for a key, so what an action does is up to the example.
+
+use core::fmt::{self, Write};
+
+/// A list of items, each a key, a label and the action the key selects.
+pub struct Menu<'a, A> {
+    items: &'a [(char, &'a str, A)],
+}
+
+impl<'a, A: Copy> Menu<'a, A> {
+    pub const fn new(items: &'a [(char, &'a str, A)]) -> Self {
+        Menu { items }
+    }
+
+    /// Write a prompt, followed by one line per item.
+    pub fn render<W: Write>(&self, out: &mut W) -> fmt::Result {
+        write!(out, "\rSelect a menu item:\r\n")?;
+        for (key, label, _) in self.items {
+            write!(out, "\r{} - {}\r\n", key, label)?;
+        }
+        Ok(())
+    }
+
+    /// The action for `key`, or `None` if no item has that key.
+    pub fn select(&self, key: char) -> Option<A> {
+        self.items
+            .iter()
+            .find(|(item_key, _, _)| key == *item_key)
+            .map(|(_, _, action)| *action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use heapless::String;
+
+    const ITEMS: [(char, &str, u8); 3] = [('1', "One", 1), ('2', "Two", 2), ('0', "Leave", 0)];
+
+    #[test]
+    fn select_by_key() {
+        let menu = Menu::new(&ITEMS);
+        assert_eq!(menu.select('1'), Some(1));
+        assert_eq!(menu.select('0'), Some(0));
+        assert_eq!(menu.select('3'), None);
+        assert_eq!(menu.select('O'), None);
+    }
+
+    #[test]
+    fn render_lists_every_item() {
+        let mut out: String<128> = String::new();
+        Menu::new(&ITEMS).render(&mut out).unwrap();
+        assert_eq!(
+            out.as_str(),
+            "\rSelect a menu item:\r\n\r1 - One\r\n\r2 - Two\r\n\r0 - Leave\r\n"
+        );
+    }
+
+    #[test]
+    fn empty_menu() {
+        let menu: Menu<u8> = Menu::new(&[]);
+        assert_eq!(menu.select('1'), None);
+        let mut out: String<32> = String::new();
+        menu.render(&mut out).unwrap();
+        assert_eq!(out.as_str(), "\rSelect a menu item:\r\n");
+    }
+}
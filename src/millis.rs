@@ -0,0 +1,68 @@
+// src/millis.rs
+
+//! A millisecond time base driven by the SysTick exception.
+//!
+//! Call `init` once during setup, and forward the SysTick exception to `tick`
+//! from the example, as follows.
+//!
+//! ```ignore
+//! #[exception]
+//! fn SysTick() {
+//!     millis::tick();
+//! }
+//! ```
+//!
+//! The count wraps after roughly 49 days, so compare times with `wrapping_sub`.
+//!
+//! `micros` adds the progress of SysTick through the current millisecond, for
+//! finer timing without using up a timer. It wraps after roughly 71 minutes.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use cortex_m::peripheral::{syst::SystClkSource, SYST};
+use stm32f1xx_hal::rcc::Clocks;
+
+const TICKS_PER_SECOND: u32 = 1_000;
+const MICROS_PER_MILLI: u32 = 1_000;
+
+static MILLIS: AtomicU32 = AtomicU32::new(0);
+
+/// Configure SysTick to fire once per millisecond.
+/// SYST is consumed so nothing else can reconfigure it.
+pub fn init(mut syst: SYST, clocks: &Clocks) {
+    syst.set_clock_source(SystClkSource::Core);
+    syst.set_reload(clocks.sysclk().raw() / TICKS_PER_SECOND - 1);
+    syst.clear_current();
+    syst.enable_counter();
+    syst.enable_interrupt();
+}
+
+/// Advance the count by one millisecond. Only call this from the SysTick exception.
+pub fn tick() {
+    MILLIS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Milliseconds elapsed since `init`.
+pub fn millis() -> u32 {
+    MILLIS.load(Ordering::Relaxed)
+}
+
+/// Microseconds elapsed since `init`, wrapping.
+///
+/// SysTick counts down from its reload value through each millisecond. If the
+/// millisecond count changes while reading the counter, the counter has reloaded,
+/// so both are read again. This relies on the SysTick exception running as soon
+/// as the counter reloads, so do not call it with interrupts disabled, or from a
+/// handler that SysTick cannot preempt.
+pub fn micros() -> u32 {
+    loop {
+        let ms = millis();
+        let current = SYST::get_current();
+        if ms == millis() {
+            let reload = SYST::get_reload();
+            let elapsed = (reload - current) as u64 * MICROS_PER_MILLI as u64 / (reload as u64 + 1);
+            return ms
+                .wrapping_mul(MICROS_PER_MILLI)
+                .wrapping_add(elapsed as u32);
+        }
+    }
+}
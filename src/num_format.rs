@@ -0,0 +1,92 @@
+// src/num_format.rs
+
+//! Number formats for the numeric values reported by the diagnostic commands.
+//!
+//! Hexadecimal and binary values are zero padded to the full 32 bits, so register
+//! values line up and individual bits are easy to find.
+
+use core::fmt::Write;
+use heapless::String;
+
+/// Long enough for a 32-bit binary number with its `0b` prefix.
+pub const NUM_FORMAT_SIZE: usize = 40;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NumFormat {
+    Dec,
+    Hex,
+    Bin,
+}
+
+impl NumFormat {
+    /// The format that follows this one when cycling with a command.
+    pub fn next(self) -> NumFormat {
+        match self {
+            NumFormat::Dec => NumFormat::Hex,
+            NumFormat::Hex => NumFormat::Bin,
+            NumFormat::Bin => NumFormat::Dec,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NumFormat::Dec => "decimal",
+            NumFormat::Hex => "hexadecimal",
+            NumFormat::Bin => "binary",
+        }
+    }
+}
+
+/// Replace the contents of `out` with `n` written in `fmt`.
+pub fn format_num(n: u32, fmt: NumFormat, out: &mut String<NUM_FORMAT_SIZE>) {
+    out.clear();
+    // Every format fits in NUM_FORMAT_SIZE, so writing cannot fail.
+    let _ = match fmt {
+        NumFormat::Dec => write!(out, "{}", n),
+        NumFormat::Hex => write!(out, "0x{:08X}", n),
+        NumFormat::Bin => write!(out, "0b{:032b}", n),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formatted(n: u32, fmt: NumFormat) -> String<NUM_FORMAT_SIZE> {
+        let mut out = String::new();
+        format_num(n, fmt, &mut out);
+        out
+    }
+
+    #[test]
+    fn formats_are_zero_padded() {
+        assert_eq!(formatted(42, NumFormat::Dec).as_str(), "42");
+        assert_eq!(formatted(42, NumFormat::Hex).as_str(), "0x0000002A");
+        assert_eq!(
+            formatted(5, NumFormat::Bin).as_str(),
+            "0b00000000000000000000000000000101"
+        );
+    }
+
+    #[test]
+    fn largest_value_fits() {
+        assert_eq!(formatted(u32::MAX, NumFormat::Dec).as_str(), "4294967295");
+        assert_eq!(formatted(u32::MAX, NumFormat::Hex).as_str(), "0xFFFFFFFF");
+        assert_eq!(formatted(u32::MAX, NumFormat::Bin).len(), 34);
+    }
+
+    #[test]
+    fn previous_contents_are_replaced() {
+        let mut out: String<NUM_FORMAT_SIZE> = String::new();
+        format_num(u32::MAX, NumFormat::Bin, &mut out);
+        format_num(0, NumFormat::Dec, &mut out);
+        assert_eq!(out.as_str(), "0");
+    }
+
+    #[test]
+    fn formats_cycle() {
+        assert_eq!(NumFormat::Dec.next(), NumFormat::Hex);
+        assert_eq!(NumFormat::Hex.next(), NumFormat::Bin);
+        assert_eq!(NumFormat::Bin.next(), NumFormat::Dec);
+    }
+}
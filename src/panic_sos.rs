@@ -0,0 +1,132 @@
+// src/panic_sos.rs
+
+//! A panic handler that blinks SOS on the onboard LED LD2 (PA5), so a crash can
+//! be recognized without a debugger or serial terminal attached.
+//!
+//! Enable it with the `panic-sos` feature, which replaces `panic-halt` in every
+//! example, for example `cargo embed --example button --features panic-sos`.
+//!
+//! The handler does not own any peripherals, so it steals them. This is normally
+//! unsound, because the code that panicked may still hold the same peripherals,
+//! but here it is acceptable for the following reasons.
+//!
+//! - Interrupts are disabled first, and the handler never returns, so nothing else
+//!   runs again and nothing can observe the stolen peripherals.
+//! - Only GPIO output levels, the GPIOA clock enable, and the mode of PA5 are
+//!   touched. The other pin modes, the clocks, and the other peripherals are left
+//!   as the application configured them.
+//! - Any peripheral in the middle of an operation is simply abandoned.
+//!
+//! The trade-off is that the handler knows nothing about the application. Every
+//! general purpose output on GPIOA, GPIOB, and GPIOC is driven low to turn off
+//! external LEDs, which may also switch off anything else driven by those pins.
+//! PA5 is then made a push-pull output, even if the application used it for
+//! something else. Timing uses a crude cycle counted delay, worked out from the
+//! system clock the RCC reports, so the blink keeps its pace with every
+//! `ClockProfile`, and before the clocks are set up. HSE is assumed to be the 8MHz
+//! clock that the ST-Link provides.
+
+#![allow(unsafe_code)]
+
+use core::panic::PanicInfo;
+use cortex_m::{asm, interrupt};
+use stm32f1xx_hal::pac::{self, gpioa};
+
+const HSI_HZ: u32 = 8_000_000;
+const HSE_HZ: u32 = 8_000_000;
+const UNIT_MS: u32 = 100;
+const LED_PIN: u32 = 5;
+
+// SOS in morse code as (duration, led_on) pairs in units. A dot is one unit on, a
+// dash is three, symbols are separated by one unit off, letters by three, and the
+// message repeats after seven.
+const SOS: [(u32, bool); 18] = [
+    (1, true),
+    (1, false),
+    (1, true),
+    (1, false),
+    (1, true),
+    (3, false),
+    (3, true),
+    (1, false),
+    (3, true),
+    (1, false),
+    (3, true),
+    (3, false),
+    (1, true),
+    (1, false),
+    (1, true),
+    (1, false),
+    (1, true),
+    (7, false),
+];
+
+/// Drive every general purpose output on `port` low.
+/// Inputs and alternate function outputs, like USART TX, are left alone.
+fn outputs_low(port: &gpioa::RegisterBlock) {
+    let config = (port.crh.read().bits() as u64) << 32 | port.crl.read().bits() as u64;
+    let mut mask: u32 = 0;
+    for pin in 0..16 {
+        let bits = (config >> (4 * pin)) & 0xF;
+        let is_output = bits & 0b0011 != 0;
+        let is_alternate = bits & 0b1000 != 0;
+        if is_output && !is_alternate {
+            mask |= 1 << pin;
+        }
+    }
+    // SAFETY: Every bit pattern is valid for BSRR. The upper half resets pins.
+    port.bsrr.write(|w| unsafe { w.bits(mask << 16) });
+}
+
+/// The system clock, as currently selected in the RCC.
+fn sysclk_hz(rcc: &pac::RCC) -> u32 {
+    let cfgr = rcc.cfgr.read();
+    match cfgr.sws().bits() {
+        0b01 => HSE_HZ,
+        0b10 => {
+            let pll_in_hz = match (cfgr.pllsrc().bit(), cfgr.pllxtpre().bit()) {
+                (false, _) => HSI_HZ / 2,
+                (true, false) => HSE_HZ,
+                (true, true) => HSE_HZ / 2,
+            };
+            // PLLMUL counts from 2, and its last two settings both multiply by 16.
+            let multiplier = (cfgr.pllmul().bits() as u32 + 2).min(16);
+            pll_in_hz * multiplier
+        }
+        _ => HSI_HZ,
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    interrupt::disable();
+
+    // SAFETY: See the module documentation.
+    let dp = unsafe { pac::Peripherals::steal() };
+    outputs_low(&dp.GPIOA);
+    outputs_low(&dp.GPIOB);
+    outputs_low(&dp.GPIOC);
+
+    // Make sure PA5 is a push-pull output, even if the panic happened before setup.
+    dp.RCC.apb2enr.modify(|_, w| w.iopaen().set_bit());
+    dp.GPIOA
+        .crl
+        .modify(|_, w| w.mode5().output2().cnf5().push_pull());
+
+    let unit_cycles = sysclk_hz(&dp.RCC) / 1_000 * UNIT_MS;
+    loop {
+        for (units, led_on) in SOS {
+            // SAFETY: Every bit pattern is valid for BSRR.
+            match led_on {
+                true => dp.GPIOA.bsrr.write(|w| unsafe { w.bits(1 << LED_PIN) }),
+                false => dp
+                    .GPIOA
+                    .bsrr
+                    .write(|w| unsafe { w.bits(1 << (LED_PIN + 16)) }),
+            }
+            for _ in 0..units {
+                asm::delay(unit_cycles);
+            }
+        }
+    }
+}
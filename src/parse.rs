@@ -0,0 +1,125 @@
+// src/parse.rs
+
+//! Parsing of numeric command arguments, like periods, counts and widths.
+//!
+//! An argument that is empty or not a decimal number is rejected. A number outside
+//! the accepted range is clamped to the nearest limit instead, and the result says
+//! so, so the command can still run and the caller can warn that the value was
+//! changed. A number too large for a `u32` counts as out of range high, rather than
+//! as a parse error. Arguments that select something rather than set an amount,
+//! like a pin number, should still be rejected when out of range, because the
+//! nearest valid choice is not what was asked for.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParseErr {
+    Empty,
+    NotANumber,
+}
+
+/// A parsed argument, and whether it had to be clamped into range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClampedArg {
+    pub value: u32,
+    pub clamped: bool,
+}
+
+/// Parse the decimal number in `s`, ignoring surrounding whitespace, and clamp it to
+/// `min..=max`. Signs are not accepted.
+pub fn parse_clamped(s: &str, min: u32, max: u32) -> Result<ClampedArg, ParseErr> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err(ParseErr::Empty);
+    }
+    // `None` once the number is too large for a `u32`.
+    let mut n: Option<u32> = Some(0);
+    for c in s.bytes() {
+        if !c.is_ascii_digit() {
+            return Err(ParseErr::NotANumber);
+        }
+        n = n
+            .and_then(|n| n.checked_mul(10))
+            .and_then(|n| n.checked_add((c - b'0') as u32));
+    }
+    Ok(match n {
+        Some(n) => ClampedArg {
+            value: n.clamp(min, max),
+            clamped: n < min || max < n,
+        },
+        None => ClampedArg {
+            value: max,
+            clamped: true,
+        },
+    })
+}
+
+/// Parse an index into something with `count` entries, rejecting it when it is out
+/// of range rather than clamping it.
+pub fn parse_index(s: &str, count: usize) -> Option<usize> {
+    let last = u32::try_from(count).ok()?.checked_sub(1)?;
+    match parse_clamped(s, 0, last) {
+        Ok(index) if !index.clamped => Some(index.value as usize),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clamped(value: u32, clamped: bool) -> Result<ClampedArg, ParseErr> {
+        Ok(ClampedArg { value, clamped })
+    }
+
+    #[test]
+    fn numbers_in_range() {
+        assert_eq!(parse_clamped("42", 0, 100), clamped(42, false));
+        assert_eq!(parse_clamped(" 7\t", 0, 100), clamped(7, false));
+        assert_eq!(parse_clamped("0", 0, 100), clamped(0, false));
+        assert_eq!(parse_clamped("100", 0, 100), clamped(100, false));
+        assert_eq!(parse_clamped("007", 0, 100), clamped(7, false));
+    }
+
+    #[test]
+    fn numbers_out_of_range_are_clamped() {
+        assert_eq!(parse_clamped("5", 10, 100), clamped(10, true));
+        assert_eq!(parse_clamped("101", 10, 100), clamped(100, true));
+        assert_eq!(
+            parse_clamped("4294967295", 0, u32::MAX),
+            clamped(u32::MAX, false)
+        );
+        // Too large for a `u32` is out of range high.
+        assert_eq!(
+            parse_clamped("4294967296", 0, u32::MAX),
+            clamped(u32::MAX, true)
+        );
+        assert_eq!(
+            parse_clamped("99999999999999999999", 1, 60),
+            clamped(60, true)
+        );
+    }
+
+    #[test]
+    fn malformed_arguments() {
+        assert_eq!(parse_clamped("", 0, 100), Err(ParseErr::Empty));
+        assert_eq!(parse_clamped("   ", 0, 100), Err(ParseErr::Empty));
+        for s in ["-1", "+1", "1.5", "12a", "1 2", "0x10"] {
+            assert_eq!(
+                parse_clamped(s, 0, 100),
+                Err(ParseErr::NotANumber),
+                "{:?}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn indexes_out_of_range_are_rejected() {
+        assert_eq!(parse_index("0", 9), Some(0));
+        assert_eq!(parse_index(" 8 ", 9), Some(8));
+        assert_eq!(parse_index("9", 9), None);
+        assert_eq!(parse_index("99999999999", 9), None);
+        assert_eq!(parse_index("", 9), None);
+        assert_eq!(parse_index("-1", 9), None);
+        assert_eq!(parse_index("0", 0), None);
+    }
+}
@@ -0,0 +1,559 @@
+// src/pins.rs
+
+//! Output pin setup with an explicit slew rate, and button input setup.
+//!
+//! The HAL configures every output for the fastest 50MHz slew rate. Fast edges
+//! are needed for clean waveforms, like the square wave in `square_wave`, but
+//! they ring and radiate more than necessary on outputs that only drive LEDs.
+//! LEDs switch at human speeds, so `led_output` uses the slowest 2MHz rate,
+//! which makes no visible difference. Every LED the examples drive from GPIO is
+//! set up this way, while `square_wave` asks for 50MHz explicitly.
+//!
+//! User button B1 has an external pull-up resistor, and pulls PC13 low when pressed,
+//! so it works as a floating input. A button added to another pin needs an internal
+//! pull resistor instead, to hold the pin at the released level. A button wired to
+//! ground needs a pull-up and is active low, and a button wired to 3.3V needs a
+//! pull-down and is active high. `button_input` configures either, and `Button`
+//! hides which level counts as pressed.
+//!
+//! Button contacts bounce for a few milliseconds when they close or open, so a
+//! raw reading can flip several times for one press. `Debouncer` only reports a
+//! change once the new level has held for `DEBOUNCE_MS`. It keeps no clock of its
+//! own, and is told how much time passed since the last sample instead, so it
+//! works the same from a delay loop or from `millis`.
+//!
+//! `GestureDetector` builds on the debounced state, and tells a single click, a
+//! double click and a long press apart.
+//!
+//! `BounceMeter` works on raw readings instead, and measures how long the contacts
+//! of one press bounce, which is a good way to pick a debounce time.
+
+use heapless::Vec;
+use stm32f1xx_hal::gpio::{
+    Active, Floating, IOPinSpeed, Input, Output, OutputSpeed, Pin, PullDown, PullUp, HL,
+};
+
+/// Slew rate for outputs that drive LEDs.
+pub const LED_SPEED: IOPinSpeed = IOPinSpeed::Mhz2;
+
+/// Configure `pin` as a push-pull output with the given slew rate.
+/// Use `crl` for pins 0-7, and `crh` for pins 8-15.
+pub fn push_pull_output<const P: char, const N: u8, MODE, CR>(
+    pin: Pin<P, N, MODE>,
+    cr: &mut CR,
+    speed: IOPinSpeed,
+) -> Pin<P, N, Output>
+where
+    MODE: Active,
+    Pin<P, N, MODE>: HL<Cr = CR>,
+    Pin<P, N, Output>: HL<Cr = CR>,
+{
+    let mut pin = pin.into_push_pull_output(cr);
+    pin.set_speed(cr, speed);
+    pin
+}
+
+/// Configure `pin` as a push-pull output for driving an LED.
+pub fn led_output<const P: char, const N: u8, MODE, CR>(
+    pin: Pin<P, N, MODE>,
+    cr: &mut CR,
+) -> Pin<P, N, Output>
+where
+    MODE: Active,
+    Pin<P, N, MODE>: HL<Cr = CR>,
+    Pin<P, N, Output>: HL<Cr = CR>,
+{
+    push_pull_output(pin, cr, LED_SPEED)
+}
+
+/// Internal resistor for a button input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ButtonPull {
+    /// No internal resistor, for buttons with an external one, like B1.
+    Floating,
+    Up,
+    Down,
+}
+
+/// Whether a button is pressed, given the level of its pin.
+pub fn button_pressed(is_high: bool, active_low: bool) -> bool {
+    is_high != active_low
+}
+
+/// The pin type depends on the resistor, so each one is a separate variant.
+enum ButtonPin<const P: char, const N: u8> {
+    Floating(Pin<P, N, Input<Floating>>),
+    Up(Pin<P, N, Input<PullUp>>),
+    Down(Pin<P, N, Input<PullDown>>),
+}
+
+/// A button input that knows which level means pressed.
+pub struct Button<const P: char, const N: u8> {
+    pin: ButtonPin<P, N>,
+    active_low: bool,
+}
+
+impl<const P: char, const N: u8> Button<P, N> {
+    pub fn is_pressed(&self) -> bool {
+        let is_high = match &self.pin {
+            ButtonPin::Floating(pin) => pin.is_high(),
+            ButtonPin::Up(pin) => pin.is_high(),
+            ButtonPin::Down(pin) => pin.is_high(),
+        };
+        button_pressed(is_high, self.active_low)
+    }
+}
+
+/// Configure `pin` as a button input with the given internal resistor.
+/// `active_low` is true for a button that pulls the pin low when pressed.
+pub fn button_input<const P: char, const N: u8, MODE, CR>(
+    pin: Pin<P, N, MODE>,
+    cr: &mut CR,
+    pull: ButtonPull,
+    active_low: bool,
+) -> Button<P, N>
+where
+    MODE: Active,
+    Pin<P, N, MODE>: HL<Cr = CR>,
+{
+    let pin = match pull {
+        ButtonPull::Floating => ButtonPin::Floating(pin.into_floating_input(cr)),
+        ButtonPull::Up => ButtonPin::Up(pin.into_pull_up_input(cr)),
+        ButtonPull::Down => ButtonPin::Down(pin.into_pull_down_input(cr)),
+    };
+    Button { pin, active_low }
+}
+
+/// How long a new button level has to hold before `Debouncer` reports it.
+pub const DEBOUNCE_MS: u32 = 20;
+
+/// A debounced change of the button state.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ButtonEvent {
+    Pressed,
+    Released,
+}
+
+/// Filters the bounce out of button readings. The button starts out released.
+pub struct Debouncer {
+    debounce_ms: u32,
+    pressed: bool,
+    /// How long the raw reading has differed from `pressed`, if it does.
+    pending_ms: Option<u32>,
+}
+
+impl Debouncer {
+    pub const fn new(debounce_ms: u32) -> Self {
+        Debouncer {
+            debounce_ms,
+            pressed: false,
+            pending_ms: None,
+        }
+    }
+
+    /// Feed one raw reading, `true` for pressed, taken `elapsed_ms` after the one
+    /// before. The timing starts at the first sample of the new level, so a change
+    /// is reported at the first sample at least `debounce_ms` after that. A reading
+    /// that goes back to the debounced level starts the wait over.
+    pub fn update(&mut self, raw: bool, elapsed_ms: u32) -> Option<ButtonEvent> {
+        if raw == self.pressed {
+            self.pending_ms = None;
+            return None;
+        }
+        let pending_ms = match self.pending_ms {
+            Some(pending_ms) => pending_ms.saturating_add(elapsed_ms),
+            None => 0,
+        };
+        if pending_ms < self.debounce_ms {
+            self.pending_ms = Some(pending_ms);
+            return None;
+        }
+        self.pressed = raw;
+        self.pending_ms = None;
+        match raw {
+            true => Some(ButtonEvent::Pressed),
+            false => Some(ButtonEvent::Released),
+        }
+    }
+
+    /// The debounced state, `true` for pressed.
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+}
+
+impl Default for Debouncer {
+    fn default() -> Self {
+        Self::new(DEBOUNCE_MS)
+    }
+}
+
+/// A press held this long is a long press.
+pub const LONG_PRESS_MS: u32 = 800;
+/// A second press this soon after the first one makes a double click.
+pub const DOUBLE_CLICK_MS: u32 = 400;
+
+/// A button gesture, reported by `GestureDetector`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ButtonGesture {
+    SingleClick,
+    DoubleClick,
+    LongPress,
+}
+
+#[derive(Clone, Copy)]
+enum GestureState {
+    Idle,
+    /// The first press, held for `held_ms` so far.
+    Pressed {
+        held_ms: u32,
+    },
+    /// Released after a short press that started `since_press_ms` ago, waiting to
+    /// see whether a second press follows.
+    Released {
+        since_press_ms: u32,
+    },
+    /// The gesture was reported, waiting for the button to be released.
+    Done,
+}
+
+/// Turns debounced button presses into gestures.
+///
+/// - A press held for `LONG_PRESS_MS` is a `LongPress`, reported while it is
+///   still held, so the user knows when to let go.
+/// - A second press within `DOUBLE_CLICK_MS` of the start of the first one is a
+///   `DoubleClick`, reported as soon as the second press is debounced.
+/// - Any other press is a `SingleClick`. It is reported on release, or
+///   `DOUBLE_CLICK_MS` after the press started if that is later, because until
+///   then it could still turn into a double click.
+pub struct GestureDetector {
+    debouncer: Debouncer,
+    state: GestureState,
+}
+
+impl GestureDetector {
+    pub const fn new(debouncer: Debouncer) -> Self {
+        GestureDetector {
+            debouncer,
+            state: GestureState::Idle,
+        }
+    }
+
+    /// Feed one raw reading, `true` for pressed, taken `elapsed_ms` after the one
+    /// before, and return the gesture it completes, if any.
+    pub fn update(&mut self, raw: bool, elapsed_ms: u32) -> Option<ButtonGesture> {
+        let event = self.debouncer.update(raw, elapsed_ms);
+        let (state, gesture) = match (self.state, event) {
+            (GestureState::Idle, Some(ButtonEvent::Pressed)) => {
+                (GestureState::Pressed { held_ms: 0 }, None)
+            }
+            (GestureState::Pressed { held_ms }, event) => {
+                let held_ms = held_ms.saturating_add(elapsed_ms);
+                match event {
+                    Some(ButtonEvent::Released) if DOUBLE_CLICK_MS <= held_ms => {
+                        (GestureState::Idle, Some(ButtonGesture::SingleClick))
+                    }
+                    Some(ButtonEvent::Released) => (
+                        GestureState::Released {
+                            since_press_ms: held_ms,
+                        },
+                        None,
+                    ),
+                    _ if LONG_PRESS_MS <= held_ms => {
+                        (GestureState::Done, Some(ButtonGesture::LongPress))
+                    }
+                    _ => (GestureState::Pressed { held_ms }, None),
+                }
+            }
+            (GestureState::Released { .. }, Some(ButtonEvent::Pressed)) => {
+                (GestureState::Done, Some(ButtonGesture::DoubleClick))
+            }
+            (GestureState::Released { since_press_ms }, _) => {
+                match since_press_ms.saturating_add(elapsed_ms) {
+                    since_press_ms if DOUBLE_CLICK_MS <= since_press_ms => {
+                        (GestureState::Idle, Some(ButtonGesture::SingleClick))
+                    }
+                    since_press_ms => (GestureState::Released { since_press_ms }, None),
+                }
+            }
+            (GestureState::Done, Some(ButtonEvent::Released)) => (GestureState::Idle, None),
+            (state, _) => (state, None),
+        };
+        self.state = state;
+        gesture
+    }
+}
+
+impl Default for GestureDetector {
+    fn default() -> Self {
+        Self::new(Debouncer::default())
+    }
+}
+
+/// Edges `BounceMeter` records for one press. Later edges replace the last one.
+pub const BOUNCE_EDGES_MAX: usize = 32;
+/// A press is considered stable once the button has not changed for this long.
+pub const BOUNCE_SETTLE_MS: u32 = 50;
+
+/// Time from the first to the last edge in `edges`, in milliseconds.
+/// Fewer than two edges means there was no bounce.
+pub fn bounce_window(edges: &[u32]) -> u32 {
+    match (edges.first(), edges.last()) {
+        (Some(first), Some(last)) => last.wrapping_sub(*first),
+        _ => 0,
+    }
+}
+
+/// Records the edges of one button press, to measure how long the contacts bounce.
+/// Edges are sampled by polling, so the measurement is only as fine as `millis()`.
+pub struct BounceMeter {
+    edges: Vec<u32, BOUNCE_EDGES_MAX>,
+    armed: bool,
+    last_level: bool,
+}
+
+impl BounceMeter {
+    pub fn new() -> Self {
+        BounceMeter {
+            edges: Vec::new(),
+            armed: false,
+            last_level: false,
+        }
+    }
+
+    /// Wait for the next press, starting from the current button level.
+    pub fn arm(&mut self, button_down: bool) {
+        self.edges.clear();
+        self.armed = true;
+        self.last_level = button_down;
+    }
+
+    /// Sample the button. Returns the bounce window and edge count once the
+    /// button has settled after the first edge.
+    pub fn update(&mut self, now_ms: u32, button_down: bool) -> Option<(u32, usize)> {
+        if !self.armed {
+            return None;
+        }
+        if button_down != self.last_level {
+            self.last_level = button_down;
+            if self.edges.is_full() {
+                // Keep the first edge, and track the latest edge in the last slot.
+                self.edges.pop();
+            }
+            let _ = self.edges.push(now_ms);
+            return None;
+        }
+        match self.edges.last() {
+            Some(last) if BOUNCE_SETTLE_MS <= now_ms.wrapping_sub(*last) => {
+                self.armed = false;
+                Some((bounce_window(&self.edges), self.edges.len()))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for BounceMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn active_levels() {
+        // Wired to ground with a pull-up, like B1, the button reads low when pressed.
+        assert!(button_pressed(false, true));
+        assert!(!button_pressed(true, true));
+        // Wired to 3.3V with a pull-down, it reads high when pressed.
+        assert!(button_pressed(true, false));
+        assert!(!button_pressed(false, false));
+    }
+
+    #[test]
+    fn window_from_first_to_last_edge() {
+        assert_eq!(bounce_window(&[]), 0);
+        assert_eq!(bounce_window(&[100]), 0);
+        assert_eq!(bounce_window(&[100, 101, 104, 107]), 7);
+        // `millis()` wrapped during the press.
+        assert_eq!(bounce_window(&[u32::MAX - 1, 2]), 4);
+    }
+
+    #[test]
+    fn meter_reports_once_settled() {
+        let mut meter = BounceMeter::new();
+        // Nothing is measured until armed.
+        assert_eq!(meter.update(0, true), None);
+        meter.arm(false);
+        assert_eq!(meter.update(10, false), None);
+        for (now_ms, level) in [(20, true), (21, false), (23, true)] {
+            assert_eq!(meter.update(now_ms, level), None);
+        }
+        assert_eq!(meter.update(23 + BOUNCE_SETTLE_MS - 1, true), None);
+        assert_eq!(meter.update(23 + BOUNCE_SETTLE_MS, true), Some((3, 3)));
+        // Disarmed after reporting.
+        assert_eq!(meter.update(200, false), None);
+    }
+
+    #[test]
+    fn meter_keeps_the_first_and_latest_edge_when_full() {
+        let mut meter = BounceMeter::new();
+        meter.arm(false);
+        let mut level = false;
+        for now_ms in 0..BOUNCE_EDGES_MAX as u32 + 10 {
+            level = !level;
+            meter.update(now_ms, level);
+        }
+        let last_ms = BOUNCE_EDGES_MAX as u32 + 9;
+        assert_eq!(
+            meter.update(last_ms + BOUNCE_SETTLE_MS, level),
+            Some((last_ms, BOUNCE_EDGES_MAX))
+        );
+    }
+
+    /// Feed `debouncer` the raw `level` every `step_ms`, `count` times, and return
+    /// the sample, counting from 1, of each event with the event.
+    fn debounce(
+        debouncer: &mut Debouncer,
+        level: bool,
+        step_ms: u32,
+        count: u32,
+    ) -> Vec<(u32, ButtonEvent), 8> {
+        (1..=count)
+            .filter_map(|n| debouncer.update(level, step_ms).map(|event| (n, event)))
+            .collect()
+    }
+
+    #[test]
+    fn change_is_reported_after_holding_steady() {
+        let mut debouncer = Debouncer::new(DEBOUNCE_MS);
+        assert!(!debouncer.is_pressed());
+        // The wait starts at the first pressed sample, so with a sample every 5ms
+        // the press is reported at the fifth one, 20ms after the first.
+        assert_eq!(
+            debounce(&mut debouncer, true, 5, 10),
+            [(5, ButtonEvent::Pressed)]
+        );
+        assert!(debouncer.is_pressed());
+        // Holding the level reports nothing more.
+        assert_eq!(debounce(&mut debouncer, true, 5, 10), []);
+    }
+
+    #[test]
+    fn bounce_restarts_the_wait() {
+        let mut debouncer = Debouncer::new(DEBOUNCE_MS);
+        assert_eq!(debounce(&mut debouncer, true, 5, 4), []);
+        // Going back to released before the 20ms are up starts the wait over.
+        assert_eq!(debouncer.update(false, 5), None);
+        assert_eq!(debounce(&mut debouncer, true, 5, 4), []);
+        assert_eq!(debouncer.update(true, 5), Some(ButtonEvent::Pressed));
+    }
+
+    #[test]
+    fn release_is_debounced_too() {
+        let mut debouncer = Debouncer::default();
+        debounce(&mut debouncer, true, 5, 5);
+        assert_eq!(debounce(&mut debouncer, false, 5, 3), []);
+        assert_eq!(debouncer.update(true, 5), None);
+        assert_eq!(
+            debounce(&mut debouncer, false, 5, 10),
+            [(5, ButtonEvent::Released)]
+        );
+        assert!(!debouncer.is_pressed());
+    }
+
+    #[test]
+    fn debounce_across_millis_wrapping() {
+        let mut debouncer = Debouncer::default();
+        let mut last_ms = u32::MAX - 7;
+        let mut events = Vec::<(u32, ButtonEvent), 8>::new();
+        for now_ms in (0..40).map(|i| (u32::MAX - 7).wrapping_add(i * 4)) {
+            if let Some(event) = debouncer.update(true, now_ms.wrapping_sub(last_ms)) {
+                events.push((now_ms, event)).unwrap();
+            }
+            last_ms = now_ms;
+        }
+        // The first sample is at MAX - 7, so the press is reported 20ms later.
+        assert_eq!(events, [(12, ButtonEvent::Pressed)]);
+        // A long gap between samples cannot overflow the wait.
+        let mut debouncer = Debouncer::default();
+        debouncer.update(true, 0);
+        assert_eq!(debouncer.update(true, u32::MAX), Some(ButtonEvent::Pressed));
+    }
+
+    /// Sample `detector` every `GESTURE_STEP_MS`, holding each level for its
+    /// duration in turn, and return the time of each gesture with the gesture.
+    fn gestures(presses: &[(bool, u32)]) -> Vec<(u32, ButtonGesture), 8> {
+        const GESTURE_STEP_MS: u32 = 5;
+        let mut detector = GestureDetector::default();
+        let mut now_ms = 0;
+        let mut found = Vec::new();
+        for &(level, duration_ms) in presses {
+            for _ in 0..duration_ms / GESTURE_STEP_MS {
+                now_ms += GESTURE_STEP_MS;
+                if let Some(gesture) = detector.update(level, GESTURE_STEP_MS) {
+                    found.push((now_ms, gesture)).unwrap();
+                }
+            }
+        }
+        found
+    }
+
+    /// When a press starting with the first sample is debounced.
+    const PRESSED_MS: u32 = 5 + DEBOUNCE_MS;
+
+    #[test]
+    fn single_click_waits_for_the_double_click_window() {
+        assert_eq!(
+            gestures(&[(true, 100), (false, 1_000)]),
+            [(PRESSED_MS + DOUBLE_CLICK_MS, ButtonGesture::SingleClick)]
+        );
+        // A press held past the window, but not long, is a click as soon as it ends.
+        assert_eq!(
+            gestures(&[(true, 500), (false, 1_000)]),
+            [(500 + PRESSED_MS, ButtonGesture::SingleClick)]
+        );
+    }
+
+    #[test]
+    fn double_click_inside_the_window() {
+        assert_eq!(
+            gestures(&[(true, 100), (false, 100), (true, 100), (false, 1_000)]),
+            [(200 + PRESSED_MS, ButtonGesture::DoubleClick)]
+        );
+        // A second press after the window is two single clicks.
+        assert_eq!(
+            gestures(&[(true, 100), (false, 500), (true, 100), (false, 1_000)]),
+            [
+                (PRESSED_MS + DOUBLE_CLICK_MS, ButtonGesture::SingleClick),
+                (
+                    600 + PRESSED_MS + DOUBLE_CLICK_MS,
+                    ButtonGesture::SingleClick
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn long_press_fires_once_while_held() {
+        // Reported while still held, and the release after it is not a click.
+        assert_eq!(
+            gestures(&[(true, 3_000), (false, 1_000)]),
+            [(PRESSED_MS + LONG_PRESS_MS, ButtonGesture::LongPress)]
+        );
+        // After a long press, the next press starts a new gesture.
+        assert_eq!(
+            gestures(&[(true, 1_000), (false, 200), (true, 100), (false, 1_000)]),
+            [
+                (PRESSED_MS + LONG_PRESS_MS, ButtonGesture::LongPress),
+                (
+                    1_200 + PRESSED_MS + DOUBLE_CLICK_MS,
+                    ButtonGesture::SingleClick
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,108 @@
+// src/report.rs
+
+//! Replies for scripts that drive the board over USART.
+//!
+//! An acknowledgment line says whether each command was accepted, so a script
+//! can check every command instead of parsing the text meant for people.
+//!
+//! A self test report has a PASS or FAIL line for every check, and a summary line
+//! that a script can match without reading the rest.
+
+use core::fmt::{self, Write};
+use heapless::String;
+
+/// Whether a command was accepted, for acknowledgments.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CmdResult {
+    Ok,
+    Err,
+}
+
+/// Replace the contents of `out` with the acknowledgment line for `result`.
+pub fn ack_line<const N: usize>(result: CmdResult, out: &mut String<N>) {
+    out.clear();
+    let _ = match result {
+        CmdResult::Ok => out.push_str("OK\r\n"),
+        CmdResult::Err => out.push_str("ERR\r\n"),
+    };
+}
+
+/// The outcome of one self test check, with the reason if it failed.
+pub type CheckResult = Result<(), &'static str>;
+
+/// Write a PASS or FAIL line for every check, followed by a summary line.
+pub fn write_selftest_report<W: Write>(out: &mut W, checks: &[(&str, CheckResult)]) -> fmt::Result {
+    for (name, result) in checks {
+        match result {
+            Ok(()) => write!(out, "\rPASS {}\r\n", name)?,
+            Err(reason) => write!(out, "\rFAIL {}: {}\r\n", name, reason)?,
+        }
+    }
+    let passed = checks.iter().filter(|(_, result)| result.is_ok()).count();
+    match passed == checks.len() {
+        true => write!(out, "\rSelf test PASS, {} checks.\r\n", checks.len()),
+        false => write!(
+            out,
+            "\rSelf test FAIL, {} of {} checks passed.\r\n",
+            passed,
+            checks.len()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ack_lines() {
+        let mut out: String<16> = String::new();
+        ack_line(CmdResult::Ok, &mut out);
+        assert_eq!(out.as_str(), "OK\r\n");
+        ack_line(CmdResult::Err, &mut out);
+        assert_eq!(out.as_str(), "ERR\r\n");
+    }
+
+    #[test]
+    fn ack_line_does_not_fit() {
+        // The line is left out rather than cut short.
+        let mut out: String<4> = String::new();
+        ack_line(CmdResult::Err, &mut out);
+        assert_eq!(out.as_str(), "");
+        ack_line(CmdResult::Ok, &mut out);
+        assert_eq!(out.as_str(), "OK\r\n");
+    }
+
+    #[test]
+    fn passing_self_test() {
+        let mut out: String<128> = String::new();
+        write_selftest_report(&mut out, &[("clock", Ok(())), ("button", Ok(()))]).unwrap();
+        assert_eq!(
+            out.as_str(),
+            "\rPASS clock\r\n\rPASS button\r\n\rSelf test PASS, 2 checks.\r\n"
+        );
+    }
+
+    #[test]
+    fn failing_self_test() {
+        let mut out: String<128> = String::new();
+        let checks = [
+            ("clock", Ok(())),
+            ("vrefint", Err("out of range")),
+            ("leds", Err("stuck")),
+        ];
+        write_selftest_report(&mut out, &checks).unwrap();
+        assert_eq!(
+            out.as_str(),
+            "\rPASS clock\r\n\rFAIL vrefint: out of range\r\n\rFAIL leds: stuck\r\n\
+             \rSelf test FAIL, 1 of 3 checks passed.\r\n"
+        );
+    }
+
+    #[test]
+    fn no_checks_pass() {
+        let mut out: String<64> = String::new();
+        write_selftest_report(&mut out, &[]).unwrap();
+        assert_eq!(out.as_str(), "\rSelf test PASS, 0 checks.\r\n");
+    }
+}
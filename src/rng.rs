@@ -0,0 +1,67 @@
+// src/rng.rs
+
+//! A small pseudo-random number generator for visual effects.
+//!
+//! The STM32F103 has no hardware RNG, so `xorshift32` provides a fast, repeatable
+//! sequence instead. The same seed always produces the same sequence. It is not
+//! suitable for anything security related.
+
+/// Advance `state` with Marsaglia's 32-bit xorshift, and return the new value.
+///
+/// A zero state is a fixed point and stays zero forever, so seed with a nonzero
+/// value, for example with `seed_from`.
+pub fn xorshift32(state: &mut u32) -> u32 {
+    let mut x = *state;
+    x ^= x << 13;
+    x ^= x >> 17;
+    x ^= x << 5;
+    *state = x;
+    x
+}
+
+/// Fold `words`, for example the unique device ID, into a nonzero seed.
+pub fn seed_from(words: &[u32]) -> u32 {
+    match words.iter().fold(0, |seed, word| seed ^ word) {
+        0 => 1,
+        seed => seed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_sequence() {
+        let mut state = 1;
+        assert_eq!(xorshift32(&mut state), 270_369);
+        assert_eq!(xorshift32(&mut state), 67_634_689);
+        assert_eq!(xorshift32(&mut state), 2_647_435_461);
+        assert_eq!(state, 2_647_435_461);
+    }
+
+    #[test]
+    fn zero_is_a_fixed_point() {
+        let mut state = 0;
+        assert_eq!(xorshift32(&mut state), 0);
+        assert_eq!(state, 0);
+    }
+
+    #[test]
+    fn no_short_cycle() {
+        let mut state = seed_from(&[0x1234_5678]);
+        let first = xorshift32(&mut state);
+        for _ in 0..10_000 {
+            let value = xorshift32(&mut state);
+            assert_ne!(value, 0);
+            assert_ne!(value, first);
+        }
+    }
+
+    #[test]
+    fn seeds_are_nonzero() {
+        assert_eq!(seed_from(&[]), 1);
+        assert_eq!(seed_from(&[0xABCD, 0xABCD]), 1);
+        assert_eq!(seed_from(&[0xF0, 0x0F, 0x100]), 0x1FF);
+    }
+}
@@ -0,0 +1,64 @@
+// src/scheduler.rs
+
+//! A cooperative scheduler for periodic tasks, driven by `millis()`.
+//!
+//! Each task has a period, and the main loop asks the scheduler which task is due,
+//! runs it, and asks again, as follows. Tasks must return quickly instead of
+//! waiting, because nothing runs until they return.
+//!
+//! ```ignore
+//! let mut scheduler = Scheduler::new(millis(), [500, 5_000]);
+//! loop {
+//!     while let Some(task) = scheduler.next_due(millis()) {
+//!         match task {
+//!             0 => led.toggle(),
+//!             _ => send_status(&mut tx_queue),
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! The next run is scheduled one period after the previous one was due, rather
+//! than after it actually ran, so a task that runs a little late does not drift.
+//! A task that falls more than a whole period behind skips the missed runs instead
+//! of running repeatedly to catch up.
+
+pub struct Scheduler<const N: usize> {
+    periods_ms: [u32; N],
+    next_ms: [u32; N],
+}
+
+/// Whether `due_ms` has been reached at `now_ms`, allowing for the count wrapping.
+/// Times up to half the range of a `u32`, about 24 days, apart compare correctly.
+fn is_due(now_ms: u32, due_ms: u32) -> bool {
+    (now_ms.wrapping_sub(due_ms) as i32) >= 0
+}
+
+impl<const N: usize> Scheduler<N> {
+    /// Every task is first due at `now_ms`.
+    pub fn new(now_ms: u32, periods_ms: [u32; N]) -> Self {
+        Scheduler {
+            periods_ms,
+            next_ms: [now_ms; N],
+        }
+    }
+
+    /// Change the period of `task`, taking effect after its next run.
+    pub fn set_period(&mut self, task: usize, period_ms: u32) {
+        self.periods_ms[task] = period_ms;
+    }
+
+    /// Return the first task that is due at `now_ms`, and schedule its next run.
+    /// Call this until it returns `None`, running each task it returns.
+    /// A period of 0 is treated as 1ms, so a task cannot be due forever.
+    pub fn next_due(&mut self, now_ms: u32) -> Option<usize> {
+        let task = (0..N).find(|&task| is_due(now_ms, self.next_ms[task]))?;
+        let period_ms = self.periods_ms[task].max(1);
+        let next_ms = self.next_ms[task].wrapping_add(period_ms);
+        self.next_ms[task] = match is_due(now_ms, next_ms) {
+            true => now_ms.wrapping_add(period_ms),
+            false => next_ms,
+        };
+        Some(task)
+    }
+}
@@ -0,0 +1,140 @@
+// src/serial_config.rs
+
+//! Changing the configuration of a USART after `Serial::split`.
+//!
+//! The HAL has no way to reunite split `Tx` and `Rx` halves into a `Serial`, and
+//! `Serial::release` needs the whole `Serial`. It does provide a free function,
+//! `serial::reconfigure`, that takes both halves instead. It waits for the
+//! transmitter to finish, then reapplies the baud rate, word length, parity and
+//! stop bits, so no register block has to be re-taken and no `unsafe` is needed.
+//! Holding both halves mutably is what makes this sound, because nothing else can
+//! use the USART while it changes.
+//!
+//! The HAL asserts that the baud rate divisor is at least 16, which is a panic at
+//! runtime for a baud rate that is too high for the bus clock. `reconfigure_serial`
+//! checks the divisor first, so a bad baud rate typed over USART is an error.
+//!
+//! With the 48MHz system clock used by the examples, USART2 is on the 24MHz APB1
+//! bus, so the divisor for 115200 baud is 208, and the actual rate is 115384 baud.
+//!
+//! `usart2_split` sets up USART2 on either of its two pin pairs, chosen with the
+//! AFIO remap, see RM0008 section 9.3.8. The HAL sets the USART2_REMAP bit in
+//! `afio.mapr` from the type of the pins passed to `Serial::new`, so choosing the
+//! pins is choosing the remap. The pins conflict with other features as follows.
+//!
+//! - `Usart2Pins::Default` is TX on PA2 and RX on PA3, which the Nucleo-F103RB
+//!   wires to the ST-Link virtual COM port, and not to Arduino D1 and D0, unless
+//!   solder bridges SB62 and SB63 are closed instead of SB13 and SB14. The same
+//!   pins are ADC12_IN2 and ADC12_IN3, and TIM2_CH3 and TIM2_CH4, so neither can
+//!   be used while USART2 is.
+//! - `Usart2Pins::Remapped` is TX on PD5 and RX on PD6. These only exist on the
+//!   100 and 144 pin packages. The Nucleo-F103RB has the 64 pin STM32F103RBT6,
+//!   where port D stops at PD2, so on this board the remap leaves USART2 with no
+//!   pins at all, and the ST-Link virtual COM port goes silent. It is here for
+//!   boards with a larger package, where PD5 and PD6 are also FSMC_NWE and
+//!   FSMC_NWAIT, and so are not available while external memory is in use.
+
+use stm32f1xx_hal::{
+    afio::MAPR,
+    gpio::{Alternate, Floating, Input, PushPull, PA2, PA3, PD5, PD6},
+    pac::USART2,
+    rcc::Clocks,
+    serial::{self, Config, Instance, Rx, Serial, Tx},
+};
+
+/// Smallest divisor the USART accepts, with 16 times oversampling.
+pub const BAUD_DIV_MIN: u16 = 16;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SerialConfigError {
+    /// The baud rate is zero, or too high or too low for the bus clock.
+    ImpossibleBaud,
+}
+
+/// Baud rate register value for `baud` on a USART clocked at `pclk`.
+///
+/// The integer part of the division is the mantissa and the remainder in sixteenths
+/// is the fraction, which together are simply `pclk / baud`, rounded down the way
+/// the HAL does it. Saturates at `u16::MAX` for a rate that is too low, or zero.
+pub fn baud_div(pclk: u32, baud: u32) -> u16 {
+    match baud {
+        0 => u16::MAX,
+        baud => (pclk / baud).min(u16::MAX as u32) as u16,
+    }
+}
+
+/// Actual baud rate produced by divisor `div` on a USART clocked at `pclk`.
+pub fn actual_baud(pclk: u32, div: u16) -> u32 {
+    match div {
+        0 => 0,
+        div => pclk / div as u32,
+    }
+}
+
+/// Apply `config` to the USART that `tx` and `rx` belong to, after any byte still
+/// being transmitted has gone out. Returns the actual baud rate.
+pub fn reconfigure_serial<USART: Instance>(
+    tx: &mut Tx<USART>,
+    rx: &mut Rx<USART>,
+    config: Config,
+    clocks: &Clocks,
+) -> Result<u32, SerialConfigError> {
+    let pclk = USART::clock(clocks).raw();
+    let div = baud_div(pclk, config.baudrate.0);
+    if div < BAUD_DIV_MIN || u16::MAX == div {
+        return Err(SerialConfigError::ImpossibleBaud);
+    }
+    // Wait for the transmitter first, because `reconfigure` returns `WouldBlock`
+    // while it is busy, and that is its only error.
+    nb::block!(tx.flush()).ok();
+    serial::reconfigure(tx, rx, config, clocks).ok();
+    Ok(actual_baud(pclk, div))
+}
+
+/// The pins USART2 can use, TX first.
+pub enum Usart2Pins {
+    /// PA2 and PA3, connected to the ST-Link virtual COM port.
+    Default(PA2<Alternate<PushPull>>, PA3<Input<Floating>>),
+    /// PD5 and PD6, which the Nucleo-F103RB does not have.
+    Remapped(PD5<Alternate<PushPull>>, PD6<Input<Floating>>),
+}
+
+/// Set up USART2 on `pins` with `config`, selecting the matching AFIO remap, and
+/// split it.
+pub fn usart2_split(
+    usart: USART2,
+    pins: Usart2Pins,
+    mapr: &mut MAPR,
+    config: Config,
+    clocks: &Clocks,
+) -> (Tx<USART2>, Rx<USART2>) {
+    match pins {
+        Usart2Pins::Default(tx, rx) => Serial::new(usart, (tx, rx), mapr, config, clocks).split(),
+        Usart2Pins::Remapped(tx, rx) => Serial::new(usart, (tx, rx), mapr, config, clocks).split(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn divisors_on_the_apb1_clock() {
+        // 115200 baud from 24MHz, as in the module documentation.
+        assert_eq!(baud_div(24_000_000, 115_200), 208);
+        assert_eq!(actual_baud(24_000_000, 208), 115_384);
+        assert_eq!(baud_div(24_000_000, 9_600), 2_500);
+        assert_eq!(actual_baud(24_000_000, 2_500), 9_600);
+        // The fastest rate, at the smallest divisor.
+        assert_eq!(baud_div(24_000_000, 1_500_000), BAUD_DIV_MIN);
+    }
+
+    #[test]
+    fn impossible_rates_saturate() {
+        assert_eq!(baud_div(24_000_000, 0), u16::MAX);
+        assert_eq!(baud_div(24_000_000, 300), u16::MAX);
+        assert_eq!(baud_div(24_000_000, 3_000_000), 8);
+        assert_eq!(baud_div(24_000_000, 48_000_000), 0);
+        assert_eq!(actual_baud(24_000_000, 0), 0);
+    }
+}
@@ -0,0 +1,112 @@
+// src/serial_rx.rs
+
+//! Interrupt driven reception into a ring buffer.
+//!
+//! The USART only holds one received byte. At 115200 baud the next one arrives
+//! about 87us later, so a main loop that polls `rx.read()` loses bytes to overruns
+//! whenever it spends longer than that in a delay or a slow command. Here, the
+//! USART interrupt handler moves every byte into a queue as it arrives, and the main
+//! loop takes them out with `dequeue` whenever it gets around to it.
+//!
+//! When the queue is full, the handler drops the oldest byte to make room for the
+//! new one, and counts it. The handler must never wait for the main loop, and the
+//! bytes that were received last are the ones a reader catching up is interested
+//! in. An overrun, or a framing, noise or parity error, also counts as a dropped
+//! byte. Reading the USART clears the error flags, which matters because the
+//! receive interrupt also fires for an overrun, and would otherwise fire again as
+//! soon as the handler returned, forever.
+//!
+//! ```ignore
+//! static RX: SerialRx<USART2, 256> = SerialRx::new();
+//!
+//! #[interrupt]
+//! fn USART2() {
+//!     RX.on_interrupt();
+//! }
+//!
+//! RX.init(rx);
+//! // Unmask the USART2 interrupt in the NVIC, then from the main loop.
+//! while let Some(byte) = RX.dequeue() {}
+//! ```
+
+use crate::shared::Shared;
+use heapless::spsc::Queue;
+use stm32f1xx_hal::serial::{Instance, Rx};
+
+struct Inner<USART, const N: usize> {
+    rx: Option<Rx<USART>>,
+    queue: Queue<u8, N>,
+    dropped: u32,
+}
+
+/// A receive queue filled by the USART interrupt, meant to be a `static`.
+/// `N` is the size of the queue, which holds at most `N - 1` bytes.
+pub struct SerialRx<USART, const N: usize> {
+    inner: Shared<Inner<USART, N>>,
+}
+
+impl<USART: Instance, const N: usize> SerialRx<USART, N> {
+    pub const fn new() -> Self {
+        SerialRx {
+            inner: Shared::new(Inner {
+                rx: None,
+                queue: Queue::new(),
+                dropped: 0,
+            }),
+        }
+    }
+
+    /// Hand over the receiver, and enable its RXNE interrupt.
+    /// The interrupt is only enabled once the receiver is stored, so the handler
+    /// never finds it missing.
+    pub fn init(&self, rx: Rx<USART>) {
+        self.inner.with(|inner| inner.rx.insert(rx).listen());
+    }
+
+    /// Call from the USART interrupt handler. Queues the received byte, dropping
+    /// the oldest one if the queue is full.
+    pub fn on_interrupt(&self) {
+        self.inner.with(|inner| {
+            let Some(rx) = inner.rx.as_mut() else {
+                return;
+            };
+            match rx.read() {
+                Ok(byte) => {
+                    if inner.queue.enqueue(byte).is_err() {
+                        inner.queue.dequeue();
+                        inner.queue.enqueue(byte).ok();
+                        inner.dropped = inner.dropped.saturating_add(1);
+                    }
+                }
+                Err(nb::Error::Other(_)) => inner.dropped = inner.dropped.saturating_add(1),
+                Err(nb::Error::WouldBlock) => (),
+            }
+        });
+    }
+
+    /// The oldest received byte, if any.
+    pub fn dequeue(&self) -> Option<u8> {
+        self.inner.with(|inner| inner.queue.dequeue())
+    }
+
+    /// Number of received bytes waiting to be read.
+    pub fn len(&self) -> usize {
+        self.inner.with(|inner| inner.queue.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return the number of bytes dropped to a full queue or a receive error, and
+    /// reset it. The count saturates rather than wrapping.
+    pub fn take_dropped(&self) -> u32 {
+        self.inner.with(|inner| core::mem::take(&mut inner.dropped))
+    }
+}
+
+impl<USART: Instance, const N: usize> Default for SerialRx<USART, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,160 @@
+// src/settings.rs
+
+//! User settings kept in flash, so they survive a reset.
+//!
+//! The settings live in the last 1KB page of the 128KB flash, which `memory.x`
+//! leaves out of the program region so the program can never overlap it. Flash can
+//! only be written after the whole page is erased, so saving always rewrites the
+//! entire record.
+//!
+//! The record starts with a magic number that marks it as valid. Erased flash reads
+//! as all ones, so a page that was never saved is rejected and the defaults are
+//! used. The layout, with multi-byte values in little endian order, is:
+//!   magic (4), banner length (1), prompt length (1), flags (1), zero (1),
+//!   banner bytes (BANNER_SIZE), prompt bytes (PROMPT_SIZE)
+//! Unused string bytes are zero. An empty string is stored as such, and means the
+//! example should use its built in default. The only flag is `FLAG_INVERT_LEDS`,
+//! and a record with any other flag set is rejected.
+//!
+//! `shell` saves the banner and prompt, and `serial_led_control` the LED inversion.
+//! Both save a copy of the settings they loaded with only their own fields changed,
+//! so neither loses the other's.
+
+use heapless::String;
+use stm32f1xx_hal::flash::{Error, FlashWriter};
+
+pub const BANNER_SIZE: usize = 64;
+pub const PROMPT_SIZE: usize = 16;
+/// Size of the stored record. Flash is written in half words, so this is even.
+pub const SETTINGS_SIZE: usize = BANNER_START + BANNER_SIZE + PROMPT_SIZE;
+/// Offset of the settings page from the start of flash.
+pub const SETTINGS_OFFSET: u32 = 127 * 1024;
+const SETTINGS_PAGE_SIZE: usize = 1024;
+const SETTINGS_MAGIC: u32 = 0x5E77_0002;
+const FLAGS: usize = 6;
+const FLAG_INVERT_LEDS: u8 = 0x01;
+const BANNER_START: usize = 8;
+const PROMPT_START: usize = BANNER_START + BANNER_SIZE;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Settings {
+    pub banner: String<BANNER_SIZE>,
+    pub prompt: String<PROMPT_SIZE>,
+    /// Every LED output inverted, for LEDs wired active low.
+    pub invert_leds: bool,
+}
+
+impl Settings {
+    pub fn to_bytes(&self) -> [u8; SETTINGS_SIZE] {
+        let mut bytes = [0; SETTINGS_SIZE];
+        bytes[..4].copy_from_slice(&SETTINGS_MAGIC.to_le_bytes());
+        bytes[4] = self.banner.len() as u8;
+        bytes[5] = self.prompt.len() as u8;
+        if self.invert_leds {
+            bytes[FLAGS] |= FLAG_INVERT_LEDS;
+        }
+        bytes[BANNER_START..BANNER_START + self.banner.len()]
+            .copy_from_slice(self.banner.as_bytes());
+        bytes[PROMPT_START..PROMPT_START + self.prompt.len()]
+            .copy_from_slice(self.prompt.as_bytes());
+        bytes
+    }
+
+    /// Decode a stored record. Returns `None` if the magic number is missing, or a
+    /// length or string is invalid, as for erased or corrupted flash.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Settings> {
+        if bytes.len() < SETTINGS_SIZE || bytes[..4] != SETTINGS_MAGIC.to_le_bytes() {
+            return None;
+        }
+        let banner_len = bytes[4] as usize;
+        let prompt_len = bytes[5] as usize;
+        let flags = bytes[FLAGS];
+        if BANNER_SIZE < banner_len || PROMPT_SIZE < prompt_len || 0 != flags & !FLAG_INVERT_LEDS {
+            return None;
+        }
+        let banner = bytes_to_string(&bytes[BANNER_START..BANNER_START + banner_len])?;
+        let prompt = bytes_to_string(&bytes[PROMPT_START..PROMPT_START + prompt_len])?;
+        Some(Settings {
+            banner,
+            prompt,
+            invert_leds: 0 != flags & FLAG_INVERT_LEDS,
+        })
+    }
+}
+
+fn bytes_to_string<const N: usize>(bytes: &[u8]) -> Option<String<N>> {
+    let mut string = String::new();
+    string.push_str(core::str::from_utf8(bytes).ok()?).ok()?;
+    Some(string)
+}
+
+/// Read the saved settings, if there are any.
+pub fn load_settings(writer: &FlashWriter) -> Option<Settings> {
+    let bytes = writer.read(SETTINGS_OFFSET, SETTINGS_SIZE).ok()?;
+    Settings::from_bytes(bytes)
+}
+
+/// Erase the settings page and write `settings` to it.
+pub fn save_settings(writer: &mut FlashWriter, settings: &Settings) -> Result<(), Error> {
+    writer.erase(SETTINGS_OFFSET, SETTINGS_PAGE_SIZE)?;
+    writer.write(SETTINGS_OFFSET, &settings.to_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(banner: &str, prompt: &str, invert_leds: bool) -> Settings {
+        Settings {
+            banner: String::try_from(banner).unwrap(),
+            prompt: String::try_from(prompt).unwrap(),
+            invert_leds,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for original in [
+            Settings::default(),
+            settings("Hello!", "$ ", false),
+            settings("", "", true),
+            settings(&"b".repeat(BANNER_SIZE), &"p".repeat(PROMPT_SIZE), true),
+        ] {
+            assert_eq!(Settings::from_bytes(&original.to_bytes()), Some(original));
+        }
+    }
+
+    #[test]
+    fn layout() {
+        let bytes = settings("Hi", ">", true).to_bytes();
+        assert_eq!(SETTINGS_SIZE % 2, 0);
+        assert_eq!(bytes[..4], SETTINGS_MAGIC.to_le_bytes());
+        assert_eq!(bytes[4..BANNER_START], [2, 1, FLAG_INVERT_LEDS, 0]);
+        assert_eq!(&bytes[BANNER_START..BANNER_START + 3], b"Hi\0");
+        assert_eq!(&bytes[PROMPT_START..PROMPT_START + 2], b">\0");
+    }
+
+    #[test]
+    fn erased_flash_is_rejected() {
+        assert_eq!(Settings::from_bytes(&[0xFF; SETTINGS_SIZE]), None);
+        assert_eq!(Settings::from_bytes(&[0; SETTINGS_SIZE]), None);
+    }
+
+    #[test]
+    fn corrupted_records_are_rejected() {
+        let good = settings("Hi", ">", false).to_bytes();
+        assert_eq!(Settings::from_bytes(&good[..SETTINGS_SIZE - 1]), None);
+        let corrupt = |index: usize, value: u8| {
+            let mut bytes = good;
+            bytes[index] = value;
+            Settings::from_bytes(&bytes)
+        };
+        // An older record version.
+        assert_eq!(corrupt(0, 0x01), None);
+        assert_eq!(corrupt(4, BANNER_SIZE as u8 + 1), None);
+        assert_eq!(corrupt(5, PROMPT_SIZE as u8 + 1), None);
+        assert_eq!(corrupt(FLAGS, 0x02), None);
+        // Invalid UTF-8 in the banner.
+        assert_eq!(corrupt(BANNER_START, 0xFF), None);
+    }
+}
@@ -0,0 +1,55 @@
+// src/shared.rs
+
+//! State shared between interrupt handlers and the main loop, without `unsafe`.
+//!
+//! `Shared<T>` wraps `cortex_m::interrupt::Mutex<RefCell<T>>`, and every access
+//! runs inside `cortex_m::interrupt::free`. Keep the following in mind.
+//!
+//! - Interrupts are disabled for as long as the closure passed to `with` runs,
+//!   so keep it short to avoid delaying other interrupts.
+//! - Calling `with` on the same `Shared` from inside its own closure panics,
+//!   because the `RefCell` is already borrowed.
+//! - The critical section only covers a single core, which is all the
+//!   STM32F103 has.
+//!
+//! Peripherals that are moved into an interrupt handler after setup are usually
+//! stored as `Shared<Option<T>>`, as follows.
+//!
+//! ```ignore
+//! static G_LED: Shared<Option<ErasedPin<Output>>> = Shared::new(None);
+//!
+//! G_LED.lock_set(Some(led));
+//! G_LED.with(|led| led.as_mut().map(|led| led.toggle()));
+//! ```
+
+use core::cell::RefCell;
+use cortex_m::interrupt::{self, Mutex};
+
+pub struct Shared<T> {
+    inner: Mutex<RefCell<T>>,
+}
+
+impl<T> Shared<T> {
+    pub const fn new(value: T) -> Self {
+        Shared {
+            inner: Mutex::new(RefCell::new(value)),
+        }
+    }
+
+    /// Run `f` with exclusive access to the value, inside a critical section.
+    pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        interrupt::free(|cs| f(&mut self.inner.borrow(cs).borrow_mut()))
+    }
+
+    /// Replace the value.
+    pub fn lock_set(&self, value: T) {
+        self.with(|shared| *shared = value);
+    }
+}
+
+impl<T: Copy> Shared<T> {
+    /// Copy the value out.
+    pub fn lock_get(&self) -> T {
+        self.with(|shared| *shared)
+    }
+}
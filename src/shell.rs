@@ -0,0 +1,237 @@
+// src/shell.rs
+
+//! Command parsing for the `shell` example.
+//!
+//! `dispatch` turns a command line into a `ShellResponse` without touching any
+//! hardware, so every command can be checked on the host, and the example only
+//! carries the response out. Parsing is allocation free, and text arguments
+//! borrow straight from the line.
+//!
+//! `PulseSchedule` times the pulse train that `pulse` starts, on the `millis()`
+//! time base, with the rising edges on whole multiples of the period.
+
+use crate::parse::{parse_clamped, ClampedArg};
+
+const PULSE_PERIOD_MIN_MS: u32 = 2;
+const PULSE_PERIOD_MAX_MS: u32 = 60_000;
+const PULSE_USAGE: &str = "Usage: pulse <high ms> <period ms>, or pulse off";
+
+/// What the shell should do for one command line.
+#[derive(Debug, PartialEq)]
+pub enum ShellResponse<'a> {
+    Empty,
+    Help,
+    Led(bool),
+    Echo(&'a str),
+    Clock,
+    Reset,
+    SetBanner(&'a str),
+    SetPrompt(&'a str),
+    Pulse {
+        high_ms: ClampedArg,
+        period_ms: ClampedArg,
+    },
+    PulseOff,
+    /// Usage line for a command that was not understood.
+    Usage(&'static str),
+}
+
+/// Parse the arguments of `pulse`. The period is clamped first, then the high
+/// time is clamped to leave at least 1 ms low.
+fn parse_pulse(args: &str) -> Option<ShellResponse<'static>> {
+    let (high, period) = args.split_once(' ')?;
+    let period_ms = parse_clamped(period, PULSE_PERIOD_MIN_MS, PULSE_PERIOD_MAX_MS).ok()?;
+    let high_ms = parse_clamped(high, 1, period_ms.value - 1).ok()?;
+    Some(ShellResponse::Pulse { high_ms, period_ms })
+}
+
+/// Turn a command line into the response the shell should carry out.
+pub fn dispatch(line: &str) -> ShellResponse<'_> {
+    let line = line.trim();
+    let (command, args) = match line.split_once(' ') {
+        Some((command, args)) => (command, args.trim()),
+        None => (line, ""),
+    };
+    match (command, args) {
+        ("", _) => ShellResponse::Empty,
+        ("help", "") => ShellResponse::Help,
+        ("led", "on") => ShellResponse::Led(true),
+        ("led", "off") => ShellResponse::Led(false),
+        ("led", _) => ShellResponse::Usage("Usage: led on|off"),
+        ("echo", text) => ShellResponse::Echo(text),
+        ("clock", "") => ShellResponse::Clock,
+        ("reset", "") => ShellResponse::Reset,
+        ("banner", text) => ShellResponse::SetBanner(text),
+        ("prompt", text) => ShellResponse::SetPrompt(text),
+        ("pulse", "off") => ShellResponse::PulseOff,
+        ("pulse", args) => parse_pulse(args).unwrap_or(ShellResponse::Usage(PULSE_USAGE)),
+        _ => ShellResponse::Usage("Unknown command, type help for a list."),
+    }
+}
+
+/// A pulse train on the `millis()` time base, high for `high_ms` at the start of
+/// every period.
+#[derive(Clone, Copy, Debug)]
+pub struct PulseSchedule {
+    start_ms: u32,
+    high_ms: u32,
+    period_ms: u32,
+}
+
+impl PulseSchedule {
+    /// The first rising edge is the first whole multiple of the period at least
+    /// `lead_ms` after `now_ms`.
+    pub fn new(now_ms: u32, lead_ms: u32, high_ms: u32, period_ms: u32) -> Self {
+        let earliest_ms = now_ms.wrapping_add(lead_ms);
+        let start_ms = earliest_ms.div_ceil(period_ms).wrapping_mul(period_ms);
+        PulseSchedule {
+            start_ms,
+            high_ms,
+            period_ms,
+        }
+    }
+
+    pub fn high_ms(&self) -> u32 {
+        self.high_ms
+    }
+
+    pub fn period_ms(&self) -> u32 {
+        self.period_ms
+    }
+
+    /// The time of rising edge `n`, counting from 0. The falling edge follows
+    /// `high_ms` later.
+    pub fn rising_edge_ms(&self, n: u32) -> u32 {
+        self.start_ms.wrapping_add(n.wrapping_mul(self.period_ms))
+    }
+
+    /// The output level at `now_ms`, low until the first rising edge.
+    pub fn level(&self, now_ms: u32) -> bool {
+        let elapsed_ms = now_ms.wrapping_sub(self.start_ms);
+        // Times up to half the range of a `u32` before the start count as early.
+        (elapsed_ms as i32) >= 0 && elapsed_ms % self.period_ms < self.high_ms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_without_arguments() {
+        assert_eq!(dispatch(""), ShellResponse::Empty);
+        assert_eq!(dispatch("   "), ShellResponse::Empty);
+        assert_eq!(dispatch("help"), ShellResponse::Help);
+        assert_eq!(dispatch("clock"), ShellResponse::Clock);
+        assert_eq!(dispatch(" reset \t"), ShellResponse::Reset);
+    }
+
+    #[test]
+    fn led_command() {
+        assert_eq!(dispatch("led on"), ShellResponse::Led(true));
+        assert_eq!(dispatch("led   off "), ShellResponse::Led(false));
+        assert_eq!(dispatch("led"), ShellResponse::Usage("Usage: led on|off"));
+        assert_eq!(
+            dispatch("led dim"),
+            ShellResponse::Usage("Usage: led on|off")
+        );
+    }
+
+    #[test]
+    fn text_arguments_are_borrowed() {
+        assert_eq!(
+            dispatch("echo  hello  world "),
+            ShellResponse::Echo("hello  world")
+        );
+        assert_eq!(dispatch("echo"), ShellResponse::Echo(""));
+        assert_eq!(
+            dispatch("banner Hi there"),
+            ShellResponse::SetBanner("Hi there")
+        );
+        assert_eq!(dispatch("prompt $"), ShellResponse::SetPrompt("$"));
+    }
+
+    #[test]
+    fn unknown_commands() {
+        let unknown = ShellResponse::Usage("Unknown command, type help for a list.");
+        assert_eq!(dispatch("helo"), unknown);
+        assert_eq!(dispatch("help me"), unknown);
+        assert_eq!(dispatch("HELP"), unknown);
+    }
+
+    #[test]
+    fn pulse_command() {
+        let pulse = |high, period| ShellResponse::Pulse {
+            high_ms: ClampedArg {
+                value: high,
+                clamped: false,
+            },
+            period_ms: ClampedArg {
+                value: period,
+                clamped: false,
+            },
+        };
+        assert_eq!(dispatch("pulse 10 100"), pulse(10, 100));
+        assert_eq!(dispatch("pulse off"), ShellResponse::PulseOff);
+        for line in ["pulse", "pulse 10", "pulse x 100", "pulse 10 y"] {
+            assert_eq!(
+                dispatch(line),
+                ShellResponse::Usage(PULSE_USAGE),
+                "{:?}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn pulse_arguments_are_clamped() {
+        let ShellResponse::Pulse { high_ms, period_ms } = dispatch("pulse 500 1") else {
+            panic!("not a pulse");
+        };
+        // The period is clamped first, and the high time leaves 1 ms low.
+        assert_eq!(
+            (period_ms.value, period_ms.clamped),
+            (PULSE_PERIOD_MIN_MS, true)
+        );
+        assert_eq!((high_ms.value, high_ms.clamped), (1, true));
+        let ShellResponse::Pulse { high_ms, period_ms } = dispatch("pulse 0 100000") else {
+            panic!("not a pulse");
+        };
+        assert_eq!(
+            (period_ms.value, period_ms.clamped),
+            (PULSE_PERIOD_MAX_MS, true)
+        );
+        assert_eq!((high_ms.value, high_ms.clamped), (1, true));
+    }
+
+    #[test]
+    fn pulses_start_on_a_whole_period() {
+        let schedule = PulseSchedule::new(1_234, 100, 10, 250);
+        assert_eq!(schedule.rising_edge_ms(0), 1_500);
+        assert_eq!(schedule.rising_edge_ms(2), 2_000);
+        assert!(!schedule.level(1_499));
+        assert!(schedule.level(1_500));
+        assert!(schedule.level(1_509));
+        assert!(!schedule.level(1_510));
+        assert!(schedule.level(1_750));
+        // An edge exactly `lead_ms` away is early enough.
+        assert_eq!(
+            PulseSchedule::new(900, 100, 10, 250).rising_edge_ms(0),
+            1_000
+        );
+    }
+
+    #[test]
+    fn pulses_before_the_start_are_low() {
+        let schedule = PulseSchedule::new(0, 0, 1, 2);
+        assert!(schedule.level(0));
+        assert!(!schedule.level(1));
+        assert!(schedule.level(2));
+        // Times well before the start count as early, even across the wrap.
+        let schedule = PulseSchedule::new(500, 0, 5, 1_000);
+        assert_eq!(schedule.rising_edge_ms(0), 1_000);
+        assert!(!schedule.level(0));
+        assert!(!schedule.level(1_000u32.wrapping_sub(1 << 30)));
+        assert!(schedule.level(1_000 + 7 * 1_000));
+    }
+}
@@ -0,0 +1,84 @@
+// src/sht3x.rs
+
+//! Data checks and conversions for the Sensirion SHT3x temperature and humidity
+//! sensor.
+//!
+//! The sensor sends each 16-bit word followed by a CRC-8 of it. Values are kept
+//! in hundredths of a degree Celsius and of a percent, so the datasheet
+//! conversion formulas run in integer arithmetic.
+
+const SHT3X_CRC_POLYNOMIAL: u8 = 0x31;
+const SHT3X_CRC_INIT: u8 = 0xFF;
+
+/// CRC-8 used by Sensirion sensors, with polynomial 0x31 and initial value 0xFF.
+/// The datasheet example is `sht3x_crc(&[0xBE, 0xEF]) == 0x92`.
+pub fn sht3x_crc(data: &[u8]) -> u8 {
+    let mut crc = SHT3X_CRC_INIT;
+    for byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = match crc & 0x80 {
+                0 => crc << 1,
+                _ => (crc << 1) ^ SHT3X_CRC_POLYNOMIAL,
+            };
+        }
+    }
+    crc
+}
+
+/// Temperature in hundredths of a degree Celsius, `-45 + 175 * raw / 65535`.
+pub fn convert_temp(raw: u16) -> i32 {
+    -4_500 + (17_500 * raw as i32) / u16::MAX as i32
+}
+
+/// Relative humidity in hundredths of a percent, `100 * raw / 65535`.
+pub fn convert_humidity(raw: u16) -> u16 {
+    ((10_000 * raw as u32) / u16::MAX as u32) as u16
+}
+
+/// Check the CRC of a data word and its CRC byte, as sent by the sensor.
+/// Returns `None` if it does not match, or if `bytes` is not three bytes long.
+pub fn checked_word(bytes: &[u8]) -> Option<u16> {
+    match bytes {
+        [msb, lsb, crc] if sht3x_crc(&[*msb, *lsb]) == *crc => {
+            Some(u16::from_be_bytes([*msb, *lsb]))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc_datasheet_example() {
+        assert_eq!(sht3x_crc(&[0xBE, 0xEF]), 0x92);
+        assert_eq!(sht3x_crc(&[]), SHT3X_CRC_INIT);
+    }
+
+    #[test]
+    fn checked_words() {
+        assert_eq!(checked_word(&[0xBE, 0xEF, 0x92]), Some(0xBEEF));
+        assert_eq!(checked_word(&[0xBE, 0xEF, 0x93]), None);
+        assert_eq!(checked_word(&[0xBF, 0xEF, 0x92]), None);
+        assert_eq!(checked_word(&[0xBE, 0xEF]), None);
+    }
+
+    #[test]
+    fn temperature_range() {
+        assert_eq!(convert_temp(0), -4_500);
+        assert_eq!(convert_temp(u16::MAX), 13_000);
+        // About 25C, and the conversion truncates.
+        assert_eq!(convert_temp(0x6666), 2_500);
+        assert_eq!(convert_temp(3), -4_500);
+        assert_eq!(convert_temp(4), -4_499);
+    }
+
+    #[test]
+    fn humidity_range() {
+        assert_eq!(convert_humidity(0), 0);
+        assert_eq!(convert_humidity(u16::MAX), 10_000);
+        assert_eq!(convert_humidity(0x8000), 5_000);
+    }
+}
@@ -0,0 +1,153 @@
+// src/soft_pwm.rs
+
+//! Software PWM, for dimming LEDs on pins without a timer channel.
+//!
+//! A phase counter advances by `step` every time `update` is called, and the output
+//! is on while the phase is below the duty. The PWM period is `256 / step` calls, so
+//! the main loop has to call `update` often enough for that to be well above the
+//! roughly 100Hz where flicker becomes visible. A larger step gives a shorter
+//! period, but fewer distinct brightness levels.
+//!
+//! Unlike timer PWM, the output jitters whenever the main loop is busy, which is
+//! fine for LED brightness but not for anything that needs an exact duty cycle.
+//!
+//! Perceived brightness is far from linear in the duty cycle. Going from 10% to
+//! 20% duty looks like a big step, while 80% and 90% look almost the same. Pass a
+//! perceptual level through `gamma_correct` first, so equal steps in level look
+//! like equal steps in brightness.
+
+/// Whether the output is on at `phase`, for a duty cycle of `duty / 256`.
+/// A duty of 0 is always off, and a duty of `u8::MAX` is always on.
+pub fn soft_pwm_level(phase: u8, duty: u8) -> bool {
+    u8::MAX == duty || phase < duty
+}
+
+/// Duty for each perceptual level, from the CIE 1976 lightness formula, which
+/// relates lightness L* from 0 to 100 to luminance Y. It gives Y = L* / 903.3 up
+/// to L* = 8, and Y = ((L* + 16) / 116)^3 above.
+static GAMMA_TABLE: [u8; 256] = gamma_table();
+
+const fn gamma_table() -> [u8; 256] {
+    // With L* = 100 * level / 255, both pieces are scaled to whole numbers.
+    const LINEAR_DENOMINATOR: u64 = 9_033;
+    const CUBE_ROOT_DENOMINATOR: u64 = 116 * 255;
+    const CUBE_DENOMINATOR: u64 =
+        CUBE_ROOT_DENOMINATOR * CUBE_ROOT_DENOMINATOR * CUBE_ROOT_DENOMINATOR;
+    let mut table = [0; 256];
+    let mut level = 0;
+    while level < table.len() {
+        let l = level as u64;
+        let duty = match 100 * l <= 8 * 255 {
+            true => (1_000 * l + LINEAR_DENOMINATOR / 2) / LINEAR_DENOMINATOR,
+            false => {
+                let root = 100 * l + 16 * 255;
+                (root * root * root * 255 + CUBE_DENOMINATOR / 2) / CUBE_DENOMINATOR
+            }
+        };
+        table[level] = duty as u8;
+        level += 1;
+    }
+    table
+}
+
+/// The duty that makes perceptual brightness `level` of 255 look right.
+/// Level 0 is off and level 255 is full on.
+pub fn gamma_correct(level: u8) -> u8 {
+    GAMMA_TABLE[level as usize]
+}
+
+pub struct SoftPwm {
+    phase: u8,
+    step: u8,
+    duty: u8,
+}
+
+impl SoftPwm {
+    pub const fn new(step: u8, duty: u8) -> Self {
+        SoftPwm {
+            phase: 0,
+            step,
+            duty,
+        }
+    }
+
+    pub fn duty(&self) -> u8 {
+        self.duty
+    }
+
+    pub fn set_duty(&mut self, duty: u8) {
+        self.duty = duty;
+    }
+
+    /// Advance the phase, and return whether the output is on.
+    pub fn update(&mut self) -> bool {
+        self.phase = self.phase.wrapping_add(self.step);
+        soft_pwm_level(self.phase, self.duty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Phases in one period at which the output is on at `duty`.
+    fn on_phases(duty: u8) -> usize {
+        (0..=u8::MAX)
+            .filter(|&phase| soft_pwm_level(phase, duty))
+            .count()
+    }
+
+    #[test]
+    fn level_follows_the_duty() {
+        assert_eq!(on_phases(0), 0);
+        assert_eq!(on_phases(1), 1);
+        assert_eq!(on_phases(128), 128);
+        assert_eq!(on_phases(254), 254);
+        // The largest duty is always on, not 255 of 256.
+        assert_eq!(on_phases(u8::MAX), 256);
+    }
+
+    #[test]
+    fn update_steps_the_phase() {
+        // A step of 64 gives a period of 4 updates.
+        let mut pwm = SoftPwm::new(64, 128);
+        let levels: [bool; 8] = core::array::from_fn(|_| pwm.update());
+        assert_eq!(levels, [true, false, false, true, true, false, false, true]);
+        pwm.set_duty(0);
+        assert_eq!(pwm.duty(), 0);
+        assert!((0..4).all(|_| !pwm.update()));
+    }
+
+    #[test]
+    fn gamma_spans_the_whole_range() {
+        assert_eq!(gamma_correct(0), 0);
+        assert_eq!(gamma_correct(u8::MAX), u8::MAX);
+        assert!((1..=u8::MAX).all(|level| gamma_correct(level - 1) <= gamma_correct(level)));
+    }
+
+    #[test]
+    fn gamma_matches_the_cie_formula() {
+        for level in 0..=u8::MAX {
+            let lightness = 100.0 * level as f64 / 255.0;
+            let luminance = match lightness <= 8.0 {
+                true => lightness / 903.3,
+                false => ((lightness + 16.0) / 116.0).powi(3),
+            };
+            let expected = (255.0 * luminance).round() as u8;
+            assert!(
+                gamma_correct(level).abs_diff(expected) <= 1,
+                "level {}, duty {}, expected {}",
+                level,
+                gamma_correct(level),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn gamma_is_darker_than_linear() {
+        // Half the perceived brightness is under a fifth of the duty.
+        assert!(gamma_correct(128) < 255 / 5);
+        assert!((1..u8::MAX).all(|level| gamma_correct(level) <= level));
+    }
+}
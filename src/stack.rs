@@ -0,0 +1,77 @@
+// src/stack.rs
+
+//! Stack usage estimation by painting the unused stack with a known pattern.
+//!
+//! The `cortex-m-rt` linker script places the stack at the top of RAM. It grows
+//! down from `_stack_start`, the end of RAM, toward `_stack_end`, which is just
+//! above the statically allocated `.data`, `.bss` and `.uninit` sections. There is
+//! no heap, so everything between those two symbols is available to the stack.
+//! Nothing checks for overflow, so a stack that grows past `_stack_end` silently
+//! corrupts static variables.
+//!
+//! Call `paint_stack` once at boot, before interrupts are enabled. It fills the
+//! unused part of the stack with `STACK_PAINT`. Later, `unused_stack_words` counts
+//! how many words at the bottom of the stack still hold the pattern, which is the
+//! stack that has never been used since painting. The estimate can be slightly
+//! optimistic, because a stack frame may reserve words without writing them, or
+//! happen to write the pattern itself.
+//!
+//! The linker symbols are only available as extern statics, and the stack is
+//! accessed through raw pointers, so `unsafe` is allowed in this module only.
+
+#![allow(unsafe_code)]
+
+use core::ptr;
+
+/// The pattern painted into unused stack words.
+pub const STACK_PAINT: u32 = 0xC0DE_C0DE;
+
+/// Words just below the current stack pointer that are left unpainted, so the
+/// frames of `paint_stack` itself and anything it calls are never overwritten.
+const PAINT_MARGIN_WORDS: usize = 16;
+
+extern "C" {
+    static _stack_start: u32;
+    static _stack_end: u32;
+}
+
+/// Lowest and one past the highest word address of the stack region.
+fn stack_bounds() -> (*mut u32, *mut u32) {
+    // Only the addresses of the linker symbols are taken, they are never read.
+    (
+        ptr::addr_of!(_stack_end) as *mut u32,
+        ptr::addr_of!(_stack_start) as *mut u32,
+    )
+}
+
+/// Size of the whole stack region in bytes.
+pub fn stack_size() -> usize {
+    let (bottom, top) = stack_bounds();
+    top as usize - bottom as usize
+}
+
+/// Fill the stack below the current stack pointer with `STACK_PAINT`, and return
+/// the number of words painted. Call this before interrupts are enabled, because
+/// an interrupt handler's frame below the stack pointer would be overwritten.
+pub fn paint_stack() -> usize {
+    let (bottom, _) = stack_bounds();
+    let words = (cortex_m::register::msp::read() as usize).saturating_sub(bottom as usize) / 4;
+    let words = words.saturating_sub(PAINT_MARGIN_WORDS);
+    for offset in 0..words {
+        // SAFETY: Every word is inside the stack region, below the current stack
+        // and its margin, so it is not in use.
+        unsafe { ptr::write_volatile(bottom.add(offset), STACK_PAINT) };
+    }
+    words
+}
+
+/// Count the words from the bottom of the stack up that still hold `STACK_PAINT`.
+/// The scan stops at the first overwritten word, or at the top of the stack.
+pub fn unused_stack_words() -> usize {
+    let (bottom, top) = stack_bounds();
+    let words = (top as usize - bottom as usize) / 4;
+    // SAFETY: Every word read is inside the stack region, and RAM is always readable.
+    (0..words)
+        .take_while(|offset| unsafe { ptr::read_volatile(bottom.add(*offset)) } == STACK_PAINT)
+        .count()
+}
@@ -0,0 +1,291 @@
+// src/sysex.rs
+
+//! Binary configuration frames, modeled on MIDI system exclusive messages, for
+//! setting `serial_led_control` from a script without typing commands.
+//!
+//! Frames are laid out as follows.
+//!   SYSEX_START, command, length, payload[length], checksum, SYSEX_END
+//! All bytes between the start and end markers are 7-bit, so the markers can
+//! never appear inside a frame. The checksum is chosen so that the 7-bit sum of
+//! the command, length, payload and checksum bytes is zero.
+//!
+//! In the other direction, `led_event` reports LED changes as MIDI note messages.
+
+use heapless::Vec;
+
+pub const SYSEX_START: u8 = 0xF0;
+pub const SYSEX_END: u8 = 0xF7;
+pub const SYSEX_DATA_MASK: u8 = 0x7F;
+pub const SYSEX_PAYLOAD_MAX: usize = 16;
+const SYSEX_FRAME_MAX: usize = SYSEX_PAYLOAD_MAX + 5;
+pub const SYSEX_SET_ENABLES: u8 = 0x01;
+// Bit assignments for the SYSEX_SET_ENABLES payload byte.
+pub const SYSEX_STATIC_BIT: u8 = 0x01;
+pub const SYSEX_BLINK_BIT: u8 = 0x02;
+pub const SYSEX_STROBE_BIT: u8 = 0x04;
+pub const SYSEX_CONTROLLED_BIT: u8 = 0x08;
+pub const SYSEX_INVERSION_BIT: u8 = 0x10;
+
+#[derive(Debug, PartialEq)]
+pub enum SysexMsg {
+    SetEnables(u8), // Bitmask of SYSEX_*_BIT flags
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SysexError {
+    MissingStart,
+    MissingEnd,
+    Truncated,
+    BadLength,
+    BadData,
+    BadChecksum,
+    UnknownCommand(u8),
+}
+
+pub fn sysex_checksum(data: &[u8]) -> u8 {
+    let sum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    sum.wrapping_neg() & SYSEX_DATA_MASK
+}
+
+pub fn parse_sysex(bytes: &[u8]) -> Result<SysexMsg, SysexError> {
+    match bytes.first() {
+        Some(&SYSEX_START) => (),
+        Some(_) => return Err(SysexError::MissingStart),
+        None => return Err(SysexError::Truncated),
+    }
+    // Start, command, length, checksum and end bytes.
+    if bytes.len() < 5 {
+        return Err(SysexError::Truncated);
+    }
+    let length = bytes[2] as usize;
+    if SYSEX_PAYLOAD_MAX < length {
+        return Err(SysexError::BadLength);
+    }
+    if bytes.len() < length + 5 {
+        return Err(SysexError::Truncated);
+    }
+    if bytes.len() > length + 5 || bytes[length + 4] != SYSEX_END {
+        return Err(SysexError::MissingEnd);
+    }
+    let body = &bytes[1..length + 3];
+    let checksum = bytes[length + 3];
+    if body
+        .iter()
+        .chain([checksum].iter())
+        .any(|b| SYSEX_DATA_MASK < *b)
+    {
+        return Err(SysexError::BadData);
+    }
+    if sysex_checksum(body) != checksum {
+        return Err(SysexError::BadChecksum);
+    }
+    let payload = &body[2..];
+    match (body[0], payload) {
+        (SYSEX_SET_ENABLES, [mask]) => Ok(SysexMsg::SetEnables(*mask)),
+        (SYSEX_SET_ENABLES, _) => Err(SysexError::BadLength),
+        (command, _) => Err(SysexError::UnknownCommand(command)),
+    }
+}
+
+/// Collects bytes from SYSEX_START through SYSEX_END into a single frame.
+pub struct SysexAssembler {
+    frame: Vec<u8, SYSEX_FRAME_MAX>,
+    receiving: bool,
+}
+
+impl SysexAssembler {
+    pub fn new() -> Self {
+        SysexAssembler {
+            frame: Vec::new(),
+            receiving: false,
+        }
+    }
+
+    pub fn is_receiving(&self) -> bool {
+        self.receiving
+    }
+
+    /// Returns the complete frame once the end byte arrives.
+    ///
+    /// A stray start byte must not swallow the text commands after it, so the frame
+    /// also ends early at any other byte above 7 bits, a length above
+    /// `SYSEX_PAYLOAD_MAX`, or a byte past the declared length that is not the end
+    /// byte. The frame so far is returned then, for `parse_sysex` to reject.
+    /// Another start byte starts the frame over.
+    pub fn feed(&mut self, byte: u8) -> Option<&[u8]> {
+        if SYSEX_START == byte {
+            self.frame.clear();
+            self.receiving = true;
+        }
+        if !self.receiving {
+            return None;
+        }
+        let _ = self.frame.push(byte);
+        let len = self.frame.len();
+        let ended = match (len, self.frame.get(2)) {
+            (1, _) => false,
+            _ if SYSEX_END == byte || SYSEX_DATA_MASK < byte => true,
+            (_, Some(&length)) => SYSEX_PAYLOAD_MAX < length as usize || length as usize + 5 <= len,
+            (_, None) => false,
+        };
+        if ended {
+            self.receiving = false;
+            return Some(&self.frame);
+        }
+        None
+    }
+}
+
+impl Default for SysexAssembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// LED events are modeled on MIDI note messages, so existing tools can read them.
+//   type, id, value
+// The type is note on or note off, the id is the LED's bit in the LED mask of
+// `serial_led_control`, and the value is the velocity, full for on and zero for off.
+pub const LED_EVENT_ON: u8 = 0x90;
+pub const LED_EVENT_OFF: u8 = 0x80;
+pub const LED_EVENT_VELOCITY: u8 = 0x7F;
+
+/// The event emitted when LED `id` turns on or off.
+pub fn led_event(id: u8, on: bool) -> [u8; 3] {
+    match on {
+        true => [LED_EVENT_ON, id & SYSEX_DATA_MASK, LED_EVENT_VELOCITY],
+        false => [LED_EVENT_OFF, id & SYSEX_DATA_MASK, 0],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A well formed frame carrying `payload` for `command`.
+    fn frame(command: u8, payload: &[u8]) -> Vec<u8, SYSEX_FRAME_MAX> {
+        let mut frame = Vec::new();
+        frame.push(SYSEX_START).unwrap();
+        frame.push(command).unwrap();
+        frame.push(payload.len() as u8).unwrap();
+        frame.extend_from_slice(payload).unwrap();
+        let checksum = sysex_checksum(&frame[1..]);
+        frame.push(checksum).unwrap();
+        frame.push(SYSEX_END).unwrap();
+        frame
+    }
+
+    #[test]
+    fn checksum_zeroes_the_sum() {
+        let body = [SYSEX_SET_ENABLES, 1, 0x7F];
+        let sum = body
+            .iter()
+            .fold(sysex_checksum(&body), |sum, byte| sum.wrapping_add(*byte));
+        assert_eq!(sum & SYSEX_DATA_MASK, 0);
+    }
+
+    #[test]
+    fn valid_frame() {
+        let mask = SYSEX_STATIC_BIT | SYSEX_STROBE_BIT;
+        assert_eq!(
+            parse_sysex(&frame(SYSEX_SET_ENABLES, &[mask])),
+            Ok(SysexMsg::SetEnables(mask))
+        );
+    }
+
+    #[test]
+    fn truncated_frames() {
+        let frame = frame(SYSEX_SET_ENABLES, &[SYSEX_BLINK_BIT]);
+        assert_eq!(parse_sysex(&[]), Err(SysexError::Truncated));
+        for len in 1..frame.len() {
+            assert_eq!(parse_sysex(&frame[..len]), Err(SysexError::Truncated));
+        }
+    }
+
+    #[test]
+    fn bad_checksum() {
+        let mut frame = frame(SYSEX_SET_ENABLES, &[SYSEX_BLINK_BIT]);
+        frame[4] ^= 0x01;
+        assert_eq!(parse_sysex(&frame), Err(SysexError::BadChecksum));
+    }
+
+    #[test]
+    fn malformed_frames() {
+        let mut frame = frame(SYSEX_SET_ENABLES, &[SYSEX_BLINK_BIT]);
+        assert_eq!(parse_sysex(&frame[1..]), Err(SysexError::MissingStart));
+        frame[5] = 0x00;
+        assert_eq!(parse_sysex(&frame), Err(SysexError::MissingEnd));
+        frame[5] = SYSEX_END;
+        frame[3] = 0x80;
+        assert_eq!(parse_sysex(&frame), Err(SysexError::BadData));
+    }
+
+    #[test]
+    fn wrong_lengths_and_commands() {
+        assert_eq!(
+            parse_sysex(&frame(SYSEX_SET_ENABLES, &[1, 2])),
+            Err(SysexError::BadLength)
+        );
+        assert_eq!(
+            parse_sysex(&frame(0x42, &[1])),
+            Err(SysexError::UnknownCommand(0x42))
+        );
+        let mut too_long = frame(SYSEX_SET_ENABLES, &[1]);
+        too_long[2] = SYSEX_PAYLOAD_MAX as u8 + 1;
+        assert_eq!(parse_sysex(&too_long), Err(SysexError::BadLength));
+    }
+
+    /// Feed `bytes`, and return the frames that came out, each parsed.
+    fn feed_all(
+        assembler: &mut SysexAssembler,
+        bytes: &[u8],
+    ) -> Vec<Result<SysexMsg, SysexError>, 4> {
+        let mut results = Vec::new();
+        for byte in bytes {
+            if let Some(frame) = assembler.feed(*byte) {
+                results.push(parse_sysex(frame)).unwrap();
+            }
+        }
+        results
+    }
+
+    #[test]
+    fn assembler_collects_a_frame() {
+        let mut assembler = SysexAssembler::new();
+        assert!(feed_all(&mut assembler, b"ab").is_empty());
+        let results = feed_all(&mut assembler, &frame(SYSEX_SET_ENABLES, &[3]));
+        assert_eq!(results[..], [Ok(SysexMsg::SetEnables(3))]);
+        assert!(!assembler.is_receiving());
+    }
+
+    #[test]
+    fn assembler_gives_up_on_a_stray_start() {
+        let mut assembler = SysexAssembler::new();
+        // A stray start byte, then a text command, whose second letter reads as a
+        // length far above the maximum.
+        let mut bytes: Vec<u8, 8> = Vec::new();
+        bytes.push(SYSEX_START).unwrap();
+        bytes.extend_from_slice(b"help\r").unwrap();
+        let results = feed_all(&mut assembler, &bytes);
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+        assert!(!assembler.is_receiving());
+    }
+
+    #[test]
+    fn assembler_ends_at_a_high_byte() {
+        let mut assembler = SysexAssembler::new();
+        let results = feed_all(&mut assembler, &[SYSEX_START, SYSEX_SET_ENABLES, 0x90]);
+        assert_eq!(results[..], [Err(SysexError::Truncated)]);
+        assert!(!assembler.is_receiving());
+    }
+
+    #[test]
+    fn led_events_are_note_messages() {
+        assert_eq!(led_event(3, true), [0x90, 3, 0x7F]);
+        assert_eq!(led_event(3, false), [0x80, 3, 0]);
+        // The id is masked to 7 bits, so it never looks like a status byte.
+        assert_eq!(led_event(0x85, true), [0x90, 5, 0x7F]);
+        assert_eq!(led_event(0x85, false)[1], 5);
+    }
+}
@@ -0,0 +1,174 @@
+// src/tempo.rs
+
+//! Tap tempo, measuring the time between button presses to find a beat.
+//!
+//! `TempoMeter` is given raw button readings and the time, like `BounceMeter` in
+//! `pins`, and flashes along once it has a tempo.
+
+const MS_PER_MINUTE: u32 = 60_000;
+// Presses closer than this, 600 BPM, are contact bounce rather than beats.
+pub const TEMPO_MIN_INTERVAL_MS: u32 = 100;
+// A pause longer than this, 20 BPM, starts the measurement over.
+pub const TEMPO_MAX_INTERVAL_MS: u32 = 3_000;
+const TEMPO_FLASH_MS: u32 = 50;
+
+/// Beats per minute for `interval_ms` between beats, rounded to the nearest beat.
+/// Intervals shorter than `TEMPO_MIN_INTERVAL_MS`, including zero, give 0.
+pub fn interval_to_bpm(interval_ms: u32) -> u32 {
+    match interval_ms {
+        0..TEMPO_MIN_INTERVAL_MS => 0,
+        interval_ms => (MS_PER_MINUTE + interval_ms / 2) / interval_ms,
+    }
+}
+
+/// Measures the time between button presses, for tapping along to a beat.
+pub struct TempoMeter {
+    running: bool,
+    last_level: bool,
+    last_press_ms: Option<u32>,
+    interval_ms: Option<u32>,
+}
+
+impl TempoMeter {
+    pub fn new() -> Self {
+        TempoMeter {
+            running: false,
+            last_level: false,
+            last_press_ms: None,
+            interval_ms: None,
+        }
+    }
+
+    /// Start measuring, from the current button level.
+    pub fn start(&mut self, button_down: bool) {
+        self.running = true;
+        self.last_level = button_down;
+        self.last_press_ms = None;
+        self.interval_ms = None;
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Sample the button. Returns the interval since the previous press, when a
+    /// press follows it closely enough to count as the next beat.
+    pub fn update(&mut self, now_ms: u32, button_down: bool) -> Option<u32> {
+        let pressed = button_down && !self.last_level;
+        self.last_level = button_down;
+        if !self.running || !pressed {
+            return None;
+        }
+        let Some(last_press_ms) = self.last_press_ms else {
+            self.last_press_ms = Some(now_ms);
+            return None;
+        };
+        let interval_ms = now_ms.wrapping_sub(last_press_ms);
+        if interval_ms < TEMPO_MIN_INTERVAL_MS {
+            return None;
+        }
+        self.last_press_ms = Some(now_ms);
+        self.interval_ms = Some(interval_ms).filter(|ms| *ms <= TEMPO_MAX_INTERVAL_MS);
+        self.interval_ms
+    }
+
+    /// Returns the LED level at `now_ms`, flashing on every beat of the measured
+    /// tempo, or `None` if no tempo has been measured.
+    pub fn flash(&self, now_ms: u32) -> Option<bool> {
+        match (self.running, self.last_press_ms, self.interval_ms) {
+            (true, Some(last_press_ms), Some(interval_ms)) => {
+                Some(now_ms.wrapping_sub(last_press_ms) % interval_ms < TEMPO_FLASH_MS)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for TempoMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bpm_from_intervals() {
+        assert_eq!(interval_to_bpm(500), 120);
+        assert_eq!(interval_to_bpm(1_000), 60);
+        assert_eq!(interval_to_bpm(TEMPO_MIN_INTERVAL_MS), 600);
+        assert_eq!(interval_to_bpm(TEMPO_MAX_INTERVAL_MS), 20);
+        // Rounded to the nearest beat, 60000 / 700 is 85.7.
+        assert_eq!(interval_to_bpm(700), 86);
+        assert_eq!(interval_to_bpm(TEMPO_MIN_INTERVAL_MS - 1), 0);
+        assert_eq!(interval_to_bpm(0), 0);
+    }
+
+    /// Press and release the button at `press_ms`, and return the interval.
+    fn tap(meter: &mut TempoMeter, press_ms: u32) -> Option<u32> {
+        let interval = meter.update(press_ms, true);
+        meter.update(press_ms + 10, false);
+        interval
+    }
+
+    #[test]
+    fn taps_measure_the_interval() {
+        let mut meter = TempoMeter::new();
+        // Nothing is measured until started.
+        assert_eq!(tap(&mut meter, 0), None);
+        meter.start(false);
+        assert!(meter.is_running());
+        assert_eq!(tap(&mut meter, 1_000), None);
+        assert_eq!(meter.flash(1_000), None);
+        assert_eq!(tap(&mut meter, 1_500), Some(500));
+        assert_eq!(tap(&mut meter, 2_000), Some(500));
+        // Holding the button down is not another beat.
+        assert_eq!(meter.update(2_500, true), Some(500));
+        assert_eq!(meter.update(3_000, true), None);
+        meter.stop();
+        assert_eq!(meter.flash(2_500), None);
+    }
+
+    #[test]
+    fn bounces_and_pauses() {
+        let mut meter = TempoMeter::new();
+        meter.start(false);
+        tap(&mut meter, 0);
+        // A press too soon after the last is ignored, and does not restart the interval.
+        assert_eq!(tap(&mut meter, 50), None);
+        assert_eq!(tap(&mut meter, 400), Some(400));
+        // A long pause is not a tempo, but the press still starts the next interval.
+        assert_eq!(tap(&mut meter, 10_000), None);
+        assert_eq!(meter.flash(10_000), None);
+        assert_eq!(tap(&mut meter, 10_300), Some(300));
+    }
+
+    #[test]
+    fn flashes_on_each_beat() {
+        let mut meter = TempoMeter::new();
+        meter.start(false);
+        tap(&mut meter, 0);
+        tap(&mut meter, 500);
+        assert_eq!(meter.flash(500), Some(true));
+        assert_eq!(meter.flash(500 + TEMPO_FLASH_MS - 1), Some(true));
+        assert_eq!(meter.flash(500 + TEMPO_FLASH_MS), Some(false));
+        assert_eq!(meter.flash(1_000), Some(true));
+        assert_eq!(meter.flash(1_250), Some(false));
+    }
+
+    #[test]
+    fn starting_with_the_button_down_waits_for_a_press() {
+        let mut meter = TempoMeter::new();
+        meter.start(true);
+        assert_eq!(meter.update(0, true), None);
+        assert_eq!(meter.update(10, false), None);
+        assert_eq!(tap(&mut meter, 100), None);
+        assert_eq!(tap(&mut meter, 600), Some(500));
+    }
+}
@@ -0,0 +1,423 @@
+// src/text.rs
+
+//! Text conversion modes applied to bytes echoed or forwarded over USART, and a few
+//! other helpers for text sent to a terminal.
+
+use core::fmt::Write;
+use heapless::String;
+
+const CASE_OFFSET: u8 = 0x20;
+/// Number of letters, and so of distinct ROT-N rotations.
+pub const ALPHABET_SIZE: u8 = 26;
+/// Bytes shown on each row, and so in each line, of a hex dump.
+pub const HEX_DUMP_ROW: usize = 16;
+/// The brackets, a space, and `100%` take 7 characters of a bar graph line.
+const BAR_GRAPH_LABEL: usize = 7;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TextMode {
+    NormalCase,
+    ForceUpper,
+    ForceLower,
+    InvertedCase,
+    Rot(u8), // Caesar cipher, rotating letters by the value, ROT13 is Rot(13)
+    Leet,    // Leetspeak, replacing letters with similar looking digits
+    HexDump, // Bytes shown as hex digits, formatted by the caller, bytes unchanged
+}
+
+impl TextMode {
+    /// The mode that follows this one when cycling with the user button.
+    /// The cipher needs a rotation, and hex dump mode treats every byte as data, so
+    /// they are only chosen by command, and cycling from them starts over.
+    pub fn next(self) -> TextMode {
+        match self {
+            TextMode::NormalCase => TextMode::ForceUpper,
+            TextMode::ForceUpper => TextMode::ForceLower,
+            TextMode::ForceLower => TextMode::InvertedCase,
+            TextMode::InvertedCase => TextMode::Leet,
+            TextMode::Leet | TextMode::Rot(_) | TextMode::HexDump => TextMode::NormalCase,
+        }
+    }
+}
+
+/// Decide the text mode for this pass through the main loop.
+///
+/// A serial command takes precedence over a button press in the same pass,
+/// because the command names an explicit mode while the button only cycles
+/// relative to the current mode. The button press is discarded in that case.
+/// Returns the resulting mode and whether it differs from `current`.
+pub fn resolve_mode(
+    current: TextMode,
+    serial_cmd: Option<TextMode>,
+    button_event: bool,
+) -> (TextMode, bool) {
+    let next = match (serial_cmd, button_event) {
+        (Some(text_mode), _) => text_mode,
+        // Button was just pressed. Cycle through the text modes.
+        (None, true) => current.next(),
+        (None, false) => current,
+    };
+    (next, next != current)
+}
+
+fn is_lowercase(c: u8) -> bool {
+    c.is_ascii_lowercase()
+}
+
+fn is_uppercase(c: u8) -> bool {
+    c.is_ascii_uppercase()
+}
+
+/// Rotate a letter `n` places through the alphabet, wrapping around within its
+/// case. Anything else is unchanged. Rotating by 0 or 26 is the identity.
+pub fn rot_n(c: u8, n: u8) -> u8 {
+    let base = match c {
+        b'a'..=b'z' => b'a',
+        b'A'..=b'Z' => b'A',
+        _ => return c,
+    };
+    base + (c - base + n % ALPHABET_SIZE) % ALPHABET_SIZE
+}
+
+/// The rotation in `0..ALPHABET_SIZE` that rotates letters the same as `n`, so 26
+/// is the identity and 27 is the same as 1.
+pub fn reduce_rotation(n: u32) -> u8 {
+    (n % ALPHABET_SIZE as u32) as u8
+}
+
+/// Replace a letter with the digit it looks like, in either case, so `leet`
+/// becomes `l337`. The letters are a, b, e, g, i, o, s and t. Anything else is unchanged.
+pub fn to_leet(c: u8) -> u8 {
+    match c.to_ascii_lowercase() {
+        b'a' => b'4',
+        b'b' => b'8',
+        b'e' => b'3',
+        b'g' => b'9',
+        b'i' => b'1',
+        b'o' => b'0',
+        b's' => b'5',
+        b't' => b'7',
+        _ => c,
+    }
+}
+
+pub fn convert_case(c: u8, text_mode: &TextMode) -> u8 {
+    match text_mode {
+        TextMode::Rot(n) => return rot_n(c, *n),
+        TextMode::Leet => return to_leet(c),
+        _ => (),
+    }
+    let mut result = c;
+    result += match text_mode {
+        TextMode::ForceLower | TextMode::InvertedCase if is_uppercase(c) => CASE_OFFSET,
+        _ => 0,
+    };
+    result -= match text_mode {
+        TextMode::ForceUpper | TextMode::InvertedCase if is_lowercase(c) => CASE_OFFSET,
+        _ => 0,
+    };
+    result
+}
+
+/// Maximum number of characters buffered for a line echoed in `text_mode`, out of
+/// a buffer that holds `buffer_size`.
+///
+/// The text modes echo one character per received character, so they all use the
+/// whole buffer. Hex dump mode expands every byte to three characters, and a line
+/// is one row of the dump. The match is exhaustive so that every new mode has to
+/// make this decision.
+pub fn effective_limit(text_mode: &TextMode, buffer_size: usize) -> usize {
+    match text_mode {
+        TextMode::NormalCase
+        | TextMode::ForceUpper
+        | TextMode::ForceLower
+        | TextMode::InvertedCase
+        | TextMode::Rot(_)
+        | TextMode::Leet => buffer_size,
+        TextMode::HexDump => HEX_DUMP_ROW,
+    }
+}
+
+/// Fill `out` with a ruler `width` columns wide, for checking terminal width and
+/// wrapping. Columns are numbered from 1, and every tenth column shows the tens
+/// digit of its column number instead of 0. `width` is limited to the capacity of
+/// `out`.
+pub fn ruler<const N: usize>(width: usize, out: &mut String<N>) {
+    out.clear();
+    for column in 1..=width.min(out.capacity()) {
+        let digit = match column % 10 {
+            0 => (column / 10) % 10,
+            ones => ones,
+        };
+        let _ = out.push((b'0' + digit as u8) as char);
+    }
+}
+
+/// Draw `value` out of `max` as a bar `width` characters wide, followed by the
+/// percentage, like `[#####     ]  50%`. The bar and the percentage are both
+/// rounded to the nearest step. A value above `max` is shown full, and a `max` of
+/// 0 as empty. The width is limited so the whole line fits in `out`.
+pub fn bar_graph<const N: usize>(value: u16, max: u16, width: usize, out: &mut String<N>) {
+    let width = width.min(N.saturating_sub(BAR_GRAPH_LABEL));
+    let (value, max) = match max {
+        0 => (0, 1),
+        max => (value.min(max) as u32, max as u32),
+    };
+    let filled = ((value * width as u32 + max / 2) / max) as usize;
+    let percent = (value * 100 + max / 2) / max;
+    out.clear();
+    let _ = out.push('[');
+    for i in 0..width {
+        let _ = out.push(if i < filled { '#' } else { ' ' });
+    }
+    let _ = write!(out, "] {:3}%", percent);
+}
+
+/// Number of whitespace separated words in `line`.
+pub fn count_words(line: &[u8]) -> usize {
+    line.split(|c| c.is_ascii_whitespace())
+        .filter(|word| !word.is_empty())
+        .count()
+}
+
+/// Cumulative totals for counting mode.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LineCounts {
+    pub chars: u32,
+    pub words: u32,
+    pub lines: u32,
+}
+
+impl LineCounts {
+    pub fn add_line(&mut self, line: &[u8]) {
+        self.chars += line.len() as u32;
+        self.words += count_words(line) as u32;
+        self.lines += 1;
+    }
+}
+
+/// The byte to echo immediately for received character `c`, if any.
+/// Nothing is echoed per character when local echo is off.
+pub fn echo_byte(c: u8, text_mode: &TextMode, local_echo: bool) -> Option<u8> {
+    match local_echo {
+        true => Some(convert_case(c, text_mode)),
+        false => None,
+    }
+}
+
+/// The bytes sent for `line` echoed `times` times, separated by newlines.
+/// Zero times sends nothing, and once sends the line alone.
+pub fn repeated(line: &[u8], times: usize) -> impl Iterator<Item = u8> + '_ {
+    (0..times).flat_map(move |i| {
+        let separator: &[u8] = if 0 == i { b"" } else { b"\r\n" };
+        separator.iter().chain(line).copied()
+    })
+}
+
+/// View `bytes` as text for logging. Received bytes are not guaranteed to be
+/// valid UTF-8, so only the longest valid prefix is returned, which is empty if
+/// the very first byte is invalid.
+pub fn safe_str(bytes: &[u8]) -> &str {
+    match core::str::from_utf8(bytes) {
+        Ok(text) => text,
+        // The prefix is valid by definition, so this cannot fail.
+        Err(error) => core::str::from_utf8(&bytes[..error.valid_up_to()]).unwrap_or(""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serial_command_wins_over_button() {
+        let (mode, changed) = resolve_mode(TextMode::NormalCase, Some(TextMode::ForceLower), true);
+        assert_eq!(mode, TextMode::ForceLower);
+        assert!(changed);
+    }
+
+    #[test]
+    fn button_cycles_without_a_command() {
+        assert_eq!(
+            resolve_mode(TextMode::NormalCase, None, true),
+            (TextMode::ForceUpper, true)
+        );
+        assert_eq!(
+            resolve_mode(TextMode::Rot(13), None, true),
+            (TextMode::NormalCase, true)
+        );
+    }
+
+    #[test]
+    fn line_limits() {
+        for text_mode in [
+            TextMode::NormalCase,
+            TextMode::ForceUpper,
+            TextMode::ForceLower,
+            TextMode::InvertedCase,
+            TextMode::Rot(13),
+            TextMode::Leet,
+        ] {
+            assert_eq!(effective_limit(&text_mode, 128), 128);
+        }
+        assert_eq!(effective_limit(&TextMode::HexDump, 128), HEX_DUMP_ROW);
+    }
+
+    #[test]
+    fn ruler_columns() {
+        let mut out: String<128> = String::new();
+        ruler(12, &mut out);
+        assert_eq!(out.as_str(), "123456789112");
+        ruler(0, &mut out);
+        assert_eq!(out.as_str(), "");
+        // Column 100 shows the tens digit, 0, and the ruler stops at the capacity.
+        ruler(200, &mut out);
+        assert_eq!(out.len(), 128);
+        assert_eq!(&out[95..105], "6789012345");
+        assert_eq!(&out[9..10], "1");
+        assert_eq!(&out[79..80], "8");
+    }
+
+    #[test]
+    fn word_counts() {
+        assert_eq!(count_words(b""), 0);
+        assert_eq!(count_words(b"   \t "), 0);
+        assert_eq!(count_words(b"hello"), 1);
+        assert_eq!(count_words(b"  hello,  world\t! "), 3);
+    }
+
+    #[test]
+    fn line_counts_accumulate() {
+        let mut counts = LineCounts::default();
+        counts.add_line(b"one two");
+        counts.add_line(b"");
+        counts.add_line(b" three ");
+        assert_eq!(
+            counts,
+            LineCounts {
+                chars: 14,
+                words: 3,
+                lines: 3
+            }
+        );
+    }
+
+    #[test]
+    fn echo_follows_local_echo() {
+        assert_eq!(echo_byte(b'a', &TextMode::ForceUpper, true), Some(b'A'));
+        assert_eq!(echo_byte(b'a', &TextMode::Rot(13), true), Some(b'n'));
+        assert_eq!(echo_byte(b'a', &TextMode::NormalCase, true), Some(b'a'));
+        // With echo off nothing is echoed, whatever the mode.
+        assert_eq!(echo_byte(b'a', &TextMode::ForceUpper, false), None);
+        assert_eq!(echo_byte(b'\r', &TextMode::NormalCase, false), None);
+    }
+
+    #[test]
+    fn safe_str_keeps_the_valid_prefix() {
+        assert_eq!(safe_str(b"hello"), "hello");
+        assert_eq!(safe_str(b""), "");
+        assert_eq!(safe_str("caf\u{e9}".as_bytes()), "caf\u{e9}");
+        assert_eq!(safe_str(b"ok\xFFmore"), "ok");
+        assert_eq!(safe_str(b"\xFFok"), "");
+        // A multi-byte character cut short is dropped whole.
+        assert_eq!(safe_str(&"caf\u{e9}".as_bytes()[..4]), "caf");
+    }
+
+    #[test]
+    fn repetitions_are_separated_by_newlines() {
+        let sent = |line, times| repeated(line, times).collect::<std::vec::Vec<u8>>();
+        assert_eq!(sent(b"ab", 0), b"");
+        assert_eq!(sent(b"ab", 1), b"ab");
+        assert_eq!(sent(b"ab", 3), b"ab\r\nab\r\nab");
+        // An empty line still sends the separators.
+        assert_eq!(sent(b"", 3), b"\r\n\r\n");
+    }
+
+    #[test]
+    fn rot_n_rotates_letters_only() {
+        assert_eq!(rot_n(b'a', 13), b'n');
+        assert_eq!(rot_n(b'N', 13), b'A');
+        assert_eq!(rot_n(b'z', 1), b'a');
+        assert_eq!(rot_n(b'Z', 1), b'A');
+        for c in [b'0', b' ', b'@', b'[', b'`', b'{', 0xFF] {
+            assert_eq!(rot_n(c, 13), c);
+        }
+    }
+
+    #[test]
+    fn rot_n_round_trips() {
+        for c in (b'a'..=b'z').chain(b'A'..=b'Z') {
+            assert_eq!(rot_n(rot_n(c, 13), 13), c);
+            assert_eq!(rot_n(rot_n(c, 3), ALPHABET_SIZE - 3), c);
+            // Rotations of a whole alphabet or more wrap around, without overflow.
+            assert_eq!(rot_n(c, ALPHABET_SIZE), c);
+            assert_eq!(rot_n(c, u8::MAX), rot_n(c, u8::MAX % ALPHABET_SIZE));
+        }
+    }
+
+    #[test]
+    fn rotations_reduce() {
+        assert_eq!(reduce_rotation(0), 0);
+        assert_eq!(reduce_rotation(26), 0);
+        assert_eq!(reduce_rotation(27), 1);
+        assert_eq!(reduce_rotation(u32::MAX), (u32::MAX % 26) as u8);
+    }
+
+    #[test]
+    fn unchanged_mode_is_not_a_change() {
+        assert_eq!(
+            resolve_mode(TextMode::ForceUpper, None, false),
+            (TextMode::ForceUpper, false)
+        );
+        // The button press is discarded, even though the command changes nothing.
+        assert_eq!(
+            resolve_mode(TextMode::ForceUpper, Some(TextMode::ForceUpper), true),
+            (TextMode::ForceUpper, false)
+        );
+    }
+
+    #[test]
+    fn leet_replaces_look_alike_letters() {
+        let leet: Vec<u8> = b"leet speak".iter().map(|&c| to_leet(c)).collect();
+        assert_eq!(leet, b"l337 5p34k");
+        let leet: Vec<u8> = b"ABEGIOST".iter().map(|&c| to_leet(c)).collect();
+        assert_eq!(leet, b"48391057");
+    }
+
+    #[test]
+    fn leet_leaves_everything_else() {
+        for c in 0..=u8::MAX {
+            if !b"abegiostABEGIOST".contains(&c) {
+                assert_eq!(to_leet(c), c);
+            }
+        }
+        assert_eq!(convert_case(b'e', &TextMode::Leet), b'3');
+        assert_eq!(convert_case(b'E', &TextMode::Leet), b'3');
+    }
+
+    fn bar(value: u16, max: u16, width: usize) -> String<64> {
+        let mut out = String::new();
+        bar_graph(value, max, width, &mut out);
+        out
+    }
+
+    #[test]
+    fn bar_graphs() {
+        assert_eq!(bar(50, 100, 10), "[#####     ]  50%");
+        assert_eq!(bar(0, 100, 4), "[    ]   0%");
+        assert_eq!(bar(100, 100, 4), "[####] 100%");
+        // Both the bar and the percentage round to the nearest step.
+        assert_eq!(bar(1, 8, 4), "[#   ]  13%");
+        assert_eq!(bar(4_095, 4_095, 0), "[] 100%");
+    }
+
+    #[test]
+    fn bar_graph_limits() {
+        assert_eq!(bar(200, 100, 4), "[####] 100%");
+        assert_eq!(bar(5, 0, 4), "[    ]   0%");
+        // The width is cut so the whole line fits.
+        let mut out: String<12> = String::new();
+        bar_graph(1, 1, 50, &mut out);
+        assert_eq!(out, "[#####] 100%");
+    }
+}
@@ -0,0 +1,207 @@
+// src/timer.rs
+
+//! Prescaler and auto-reload calculations for the general purpose timers.
+//!
+//! A timer clocked at `pclk_hz` generates an update event every
+//! `(PSC + 1) * (ARR + 1)` ticks, so not every frequency can be hit exactly.
+//! There are also helpers that time a piece of code with a microsecond counter,
+//! that turn a count of clock edges or bytes over a known window into a rate, and
+//! that sweep a frequency back and forth.
+
+use stm32f1xx_hal::{
+    prelude::*,
+    timer::{CounterUs, Instance},
+};
+
+/// Compute the prescaler and auto-reload values that make a timer clocked at
+/// `pclk_hz` generate `target_hz` update events per second.
+///
+/// The smallest prescaler that lets the auto-reload value fit in 16 bits is used,
+/// which keeps the resolution, and therefore the accuracy, as high as possible.
+pub fn timer_reload_for_hz(pclk_hz: u32, target_hz: u32) -> (u16, u16) {
+    if target_hz == 0 {
+        return (u16::MAX, u16::MAX);
+    }
+    let ticks = (pclk_hz / target_hz).max(1);
+    let psc = ((ticks - 1) / (u16::MAX as u32 + 1)).min(u16::MAX as u32);
+    let divisor = target_hz * (psc + 1);
+    // Round to the nearest auto-reload value to minimize the frequency error.
+    let arr = ((pclk_hz + divisor / 2) / divisor)
+        .saturating_sub(1)
+        .min(u16::MAX as u32);
+    (psc as u16, arr as u16)
+}
+
+/// Update event frequency of a timer clocked at `pclk_hz` and configured with
+/// `psc` and `arr`.
+pub fn tick_hz(pclk_hz: u32, psc: u16, arr: u16) -> u32 {
+    // The period can be 2^32 ticks, one more than fits in a `u32`.
+    (pclk_hz as u64 / ((psc as u64 + 1) * (arr as u64 + 1))) as u32
+}
+
+/// Frequency in Hz of a clock that ticked `count` times in `window_us`
+/// microseconds, rounded to the nearest Hz. Returns 0 if no time elapsed.
+pub fn estimate_freq(count: u32, window_us: u32) -> u32 {
+    if window_us == 0 {
+        return 0;
+    }
+    let hz = (count as u64 * 1_000_000 + window_us as u64 / 2) / window_us as u64;
+    hz.min(u32::MAX as u64) as u32
+}
+
+/// Throughput in bytes per second for `bytes` transferred in `elapsed_us`.
+/// Returns 0 if no time elapsed.
+pub fn throughput_bps(bytes: u32, elapsed_us: u32) -> u32 {
+    if elapsed_us == 0 {
+        return 0;
+    }
+    let bps = bytes as u64 * 1_000_000 / elapsed_us as u64;
+    bps.min(u32::MAX as u64) as u32
+}
+
+/// Frequency of a triangle sweep between `low` and `high` at `now_ms`.
+///
+/// The sweep starts at `low`, reaches `high` halfway through `period_ms`, and
+/// returns to `low` at the end of the period. A period too short
+/// to split in half stays at `low`.
+pub fn sweep_freq(now_ms: u32, low: u32, high: u32, period_ms: u32) -> u32 {
+    let half = period_ms / 2;
+    if half == 0 {
+        return low;
+    }
+    let phase = now_ms % (2 * half);
+    let offset = if phase < half {
+        phase
+    } else {
+        2 * half - phase
+    };
+    let span = high.saturating_sub(low) as u64;
+    low + (span * offset as u64 / half as u64) as u32
+}
+
+/// Longest time `time_us` can measure, one period of a 16-bit timer at 1MHz.
+pub const TIME_US_MAX: u32 = u16::MAX as u32;
+
+/// Run `f`, and return how long it took in microseconds, counted by `timer`.
+///
+/// The timer is restarted, so it cannot be used for anything else at the same
+/// time. Calling `f` and reading the counter add a few microseconds of their
+/// own, so time an empty closure to find the overhead. If `f` runs longer than
+/// `TIME_US_MAX`, the counter wraps, and `u32::MAX` is returned instead.
+pub fn time_us<TIM: Instance>(timer: &mut CounterUs<TIM>, f: impl FnOnce()) -> u32 {
+    timer.start(TIME_US_MAX.micros()).ok();
+    f();
+    let elapsed_us = timer.now().ticks();
+    match timer.wait() {
+        Ok(()) => u32::MAX,
+        Err(_) => elapsed_us,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `target_hz` is reached within `tolerance_ppm` parts per million.
+    fn assert_close(pclk_hz: u32, target_hz: u32, tolerance_ppm: u64) {
+        let (psc, arr) = timer_reload_for_hz(pclk_hz, target_hz);
+        let period = (psc as u64 + 1) * (arr as u64 + 1);
+        let error = (pclk_hz as u64).abs_diff(target_hz as u64 * period);
+        assert!(
+            error * 1_000_000 <= tolerance_ppm * target_hz as u64 * period,
+            "{} Hz from {} Hz gives psc {} arr {}",
+            target_hz,
+            pclk_hz,
+            psc,
+            arr
+        );
+    }
+
+    #[test]
+    fn exact_frequencies() {
+        assert_eq!(timer_reload_for_hz(48_000_000, 1_000), (0, 47_999));
+        assert_eq!(timer_reload_for_hz(48_000_000, 1), (732, 65_483));
+        assert_eq!(timer_reload_for_hz(72_000_000, 1_000_000), (0, 71));
+    }
+
+    #[test]
+    fn frequencies_within_tolerance() {
+        for target_hz in [1, 7, 50, 440, 1_000, 12_345, 100_000] {
+            assert_close(48_000_000, target_hz, 100);
+            assert_close(72_000_000, target_hz, 100);
+        }
+        // Only 24 ticks per period are left, so the rounding error is larger.
+        assert_close(8_000_000, 333_333, 20_000);
+    }
+
+    #[test]
+    fn tick_frequencies() {
+        assert_eq!(tick_hz(48_000_000, 0, 47_999), 1_000);
+        assert_eq!(tick_hz(72_000_000, 71, 999), 1_000);
+        // The longest period does not overflow.
+        assert_eq!(tick_hz(u32::MAX, u16::MAX, u16::MAX), 0);
+        assert_eq!(tick_hz(48_000_000, 0, 0), 48_000_000);
+        // The round trip matches the target whenever it can be hit exactly.
+        for target_hz in [1, 50, 1_000, 100_000] {
+            let (psc, arr) = timer_reload_for_hz(48_000_000, target_hz);
+            assert_eq!(tick_hz(48_000_000, psc, arr), target_hz);
+        }
+    }
+
+    #[test]
+    fn triangle_sweep() {
+        let sweep = |now_ms| sweep_freq(now_ms, 200, 2_000, 4_000);
+        assert_eq!(sweep(0), 200);
+        assert_eq!(sweep(1_000), 1_100);
+        assert_eq!(sweep(2_000), 2_000);
+        assert_eq!(sweep(3_000), 1_100);
+        // The next period starts over.
+        assert_eq!(sweep(4_000), 200);
+        assert_eq!(sweep(5_000), 1_100);
+    }
+
+    #[test]
+    fn degenerate_sweeps() {
+        assert_eq!(sweep_freq(123, 200, 2_000, 0), 200);
+        assert_eq!(sweep_freq(123, 200, 2_000, 1), 200);
+        // A high frequency below the low one stays at the low one.
+        assert_eq!(sweep_freq(500, 2_000, 200, 1_000), 2_000);
+        // The span times the offset does not overflow at the peak of the longest sweep.
+        assert_eq!(sweep_freq(u32::MAX / 2, 0, u32::MAX, u32::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn throughput() {
+        // 115200 baud with 8N1 framing is 11520 bytes per second.
+        assert_eq!(throughput_bps(1_152, 100_000), 11_520);
+        assert_eq!(throughput_bps(1, 3), 333_333);
+        assert_eq!(throughput_bps(0, 1_000), 0);
+        assert_eq!(throughput_bps(1_000, 0), 0);
+        assert_eq!(throughput_bps(u32::MAX, 1), u32::MAX);
+    }
+
+    #[test]
+    fn degenerate_targets() {
+        assert_eq!(timer_reload_for_hz(48_000_000, 0), (u16::MAX, u16::MAX));
+        assert_eq!(timer_reload_for_hz(48_000_000, 96_000_000), (0, 0));
+    }
+
+    #[test]
+    fn frequency_estimates() {
+        assert_eq!(estimate_freq(1_000, 1_000_000), 1_000);
+        assert_eq!(estimate_freq(440, 100_000), 4_400);
+        // Rounded to the nearest Hz, halves rounding up.
+        assert_eq!(estimate_freq(1, 3), 333_333);
+        assert_eq!(estimate_freq(1, 2_000_000), 1);
+        assert_eq!(estimate_freq(1, 2_000_001), 0);
+        assert_eq!(estimate_freq(0, 1_000), 0);
+    }
+
+    #[test]
+    fn degenerate_estimates() {
+        assert_eq!(estimate_freq(1_000, 0), 0);
+        // A count too fast for a `u32` saturates instead of wrapping.
+        assert_eq!(estimate_freq(u32::MAX, 1), u32::MAX);
+        assert_eq!(estimate_freq(u32::MAX, u32::MAX), 1_000_000);
+    }
+}
@@ -0,0 +1,124 @@
+// src/tx_interrupt.rs
+
+//! Interrupt driven transmission from a `TxQueue`.
+//!
+//! With a plain `TxQueue`, the main loop has to call `pump` for every byte, so text
+//! only goes out as fast as the loop runs, and a slow pass stalls it. Here, the
+//! USART interrupt handler sends the queue instead, one byte every time the
+//! transmit data register empties. Queuing text returns at once, and the main
+//! loop never touches the USART again.
+//!
+//! The handshake between the two sides is the TXE interrupt enable.
+//!
+//! - `enqueue_str` queues the bytes, then enables the TXE interrupt. If the data
+//!   register is already empty, which it is whenever the USART is idle, the
+//!   interrupt fires straight away and starts the transmission.
+//! - `on_interrupt` writes the next byte, which clears TXE until the byte moves
+//!   on to the shift register. Once the queue is empty, it disables the TXE
+//!   interrupt, because TXE stays set with nothing to send, and the handler
+//!   would otherwise run again as soon as it returns, forever.
+//!
+//! Both sides run inside the same critical section, so the handler can never see
+//! new bytes in the queue with the interrupt about to be disabled, and a byte can
+//! never be left waiting with the interrupt off.
+//!
+//! ```ignore
+//! static TX: InterruptTx<USART2, 1024> = InterruptTx::new();
+//!
+//! #[interrupt]
+//! fn USART2() {
+//!     TX.on_interrupt();
+//! }
+//!
+//! TX.init(tx);
+//! // Unmask the USART2 interrupt in the NVIC, then from the main loop.
+//! TX.enqueue_str("Hello!\r\n").ok();
+//! ```
+
+use crate::{shared::Shared, tx_queue::TxQueue};
+use core::fmt;
+use stm32f1xx_hal::serial::{Instance, Tx};
+
+struct Inner<USART, const N: usize> {
+    tx: Option<Tx<USART>>,
+    queue: TxQueue<N>,
+}
+
+/// A transmit queue sent by the USART interrupt, meant to be a `static`.
+/// `N` is the size of the queue.
+pub struct InterruptTx<USART, const N: usize> {
+    inner: Shared<Inner<USART, N>>,
+}
+
+impl<USART: Instance, const N: usize> InterruptTx<USART, N> {
+    pub const fn new() -> Self {
+        InterruptTx {
+            inner: Shared::new(Inner {
+                tx: None,
+                queue: TxQueue::new(),
+            }),
+        }
+    }
+
+    /// Hand over the transmitter. Bytes queued before this are sent once it arrives.
+    pub fn init(&self, tx: Tx<USART>) {
+        self.inner.with(|inner| {
+            let tx = inner.tx.insert(tx);
+            if !inner.queue.is_empty() {
+                tx.listen();
+            }
+        });
+    }
+
+    /// Queue every byte of `string` that fits, and start sending if needed.
+    /// Bytes that do not fit are dropped, and the number dropped is returned as the error.
+    pub fn enqueue_str(&self, string: &str) -> Result<(), usize> {
+        self.inner.with(|inner| {
+            let result = inner.queue.enqueue_str(string);
+            if let (Some(tx), false) = (inner.tx.as_mut(), inner.queue.is_empty()) {
+                tx.listen();
+            }
+            result
+        })
+    }
+
+    /// Call from the USART interrupt handler. Sends the next byte, and stops the
+    /// TXE interrupt once there is nothing left to send.
+    pub fn on_interrupt(&self) {
+        self.inner.with(|inner| {
+            if let Some(tx) = inner.tx.as_mut() {
+                inner.queue.pump(tx);
+                if inner.queue.is_empty() {
+                    tx.unlisten();
+                }
+            }
+        });
+    }
+
+    /// Number of bytes waiting to be sent.
+    pub fn len(&self) -> usize {
+        self.inner.with(|inner| inner.queue.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return the number of bytes dropped because the queue was full, and reset it.
+    pub fn take_dropped(&self) -> u32 {
+        self.inner.with(|inner| inner.queue.take_dropped())
+    }
+}
+
+impl<USART: Instance, const N: usize> Default for InterruptTx<USART, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Formats straight into the queue, so `write!` returns as soon as the text is queued.
+impl<USART: Instance, const N: usize> fmt::Write for &InterruptTx<USART, N> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        self.enqueue_str(string).map_err(|_| fmt::Error)
+    }
+}
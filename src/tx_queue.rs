@@ -0,0 +1,335 @@
+// src/tx_queue.rs
+
+//! A bounded transmit queue that decouples producing text from sending it.
+//!
+//! Writing directly to `Tx` and flushing blocks the main loop until the whole
+//! message is out, which stalls LED timing for long messages. Instead, text is
+//! pushed into the queue and `pump` is called once per pass through the main
+//! loop to move at most one byte into the USART, without ever blocking.
+//!
+//! When more text is produced than the USART can send, the queue fills up. The
+//! newest bytes are then dropped, and counted. Dropping the oldest bytes instead
+//! would cut into text that is already partway out, garbling two messages rather
+//! than truncating one, and the bytes already queued are usually the most
+//! important, like the start of a report. The count lets a command report how
+//! much was lost, so a queue that is too small for the traffic is easy to spot.
+//!
+//! Some devices cannot keep up with back to back bytes at the full line rate, and
+//! have no flow control. `pump_throttled` spaces the bytes out with a `Throttle`,
+//! sending each one at least a minimum gap after the one before, however fast the
+//! USART could go.
+
+use core::fmt;
+use heapless::spsc::Queue;
+use stm32f1xx_hal::serial::{Instance, Tx};
+
+/// Queue of bytes waiting to be transmitted.
+/// Note that `N` slots hold at most `N - 1` bytes.
+pub struct TxQueue<const N: usize> {
+    queue: Queue<u8, N>,
+    dropped: u32,
+}
+
+impl<const N: usize> TxQueue<N> {
+    pub const fn new() -> Self {
+        TxQueue {
+            queue: Queue::new(),
+            dropped: 0,
+        }
+    }
+
+    /// Queue a single byte, returning it if the queue is full.
+    pub fn enqueue(&mut self, byte: u8) -> Result<(), u8> {
+        self.queue.enqueue(byte).inspect_err(|_| {
+            self.dropped = self.dropped.saturating_add(1);
+        })
+    }
+
+    /// Queue every byte of `string` that fits.
+    /// Bytes that do not fit are dropped, and the number dropped is returned as the error.
+    pub fn enqueue_str(&mut self, string: &str) -> Result<(), usize> {
+        let mut dropped = 0;
+        for byte in string.bytes() {
+            if self.enqueue(byte).is_err() {
+                dropped += 1;
+            }
+        }
+        match dropped {
+            0 => Ok(()),
+            _ => Err(dropped),
+        }
+    }
+
+    /// Write the next queued byte if the USART is ready for it.
+    /// Returns true if a byte was written.
+    pub fn pump<USART: Instance>(&mut self, tx: &mut Tx<USART>) -> bool {
+        self.pump_with(|byte| tx.write(byte).is_ok())
+    }
+
+    /// Offer the next queued byte to `write`, and dequeue it if it was accepted.
+    fn pump_with(&mut self, write: impl FnOnce(u8) -> bool) -> bool {
+        match self.queue.peek() {
+            Some(&byte) if write(byte) => {
+                self.queue.dequeue();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Like `pump`, but only write the byte once `throttle` allows it at `now_us`.
+    pub fn pump_throttled<USART: Instance>(
+        &mut self,
+        tx: &mut Tx<USART>,
+        throttle: &mut Throttle,
+        now_us: u32,
+    ) -> bool {
+        self.pump_throttled_with(|byte| tx.write(byte).is_ok(), throttle, now_us)
+    }
+
+    fn pump_throttled_with(
+        &mut self,
+        write: impl FnOnce(u8) -> bool,
+        throttle: &mut Throttle,
+        now_us: u32,
+    ) -> bool {
+        if !throttle.ready(now_us) {
+            return false;
+        }
+        let sent = self.pump_with(write);
+        if sent {
+            throttle.sent(now_us);
+        }
+        sent
+    }
+
+    /// Number of bytes dropped because the queue was full, since the last
+    /// `take_dropped`. The count saturates rather than wrapping.
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Return the dropped byte count, and reset it.
+    pub fn take_dropped(&mut self) -> u32 {
+        core::mem::take(&mut self.dropped)
+    }
+
+    /// Number of bytes waiting to be sent. Read it from the context that owns the
+    /// queue, or through `Shared` when an interrupt handler writes to it too.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Most bytes the queue can hold, which is `N - 1`.
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+}
+
+/// Whether the next byte may be sent at `now_us`, with the last one sent at
+/// `last_us`, at least `min_gap_us` microseconds apart. Times wrap, so the gap
+/// is found with `wrapping_sub`.
+pub fn next_send_allowed(last_us: u32, now_us: u32, min_gap_us: u32) -> bool {
+    min_gap_us <= now_us.wrapping_sub(last_us)
+}
+
+/// A minimum gap between transmitted bytes, measured from when each byte is
+/// written to the USART. A gap shorter than one byte time at the baud rate has
+/// no effect, since the USART is slower than that anyway. A gap of 0 turns the
+/// throttle off.
+pub struct Throttle {
+    min_gap_us: u32,
+    last_us: Option<u32>,
+}
+
+impl Throttle {
+    pub const fn new(min_gap_us: u32) -> Self {
+        Throttle {
+            min_gap_us,
+            last_us: None,
+        }
+    }
+
+    pub fn min_gap_us(&self) -> u32 {
+        self.min_gap_us
+    }
+
+    pub fn set_min_gap_us(&mut self, min_gap_us: u32) {
+        self.min_gap_us = min_gap_us;
+    }
+
+    /// Whether a byte may be sent at `now_us`. The first byte always may.
+    pub fn ready(&self, now_us: u32) -> bool {
+        match self.last_us {
+            Some(last_us) => next_send_allowed(last_us, now_us, self.min_gap_us),
+            None => true,
+        }
+    }
+
+    /// Record that a byte was sent at `now_us`.
+    pub fn sent(&mut self, now_us: u32) {
+        self.last_us = Some(now_us);
+    }
+}
+
+impl<const N: usize> Default for TxQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> fmt::Write for TxQueue<N> {
+    fn write_str(&mut self, string: &str) -> fmt::Result {
+        self.enqueue_str(string).map_err(|_| fmt::Error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Pump every queued byte into a buffer, as a USART that is always ready.
+    fn drain<const N: usize>(queue: &mut TxQueue<N>) -> heapless::Vec<u8, N> {
+        let mut sent = heapless::Vec::new();
+        while queue.pump_with(|byte| sent.push(byte).is_ok()) {}
+        sent
+    }
+
+    #[test]
+    fn bytes_come_out_in_order() {
+        let mut queue: TxQueue<8> = TxQueue::new();
+        assert_eq!(queue.enqueue_str("abc"), Ok(()));
+        queue.enqueue(b'd').unwrap();
+        assert_eq!(drain(&mut queue)[..], *b"abcd");
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn overflow_is_returned_and_counted() {
+        let mut queue: TxQueue<4> = TxQueue::new();
+        assert_eq!(queue.capacity(), 3);
+        assert_eq!(queue.enqueue_str("ab"), Ok(()));
+        assert_eq!(queue.enqueue(b'c'), Ok(()));
+        assert_eq!(queue.enqueue(b'd'), Err(b'd'));
+        assert_eq!(queue.enqueue_str("efg"), Err(3));
+        assert_eq!(queue.dropped(), 4);
+        assert_eq!(queue.take_dropped(), 4);
+        assert_eq!(queue.dropped(), 0);
+    }
+
+    #[test]
+    fn busy_usart_keeps_the_byte() {
+        let mut queue: TxQueue<4> = TxQueue::new();
+        queue.enqueue(b'a').unwrap();
+        assert!(!queue.pump_with(|_| false));
+        assert_eq!(drain(&mut queue)[..], *b"a");
+        assert!(!queue.pump_with(|_| true));
+    }
+
+    #[test]
+    fn full_queue_drops_the_newest_bytes() {
+        let mut queue: TxQueue<4> = TxQueue::new();
+        assert_eq!(queue.enqueue_str("first"), Err(2));
+        // The queued text is kept whole, and the overflow is cut from its end.
+        assert_eq!(drain(&mut queue)[..], *b"fir");
+        // Space freed by sending is used again.
+        assert_eq!(queue.enqueue_str("xyz"), Ok(()));
+        assert!(queue.pump_with(|_| true));
+        assert_eq!(queue.enqueue(b'!'), Ok(()));
+        assert_eq!(drain(&mut queue)[..], *b"yz!");
+        assert_eq!(queue.dropped(), 2);
+    }
+
+    #[test]
+    fn repeated_overflow_leaves_the_queue_intact() {
+        let mut queue: TxQueue<8> = TxQueue::new();
+        assert_eq!(queue.enqueue_str("queued!"), Ok(()));
+        for (extra, byte) in (1..=100).zip((b'a'..=b'z').cycle()) {
+            assert_eq!(queue.enqueue(byte), Err(byte));
+            assert_eq!(queue.len(), queue.capacity());
+            assert_eq!(queue.dropped(), extra);
+        }
+        assert_eq!(drain(&mut queue)[..], *b"queued!");
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn formatted_overflow_is_an_error() {
+        use core::fmt::Write;
+        let mut queue: TxQueue<4> = TxQueue::new();
+        assert!(write!(queue, "{}", 12).is_ok());
+        assert!(write!(queue, "{}", 345).is_err());
+        assert_eq!(drain(&mut queue)[..], *b"123");
+        assert_eq!(queue.dropped(), 2);
+    }
+
+    #[test]
+    fn len_follows_pushes_and_pops() {
+        let mut queue: TxQueue<8> = TxQueue::new();
+        assert_eq!((queue.len(), queue.capacity()), (0, 7));
+        assert!(queue.is_empty());
+        queue.enqueue_str("abc").unwrap();
+        assert_eq!(queue.len(), 3);
+        assert!(queue.pump_with(|_| true));
+        assert_eq!(queue.len(), 2);
+        // A byte the USART refused is still queued.
+        assert!(!queue.pump_with(|_| false));
+        assert_eq!(queue.len(), 2);
+        queue.enqueue_str("defghij").ok();
+        assert_eq!(queue.len(), queue.capacity());
+        drain(&mut queue);
+        assert_eq!(queue.len(), 0);
+        assert!(queue.is_empty());
+        // Pumping an empty queue changes nothing.
+        assert!(!queue.pump_with(|_| true));
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn send_gaps_allow_for_wrapping() {
+        assert!(next_send_allowed(100, 200, 100));
+        assert!(!next_send_allowed(100, 199, 100));
+        assert!(next_send_allowed(u32::MAX - 49, 50, 100));
+        assert!(!next_send_allowed(u32::MAX - 49, 49, 100));
+        assert!(next_send_allowed(5, 5, 0));
+    }
+
+    #[test]
+    fn throttle_spaces_the_bytes_out() {
+        let mut queue: TxQueue<8> = TxQueue::new();
+        let mut throttle = Throttle::new(1_000);
+        queue.enqueue_str("abc").unwrap();
+        let mut sent = heapless::Vec::<(u32, u8), 8>::new();
+        for now_us in (0..5_000).step_by(250) {
+            queue.pump_throttled_with(
+                |byte| sent.push((now_us, byte)).is_ok(),
+                &mut throttle,
+                now_us,
+            );
+        }
+        // The first byte goes at once, and each one after waits out the gap.
+        assert_eq!(sent, [(0, b'a'), (1_000, b'b'), (2_000, b'c')]);
+    }
+
+    #[test]
+    fn busy_usart_does_not_restart_the_gap() {
+        let mut queue: TxQueue<8> = TxQueue::new();
+        let mut throttle = Throttle::new(1_000);
+        queue.enqueue_str("ab").unwrap();
+        assert!(queue.pump_throttled_with(|_| true, &mut throttle, 0));
+        // The USART is still busy when the gap is over, so the byte goes late, and
+        // the next gap counts from when it actually went.
+        assert!(!queue.pump_throttled_with(|_| false, &mut throttle, 1_000));
+        assert!(queue.pump_throttled_with(|_| true, &mut throttle, 1_300));
+        assert!(!throttle.ready(2_299));
+        assert!(throttle.ready(2_300));
+        // A gap of 0 turns the throttle off.
+        throttle.set_min_gap_us(0);
+        assert_eq!(throttle.min_gap_us(), 0);
+        assert!(throttle.ready(1_300));
+    }
+}
@@ -0,0 +1,258 @@
+// src/xmodem.rs
+
+//! The receiving side of the XMODEM/CRC file transfer protocol.
+//!
+//! The sender transmits 128 byte blocks framed as follows.
+//!
+//! ```text
+//! SOH | block number | 255 - block number | 128 data bytes | CRC high | CRC low
+//! ```
+//!
+//! Block numbers start at 1 and wrap at 255. The receiver answers every packet
+//! with ACK or NAK, and the sender ends the transfer with EOT. `Receiver` only
+//! decodes the byte stream. Sending the replies, the initial `C` that selects
+//! CRC mode, and timeouts are left to the caller, because they depend on the
+//! USART and time base in use. The last block is padded with `SUB` bytes.
+
+/// Start of a 128 byte packet.
+pub const SOH: u8 = 0x01;
+/// End of transmission.
+pub const EOT: u8 = 0x04;
+/// Positive acknowledgement.
+pub const ACK: u8 = 0x06;
+/// Negative acknowledgement, asks the sender to repeat the packet.
+pub const NAK: u8 = 0x15;
+/// Cancel the transfer. Send it twice to abort from the receiving side.
+pub const CAN: u8 = 0x18;
+/// Padding in the final block.
+pub const SUB: u8 = 0x1A;
+/// Sent by the receiver instead of NAK to request a CRC mode transfer.
+pub const CRC_MODE: u8 = b'C';
+/// Data bytes in a block.
+pub const BLOCK_SIZE: usize = 128;
+
+// Block number, its complement, data, and two CRC bytes.
+const PACKET_SIZE: usize = 2 + BLOCK_SIZE + 2;
+const CRC_POLYNOMIAL: u16 = 0x1021;
+
+/// CRC-16 as used by XMODEM, polynomial 0x1021 with an initial value of 0.
+pub fn xmodem_crc(block: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in block {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ CRC_POLYNOMIAL
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// What the caller should do after feeding a byte to `Receiver`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum XmodemEvent {
+    /// More bytes are needed.
+    Pending,
+    /// A new block arrived and is available from `Receiver::block`.
+    /// Store it, call `Receiver::accept`, and reply ACK.
+    Block,
+    /// The previous block arrived again because an ACK was lost. Reply ACK.
+    Repeated,
+    /// The packet failed validation. Reply NAK.
+    Corrupt,
+    /// The sender finished the transfer. Reply ACK.
+    Done,
+    /// The sender cancelled, or a block arrived out of sequence.
+    Cancelled,
+}
+
+pub struct Receiver {
+    packet: [u8; PACKET_SIZE],
+    len: usize,
+    in_packet: bool,
+    expected: u8,
+    blocks: u32,
+}
+
+impl Receiver {
+    pub const fn new() -> Self {
+        Receiver {
+            packet: [0; PACKET_SIZE],
+            len: 0,
+            in_packet: false,
+            expected: 1,
+            blocks: 0,
+        }
+    }
+
+    /// Decode one received byte.
+    pub fn feed(&mut self, byte: u8) -> XmodemEvent {
+        if !self.in_packet {
+            return match byte {
+                SOH => {
+                    self.in_packet = true;
+                    self.len = 0;
+                    XmodemEvent::Pending
+                }
+                EOT => XmodemEvent::Done,
+                CAN => XmodemEvent::Cancelled,
+                // Line noise between packets is ignored.
+                _ => XmodemEvent::Pending,
+            };
+        }
+        self.packet[self.len] = byte;
+        self.len += 1;
+        if self.len < PACKET_SIZE {
+            return XmodemEvent::Pending;
+        }
+        self.in_packet = false;
+        self.check_packet()
+    }
+
+    fn check_packet(&self) -> XmodemEvent {
+        let number = self.packet[0];
+        if number != !self.packet[1] {
+            return XmodemEvent::Corrupt;
+        }
+        let crc = u16::from_be_bytes([self.packet[PACKET_SIZE - 2], self.packet[PACKET_SIZE - 1]]);
+        if xmodem_crc(self.block()) != crc {
+            return XmodemEvent::Corrupt;
+        }
+        if number == self.expected {
+            XmodemEvent::Block
+        } else if number == self.expected.wrapping_sub(1) {
+            XmodemEvent::Repeated
+        } else {
+            XmodemEvent::Cancelled
+        }
+    }
+
+    /// Accept the block that was just reported with `XmodemEvent::Block`.
+    /// Call this once the block has been stored, before replying ACK.
+    pub fn accept(&mut self) {
+        self.expected = self.expected.wrapping_add(1);
+        self.blocks += 1;
+    }
+
+    /// Data of the most recently completed packet.
+    pub fn block(&self) -> &[u8] {
+        &self.packet[2..2 + BLOCK_SIZE]
+    }
+
+    /// Discard a partially received packet, for example after a timeout.
+    /// Reply NAK so the sender repeats it.
+    pub fn abandon_packet(&mut self) {
+        self.in_packet = false;
+        self.len = 0;
+    }
+
+    /// True while a packet is partially received.
+    pub fn is_receiving_packet(&self) -> bool {
+        self.in_packet
+    }
+
+    /// True once the sender has responded, so `C` no longer needs to be sent.
+    pub fn has_started(&self) -> bool {
+        self.in_packet || 0 < self.blocks
+    }
+
+    /// Number of blocks accepted so far.
+    pub fn blocks(&self) -> u32 {
+        self.blocks
+    }
+}
+
+impl Default for Receiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed a whole packet for block `number`, and return the last event.
+    fn feed_packet(receiver: &mut Receiver, number: u8, data: &[u8; BLOCK_SIZE]) -> XmodemEvent {
+        let crc = xmodem_crc(data).to_be_bytes();
+        let mut event = receiver.feed(SOH);
+        for byte in [number, !number].iter().chain(data).chain(&crc) {
+            event = receiver.feed(*byte);
+        }
+        event
+    }
+
+    #[test]
+    fn crc_check_value() {
+        // The standard check value for CRC-16/XMODEM.
+        assert_eq!(xmodem_crc(b"123456789"), 0x31C3);
+        assert_eq!(xmodem_crc(&[]), 0);
+        assert_eq!(
+            xmodem_crc(&[SUB; BLOCK_SIZE]),
+            xmodem_crc(&[SUB; BLOCK_SIZE])
+        );
+    }
+
+    #[test]
+    fn blocks_in_sequence() {
+        let mut receiver = Receiver::new();
+        assert!(!receiver.has_started());
+        let data = [0x55; BLOCK_SIZE];
+        assert_eq!(feed_packet(&mut receiver, 1, &data), XmodemEvent::Block);
+        assert_eq!(receiver.block(), &data[..]);
+        receiver.accept();
+        assert_eq!(
+            feed_packet(&mut receiver, 2, &[SUB; BLOCK_SIZE]),
+            XmodemEvent::Block
+        );
+        receiver.accept();
+        assert_eq!(receiver.blocks(), 2);
+        assert_eq!(receiver.feed(EOT), XmodemEvent::Done);
+    }
+
+    #[test]
+    fn repeated_and_out_of_sequence_blocks() {
+        let mut receiver = Receiver::new();
+        let data = [0; BLOCK_SIZE];
+        feed_packet(&mut receiver, 1, &data);
+        receiver.accept();
+        assert_eq!(feed_packet(&mut receiver, 1, &data), XmodemEvent::Repeated);
+        assert_eq!(feed_packet(&mut receiver, 3, &data), XmodemEvent::Cancelled);
+    }
+
+    #[test]
+    fn corrupt_packets() {
+        let mut receiver = Receiver::new();
+        let data = [0x42; BLOCK_SIZE];
+        let crc = xmodem_crc(&data).to_be_bytes();
+        // A bad block number complement.
+        receiver.feed(SOH);
+        let mut event = XmodemEvent::Pending;
+        for byte in [1, 1].iter().chain(&data).chain(&crc) {
+            event = receiver.feed(*byte);
+        }
+        assert_eq!(event, XmodemEvent::Corrupt);
+        // A bad CRC.
+        receiver.feed(SOH);
+        for byte in [1, !1].iter().chain(&data).chain(&[crc[0], !crc[1]]) {
+            event = receiver.feed(*byte);
+        }
+        assert_eq!(event, XmodemEvent::Corrupt);
+        assert_eq!(receiver.blocks(), 0);
+    }
+
+    #[test]
+    fn noise_cancel_and_abandon() {
+        let mut receiver = Receiver::new();
+        assert_eq!(receiver.feed(b'x'), XmodemEvent::Pending);
+        assert!(!receiver.is_receiving_packet());
+        assert_eq!(receiver.feed(SOH), XmodemEvent::Pending);
+        assert!(receiver.is_receiving_packet());
+        receiver.abandon_packet();
+        assert!(!receiver.is_receiving_packet());
+        assert_eq!(receiver.feed(CAN), XmodemEvent::Cancelled);
+    }
+}